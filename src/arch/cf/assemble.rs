@@ -0,0 +1,1043 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashSet;
+use std::mem::size_of;
+use crate::ast::*;
+use crate::mem::{BE, Endian, Image, Save};
+use super::*;
+
+/// Options controlling assembly.
+#[derive(Clone, Copy, Debug)]
+pub struct AsmOptions {
+    /// Address of the first statement.
+    pub origin: u32,
+
+    /// Hardware features available to the target (see `flags`).
+    pub features: u16,
+
+    /// Whether absolute addresses that do not fit in 16 bits may use the
+    /// PC-relative mode instead of the absolute long mode, where the
+    /// instruction permits.
+    pub pc_relative: bool,
+
+    /// Whether to rewrite instructions to smaller equivalent encodings (see
+    /// `optimize`).
+    pub optimize: bool,
+
+    /// Whether to assemble for a relocatable object, in which undefined
+    /// symbols are external and each section may be moved by the linker.
+    /// References that depend on such addresses become relocations.
+    pub relocatable: bool,
+}
+
+impl AsmOptions {
+    /// Returns default options for assembly at `origin`: ISA_A, absolute
+    /// addressing only, no optimisation, not relocatable.
+    pub fn new(origin: u32) -> Self {
+        Self { origin, features: ISA_A, pc_relative: false, optimize: false, relocatable: false }
+    }
+}
+
+/// The output of assembly.
+#[derive(Clone, Debug)]
+pub struct Assembly {
+    /// Address of the first byte.
+    pub origin: u32,
+
+    /// Assembled bytes.
+    pub bytes: Vec<u8>,
+
+    /// Address of each statement.
+    pub addrs: Vec<u32>,
+
+    /// Size in bytes of each statement.
+    pub sizes: Vec<u32>,
+
+    /// Number of layout passes performed.
+    pub passes: usize,
+
+    /// Statements rewritten by optimisation.
+    pub optimizations: Vec<Optimization>,
+
+    /// Index of each statement that begins a section, with the section name.
+    pub sections: Vec<(usize, Ident)>,
+
+    /// Fields that depend on addresses not known until link time, in
+    /// relocatable assembly.
+    pub relocs: Vec<Reloc>,
+}
+
+impl Assembly {
+    /// Returns the bytes assembled for the statement with index `n`.
+    pub fn stmt_bytes(&self, n: usize) -> &[u8] {
+        let start = self.addrs[n].wrapping_sub(self.origin) as usize;
+        &self.bytes[start..start + self.sizes[n] as usize]
+    }
+
+    /// Returns the assembled bytes as a memory image.  Gaps that no
+    /// statement fills, such as those skipped by `.org`, remain gaps.
+    pub fn image(&self) -> Image {
+        let mut image = Image::new();
+        for n in 0..self.addrs.len() {
+            image.write(self.addrs[n], self.stmt_bytes(n));
+        }
+        image
+    }
+}
+
+/// An error detected during assembly.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AsmError {
+    /// Index of the statement containing the error.
+    pub stmt: usize,
+
+    /// Kind of error.
+    pub kind: AsmErrorKind,
+
+    /// Source location of the statement or, for an error in a particular
+    /// argument, of that argument, if known.
+    pub span: Option<Span>,
+}
+
+/// Kinds of errors detected during assembly.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum AsmErrorKind {
+    /// The operation is neither a known instruction nor a directive.
+    UnknownOp(Ident),
+
+    /// A referenced symbol has no value.
+    Undefined(Ident),
+
+    /// The instruction could not be encoded.
+    Encode(Instructions, EncodeError),
+
+    /// The branch target, optionally a label, is out of range of every
+    /// branch form available to the target.
+    Unreachable(Instructions, Option<Ident>),
+
+    /// The argument with the given index to a data directive is out of range.
+    DataRange(usize),
+
+    /// An argument to a data directive could not be evaluated.
+    DataEval(EvalError),
+
+    /// The argument with the given index is not valid for the directive.
+    Operand(usize),
+
+    /// An origin directive would move the location counter backwards, to the
+    /// given address.
+    Origin(u32),
+
+    /// The argument with the given index depends on an address not known
+    /// until link time, but its field cannot be relocated.
+    Relocation(usize),
+}
+
+impl AsmError {
+    /// Returns a description of the error, using `pool` to name symbols.
+    pub fn message(&self, pool: &IdentPool) -> String {
+        match self.kind {
+            AsmErrorKind::UnknownOp(i) =>
+                format!("unknown operation '{}'", pool.source_name(i)),
+            AsmErrorKind::Undefined(i) =>
+                format!("undefined symbol '{}'", pool.source_name(i)),
+            AsmErrorKind::Encode(inst, ref e) =>
+                e.message(inst, pool),
+            AsmErrorKind::Unreachable(inst, Some(i)) =>
+                format!("label '{}' cannot be reached by any branch form; '{}' is out of range",
+                    pool.source_name(i), inst.name()),
+            AsmErrorKind::Unreachable(inst, None) =>
+                format!("branch target cannot be reached by any branch form; '{}' is out of range",
+                    inst.name()),
+            AsmErrorKind::DataRange(n) =>
+                format!("operand {} is out of range for the data size", n + 1),
+            AsmErrorKind::DataEval(ref e) =>
+                e.message(pool),
+            AsmErrorKind::Operand(n) =>
+                format!("operand {} is not valid for the directive", n + 1),
+            AsmErrorKind::Origin(a) =>
+                format!("origin ${:X} is behind the location counter", a),
+            AsmErrorKind::Relocation(n) =>
+                format!("operand {} cannot be relocated", n + 1),
+        }
+    }
+}
+
+/// Assembles `block` into machine code.
+///
+/// Statements are laid out starting at `opts.origin`, and labels receive the
+/// addresses of their statements in `syms`.  Layout repeats until no form
+/// changes: each branch starts in the size written, or in its shortest form
+/// (`.b`) if the mnemonic has no size, and each bare absolute address starts
+/// as absolute short; a form grows only when its value no longer fits, so
+/// the process always terminates.  The chosen forms are written back to
+/// `block`, which is then resolved against `syms`.
+///
+/// Symbols named by `.globl` become global, and labels are assigned to the
+/// section begun by the most recent `.section` directive, if any.
+///
+/// In relocatable assembly, each `.section` directive is aligned to 4 bytes,
+/// references to undefined symbols are external, and each field whose value
+/// depends on a label or external symbol becomes a relocation (see
+/// `Reloc`).  Such references use absolute long or 16-bit branch forms, as
+/// their values are not known.
+pub fn assemble(block: &mut CfBlock, syms: &mut SymbolTable, opts: &AsmOptions)
+    -> Result<Assembly, Vec<AsmError>>
+{
+    let mut asm = Assembler { opts, forms: vec![], errors: vec![], optimizations: vec![], chunks: None };
+
+    publish(block, syms);
+    asm.prepare(block, syms);
+    let (addrs, passes) = asm.layout(block, syms);
+    let mut assembly = asm.emit(block, syms, addrs, passes);
+    assembly.optimizations = asm.optimizations;
+
+    resolve(block, syms);
+
+    if asm.errors.is_empty() {
+        Ok(assembly)
+    } else {
+        Err(asm.errors)
+    }
+}
+
+// Relaxable forms within a statement
+#[derive(Clone, Copy, Default, Debug)]
+struct Form {
+    branch: bool,   // op is a branch of the given size family
+    abs:    u32,    // bit n set if arg n is a relaxable absolute address
+}
+
+struct Assembler<'a> {
+    opts:          &'a AsmOptions,
+    forms:         Vec<Form>,
+    errors:        Vec<AsmError>,
+    optimizations: Vec<Optimization>,
+    chunks:        Option<Chunks>,
+}
+
+impl<'a> Assembler<'a> {
+    // Selects the shortest forms.
+    fn prepare(&mut self, block: &mut CfBlock, syms: &SymbolTable) {
+        // Label values are not known until layout, so optimisation considers
+        // only values defined elsewhere.
+        let labels: HashSet<Ident> = block.stmts.iter()
+            .flat_map(|s| s.labels.iter().copied())
+            .collect();
+        let lookup = |i| if labels.contains(&i) { None } else { syms.value(i) };
+
+        if self.opts.relocatable {
+            self.chunks = Some(Chunks::of(block));
+        }
+
+        for (n, stmt) in block.stmts.iter_mut().enumerate() {
+            let mut form = Form::default();
+
+            if self.opts.optimize {
+                if let Some(inst) = inst_of(stmt) {
+                    let args = stmt.args.clone();
+                    if let Some(saved) = optimize(stmt, self.opts.features, &lookup) {
+                        self.optimizations.push(Optimization { stmt: n, inst, args, saved });
+                    }
+                }
+            }
+
+            if let Some(inst) = inst_of(stmt) {
+                if branch_family(inst).is_some() {
+                    form.branch = true;
+                } else {
+                    for (n, arg) in stmt.args.iter_mut().enumerate() {
+                        if let Some(e) = bare_expr(arg) {
+                            *arg      = Slot::Value(Arg::AbsShort(e), arg.span());
+                            form.abs |= 1 << n;
+                        }
+                    }
+                }
+            }
+
+            self.forms.push(form);
+        }
+    }
+
+    // Assigns addresses until forms are stable.
+    fn layout(&mut self, block: &mut CfBlock, syms: &mut SymbolTable) -> (Vec<u32>, usize) {
+        let mut addrs  = vec![0; block.stmts.len()];
+        let mut passes = 0;
+
+        loop {
+            passes += 1;
+
+            let mut addr    = self.opts.origin;
+            let mut section = None;
+            for (n, stmt) in block.stmts.iter().enumerate() {
+                addr = align(stmt, addr);
+                match origin_of(stmt, syms) {
+                    Some(Ok(org)) if org >= addr => addr = org,
+                    _                            => (),
+                }
+                // Labels of a section directive end the previous section.
+                for &label in &stmt.labels {
+                    syms.set_value(label, addr);
+                    syms.entry(label).section = section;
+                }
+                if let Some(name) = section_of(stmt) {
+                    if self.opts.relocatable {
+                        addr = addr.wrapping_add(3) & !3;
+                    }
+                    section = Some(name);
+                }
+                addrs[n] = addr;
+                addr = addr.wrapping_add(self.size_of(stmt, syms));
+            }
+
+            let mut changed = false;
+            for (n, stmt) in block.stmts.iter_mut().enumerate() {
+                changed |= self.relax(n, stmt, addrs[n], syms);
+            }
+            if !changed {
+                return (addrs, passes)
+            }
+        }
+    }
+
+    fn size_of(&self, stmt: &CfStmt, syms: &SymbolTable) -> u32 {
+        let args = args_of(stmt);
+        match stmt.op.value_copy() {
+            Some(Op::Inst(inst)) => {
+                // Erroneous instructions occupy an opword; emit reports them.
+                match select(inst, &args, self.opts.features) {
+                    Ok(enc) => size(enc, &args),
+                    Err(_)  => 2,
+                }
+            },
+            Some(Op::Asm(op)) => {
+                let unit  = op.unit_size().unwrap_or(0) as u32;
+                let count = || match args.first() {
+                    Some(Arg::Expr(e)) => match eval(e, &|i| syms.value(i)) {
+                        Ok(c) if in_range(c.0, 0, i32::MAX) => c.0,
+                        _                                   => 0,
+                    },
+                    _ => 0,
+                };
+                match op {
+                    AsmOp::Data8 | AsmOp::Data16 | AsmOp::Data32 | AsmOp::Data64
+                                 | AsmOp::Float32 | AsmOp::Float64 => {
+                        args.iter().map(|a| match text_of(a) {
+                            Some(s) if unit == 1 => s.len() as u32,
+                            _                    => unit,
+                        }).sum()
+                    },
+                    AsmOp::Chars(e) | AsmOp::CharsZ(e) => {
+                        let term = if let AsmOp::CharsZ(_) = op { unit } else { 0 };
+                        args.iter().map(|a| match text_of(a) {
+                            Some(s) => e.len(s) as u32 + term,
+                            _       => unit,
+                        }).sum()
+                    },
+                    AsmOp::Zero8 | AsmOp::Zero16 | AsmOp::Zero32 | AsmOp::Zero64 => {
+                        count().wrapping_mul(unit)
+                    },
+                    AsmOp::Skip => count(),
+                    AsmOp::Empty | AsmOp::Origin | AsmOp::Public | AsmOp::Section => 0,
+                }
+            },
+            None => 0,
+        }
+    }
+
+    // Grows forms that do not fit at the current addresses.  Returns whether
+    // any form changed.
+    fn relax(&self, n: usize, stmt: &mut CfStmt, addr: u32, syms: &SymbolTable) -> bool {
+        let form   = self.forms[n];
+        let lookup = |i| syms.value(i);
+
+        let inst = match inst_of(stmt) {
+            Some(i) => i,
+            None    => return false,
+        };
+
+        if form.branch {
+            let args   = args_of(stmt);
+            let target = match args.first() {
+                Some(Arg::Expr(e)) => e,
+                _                  => return false,
+            };
+            // A target not known until link time takes the largest form.
+            if !self.relocates(n, target, syms, true) {
+                let disp = match eval(target, &lookup) {
+                    Ok(t)  => t.0.wrapping_sub(addr.wrapping_add(2)),
+                    Err(_) => return false,
+                };
+                if branch_fits(inst, disp) {
+                    return false
+                }
+            }
+            // Grow if a larger form is available
+            let family = branch_family(inst).unwrap();
+            let next   = family.iter().skip_while(|&&i| i != inst).nth(1);
+            return match next {
+                Some(&next) if select(next, &args, self.opts.features).is_ok() => {
+                    stmt.op = Slot::Value(Op::Inst(next), stmt.op.span());
+                    true
+                },
+                _ => false,
+            }
+        }
+
+        let mut changed = false;
+        for i in 0..stmt.args.len() {
+            if form.abs & 1 << i == 0 {
+                continue
+            }
+            let next = match stmt.args[i] {
+                Slot::Value(Arg::AbsShort(ref e), _) if self.relocates(n, e, syms, false) => {
+                    Arg::AbsLong(e.clone())
+                },
+                Slot::Value(Arg::AbsShort(ref e), _) => match eval(e, &lookup) {
+                    Ok(v) if !in_range(v.0, -0x8000, 0x7FFF) => {
+                        if self.opts.pc_relative && self.pc_fits(inst, stmt, i, addr, v.0) {
+                            Arg::PcDisp(PcReg, e.clone())
+                        } else {
+                            Arg::AbsLong(e.clone())
+                        }
+                    },
+                    _ => continue,
+                },
+                Slot::Value(Arg::PcDisp(_, ref e), _) => match eval(e, &lookup) {
+                    Ok(v) if !self.pc_fits(inst, stmt, i, addr, v.0) => {
+                        Arg::AbsLong(e.clone())
+                    },
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            stmt.args[i] = Slot::Value(next, stmt.arg_span(i));
+            changed = true;
+        }
+        changed
+    }
+
+    // Determines whether a reference to `expr` from statement `n`, absolute
+    // or `pc` relative, depends on an address not known until link time.
+    fn relocates(&self, n: usize, expr: &Expr, syms: &SymbolTable, pc: bool) -> bool {
+        let chunks = match self.chunks {
+            Some(ref c) => c,
+            None        => return false,
+        };
+        match reloc_target(expr, chunks, syms) {
+            Some(RelocTarget::Absolute)    => pc,
+            Some(RelocTarget::Label(_, c)) => !pc || c != chunks.stmts[n],
+            _                              => true,
+        }
+    }
+
+    // Determines whether argument `i` of the statement at `addr` could
+    // reach `target` in PC-relative mode.
+    fn pc_fits(&self, inst: Instructions, stmt: &CfStmt, i: usize, addr: u32, target: u32) -> bool {
+        let mut args = args_of(stmt);
+        args[i] = Arg::PcDisp(PcReg, Expr::LitInt(target as i64, None));
+
+        let enc = match select(inst, &args, self.opts.features) {
+            Ok(enc) => enc,
+            Err(_)  => return false,
+        };
+
+        // The displacement is relative to its own extension word.
+        let op_size = inst.info().size().map_or(4, Size::bytes);
+        let ext: u32 = enc.operands().iter().zip(&args).take(i)
+            .map(|(o, a)| o.ext_words(a, op_size))
+            .sum();
+        let here = addr.wrapping_add(2 * (enc.words() + ext));
+        in_range(target.wrapping_sub(here), -0x8000, 0x7FFF)
+    }
+
+    // Generates code for the final layout.
+    fn emit(&mut self, block: &mut CfBlock, syms: &SymbolTable, addrs: Vec<u32>, passes: usize)
+        -> Assembly
+    {
+        let origin    = self.opts.origin;
+        let mut bytes    = vec![];
+        let mut sizes    = vec![];
+        let mut sections = vec![];
+        let mut relocs   = vec![];
+        let lookup       = |i| syms.value(i);
+
+        for (n, stmt) in block.stmts.iter_mut().enumerate() {
+            // Pad for alignment or for a previous erroneous statement
+            let addr = addrs[n];
+            bytes.resize(addr.wrapping_sub(origin) as usize, 0);
+            let start    = bytes.len();
+            let mut args = args_of(stmt);
+
+            let unrelocatable = match (&self.chunks, stmt.op.value_copy()) {
+                (Some(chunks), Some(op)) => {
+                    match relocate(n, op, &mut args, addr, self.opts.features, chunks, syms) {
+                        Ok(r)  => { relocs.extend(r); None },
+                        Err(k) => Some(k),
+                    }
+                },
+                _ => None,
+            };
+
+            let kind = match stmt.op {
+                _ if unrelocatable.is_some() => unrelocatable,
+                Slot::Ident(i, _) => Some(AsmErrorKind::UnknownOp(i)),
+                Slot::Value(Op::Inst(inst), _) | Slot::Resolved(_, Op::Inst(inst), _) => {
+                    let result = select(inst, &args, self.opts.features).and_then(|enc| {
+                        stmt.op = Slot::Value(Op::Inst(enc.inst()), stmt.op.span());
+                        encode(enc, &args, addr, &lookup)
+                    });
+                    match result {
+                        Ok(words) => {
+                            for w in words { push(&mut bytes, w) }
+                            None
+                        },
+                        Err(EncodeError::Eval(EvalError::Undefined(i))) => {
+                            Some(AsmErrorKind::Undefined(i))
+                        },
+                        Err(EncodeError::OutOfRange(_)) if self.forms[n].branch => {
+                            let target = stmt.args.first().and_then(|a| a.ident());
+                            Some(AsmErrorKind::Unreachable(inst, target))
+                        },
+                        Err(e) => {
+                            Some(AsmErrorKind::Encode(inst, e))
+                        },
+                    }
+                },
+                Slot::Value(Op::Asm(op), _) | Slot::Resolved(_, Op::Asm(op), _) => {
+                    if let Some(name) = section_of(stmt) {
+                        sections.push((n, name));
+                    }
+                    emit_asm(op, &args, addr, &lookup, &mut bytes).err()
+                },
+            };
+
+            if let Some(kind) = kind {
+                let span = match kind {
+                    AsmErrorKind::DataRange(i)
+                        | AsmErrorKind::Operand(i)
+                        | AsmErrorKind::Relocation(i) => stmt.arg_span(i),
+                    _                                 => stmt.span,
+                };
+                self.errors.push(AsmError { stmt: n, kind, span });
+            }
+            sizes.push((bytes.len() - start) as u32);
+        }
+
+        Assembly { origin, bytes, addrs, sizes, passes, optimizations: vec![], sections, relocs }
+    }
+}
+
+fn emit_asm<F>(op: AsmOp, args: &[Arg], addr: u32, lookup: &F, bytes: &mut Vec<u8>)
+    -> Result<(), AsmErrorKind>
+where
+    F: Fn(Ident) -> Option<u32>
+{
+    let value = |expr: &Expr| match eval(expr, lookup) {
+        Ok(v)                        => Ok(v.0),
+        Err(EvalError::Undefined(i)) => Err(AsmErrorKind::Undefined(i)),
+        Err(e)                       => Err(AsmErrorKind::DataEval(e)),
+    };
+    let float = |expr: &Expr| match eval_float(expr, lookup) {
+        Ok(v)                        => Ok(v),
+        Err(EvalError::Undefined(i)) => Err(AsmErrorKind::Undefined(i)),
+        Err(e)                       => Err(AsmErrorKind::DataEval(e)),
+    };
+    let single = |expr: &Expr| match eval_single(expr, lookup) {
+        Ok(v)                        => Ok(v),
+        Err(EvalError::Undefined(i)) => Err(AsmErrorKind::Undefined(i)),
+        Err(e)                       => Err(AsmErrorKind::DataEval(e)),
+    };
+    let check = |n: usize, ok: bool| if ok { Ok(()) } else { Err(AsmErrorKind::DataRange(n)) };
+
+    match op {
+        AsmOp::Empty | AsmOp::Section => Ok(()),
+        AsmOp::Public => {
+            // Bindings were set before layout; only check the operands.
+            match args.iter().position(|a| !matches!(*a, Arg::Expr(Expr::Ident(_, _)))) {
+                Some(n) => Err(AsmErrorKind::Operand(n)),
+                None    => Ok(()),
+            }
+        },
+        AsmOp::Origin => {
+            // Layout has already moved to the origin if possible.
+            let org = match args {
+                [Arg::Expr(e)] => value(e)?,
+                _              => return Err(AsmErrorKind::Operand(args.len().min(1))),
+            };
+            if org != addr { Err(AsmErrorKind::Origin(org)) } else { Ok(()) }
+        },
+        AsmOp::Skip => {
+            let (count, fill) = match args {
+                [Arg::Expr(c)]                => (value(c)?, 0),
+                [Arg::Expr(c), Arg::Expr(f)]  => (value(c)?, value(f)?),
+                _                             => return Err(AsmErrorKind::Operand(args.len().min(2))),
+            };
+            check(0, in_range(count, 0, i32::MAX))?;
+            check(1, in_range(fill, -0x80, 0xFF))?;
+            bytes.resize(bytes.len() + count as usize, fill as u8);
+            Ok(())
+        },
+        AsmOp::Zero8 | AsmOp::Zero16 | AsmOp::Zero32 | AsmOp::Zero64 => {
+            // Reserved space: one count argument
+            let count = match args {
+                [Arg::Expr(c)] => value(c)?,
+                _              => return Err(AsmErrorKind::Operand(args.len().min(1))),
+            };
+            check(0, in_range(count, 0, i32::MAX))?;
+            let unit = op.unit_size().unwrap_or(0);
+            bytes.resize(bytes.len() + count as usize * unit, 0);
+            Ok(())
+        },
+        _ => {
+            for (n, arg) in args.iter().enumerate() {
+                let expr = match *arg {
+                    Arg::Expr(ref e) => e,
+                    _                => return Err(AsmErrorKind::Operand(n)),
+                };
+
+                // Strings take quoted text in their encoding, and byte data
+                // takes it as UTF-8.
+                match (text_of(arg), op) {
+                    (Some(s), AsmOp::Chars(e)) => {
+                        e.encode(s, bytes);
+                        continue
+                    },
+                    (Some(s), AsmOp::CharsZ(e)) => {
+                        e.encode(s, bytes);
+                        e.terminate(bytes);
+                        continue
+                    },
+                    (Some(s), AsmOp::Data8) => {
+                        bytes.extend_from_slice(s.as_bytes());
+                        continue
+                    },
+                    _ => (),
+                }
+
+                match op {
+                    AsmOp::Chars(_) | AsmOp::CharsZ(_) => {
+                        return Err(AsmErrorKind::Operand(n))
+                    },
+                    AsmOp::Float32 => {
+                        push(bytes, single(expr)?);
+                    },
+                    AsmOp::Float64 => {
+                        push(bytes, float(expr)?);
+                    },
+                    AsmOp::Data64 => {
+                        // Literals keep all 64 bits; other values extend the
+                        // 32-bit result of evaluation.
+                        let v = match *expr {
+                            Expr::LitInt(v, _) => v,
+                            _                  => value(expr)? as i32 as i64,
+                        };
+                        push(bytes, v as u64);
+                    },
+                    _ => {
+                        let v = value(expr)?;
+                        match op {
+                            AsmOp::Data8  => { check(n, in_range(v, -0x80,   0xFF  ))?; bytes.push(v as u8 ) },
+                            AsmOp::Data16 => { check(n, in_range(v, -0x8000, 0xFFFF))?; push(bytes, v as u16) },
+                            _             => {                                             push(bytes, v       ) },
+                        }
+                    },
+                }
+            }
+            Ok(())
+        },
+    }
+}
+
+// Makes the symbols named by .globl directives global.
+fn publish(block: &CfBlock, syms: &mut SymbolTable) {
+    for stmt in &block.stmts {
+        if stmt.op.value_copy() != Some(Op::Asm(AsmOp::Public)) {
+            continue
+        }
+        for arg in &stmt.args {
+            if let Some(i) = arg.ident() {
+                syms.set_binding(i, Binding::Global);
+            }
+        }
+    }
+}
+
+// Returns the address given by an origin directive, if the statement is one.
+fn origin_of(stmt: &CfStmt, syms: &SymbolTable) -> Option<Result<u32, EvalError>> {
+    if stmt.op.value_copy() != Some(Op::Asm(AsmOp::Origin)) {
+        return None
+    }
+    match args_of(stmt).first() {
+        Some(Arg::Expr(e)) => Some(eval(e, &|i| syms.value(i)).map(|v| v.0)),
+        _                  => None,
+    }
+}
+
+// Returns the name given by a section directive, if the statement is one.
+fn section_of(stmt: &CfStmt) -> Option<Ident> {
+    if stmt.op.value_copy() != Some(Op::Asm(AsmOp::Section)) {
+        return None
+    }
+    stmt.args.first()?.ident()
+}
+
+// Returns the text of a quoted literal argument.
+fn text_of(arg: &Arg) -> Option<&str> {
+    match *arg {
+        Arg::Expr(Expr::LitStr (ref s, _)) => Some(s),
+        Arg::Expr(Expr::LitChar(ref s, _)) => Some(s),
+        _                                  => None,
+    }
+}
+
+// Returns the instruction of the statement, if any.
+fn inst_of(stmt: &CfStmt) -> Option<Instructions> {
+    match stmt.op.value_copy() {
+        Some(Op::Inst(i)) => Some(i),
+        _                 => None,
+    }
+}
+
+// Returns the arguments of the statement as values.  Identifier slots become
+// identifier expressions, so that evaluation uses current symbol values.
+pub(crate) fn args_of(stmt: &CfStmt) -> Vec<Arg> {
+    stmt.args.iter().map(|a| match *a {
+        Slot::Ident    (i,        s) => Arg::Expr(Expr::Ident(i, s)),
+        Slot::Resolved (i, _,     s) => Arg::Expr(Expr::Ident(i, s)),
+        Slot::Value    (   ref v, _) => v.clone(),
+    }).collect()
+}
+
+// Returns the expression of a bare (unsized) absolute address argument.
+fn bare_expr(arg: &Slot<Arg>) -> Option<Expr> {
+    match *arg {
+        Slot::Ident    (i,                   s) => Some(Expr::Ident(i, s)),
+        Slot::Resolved (i, _,                s) => Some(Expr::Ident(i, s)),
+        Slot::Value    (   Arg::Expr(ref e), _) => Some(e.clone()),
+        _                                       => None,
+    }
+}
+
+/// Returns the `.b`, `.w`, and `.l` forms of a branch instruction, or `None`
+/// if the instruction is not a branch.
+pub fn branch_family(inst: Instructions) -> Option<[Instructions; 3]> {
+    let is_branch = encodings_for(inst).next().is_some_and(|e| matches!(
+        e.operands(), [Operand::PcRel8] | [Operand::PcRel16] | [Operand::PcRel32]
+    ));
+    if !is_branch {
+        return None
+    }
+
+    let name = inst.name();
+    let stem = &name[..name.len() - 2];
+    let form = |s| Instructions::with_name(&format!("{}.{}", stem, s));
+    Some([form("b")?, form("w")?, form("l")?])
+}
+
+// Determines whether the branch instruction can encode the displacement.
+fn branch_fits(inst: Instructions, disp: u32) -> bool {
+    match inst.info().size() {
+        Some(Size::Byte) => in_range(disp, -0x80, 0x7F) && disp != 0 && disp != u32::MAX,
+        Some(Size::Word) => in_range(disp, -0x8000, 0x7FFF),
+        _                => true,
+    }
+}
+
+// Returns the address of the statement given the address after the previous
+// statement.  Instructions and multi-byte data are word-aligned.
+fn align(stmt: &CfStmt, addr: u32) -> u32 {
+    let aligned = match stmt.op.value_copy() {
+        Some(Op::Inst(_))  => true,
+        Some(Op::Asm(op))  => op.unit_size().is_some_and(|s| s > 1),
+        None               => false,
+    };
+    if aligned { addr.wrapping_add(1) & !1 } else { addr }
+}
+
+fn push<T>(bytes: &mut Vec<u8>, value: T) where T: Copy + Endian {
+    let n = bytes.len();
+    bytes.resize(n + size_of::<T>(), 0);
+    value.save(&mut bytes[n..], BE);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::obj::ihex::*;
+    use super::*;
+
+    fn asm(src: &str, opts: &AsmOptions) -> (Result<Assembly, Vec<AsmError>>, CfBlock, IdentPool) {
+        let mut pool  = IdentPool::new();
+        let mut syms  = SymbolTable::new();
+        let mut block = parse_moto(src, &mut pool, &mut syms).unwrap();
+        let result    = assemble(&mut block, &mut syms, opts);
+        (result, block, pool)
+    }
+
+    fn words(bytes: &[u8]) -> Vec<u16> {
+        bytes.chunks(2).map(|c| (c[0] as u16) << 8 | c[1] as u16).collect()
+    }
+
+    #[test]
+    pub fn assemble_relaxes_branches() {
+        let (result, block, _) = asm(
+            "start   bra     fwd\n\
+             \x20       ds.b    200\n\
+             fwd     bra     start\n\
+             \x20       bra     fwd\n",
+            &AsmOptions::new(0x1000)
+        );
+        let a = result.unwrap();
+
+        assert_eq!( a.addrs, [0x1000, 0x1004, 0x10CC, 0x10D0] );
+        assert_eq!( words(a.stmt_bytes(0)), [0x6000, 0x00CA] );
+        assert_eq!( words(a.stmt_bytes(2)), [0x6000, 0xFF32] );
+        assert_eq!( words(a.stmt_bytes(3)), [0x60FA] );
+        assert_eq!( block.stmts[0].op, Slot::Value(Op::Inst(Instructions::BRAW), None) );
+        assert_eq!( block.stmts[3].op, Slot::Value(Op::Inst(Instructions::BRAB), None) );
+        assert!( a.passes > 1 );
+    }
+
+    #[test]
+    pub fn assemble_keeps_branch_size() {
+        let (result, block, _) = asm(
+            "start   bra.l   near\n\
+             near    beq.w   start\n\
+             \x20       bsr     near\n",
+            &AsmOptions { features: ISA_B, ..AsmOptions::new(0) }
+        );
+        let a = result.unwrap();
+
+        assert_eq!( a.addrs, [0, 6, 10] );
+        assert_eq!( words(a.stmt_bytes(0)), [0x60FF, 0x0000, 0x0004] );
+        assert_eq!( words(a.stmt_bytes(1)), [0x6700, 0xFFF8] );
+        assert_eq!( words(a.stmt_bytes(2)), [0x61FA] );
+        assert_eq!( block.stmts[0].op, Slot::Value(Op::Inst(Instructions::BRAL), None) );
+        assert_eq!( block.stmts[1].op, Slot::Value(Op::Inst(Instructions::BEQW), None) );
+        assert_eq!( block.stmts[2].op, Slot::Value(Op::Inst(Instructions::BSRB), None) );
+    }
+
+    #[test]
+    pub fn assemble_relaxes_absolute() {
+        let src = " lea     tgt,a0\n\
+                   \x20ds.b    12\n\
+                   tgt nop\n";
+
+        let (result, _, _) = asm(src, &AsmOptions::new(0x7FF0));
+        let a = result.unwrap();
+        assert_eq!( words(a.stmt_bytes(0)), [0x41F9, 0x0000, 0x8002] );
+
+        let opts = AsmOptions { pc_relative: true, ..AsmOptions::new(0x7FF0) };
+        let (result, block, _) = asm(src, &opts);
+        let a = result.unwrap();
+        assert_eq!( words(a.stmt_bytes(0)), [0x41FA, 0x000E] );
+        assert!( matches!(block.stmts[0].args[0], Slot::Value(Arg::PcDisp(..), _)) );
+
+        let (result, _, _) = asm(" jsr $1234\n", &AsmOptions::new(0));
+        assert_eq!( words(&result.unwrap().bytes), [0x4EB8, 0x1234] );
+    }
+
+    #[test]
+    pub fn assemble_data() {
+        let (result, _, _) = asm(
+            " dc.b 'AB',1\n\
+             \x20dc.w $1234\n\
+             \x20dc.l -1\n",
+            &AsmOptions::new(0)
+        );
+        let a = result.unwrap();
+        assert_eq!( a.addrs, [0, 4, 6] );
+        assert_eq!( a.bytes, [0x41, 0x42, 0x01, 0x00, 0x12, 0x34, 0xFF, 0xFF, 0xFF, 0xFF] );
+    }
+
+    #[test]
+    pub fn assemble_image() {
+        let (result, _, _) = asm(
+            " dc.b 'AB',1\n\
+             \x20dc.w $1234\n\
+             \x20org $10000\n\
+             \x20rts\n",
+            &AsmOptions::new(0xFFF8)
+        );
+        let image = result.unwrap().image();
+        let spans: Vec<(u32, usize)> = image.blocks().iter().map(|b| (b.region.vma, b.bytes.len())).collect();
+        assert_eq!( spans, [(0xFFF8, 3), (0xFFFC, 2), (0x10000, 2)] );
+
+        let file = HexFile { image, start: Some(HexStart::Linear(0x10002)) };
+        assert_eq!(
+            file.write(16),
+            ":03FFF80041420182\n\
+             :02FFFC001234BD\n\
+             :020000040001F9\n\
+             :020000004E753B\n\
+             :0400000500010002F4\n\
+             :00000001FF\n"
+        );
+    }
+
+    #[test]
+    pub fn assemble_directives() {
+        let mut pool  = IdentPool::new();
+        let mut syms  = SymbolTable::new();
+        let mut block = parse_moto(
+            " .section .data\n\
+             \x20.globl msg\n\
+             msg .asciz \"hi\",\"\"\n\
+             \x20.ascii 'ok'\n\
+             \x20.org $10\n\
+             \x20.quad -2\n\
+             \x20.skip 3,$AA\n\
+             \x20ds.q 1\n",
+            &mut pool, &mut syms
+        ).unwrap();
+        let a = assemble(&mut block, &mut syms, &AsmOptions::new(0)).unwrap();
+
+        let msg  = pool.get("msg").unwrap();
+        let data = pool.get(".data").unwrap();
+        assert_eq!( a.sections, [(0, data)] );
+        assert_eq!( a.addrs, [0, 0, 0, 4, 0x10, 0x10, 0x18, 0x1C] );
+        assert_eq!( &a.bytes[..6], b"hi\0\0ok" );
+        assert_eq!( &a.bytes[6..0x10], [0; 10] );
+        assert_eq!( &a.bytes[0x10..0x1B], [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFE, 0xAA, 0xAA, 0xAA] );
+        assert_eq!( a.bytes.len(), 0x24 );
+        assert_eq!( syms.get(msg).unwrap().binding, Binding::Global );
+        assert_eq!( syms.get(msg).unwrap().section, Some(data) );
+
+        let (result, _, pool) = asm(" ds.b 4\n .org 2\n", &AsmOptions::new(0));
+        let errors = result.unwrap_err();
+        assert_eq!( errors[0].message(&pool), "origin $2 is behind the location counter" );
+    }
+
+    #[test]
+    pub fn assemble_encoded_strings() {
+        let src = " .ascii16be \"Hi\"\n\
+                   \x20.byte 1\n\
+                   \x20.asciz16le \"\u{e9}\"\n\
+                   \x20.string32 \"A\"\n";
+        let (result, block, pool) = asm(src, &AsmOptions::new(0));
+        let a = result.unwrap();
+        assert_eq!( a.addrs, [0, 4, 6, 10] );
+        assert_eq!(
+            a.bytes,
+            [
+                0x00, 0x48, 0x00, 0x69, 0x01, 0x00,
+                0xE9, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x41, 0x00, 0x00, 0x00, 0x00,
+            ]
+        );
+        assert_eq!(
+            gas(&block, &pool).to_string(),
+            "        .short 0x48,0x69\n\
+             \x20       .byte 1\n\
+             \x20       .short 0xE900,0\n\
+             \x20       .string32 \"A\"\n"
+        );
+    }
+
+    #[test]
+    pub fn assemble_gas_strings() {
+        let src = " .asciz16le \"Hi\u{e9}\u{20ac}\"\n\
+                   \x20.asciz16be \"ok\",\"\u{e9}\"\n\
+                   \x20.asciz16be \"\u{20ac}\"\n";
+        let (result, block, pool) = asm(src, &AsmOptions::new(0));
+        let bytes = result.unwrap().bytes;
+        let text  = gas(&block, &pool).to_string();
+        assert_eq!(
+            text,
+            "        .short 0x4800,0x6900,0xE900,0xAC20,0\n\
+             \x20       .string16 \"ok\",\"\\xE9\"\n\
+             \x20       .short 0x20AC,0\n"
+        );
+
+        // The GNU form assembles to the same bytes
+        let (result, _, _) = asm(&text, &AsmOptions::new(0));
+        assert_eq!( result.unwrap().bytes, bytes );
+    }
+
+    #[test]
+    pub fn assemble_floats() {
+        let src = " .float 1.5,-2\n\
+                   \x20.double 3.14159\n\
+                   \x20dc.s 0.1*2\n";
+        let (result, block, pool) = asm(src, &AsmOptions::new(0));
+        let a = result.unwrap();
+        assert_eq!( a.addrs, [0, 8, 16] );
+        assert_eq!(
+            a.bytes,
+            [
+                0x3F, 0xC0, 0x00, 0x00, 0xC0, 0x00, 0x00, 0x00,
+                0x40, 0x09, 0x21, 0xF9, 0xF0, 0x1B, 0x86, 0x6E,
+                0x3E, 0x4C, 0xCC, 0xCD,
+            ]
+        );
+        assert_eq!(
+            gas(&block, &pool).to_string(),
+            "        .float 1.5,-2\n\
+             \x20       .double 3.14159\n\
+             \x20       .float 0.1*2\n"
+        );
+
+        // Single precision is rounded once from the decimal literal, which
+        // here differs from rounding its double-precision value.
+        let opts = AsmOptions { features: ISA_A | FPU, ..AsmOptions::new(0) };
+        let (result, _, _) = asm(" .float -1.0000000596046448\n fmove.s #1.0000000596046448,fp0\n", &opts);
+        assert_eq!( words(&result.unwrap().bytes), [0xBF80, 0x0001, 0xF23C, 0x4400, 0x3F80, 0x0001] );
+    }
+
+    #[test]
+    pub fn assemble_reports_unreachable() {
+        let src = " bra far\n\
+                   \x20ds.b $9000\n\
+                   far nop\n";
+
+        let (result, _, pool) = asm(src, &AsmOptions::new(0));
+        let errors = result.unwrap_err();
+        assert_eq!( errors.len(), 1 );
+        assert_eq!( errors[0].stmt, 0 );
+        assert_eq!( errors[0].span, Some(Span::new(1, 2, 7)) );
+        assert_eq!(
+            errors[0].message(&pool),
+            "label 'far' cannot be reached by any branch form; 'bra.w' is out of range"
+        );
+
+        let opts = AsmOptions { features: ISA_B, ..AsmOptions::new(0) };
+        let (result, _, _) = asm(src, &opts);
+        let a = result.unwrap();
+        assert_eq!( words(a.stmt_bytes(0)), [0x60FF, 0x0000, 0x9004] );
+    }
+
+    #[test]
+    pub fn assemble_reports_errors() {
+        let (result, _, pool) = asm(
+            " frob d0\n\
+             \x20move.l d0,nowhere\n\
+             \x20rems.l d1,d2:d3\n",
+            &AsmOptions::new(0)
+        );
+        let messages: Vec<String> = result.unwrap_err().iter()
+            .map(|e| e.message(&pool))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "unknown operation 'frob'",
+                "undefined symbol 'nowhere'",
+                "'rems.l' requires HWDIV",
+            ]
+        );
+    }
+}
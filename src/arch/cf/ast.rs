@@ -16,15 +16,19 @@
 
 use std::fmt::{self, Display, Formatter};
 use std::mem::transmute;
-use crate::ast::Expr;
+use crate::arch::Argument;
+use crate::ast::{AsmOp, Expr};
 use crate::num::{Field, SetField};
+use super::Instructions;
 
 /// A ColdFire assembly operation.
-#[derive(Clone, Eq, PartialEq, Debug)]
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
 pub enum Op {
-    Halt,
-    Nop,
-    //...
+    /// Machine instruction.
+    Inst(Instructions),
+
+    /// Assembler pseudo-operation.
+    Asm(AsmOp),
 }
 
 // -----------------------------------------------------------------------------
@@ -56,10 +60,12 @@ pub enum Arg {
     /// Address register indirect with displacement and scaled index.
     AddrRegIdx(AddrReg, Expr, Index),
 
-    /// Program counter relative with displacement.
+    /// Program counter relative with displacement.  The expression is the
+    /// target address, not the displacement.
     PcDisp(PcReg, Expr),
 
-    /// Program counter relative with displacement and scaled index.
+    /// Program counter relative with displacement and scaled index.  The
+    /// expression is the target address, not the displacement.
     PcIdx(PcReg, Expr, Index),
 
     /// Absolute short.
@@ -71,13 +77,64 @@ pub enum Arg {
     /// Immediate.
     Imm(Expr),
 
+    /// Bare expression: an absolute address of unspecified size, a branch
+    /// target, or an argument to a pseudo-operation.
+    Expr(Expr),
+
 // Special addressing modes
 
     /// Data register pair (REMS, REMU).
     DataRegPair(DataRegPair),
 
+    /// Data/address register list (MOVEM).
+    RegList(RegList),
+
+    /// Condition code register.
+    CcrReg(CcrReg),
+
+    /// Status register.
+    SrReg(SrReg),
+
     /// Cache specifier.
     Cache(Cache),
+
+    /// Floating-point data register.
+    FpReg(FpReg),
+}
+
+impl Argument for Arg {
+    #[inline]
+    fn from_value(value: u32) -> Self {
+        Arg::Expr(Expr::LitInt(value as i64, None))
+    }
+
+    fn each_expr<F: FnMut(&Expr)>(&self, mut f: F) {
+        match *self {
+            Arg::AddrRegDisp (_, ref e       ) => f(e),
+            Arg::AddrRegIdx  (_, ref e, ref i) => { f(e); f(&i.scale) },
+            Arg::PcDisp      (_, ref e       ) => f(e),
+            Arg::PcIdx       (_, ref e, ref i) => { f(e); f(&i.scale) },
+            Arg::AbsShort    (   ref e       ) => f(e),
+            Arg::AbsLong     (   ref e       ) => f(e),
+            Arg::Imm         (   ref e       ) => f(e),
+            Arg::Expr        (   ref e       ) => f(e),
+            _                                  => (),
+        }
+    }
+
+    fn each_expr_mut<F: FnMut(&mut Expr)>(&mut self, mut f: F) {
+        match *self {
+            Arg::AddrRegDisp (_, ref mut e           ) => f(e),
+            Arg::AddrRegIdx  (_, ref mut e, ref mut i) => { f(e); f(&mut i.scale) },
+            Arg::PcDisp      (_, ref mut e           ) => f(e),
+            Arg::PcIdx       (_, ref mut e, ref mut i) => { f(e); f(&mut i.scale) },
+            Arg::AbsShort    (   ref mut e           ) => f(e),
+            Arg::AbsLong     (   ref mut e           ) => f(e),
+            Arg::Imm         (   ref mut e           ) => f(e),
+            Arg::Expr        (   ref mut e           ) => f(e),
+            _                                          => (),
+        }
+    }
 }
 
 /// ColdFire index register and scale.
@@ -120,6 +177,11 @@ static DATA_REG_NAMES: [&str; 8] = [
 impl DataReg {
     pub const MAX_NUM: u8 = 7;
 
+    /// Returns the register with number `n`.
+    ///
+    /// # Safety
+    ///
+    /// `n` must not exceed `MAX_NUM`.
     #[inline]
     pub unsafe fn with_num_unchecked(n: u8) -> Self {
         transmute(n)
@@ -190,6 +252,11 @@ impl AddrReg {
     pub const FP: Self = A6;
     pub const SP: Self = A7;
 
+    /// Returns the register with number `n`.
+    ///
+    /// # Safety
+    ///
+    /// `n` must not exceed `MAX_NUM`.
     #[inline]
     pub unsafe fn with_num_unchecked(n: u8) -> Self {
         transmute(n)
@@ -238,6 +305,68 @@ impl Display for AddrReg {
     }
 }
 
+// -----------------------------------------------------------------------------
+// Floating-Point Registers
+
+/// A ColdFire floating-point data register.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[repr(u8)]
+pub enum FpReg {
+    FP0, FP1, FP2, FP3, FP4, FP5, FP6, FP7
+}
+
+static FP_REG_NAMES: [&str; 8] = [
+    "fp0", "fp1", "fp2", "fp3", "fp4", "fp5", "fp6", "fp7"
+];
+
+impl FpReg {
+    pub const MAX_NUM: u8 = 7;
+
+    /// Returns the register with number `n`.
+    ///
+    /// # Safety
+    ///
+    /// `n` must not exceed `MAX_NUM`.
+    #[inline]
+    pub unsafe fn with_num_unchecked(n: u8) -> Self {
+        transmute(n)
+    }
+
+    #[inline]
+    pub fn with_num(n: u8) -> Option<Self> {
+        if n <= Self::MAX_NUM {
+            Some(unsafe { Self::with_num_unchecked(n) })
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub fn decode<W, P>(word: W, pos: P) -> Self
+    where
+        W: Copy + Field<P, u8>
+    {
+        let n = word.field(pos, 0b111);
+        unsafe { Self::with_num_unchecked(n) }
+    }
+
+    #[inline]
+    pub const fn num(self) -> u8 {
+        self as u8
+    }
+
+    #[inline]
+    pub fn name(self) -> &'static str {
+        FP_REG_NAMES[self as usize]
+    }
+}
+
+impl Display for FpReg {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
 // -----------------------------------------------------------------------------
 // Index Registers
 
@@ -262,8 +391,8 @@ impl IndexReg {
         let num  = bits  & 0b111;
 
         match kind {
-            0 => IndexReg::Data(unsafe { transmute(num) }),
-            1 => IndexReg::Addr(unsafe { transmute(num) }),
+            0 => IndexReg::Data(unsafe { DataReg::with_num_unchecked(num) }),
+            1 => IndexReg::Addr(unsafe { AddrReg::with_num_unchecked(num) }),
             _ => unreachable!()
         }
     }
@@ -297,8 +426,8 @@ impl Display for IndexReg {
 /// ColdFire data register pair.
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct DataRegPair (
-    DataReg,  // remainder
-    DataReg,  // divisor
+    pub DataReg,  // remainder
+    pub DataReg,  // divisor
 );
 
 impl Display for DataRegPair {
@@ -307,6 +436,71 @@ impl Display for DataRegPair {
     }
 }
 
+// -----------------------------------------------------------------------------
+// Register Lists
+
+/// ColdFire data/address register list, as used by MOVEM.
+///
+/// Bits 0 through 7 select data registers D0 through D7, and bits 8 through
+/// 15 select address registers A0 through A7.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct RegList (pub u16);
+
+impl RegList {
+    /// Adds the given data register to the list.
+    #[inline]
+    pub fn with_data(self, reg: DataReg) -> Self {
+        RegList(self.0 | 1 << reg.num())
+    }
+
+    /// Adds the given address register to the list.
+    #[inline]
+    pub fn with_addr(self, reg: AddrReg) -> Self {
+        RegList(self.0 | 1 << (8 + reg.num()))
+    }
+}
+
+impl RegList {
+    /// Returns the maximal runs of adjacent registers in the list, as pairs
+    /// of first and last bit numbers.  Runs do not span data and address
+    /// registers.
+    pub fn runs(self) -> impl Iterator<Item=(u8, u8)> {
+        let mut n = 0;
+        std::iter::from_fn(move || {
+            while n < 16 && self.0 & 1 << n == 0 { n += 1 }
+            if n == 16 { return None }
+            let start = n;
+            while n < 15 && n % 8 != 7 && self.0 & 1 << (n + 1) != 0 { n += 1 }
+            n += 1;
+            Some((start, n - 1))
+        })
+    }
+}
+
+impl Display for RegList {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // Print maximal runs of adjacent registers as ranges: d0-d3/a6
+        let mut sep = "";
+        for (start, end) in self.runs() {
+            f.write_str(sep)?;
+            fmt_list_reg(start, f)?;
+            if end != start {
+                f.write_str("-")?;
+                fmt_list_reg(end, f)?;
+            }
+            sep = "/";
+        }
+        Ok(())
+    }
+}
+
+fn fmt_list_reg(n: u8, f: &mut Formatter) -> fmt::Result {
+    match n {
+        0..=7 => DataReg::with_num(n    ).unwrap().fmt(f),
+        _     => AddrReg::with_num(n - 8).unwrap().fmt(f),
+    }
+}
+
 // -----------------------------------------------------------------------------
 // Miscellaneous Registers
 
@@ -479,6 +673,19 @@ mod tests {
         assert_eq!( AddrReg::SP, A7 ); // As of 2019-01-01, * does not import the aliases
     }
 
+    #[test]
+    pub fn fp_reg_with_num() {
+        assert_eq!( FpReg::with_num(0), Some(FpReg::FP0) );
+        assert_eq!( FpReg::with_num(7), Some(FpReg::FP7) );
+        assert_eq!( FpReg::with_num(8), None             );
+    }
+
+    #[test]
+    pub fn fp_reg_fmt() {
+        assert_eq!( format!("{}", FpReg::FP0), "fp0" );
+        assert_eq!( format!("{}", FpReg::FP7), "fp7" );
+    }
+
     #[test]
     pub fn index_reg_fmt() {
         assert_eq!( format!("{}", IndexReg::Data(D0)), "d0" );
@@ -520,6 +727,14 @@ mod tests {
         IndexReg::Addr(A7).encode(&mut word, 3); assert_eq!( word, 0o170 );
     }
 
+    #[test]
+    pub fn reg_list_fmt() {
+        let list = RegList::default()
+            .with_data(D0).with_data(D1).with_data(D2).with_data(D7)
+            .with_addr(A0).with_addr(A5).with_addr(A6);
+        assert_eq!( format!("{}", list), "d0-d2/d7/a0/a5-fp" );
+    }
+
     #[test]
     pub fn pc_reg_fmt() {
         assert_eq!( format!("{}", PcReg), "pc" );
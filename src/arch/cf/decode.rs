@@ -14,79 +14,171 @@
 // You should have received a copy of the GNU General Public License
 // along with cfda.  If not, see <http://www.gnu.org/licenses/>.
 
-//use crate::ast::*;
+use std::sync::OnceLock;
+use crate::ast::{Slot, Stmt};
 use crate::decode::*;
-use crate::decode::DecodeIndex::*;
 use crate::mem::{BE, Load};
 
-use super::encoding::*;
-use super::CfStmt;
+use super::*;
 
+/// Context for decoding ColdFire instructions.
 #[derive(Copy, Clone, Debug)]
-pub struct CfContext;
+pub struct CfContext {
+    /// Address of the instruction.
+    pub pc: u32,
 
+    /// Hardware features available to the target (see `flags`).  Encodings
+    /// that require other features do not decode.
+    pub features: u16,
+}
+
+/// Decoder for ColdFire instructions.
+///
+/// The decoder yields a statement with the instruction and its arguments.
+/// Branch targets and PC-relative arguments hold target addresses, as in the
+/// assembler's input.  Immediates are unsigned, except for quick immediates,
+/// which are signed as the instruction interprets them.
 #[derive(Copy, Clone, Debug)]
 pub struct CfDecoder;
+
 impl Decode<[u8], CfContext> for CfDecoder {
     type Output = CfStmt;
 
     fn decode<'a>(&self, buf: &'a [u8], ctx: &CfContext) -> Option<(CfStmt, &'a [u8])> {
-        let (word, rest) = u16::load(buf, BE)?;
-        let ctx = CfDecode16 { opword: word, state: CfContext };
-        DECODE_ROOT.decode(rest, &ctx)
+        let (word, _) = u16::load(buf, BE)?;
+        decode_index()[(word >> 12) as usize]
+            .iter()
+            .find_map(|enc| enc.decode(buf, ctx))
     }
 }
 
-struct CfDecode16 {
-    pub opword: u16,
-    pub state:  CfContext,
-}
-
-impl Opword for CfDecode16 {
-    type Opword = u16;
-
-    fn opword(&self) -> u16 { self.opword }
-}
-
-impl Decode<[u8], CfDecode16> for WordEncoding {
+impl Decode<[u8], CfContext> for Encoding {
     type Output = CfStmt;
 
-    fn decode<'a>(&self, buf: &'a [u8], ctx: &CfDecode16) -> Option<(CfStmt, &'a [u8])> {
-        let word = ctx.opword;
-        if word & self.mask != self.bits { return None }
-        // operands
-        // assemble ast
-        None
+    fn decode<'a>(&self, buf: &'a [u8], ctx: &CfContext) -> Option<(CfStmt, &'a [u8])> {
+        let enc = *self;
+        if !enc.flags().has_any(ctx.features) { return None }
+
+        let (word, mut rest) = u16::load(buf, BE)?;
+        let mut opcode = word as u32;
+        if enc.words() == 2 {
+            let (ext, r) = u16::load(rest, BE)?;
+            opcode |= (ext as u32) << 16;
+            rest = r;
+        }
+        if opcode & enc.mask() != enc.bits() { return None }
+
+        let op_size = enc.inst().info().size();
+        let size    = op_size.map_or(4, Size::bytes);
+        let float   = op_size.is_some_and(Size::is_float);
+        let mut args = Vec::with_capacity(enc.operands().len());
+        for op in enc.operands() {
+            let here = ctx.pc.wrapping_add((buf.len() - rest.len()) as u32);
+            let octx = OperandContext { opcode, pc: ctx.pc, here, size, float };
+            let (arg, r) = op.decode(rest, &octx)?;
+            args.push(arg);
+            rest = r;
+        }
+        if !legal_combination(enc, &args, ctx.features) { return None }
+
+        let stmt = Stmt::new(
+            vec![],
+            Slot::Value(Op::Inst(enc.inst()), None),
+            args.into_iter().map(|a| Slot::Value(a, None)).collect(),
+        );
+        Some((stmt, rest))
     }
 }
 
-/*
-
-struct CfDecode32 {
-    pub opword: u32,
-    pub state:  u16,
-    }
-
-pub struct CfDecoder;
-
-impl Decode<[u8]> for CfDecoder {
-    type Output = ();
-
-    fn decode(&self, buf: &[u8], ctx: &()) -> Option<((), &[u8])> {
-        panic!()
-    }
+// Encodings grouped by the high 4 bits of the opword, in table order
+fn decode_index() -> &'static [Vec<Encoding>; 16] {
+    static INDEX: OnceLock<[Vec<Encoding>; 16]> = OnceLock::new();
+
+    INDEX.get_or_init(|| {
+        let mut index: [Vec<Encoding>; 16] = Default::default();
+        let words = WORD_ENCODINGS.iter().map(Encoding::Word);
+        let longs = LONG_ENCODINGS.iter().map(Encoding::Long);
+        for enc in words.chain(longs) {
+            index[(enc.bits() >> 12 & 0xF) as usize].push(enc);
+        }
+        index
+    })
 }
 
-pub enum CfOp16 {
-    Next(&'static ()),
-    Inst(CfInstruction)
+/// Decodes the instruction at the start of `buf`, located at address `pc`.
+/// Returns the statement and its size in bytes, or `None` if the bytes do
+/// not form an instruction supported by `features`.
+pub fn decode_at(buf: &[u8], pc: u32, features: u16) -> Option<(CfStmt, u32)> {
+    let (stmt, rest) = CfDecoder.decode(buf, &CfContext { pc, features })?;
+    Some((stmt, (buf.len() - rest.len()) as u32))
 }
-*/
 
-type WNode = DecodeIndex<WordEncoding>;
+#[cfg(test)]
+mod tests {
+    use crate::ast::{IdentPool, SymbolTable};
+    use super::*;
+
+    fn round_trip(src: &str, features: u16) {
+        let mut pool  = IdentPool::new();
+        let mut syms  = SymbolTable::new();
+        let mut block = parse_moto(src, &mut pool, &mut syms).unwrap();
+        let opts      = AsmOptions { features, ..AsmOptions::new(0x1000) };
+        let asm       = assemble(&mut block, &mut syms, &opts).unwrap();
+
+        for (n, stmt) in block.stmts.iter().enumerate() {
+            let bytes = asm.stmt_bytes(n);
+            let (decoded, len) = decode_at(bytes, asm.addrs[n], features)
+                .unwrap_or_else(|| panic!("cannot decode {}", moto(stmt, &pool)));
+            assert_eq!( len as usize, bytes.len() );
+            assert_eq!( moto(&decoded, &pool).to_string(), moto(stmt, &pool).to_string() );
+        }
+    }
 
-// Root Node
-static DECODE_ROOT: WNode = Empty
-//    /*......*/ Trie16(&DECODE_XXXXXX, 12)
-;
+    #[test]
+    pub fn decode_round_trip() {
+        round_trip(
+            " move.l (8,a0,d1.l*4),d0\n\
+             \x20move.w #$1234,(a1)+\n\
+             \x20move.b -(a2),(-6,a3)\n\
+             \x20movea.l ($12345678).l,a4\n\
+             \x20lea.l ($FFFF8000).w,a5\n\
+             \x20lea.l ($1000,pc),a6\n\
+             \x20jsr ($1010,pc,a0.l*2)\n\
+             \x20moveq #-5,d7\n\
+             \x20addq.l #8,a0\n\
+             \x20subq.l #1,d1\n\
+             \x20cmpi.l #$FFFFFFFF,d2\n\
+             \x20btst.b #7,(a0)\n\
+             \x20movem.l d0-d2/a6,(sp)\n\
+             \x20divs.l d1,d2\n\
+             \x20rems.l (4,a0),d1:d2\n\
+             \x20move.w sr,d0\n\
+             \x20move.b #3,ccr\n\
+             \x20bne.b $1000\n\
+             \x20bsr.w $2000\n\
+             \x20trap #15\n\
+             \x20rts\n",
+            ISA_A | HWDIV,
+        );
+        round_trip(" mov3q.l #-1,d0\n mvz.b (a0),d1\n bra.l $100000\n", ISA_B);
+        round_trip(
+            " fmove.d #3.14159,fp0\n\
+             \x20fmove.s #-0.1,fp1\n\
+             \x20fmove.s d3,fp2\n\
+             \x20fmove.d fp1,fp3\n\
+             \x20fmove.d fp0,(a0)+\n",
+            FPU,
+        );
+    }
 
+    #[test]
+    pub fn decode_rejects() {
+        // Unsupported feature, reserved brief-word bits, and illegal MOVE
+        assert!( decode_at(&[0xA1, 0x40],                   0, ISA_A).is_none() );
+        assert!( decode_at(&[0x20, 0x30, 0x10, 0x00],       0, ISA_A).is_none() );
+        assert!( decode_at(&[0x23, 0x68, 0x00, 0x08, 0x00, 0x04], 0, ISA_A).is_some() );
+        assert!( decode_at(&[0x23, 0xE8, 0x00, 0x08, 0x00, 0x00, 0x10, 0x00], 0, ISA_A).is_none() );
+        assert!( decode_at(&[0xFF, 0xFF],                   0, ISA_A).is_none() );
+        assert!( decode_at(&[0x4E],                         0, ISA_A).is_none() );
+    }
+}
@@ -0,0 +1,288 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::ast::*;
+use crate::diag::Diagnostic;
+use super::*;
+
+impl AsmError {
+    /// Returns a diagnostic for the error, with notes that explain it.
+    ///
+    /// The diagnostic applies to the offending argument, if any, or else to
+    /// the statement.  `block` and `opts` must be those passed to `assemble`.
+    pub fn diagnostic(&self, block: &CfBlock, opts: &AsmOptions, pool: &IdentPool) -> Diagnostic {
+        let stmt = &block.stmts[self.stmt];
+        match self.kind {
+            AsmErrorKind::UnknownOp(i) =>
+                unknown_op(i, stmt.op.span().or(self.span), pool),
+            AsmErrorKind::Undefined(i) | AsmErrorKind::DataEval(EvalError::Undefined(i)) =>
+                undefined(i, stmt, self.span, pool),
+            AsmErrorKind::Encode(inst, EncodeError::IllegalMode) =>
+                illegal_mode(inst, stmt, self.span),
+            AsmErrorKind::Encode(inst, EncodeError::Unsupported(f)) =>
+                unsupported(inst, f, opts.features, self.span),
+            AsmErrorKind::Encode(inst, EncodeError::OutOfRange(n)) =>
+                out_of_range(inst, n, stmt, opts.features, self.span),
+            _ =>
+                Diagnostic::error(self.message(pool), self.span),
+        }
+    }
+}
+
+/// Returns a diagnostic for an identifier left unresolved by `resolve`.
+/// Unknown mnemonics receive a note listing the sizes of the instruction,
+/// if the mnemonic names an instruction with an unknown size.
+pub fn unresolved_diagnostic(u: &Unresolved, pool: &IdentPool) -> Diagnostic {
+    match u.is_op {
+        true  => unknown_op(u.ident, u.span, pool),
+        false => Diagnostic::error(format!("undefined symbol '{}'", pool.source_name(u.ident)), u.span),
+    }
+}
+
+fn unknown_op(ident: Ident, span: Option<Span>, pool: &IdentPool) -> Diagnostic {
+    let name = pool.source_name(ident);
+    let diag = Diagnostic::error(format!("unknown mnemonic '{}'", name), span);
+
+    // Instructions with the same base name, but other sizes
+    let lower = name.to_ascii_lowercase();
+    let base  = lower.split('.').next().unwrap_or("");
+    let mut names: Vec<&str> = vec![];
+    for n in INSTRUCTIONS.iter().map(|i| i.name) {
+        if n.split('.').next() == Some(base) && !names.contains(&n) {
+            names.push(n);
+        }
+    }
+    match names.is_empty() {
+        true  => diag,
+        false => diag.with_note(format!("'{}' is available as {}", base, names.join(", "))),
+    }
+}
+
+fn undefined(ident: Ident, stmt: &CfStmt, span: Option<Span>, pool: &IdentPool) -> Diagnostic {
+    // Find the reference to the symbol
+    let span = stmt.args.iter()
+        .find_map(|a| Refers(ident).visit_arg(a).err())
+        .flatten()
+        .or(span);
+    Diagnostic::error(format!("undefined symbol '{}'", pool.source_name(ident)), span)
+}
+
+// Visitor that fails on a reference to an identifier, with the source
+// location of the reference.
+struct Refers (Ident);
+
+impl Visitor<Cf, Option<Span>> for Refers {
+    fn visit_ident(&mut self, node: Ident, span: Option<Span>) -> Result<(), Option<Span>> {
+        if node == self.0 { Err(span) } else { Ok(()) }
+    }
+}
+
+fn illegal_mode(inst: Instructions, stmt: &CfStmt, span: Option<Span>) -> Diagnostic {
+    let name = inst.name();
+    let args = args_of(stmt);
+
+    let all: Vec<Encoding> = encodings_for(inst)
+        .chain(address_variant(inst).into_iter().flat_map(encodings_for))
+        .collect();
+    let fits: Vec<Encoding> = all.iter().copied()
+        .filter(|e| e.flags().arity() == args.len())
+        .collect();
+
+    // Wrong number of operands
+    if fits.is_empty() {
+        let mut arities: Vec<usize> = all.iter().map(|e| e.flags().arity()).collect();
+        arities.sort_unstable();
+        arities.dedup();
+        let arities: Vec<String> = arities.iter().map(usize::to_string).collect();
+        return Diagnostic::error(format!("wrong number of operands for '{}'", name), span)
+            .with_note(format!("'{}' takes {} operand(s)", name, arities.join(" or ")))
+    }
+
+    // First operand that no encoding accepts
+    for (i, arg) in args.iter().enumerate() {
+        if fits.iter().any(|e| e.operands()[i].accepts(arg)) { continue }
+
+        let mut forms: Vec<&str> = vec![];
+        for f in fits.iter().flat_map(|e| e.operands()[i].forms()) {
+            if !forms.contains(&f) { forms.push(f) }
+        }
+        return Diagnostic::error(format!("illegal addressing mode for '{}'", name), stmt.arg_span(i).or(span))
+            .with_note(format!("operand {} of '{}' must be one of: {}", i + 1, name, forms.join(" ")))
+    }
+
+    // Each operand is acceptable alone, but not in combination
+    Diagnostic::error(format!("illegal addressing mode for '{}'", name), span)
+        .with_note(format!("'{}' does not permit this combination of addressing modes", name))
+}
+
+fn unsupported(inst: Instructions, required: u16, features: u16, span: Option<Span>) -> Diagnostic {
+    let name = inst.name();
+    let diag = Diagnostic::error(format!("'{}' is not available on the target core", name), span)
+        .with_note(format!("{} requires {}", name, feature_names(required)));
+    match features {
+        0 => diag,
+        _ => diag.with_note(format!("the target core provides {}", feature_list(features).join(", "))),
+    }
+}
+
+fn out_of_range(inst: Instructions, n: usize, stmt: &CfStmt, features: u16, span: Option<Span>)
+    -> Diagnostic
+{
+    let name = inst.name();
+    let args = args_of(stmt);
+    let span = stmt.arg_span(n).or(span);
+    let op   = select(inst, &args, features).ok().and_then(|e| e.operands().get(n).copied());
+    let arg  = match args.get(n) {
+        Some(a) => a,
+        None    => return Diagnostic::error(format!("operand {} of '{}' is out of range", n + 1, name), span),
+    };
+
+    let (what, indexed) = match (arg, op) {
+        (&Arg::AddrRegIdx (..), _) | (&Arg::PcIdx  (..), _) => ("displacement", true ),
+        (&Arg::AddrRegDisp(..), _) | (&Arg::PcDisp (..), _) => ("displacement", false),
+        (_, Some(Operand::PcRel8))
+            | (_, Some(Operand::PcRel16))                    => ("branch displacement", false),
+        _                                                    => ("value", false),
+    };
+    let mut diag = Diagnostic::error(
+        format!("{} out of range for operand {} of '{}'", what, n + 1, name), span
+    );
+
+    let size = inst.info().size().map_or(4, Size::bytes);
+    if let Some((min, max)) = op.and_then(|o| o.range(arg, size)) {
+        diag = diag.with_note(match op {
+            Some(Operand::Quick3Mov) => format!("the {} must be -1 or from 1 to {}", what, max),
+            Some(Operand::PcRel8)    => format!("the {} must be from {} to {}, except 0 and -1", what, min, max),
+            _                        => format!("the {} must be from {} to {}", what, min, max),
+        });
+    }
+    if indexed {
+        diag = diag.with_note("the index scale must be 1, 2, or 4");
+    }
+    diag
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Assembles `src`, returning the rendered diagnostics for its errors.
+    fn diags(src: &str, features: u16) -> Vec<String> {
+        let mut pool  = IdentPool::new();
+        let mut syms  = SymbolTable::new();
+        let mut block = parse_moto(src, &mut pool, &mut syms).unwrap();
+        let     opts  = AsmOptions { features, ..AsmOptions::new(0) };
+        match assemble(&mut block, &mut syms, &opts) {
+            Ok(_)       => vec![],
+            Err(errors) => errors.iter()
+                .map(|e| e.diagnostic(&block, &opts, &pool).render("t.s", src).to_string())
+                .collect(),
+        }
+    }
+
+    #[test]
+    pub fn diag_unknown_mnemonic() {
+        assert_eq!(
+            diags(" moveq.w #1,d0\n", ISA_A),
+            ["t.s:1:2: error: unknown mnemonic 'moveq.w'\n\
+              \x20   1 |  moveq.w #1,d0\n\
+              \x20     |  ^^^^^^^\n\
+              \x20     = note: 'moveq' is available as moveq.l\n"]
+        );
+    }
+
+    #[test]
+    pub fn diag_illegal_mode() {
+        assert_eq!(
+            diags(" lea d0,a1\n", ISA_A),
+            ["t.s:1:6: error: illegal addressing mode for 'lea.l'\n\
+              \x20   1 |  lea d0,a1\n\
+              \x20     |      ^^\n\
+              \x20     = note: operand 1 of 'lea.l' must be one of: \
+              (An) (d16,An) (d8,An,Xi) (xxx).W (xxx).L (d16,PC) (d8,PC,Xi)\n"]
+        );
+        assert_eq!(
+            diags(" nop d0\n", ISA_A),
+            ["t.s:1:2: error: wrong number of operands for 'nop'\n\
+              \x20   1 |  nop d0\n\
+              \x20     |  ^^^^^^\n\
+              \x20     = note: 'nop' takes 0 operand(s)\n"]
+        );
+    }
+
+    #[test]
+    pub fn diag_feature() {
+        assert_eq!(
+            diags("\trems.l\td1,d2:d3\n", ISA_A),
+            ["t.s:1:2: error: 'rems.l' is not available on the target core\n\
+              \x20   1 | \trems.l\td1,d2:d3\n\
+              \x20     | \t^^^^^^^^^^^^^^^\n\
+              \x20     = note: rems.l requires HWDIV\n\
+              \x20     = note: the target core provides ISA_A\n"]
+        );
+        assert!( diags("\trems.l\td1,d2:d3\n", ISA_A | HWDIV).is_empty() );
+    }
+
+    #[test]
+    pub fn diag_displacement() {
+        assert_eq!(
+            diags(" move.l (40000,a0),d0\n", ISA_A),
+            ["t.s:1:9: error: displacement out of range for operand 1 of 'move.l'\n\
+              \x20   1 |  move.l (40000,a0),d0\n\
+              \x20     |         ^^^^^^^^^^\n\
+              \x20     = note: the displacement must be from -32768 to 32767\n"]
+        );
+        assert_eq!(
+            diags(" addq.l #9,d0\n", ISA_A),
+            ["t.s:1:9: error: value out of range for operand 1 of 'addq.l'\n\
+              \x20   1 |  addq.l #9,d0\n\
+              \x20     |         ^^\n\
+              \x20     = note: the value must be from 1 to 8\n"]
+        );
+    }
+
+    #[test]
+    pub fn diag_undefined() {
+        assert_eq!(
+            diags(" move.l d0,(4,a0)\n move.l (x+2,a0),d1\n", ISA_A),
+            ["t.s:2:10: error: undefined symbol 'x'\n\
+              \x20   2 |  move.l (x+2,a0),d1\n\
+              \x20     |          ^\n"]
+        );
+    }
+
+    #[test]
+    pub fn diag_unresolved() {
+        let     src   = " frob d0\n dc.l y\n";
+        let mut pool  = IdentPool::new();
+        let mut syms  = SymbolTable::new();
+        let mut block = parse_moto(src, &mut pool, &mut syms).unwrap();
+        let     diags: Vec<String> = resolve(&mut block, &syms).iter()
+            .map(|u| unresolved_diagnostic(u, &pool).render("t.s", src).to_string())
+            .collect();
+        assert_eq!(
+            diags,
+            [
+                "t.s:1:2: error: unknown mnemonic 'frob'\n\
+                 \x20   1 |  frob d0\n\
+                 \x20     |  ^^^^\n",
+                "t.s:2:7: error: undefined symbol 'y'\n\
+                 \x20   2 |  dc.l y\n\
+                 \x20     |       ^\n",
+            ]
+        );
+    }
+}
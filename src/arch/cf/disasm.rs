@@ -0,0 +1,713 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{HashMap, HashSet};
+use crate::ast::*;
+use crate::mem::{BE, Image, Load, RegionKind};
+use super::*;
+
+// Minimum length of a run of text to render as a string
+const MIN_TEXT: usize = 4;
+
+// Greatest distance from a branch to a target named by a numeric local label
+const LOCAL_SPAN: u32 = 0x80;
+
+/// A ColdFire disassembler.
+///
+/// The disassembler renders memory as statements: instructions where code
+/// decodes, and data directives elsewhere.  Symbols in the table label the
+/// statements at their addresses, and branch targets within disassembled
+/// code receive generated labels.
+#[derive(Debug)]
+pub struct Disassembler<'a> {
+    pool:     &'a mut IdentPool,
+    syms:     &'a mut SymbolTable,
+    features: u16,
+    local:    bool,
+    locals:   LocalLabels,
+    a5:       Option<u32>,
+}
+
+impl<'a> Disassembler<'a> {
+    /// Creates a disassembler for a target with the given hardware
+    /// `features`, naming addresses with symbols from `syms`.
+    pub fn new(pool: &'a mut IdentPool, syms: &'a mut SymbolTable, features: u16) -> Self {
+        Self { pool, syms, features, local: false, locals: LocalLabels::new(), a5: None }
+    }
+
+    /// Sets whether short branches within a function name their targets
+    /// with numeric local labels, such as `1:` and `1b`, rather than with
+    /// generated `L_xxxx` labels.  A function extends from one symbol to the
+    /// next.  Numeric local labels are not added to the symbol table.
+    pub fn with_local_labels(mut self, enable: bool) -> Self {
+        self.local = enable;
+        self
+    }
+
+    /// Sets the address that register A5 holds throughout the code, if
+    /// known, as it holds the GOT in position-independent code.  An operand
+    /// `d16(%a5)` that refers to an address with a symbol then renders as
+    /// the difference of that symbol and the symbol at the A5 address.
+    pub fn with_data_base(mut self, addr: Option<u32>) -> Self {
+        self.a5 = addr;
+        self
+    }
+
+    /// Disassembles the code `bytes` located at `addr` by linear sweep.
+    ///
+    /// Words that do not decode become `.short` directives, and a trailing
+    /// odd byte becomes a `.byte` directive.
+    pub fn code(&mut self, bytes: &[u8], addr: u32) -> Vec<CfStmt> {
+        let (mut stmts, addrs) = self.sweep(bytes, addr);
+        let starts = addrs.iter().copied().collect();
+        let defs   = self.refer(&mut stmts, &addrs, &starts);
+        self.label_code(&mut stmts, &addrs, defs);
+        stmts
+    }
+
+    /// Disassembles the blocks of `image` at their Virtual Memory Addresses.
+    ///
+    /// Each block begins with the directives of `section`, if its content
+    /// came from a named section, or else with an `.org` directive, unless
+    /// it continues the previous block in the same section.  Code regions
+    /// disassemble as by `code`, vector tables render as `.long`
+    /// directives, one per vector, that name the handlers that have
+    /// symbols, other regions render as by `data`, and
+    /// reserved space renders as by `space`.  Branches may refer to
+    /// targets in any code region.
+    pub fn image(&mut self, image: &Image) -> Vec<CfStmt> {
+        let blocks = image.blocks();
+        let mut swept: Vec<_> = blocks.iter()
+            .map(|b| match b.region.kind {
+                RegionKind::Code => self.sweep(&b.bytes, b.region.vma),
+                _                => (vec![], vec![]),
+            })
+            .collect();
+        let starts = swept.iter().flat_map(|(_, a)| a.iter().copied()).collect();
+        let defs: Vec<_> = swept.iter_mut().map(|(s, a)| self.refer(s, a, &starts)).collect();
+
+        let mut stmts = vec![];
+        let mut prev  = None;
+        for (b, ((mut s, a), d)) in blocks.iter().zip(swept.into_iter().zip(defs)) {
+            let vma = b.region.vma;
+            match b.section {
+                _ if prev == Some((&b.section, vma as u64)) => {},
+                Some(ref name) => stmts.extend(self.section(name, vma)),
+                None           => stmts.push(directive(AsmOp::Origin, vec![
+                    Slot::Value(Arg::Expr(Expr::LitInt(vma as i64, None)), None)
+                ])),
+            }
+            prev = Some((&b.section, b.region.end_vma()));
+            match b.region.kind {
+                RegionKind::Code => {
+                    self.label_code(&mut s, &a, d);
+                    stmts.extend(s);
+                },
+                RegionKind::Vectors => stmts.extend(self.vectors(&b.bytes, vma)),
+                _                   => stmts.extend(self.data(&b.bytes, vma)),
+            }
+            if b.reserved() != 0 {
+                stmts.extend(self.space(b.reserved(), vma + b.bytes.len() as u32));
+            }
+        }
+        stmts
+    }
+
+    // Decodes the code `bytes` located at `addr` by linear sweep.  Returns
+    // the statements and the address of each.
+    fn sweep(&self, bytes: &[u8], addr: u32) -> (Vec<CfStmt>, Vec<u32>) {
+        let mut stmts = vec![];
+        let mut addrs = vec![];
+        let mut i     = 0;
+
+        while i < bytes.len() {
+            let pc   = addr.wrapping_add(i as u32);
+            let rest = &bytes[i..];
+            let (stmt, len) = match decode_at(rest, pc, self.features) {
+                Some(d) if pc & 1 == 0 => d,
+                _ => match u16::load(rest, BE) {
+                    Some((w, _)) if pc & 1 == 0 => data(AsmOp::Data16, w as i64),
+                    _                           => data(AsmOp::Data8,  rest[0] as i64),
+                },
+            };
+            stmts.push(stmt);
+            addrs.push(pc);
+            i += len as usize;
+        }
+        (stmts, addrs)
+    }
+
+    // Refers to the targets of instructions by name, given the addresses
+    // `starts` at which statements begin.  Returns the numeric local label
+    // to define at each statement, if any.
+    fn refer(&mut self, stmts: &mut [CfStmt], addrs: &[u32], starts: &HashSet<u32>)
+        -> Vec<Option<Ident>>
+    {
+        let locals = match self.local {
+            true  => self.local_targets(stmts, addrs, starts),
+            false => HashMap::new(),
+        };
+        let mut defs = vec![None; stmts.len()];
+        for (j, stmt) in stmts.iter_mut().enumerate() {
+            let pc = addrs[j];
+            if let Some(&n) = locals.get(&pc) {
+                defs[j] = Some(self.locals.define(n, self.pool));
+            }
+            if !matches!(stmt.op, Slot::Value(Op::Inst(_), _)) {
+                continue
+            }
+            for arg in &mut stmt.args {
+                *arg = match *arg {
+                    Slot::Value(Arg::Expr(Expr::LitInt(t, _)), _) if locals.contains_key(&(t as u32)) => {
+                        let n = locals[&(t as u32)];
+                        match self.locals.refer(n, t as u32 > pc, self.pool) {
+                            Some((i, _)) => Slot::Resolved(i, Arg::Expr(Expr::LitInt(t, None)), None),
+                            None         => continue,
+                        }
+                    },
+                    Slot::Value(Arg::Expr(Expr::LitInt(t, _)), _) => {
+                        match self.name(t as u32, starts) {
+                            Some(i) => Slot::Resolved(i, Arg::Expr(Expr::LitInt(t, None)), None),
+                            None    => continue,
+                        }
+                    },
+                    Slot::Value(Arg::PcDisp(r, Expr::LitInt(t, _)), _) => {
+                        match self.name(t as u32, starts) {
+                            Some(i) => Slot::Value(Arg::PcDisp(r, Expr::Ident(i, None)), None),
+                            None    => continue,
+                        }
+                    },
+                    Slot::Value(Arg::AddrRegDisp(AddrReg::A5, Expr::LitInt(d, _)), _) => {
+                        match self.data_ref(d) {
+                            Some(e) => Slot::Value(Arg::AddrRegDisp(AddrReg::A5, e), None),
+                            None    => continue,
+                        }
+                    },
+                    _ => continue,
+                };
+            }
+        }
+        defs
+    }
+
+    /// Renders the data `bytes` located at `addr` as directives.
+    ///
+    /// Runs of printable text become `.ascii` directives, or `.asciz` if
+    /// followed by a zero byte.  Other bytes become `.long`, `.short`, or
+    /// `.byte` directives, the largest permitted by alignment.  Directives
+    /// break at each address that has a symbol.
+    pub fn data(&mut self, bytes: &[u8], addr: u32) -> Vec<CfStmt> {
+        let mut breaks = self.breaks(addr, bytes.len() as u32).into_iter().peekable();
+
+        let mut stmts: Vec<CfStmt> = vec![];
+        let mut addrs = vec![];
+        let mut i     = 0;
+
+        while i < bytes.len() {
+            let a = addr.wrapping_add(i as u32);
+            while breaks.next_if(|&b| b <= a).is_some() { }
+            let limit = breaks.peek().map_or(bytes.len(), |&b| b.wrapping_sub(addr) as usize);
+            let chunk = &bytes[i..limit];
+            let fresh = i == 0 || !self.syms.at(a).is_empty();
+
+            // Values must not overlap the start of text
+            let text = text_len(chunk);
+            let fits = |n: usize| chunk.len() >= n
+                && (1..n).all(|j| text_len(&chunk[j..]) < MIN_TEXT);
+
+            let (op, arg, len) = if text >= MIN_TEXT {
+                let s: String = chunk[..text].iter().map(|&b| b as char).collect();
+                if chunk.get(text) == Some(&0) {
+                    (AsmOp::CharsZ(StrEncoding::UTF8), Expr::LitStr(s, None), text + 1)
+                } else {
+                    (AsmOp::Chars (StrEncoding::UTF8), Expr::LitStr(s, None), text)
+                }
+            } else if a & 3 == 0 && fits(4) {
+                (AsmOp::Data32, Expr::LitInt(u32::load(chunk, BE).unwrap().0 as i64, None), 4)
+            } else if a & 1 == 0 && fits(2) {
+                (AsmOp::Data16, Expr::LitInt(u16::load(chunk, BE).unwrap().0 as i64, None), 2)
+            } else {
+                (AsmOp::Data8,  Expr::LitInt(chunk[0] as i64, None), 1)
+            };
+
+            // Continue the previous directive if it is of the same kind
+            match stmts.last_mut() {
+                Some(prev) if !fresh
+                    && prev.op == Slot::Value(Op::Asm(op), None)
+                    && prev.args.len() < per_line(op) => {
+                    prev.args.push(Slot::Value(Arg::Expr(arg), None));
+                },
+                _ => {
+                    stmts.push(directive(op, vec![Slot::Value(Arg::Expr(arg), None)]));
+                    addrs.push(a);
+                },
+            }
+            i += len;
+        }
+
+        self.label(&mut stmts, &addrs);
+        stmts
+    }
+
+    // Renders the vector table `bytes` located at `addr` as `.long`
+    // directives, one per vector, that name the targets that have symbols.
+    // Bytes after the last whole vector render as by `data`.
+    fn vectors(&mut self, bytes: &[u8], addr: u32) -> Vec<CfStmt> {
+        let mut stmts = vec![];
+        let mut addrs = vec![];
+        let whole = bytes.len() & !3;
+        for (i, v) in bytes[..whole].chunks(4).enumerate() {
+            let v   = u32::load(v, BE).unwrap().0;
+            let arg = Arg::Expr(Expr::LitInt(v as i64, None));
+            let arg = match self.syms.at(v).first() {
+                Some(&t) => Slot::Resolved(t, arg, None),
+                None     => Slot::Value(arg, None),
+            };
+            stmts.push(directive(AsmOp::Data32, vec![arg]));
+            addrs.push(addr.wrapping_add(4 * i as u32));
+        }
+        self.label(&mut stmts, &addrs);
+        stmts.extend(self.data(&bytes[whole..], addr.wrapping_add(whole as u32)));
+        stmts
+    }
+
+    /// Renders `size` bytes of reserved space located at `addr` as `.zero`
+    /// directives, which break at each address that has a symbol.
+    pub fn space(&mut self, size: u32, addr: u32) -> Vec<CfStmt> {
+        let mut starts = vec![addr];
+        starts.extend(self.breaks(addr, size));
+        let ends = starts.iter().skip(1).copied().chain(Some(addr.wrapping_add(size)));
+
+        let mut stmts = vec![];
+        let mut addrs = vec![];
+        for (start, end) in starts.iter().copied().zip(ends) {
+            let len = Expr::LitInt(end.wrapping_sub(start) as i64, None);
+            stmts.push(directive(AsmOp::Zero8, vec![Slot::Value(Arg::Expr(len), None)]));
+            addrs.push(start);
+        }
+
+        self.label(&mut stmts, &addrs);
+        stmts
+    }
+
+    /// Returns directives that begin section `name` at `addr`: `.section`
+    /// and `.org`.
+    pub fn section(&mut self, name: &str, addr: u32) -> Vec<CfStmt> {
+        let name = self.pool.intern(name);
+        vec![
+            directive(AsmOp::Section, vec![Slot::Ident(name, None)]),
+            directive(AsmOp::Origin,  vec![Slot::Value(Arg::Expr(Expr::LitInt(addr as i64, None)), None)]),
+        ]
+    }
+
+    /// Returns a `.globl` directive for each global symbol.
+    pub fn globals(&self) -> Vec<CfStmt> {
+        self.syms.iter()
+            .filter(|(_, s)| s.binding == Binding::Global)
+            .map(|(i, _)| directive(AsmOp::Public, vec![Slot::Ident(i, None)]))
+            .collect()
+    }
+
+    // Returns a name for address `target`: an existing symbol, or a
+    // generated label if the address begins a statement in `starts`.
+    fn name(&mut self, target: u32, starts: &HashSet<u32>) -> Option<Ident> {
+        if let Some(&i) = self.syms.at(target).first() {
+            return Some(i)
+        }
+        if starts.contains(&target) {
+            return Some(self.syms.label(target, self.pool))
+        }
+        None
+    }
+
+    // Returns an expression for displacement `disp` from the A5 address:
+    // the difference of the symbols at the target and at the A5 address,
+    // if both have symbols.
+    fn data_ref(&self, disp: i64) -> Option<Expr> {
+        let base   = self.a5?;
+        let target = base.wrapping_add(disp as u32);
+        let t      = *self.syms.at(target).first()?;
+        let b      = *self.syms.at(base).first()?;
+        Some(Expr::binary(
+            BinaryOp::Sub,
+            Slot::Resolved(t, Expr::LitInt(target as i64, None), None),
+            Slot::Resolved(b, Expr::LitInt(base   as i64, None), None),
+        ))
+    }
+
+    // Returns the targets of short branches within a function that are
+    // eligible for numeric local labels, each with its label number.  A
+    // target is eligible if it begins a statement, has no symbol, and is
+    // referenced only by such branches.  Numbers restart at each symbol.
+    fn local_targets(&self, stmts: &[CfStmt], addrs: &[u32], starts: &HashSet<u32>)
+        -> HashMap<u32, u32>
+    {
+        let bounds: Vec<u32> = self.syms.iter().filter_map(|(_, s)| s.value).collect();
+        let mut near = HashSet::new();
+        let mut far  = HashSet::new();
+
+        for (stmt, &pc) in stmts.iter().zip(addrs) {
+            if !matches!(stmt.op, Slot::Value(Op::Inst(_), _)) {
+                continue
+            }
+            for arg in &stmt.args {
+                let t = match *arg {
+                    Slot::Value(Arg::Expr  (   Expr::LitInt(t, _)), _) => t as u32,
+                    Slot::Value(Arg::PcDisp(_, Expr::LitInt(t, _)), _) => { far.insert(t as u32); continue },
+                    _                                                  => continue,
+                };
+                let (lo, hi) = (pc.min(t), pc.max(t));
+                let short = hi - lo <= LOCAL_SPAN
+                    && starts.contains(&t)
+                    && self.syms.at(t).is_empty()
+                    && !bounds.iter().any(|&b| b > lo && b <= hi);
+                if short { near.insert(t); } else { far.insert(t); }
+            }
+        }
+
+        let mut numbers = HashMap::new();
+        let mut n = 0;
+        for &addr in addrs {
+            if !self.syms.at(addr).is_empty() {
+                n = 0;
+            }
+            if near.contains(&addr) && !far.contains(&addr) {
+                n += 1;
+                numbers.insert(addr, n);
+            }
+        }
+        numbers
+    }
+
+    // Returns the addresses after `addr` and within `len` bytes of it that
+    // have symbols, in order.
+    fn breaks(&self, addr: u32, len: u32) -> Vec<u32> {
+        let end = addr.wrapping_add(len);
+        let mut breaks: Vec<u32> = self.syms.iter()
+            .filter_map(|(_, s)| s.value)
+            .filter(|&v| v > addr && v < end)
+            .collect();
+        breaks.sort_unstable();
+        breaks.dedup();
+        breaks
+    }
+
+    // Labels each statement with the symbols at its address.
+    fn label(&self, stmts: &mut [CfStmt], addrs: &[u32]) {
+        for (stmt, &addr) in stmts.iter_mut().zip(addrs) {
+            stmt.labels = self.syms.at(addr).to_vec();
+        }
+    }
+
+    // Labels code statements as `label` does, then with the numeric local
+    // labels `defs`.
+    fn label_code(&self, stmts: &mut [CfStmt], addrs: &[u32], defs: Vec<Option<Ident>>) {
+        self.label(stmts, addrs);
+        for (stmt, def) in stmts.iter_mut().zip(defs) {
+            stmt.labels.extend(def);
+        }
+    }
+}
+
+fn directive(op: AsmOp, args: Vec<Slot<Arg>>) -> CfStmt {
+    Stmt::new(vec![], Slot::Value(Op::Asm(op), None), args)
+}
+
+fn data(op: AsmOp, value: i64) -> (CfStmt, u32) {
+    let stmt = directive(op, vec![Slot::Value(Arg::Expr(Expr::LitInt(value, None)), None)]);
+    let size = op.unit_size().unwrap_or(1) as u32;
+    (stmt, size)
+}
+
+// Values per line for each data directive
+fn per_line(op: AsmOp) -> usize {
+    match op {
+        AsmOp::Data8  => 8,
+        AsmOp::Data16 => 8,
+        AsmOp::Data32 => 4,
+        _             => 1,
+    }
+}
+
+// Length of the run of text at the start of `bytes`
+fn text_len(bytes: &[u8]) -> usize {
+    bytes.iter().take_while(|&&b| is_text(b)).count()
+}
+
+#[inline]
+fn is_text(b: u8) -> bool {
+    matches!(b, b'\t' | b'\n' | b'\r' | 0x20..=0x7E)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::obj::raw::*;
+    use crate::obj::srec::*;
+    use super::*;
+
+    fn render(stmts: Vec<CfStmt>, pool: &IdentPool) -> String {
+        gas(&CfBlock { stmts }, pool).to_string()
+    }
+
+    #[test]
+    pub fn disassemble_code() {
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        let start    = pool.intern("start");
+        syms.set_value(start, 0x400);
+        syms.set_binding(start, Binding::Global);
+
+        let bytes = [
+            0x70, 0x03,                 // moveq #3,d0
+            0x53, 0x80,                 // subq.l #1,d0
+            0x66, 0xFC,                 // bne.b 0x402
+            0x41, 0xFA, 0x00, 0x04,     // lea (0x40C,pc),a0
+            0xFF, 0xFF,                 // (undecodable)
+            0x4E, 0x75,                 // rts
+            0x4E,                       // (odd byte)
+        ];
+
+        let mut d = Disassembler::new(&mut pool, &mut syms, ISA_A);
+        let mut stmts = d.globals();
+        stmts.extend(d.section(".text", 0x400));
+        stmts.extend(d.code(&bytes, 0x400));
+
+        assert_eq!(
+            render(stmts, &pool),
+            "        .globl start\n\
+             \x20       .section .text\n\
+             \x20       .org 0x400\n\
+             start:\n\
+             \x20       moveq.l #3,%d0\n\
+             L_0402:\n\
+             \x20       subq.l #1,%d0\n\
+             \x20       bne.b L_0402\n\
+             \x20       lea.l L_040C(%pc),%a0\n\
+             \x20       .short 0xFFFF\n\
+             L_040C:\n\
+             \x20       rts\n\
+             \x20       .byte 0x4E\n"
+        );
+    }
+
+    #[test]
+    pub fn disassemble_local_labels() {
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        let f        = pool.intern("f");
+        let g        = pool.intern("g");
+        syms.set_value(f, 0x400);
+        syms.set_value(g, 0x40A);
+
+        let bytes = [
+            0x70, 0x03,                 // moveq #3,d0
+            0x53, 0x80,                 // subq.l #1,d0
+            0x66, 0xFC,                 // bne.b 0x402
+            0x67, 0x02,                 // beq.b 0x40A (g)
+            0x60, 0xF6,                 // bra.b 0x400 (f)
+            0x4A, 0x80,                 // tst.l d0
+            0x67, 0x02,                 // beq.b 0x410
+            0x60, 0xFC,                 // bra.b 0x40C
+            0x4E, 0x75,                 // rts
+        ];
+
+        let mut d = Disassembler::new(&mut pool, &mut syms, ISA_A).with_local_labels(true);
+        let stmts = d.code(&bytes, 0x400);
+
+        assert_eq!(
+            render(stmts, &pool),
+            "f:\n\
+             \x20       moveq.l #3,%d0\n\
+             1:\n\
+             \x20       subq.l #1,%d0\n\
+             \x20       bne.b 1b\n\
+             \x20       beq.b g\n\
+             \x20       bra.b f\n\
+             g:\n\
+             \x20       tst.l %d0\n\
+             1:\n\
+             \x20       beq.b 2f\n\
+             \x20       bra.b 1b\n\
+             2:\n\
+             \x20       rts\n"
+        );
+
+        // A target also reached from another function keeps a global name
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        let g        = pool.intern("g");
+        syms.set_value(g, 0x404);
+        let bytes = [0x4E, 0x71, 0x66, 0xFC, 0x60, 0xFA];  // nop; bne.b 0x400; bra.b 0x400
+        let mut d = Disassembler::new(&mut pool, &mut syms, ISA_A).with_local_labels(true);
+        let stmts = d.code(&bytes, 0x400);
+        assert_eq!( stmts[0].labels.len(), 1 );
+        assert_eq!( pool.name(stmts[0].labels[0]), "L_0400" );
+    }
+
+    #[test]
+    pub fn disassemble_data() {
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        let tbl      = pool.intern("tbl");
+        syms.set_value(tbl, 0x108);
+
+        let mut bytes = b"Hello\0ab".to_vec();
+        bytes.extend([0x12, 0x34, 0x56, 0x78, 0xAB, 0xCD, 0xEF]);
+        bytes.extend(b"text");
+
+        let mut d = Disassembler::new(&mut pool, &mut syms, ISA_A);
+        let stmts = d.data(&bytes, 0x100);
+        let text  = render(stmts, &pool);
+
+        assert_eq!(
+            text,
+            "        .asciz \"Hello\"\n\
+             \x20       .short 0x6162\n\
+             tbl:\n\
+             \x20       .long 0x12345678\n\
+             \x20       .short 0xABCD\n\
+             \x20       .byte 0xEF\n\
+             \x20       .ascii \"text\"\n"
+        );
+
+        // The rendering assembles to the original bytes
+        let mut pool2 = IdentPool::new();
+        let mut syms2 = SymbolTable::new();
+        let mut block = parse_moto(&text, &mut pool2, &mut syms2).unwrap();
+        let asm = assemble(&mut block, &mut syms2, &AsmOptions::new(0x100)).unwrap();
+        assert_eq!( asm.bytes, bytes );
+    }
+
+    #[test]
+    pub fn disassemble_srecords() {
+        let mut pool  = IdentPool::new();
+        let mut syms  = SymbolTable::new();
+        let mut block = parse_moto(
+            "start   moveq   #1,d0\n\
+             \x20       bra     far\n\
+             \x20       org     $1100\n\
+             far     rts\n",
+            &mut pool, &mut syms,
+        ).unwrap();
+        let asm  = assemble(&mut block, &mut syms, &AsmOptions::new(0x1000)).unwrap();
+        let file = SRecordFile { image: asm.image(), entry: Some(0x1000), ..SRecordFile::default() };
+        let text = file.write(SRecordFormat::S19, 16).unwrap();
+
+        let file = SRecordFile::read(&text).unwrap();
+        assert_eq!( file.image.blocks().len(), 2 );
+
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        let mut d = Disassembler::new(&mut pool, &mut syms, ISA_A);
+        let stmts = d.image(&file.image);
+        assert_eq!(
+            render(stmts, &pool),
+            "        .org 0x1000\n\
+             \x20       moveq.l #1,%d0\n\
+             \x20       bra.w L_1100\n\
+             \x20       .org 0x1100\n\
+             L_1100:\n\
+             \x20       rts\n"
+        );
+    }
+
+    #[test]
+    pub fn disassemble_map() {
+        let map = MemoryMap::parse(
+            "0 8 0 vectors\n\
+             8 * 8 code .text\n"
+        ).unwrap();
+        let bytes = [0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x08, 0x4E, 0x75];
+        let image = map.load(&bytes).unwrap();
+
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        let mut d = Disassembler::new(&mut pool, &mut syms, ISA_A);
+        let stmts = d.image(&image);
+        assert_eq!(
+            render(stmts, &pool),
+            "        .org 0\n\
+             \x20       .long 0x1000\n\
+             \x20       .long 8\n\
+             \x20       .section .text\n\
+             \x20       .org 8\n\
+             \x20       rts\n"
+        );
+    }
+
+    #[test]
+    pub fn disassemble_vectors() {
+        let map = MemoryMap::parse(
+            "0     16 0  data\n\
+             0x400 *  16 code\n"
+        ).unwrap();
+        let bytes = [
+            0x20, 0x00, 0x10, 0x00,     // initial SP
+            0x00, 0x00, 0x04, 0x00,     // reset
+            0x00, 0x00, 0x04, 0x02,     // access error
+            0x00, 0x00, 0x04, 0x02,     // address error
+            0x4E, 0x71,                 // nop
+            0x4E, 0x73,                 // rte
+            0x4E, 0x75,                 // rts
+        ];
+        let mut image = map.load(&bytes).unwrap();
+
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        analyze_vectors(&mut image, 0, &mut syms, &mut pool);
+        image.set_kind(0x404, 2, RegionKind::Data);
+
+        let mut d = Disassembler::new(&mut pool, &mut syms, ISA_A);
+        let stmts = d.image(&image);
+        assert_eq!(
+            render(stmts, &pool),
+            "        .org 0\n\
+             \x20       .long 0x20001000\n\
+             \x20       .long reset\n\
+             \x20       .long handler_0402\n\
+             \x20       .long handler_0402\n\
+             \x20       .org 0x400\n\
+             reset:\n\
+             \x20       nop\n\
+             handler_0402:\n\
+             \x20       rte\n\
+             \x20       .short 0x4E75\n"
+        );
+    }
+
+    #[test]
+    pub fn disassemble_traversed() {
+        let bytes = [
+            0x20, 0x3A, 0x00, 0x04,     // move.l (0x406,pc),d0
+            0x4E, 0x75,                 // rts
+            0x12, 0x34, 0x56, 0x78,     // (data)
+        ];
+        let mut image = raw_image(&bytes, 0x400);
+        traverse(&mut image, &[0x400], ISA_A);
+
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        let mut d = Disassembler::new(&mut pool, &mut syms, ISA_A);
+        let stmts = d.image(&image);
+        assert_eq!(
+            render(stmts, &pool),
+            "        .org 0x400\n\
+             \x20       move.l 0x406(%pc),%d0\n\
+             \x20       rts\n\
+             \x20       .short 0x1234,0x5678\n"
+        );
+    }
+}
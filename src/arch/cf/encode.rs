@@ -0,0 +1,516 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::ast::{eval, eval_float, eval_single, EvalError, Expr, Ident, IdentPool};
+use super::*;
+
+/// An error encountered when encoding a ColdFire instruction.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum EncodeError {
+    /// The instruction has no known encodings.
+    NoEncoding,
+
+    /// No encoding of the instruction accepts the given arguments.
+    IllegalMode,
+
+    /// The encodings that accept the arguments require hardware features
+    /// that are not enabled.  Any one of the given features is sufficient.
+    Unsupported(u16),
+
+    /// The value of the argument with the given index does not fit in the
+    /// encoding.
+    OutOfRange(usize),
+
+    /// An argument expression could not be evaluated.
+    Eval(EvalError),
+}
+
+impl EncodeError {
+    /// Returns a description of the error for instruction `inst`, using
+    /// `pool` to name symbols.
+    pub fn message(&self, inst: Instructions, pool: &IdentPool) -> String {
+        let name = inst.name();
+        match *self {
+            EncodeError::NoEncoding     => format!("no encoding is known for '{}'", name),
+            EncodeError::IllegalMode    => format!("illegal addressing mode for '{}'", name),
+            EncodeError::Unsupported(f) => format!("'{}' requires {}", name, feature_names(f)),
+            EncodeError::OutOfRange(n)  => format!("operand {} of '{}' is out of range", n + 1, name),
+            EncodeError::Eval(ref e)    => e.message(pool),
+        }
+    }
+}
+
+/// Selects the first encoding of `inst` that accepts `args` and is supported
+/// by `features`.
+///
+/// Generic mnemonics with an address register destination, such as `add.l`
+/// or `move.l`, select the encoding of the corresponding address variant
+/// (`adda.l`, `movea.l`); the result's `inst()` reflects the substitution.
+pub fn select(inst: Instructions, args: &[Arg], features: u16) -> Result<Encoding, EncodeError> {
+    let mut result = Err(EncodeError::NoEncoding);
+
+    for inst in Some(inst).into_iter().chain(address_variant(inst)) {
+        for enc in encodings_for(inst) {
+            if !accepts(enc, args, features) {
+                if matches!(result, Err(EncodeError::NoEncoding)) {
+                    result = Err(EncodeError::IllegalMode);
+                }
+                continue;
+            }
+            if enc.flags().has_any(features) {
+                return Ok(enc);
+            }
+            if !matches!(result, Err(EncodeError::Unsupported(_))) {
+                result = Err(EncodeError::Unsupported(enc.flags().features()));
+            }
+        }
+    }
+
+    result
+}
+
+/// Returns the address variant of a generic mnemonic, such as `adda.l` for
+/// `add.l`, if any.
+pub fn address_variant(inst: Instructions) -> Option<Instructions> {
+    use Instructions::*;
+    match inst {
+        ADDL  => Some(ADDAL),
+        SUBL  => Some(SUBAL),
+        CMPW  => Some(CMPAW),
+        CMPL  => Some(CMPAL),
+        MOVEW => Some(MOVEAW),
+        MOVEL => Some(MOVEAL),
+        _     => None,
+    }
+}
+
+fn accepts(enc: Encoding, args: &[Arg], features: u16) -> bool {
+    let ops = enc.operands();
+    ops.len() == args.len()
+        && ops.iter().zip(args).all(|(o, a)| o.accepts(a))
+        && legal_combination(enc, args, features)
+}
+
+/// Returns whether the combination of `args` is permitted by `enc` on a target
+/// with `features`.  Only ColdFire MOVE restricts combinations.
+pub fn legal_combination(enc: Encoding, args: &[Arg], features: u16) -> bool {
+    // ColdFire MOVE limits the combined extension words of its source and
+    // destination.  Only a few combinations are permitted.
+    if enc.operands().get(1) != Some(&Operand::Md_ipmdxnf___6) {
+        return true
+    }
+
+    const SHORT: u16 = MODE_ADDR_DISP | MODE_PC_DISP;
+    const LONG:  u16 = MODE_ADDR_DISP_IDX | MODE_PC_DISP_IDX
+                     | MODE_ABS_NEAR | MODE_ABS_FAR | MODE_IMM;
+    const NONE:  u16 = MODE_DATA | MODE_ADDR
+                     | MODE_ADDR_IND | MODE_ADDR_IND_INC | MODE_ADDR_IND_DEC;
+
+    let src = mode_of(&args[0]);
+    let dst = mode_of(&args[1]);
+
+    if src & SHORT != 0 {
+        dst & (LONG | SHORT) == 0 || dst == MODE_ADDR_DISP
+    } else if src & LONG != 0 {
+        // ISA_B and later permit byte and word immediates to (d16,An)
+        dst & !NONE == 0
+            || src == MODE_IMM
+            && dst == MODE_ADDR_DISP
+            && enc.inst() != Instructions::MOVEL
+            && features & ISA_B_UP != 0
+    } else {
+        true
+    }
+}
+
+/// Returns the size in bytes of the instruction encoded with `enc` and
+/// `args`.  A bare expression argument in a general addressing mode position
+/// is assumed to be absolute long.
+pub fn size(enc: Encoding, args: &[Arg]) -> u32 {
+    let op_size = enc.inst().info().size().map_or(4, Size::bytes);
+    let ext: u32 = enc.operands().iter().zip(args)
+        .map(|(o, a)| o.ext_words(a, op_size))
+        .sum();
+    (enc.words() + ext) * 2
+}
+
+/// Encodes an instruction located at address `pc` with `enc` and `args`.
+/// The function `lookup` provides symbol values.  Returns the instruction
+/// words, opword first.
+pub fn encode<F>(enc: Encoding, args: &[Arg], pc: u32, lookup: &F) -> Result<Vec<u16>, EncodeError>
+where
+    F: Fn(Ident) -> Option<u32>
+{
+    let op_size = enc.inst().info().size();
+    let mut e = Encoder {
+        opcode: enc.bits(),
+        words:  enc.words(),
+        ext:    vec![],
+        size:   op_size.map_or(4, Size::bytes),
+        float:  op_size.is_some_and(Size::is_float),
+        index:  0,
+        pc,
+        lookup,
+    };
+
+    for (i, (&op, arg)) in enc.operands().iter().zip(args).enumerate() {
+        e.index = i;
+        e.operand(op, arg)?;
+    }
+
+    let mut words = vec![e.opcode as u16];
+    if enc.words() == 2 {
+        words.push((e.opcode >> 16) as u16);
+    }
+    words.extend(e.ext);
+    Ok(words)
+}
+
+struct Encoder<'a, F> {
+    opcode: u32,        // opword, plus extension word in upper 16 bits
+    words:  u32,        // count of opcode words
+    ext:    Vec<u16>,   // operand extension words
+    size:   u32,        // operation size in bytes
+    float:  bool,       // whether operation size is floating-point
+    index:  usize,      // index of current argument
+    pc:     u32,        // address of opword
+    lookup: &'a F,
+}
+
+impl<'a, F> Encoder<'a, F> where F: Fn(Ident) -> Option<u32> {
+    fn operand(&mut self, op: Operand, arg: &Arg) -> Result<(), EncodeError> {
+        use Operand::*;
+        match (op, arg) {
+            (DataReg0,  &Arg::DataReg(r)) => self.set( 0, 7, r.num() as u32),
+            (DataReg9,  &Arg::DataReg(r)) => self.set( 9, 7, r.num() as u32),
+            (DataReg28, &Arg::DataReg(r)) => self.set(28, 7, r.num() as u32),
+            (DataRegQ,  &Arg::DataReg(r)) => {
+                self.set(28, 7, r.num() as u32);
+                self.set(16, 7, r.num() as u32);
+            },
+            (DataRegPair16, &Arg::DataRegPair(p)) => {
+                self.set(16, 7, p.0.num() as u32);
+                self.set(28, 7, p.1.num() as u32);
+            },
+            (AddrReg0,  &Arg::AddrReg(r)) => self.set( 0, 7, r.num() as u32),
+            (AddrReg9,  &Arg::AddrReg(r)) => self.set( 9, 7, r.num() as u32),
+            (FpReg23,   &Arg::FpReg(r)  ) => self.set(23, 7, r.num() as u32),
+            (FpReg26,   &Arg::FpReg(r)  ) => self.set(26, 7, r.num() as u32),
+            (Ccr,       &Arg::CcrReg(_) ) => (),
+            (Sr,        &Arg::SrReg(_)  ) => (),
+            (RegList16, &Arg::RegList(l)) => self.set(16, 0xFFFF, l.0 as u32),
+            (RegList16, &Arg::DataReg(r)) => self.set(16 + r.num() as u32, 1, 1),
+            (RegList16, &Arg::AddrReg(r)) => self.set(24 + r.num() as u32, 1, 1),
+            (Imm8, Arg::Imm(e)) => {
+                let v = self.value(e)?;
+                self.check(in_range(v, -0x80, 0xFF))?;
+                self.push16(v & 0xFF);
+            },
+            (Imm16, Arg::Imm(e)) => {
+                let v = self.value(e)?;
+                self.check(in_range(v, -0x8000, 0xFFFF))?;
+                self.push16(v);
+            },
+            (Imm32, Arg::Imm(e)) => {
+                let v = self.value(e)?;
+                self.push32(v);
+            },
+            (Quick3, Arg::Imm(e)) => {
+                let v = self.value(e)?;
+                self.check(in_range(v, 1, 8))?;
+                self.set(9, 7, v);
+            },
+            (Quick3Mov, Arg::Imm(e)) => {
+                let v = self.value(e)?;
+                // -1 is encoded as 0
+                self.check(v == u32::MAX || in_range(v, 1, 7))?;
+                self.set(9, 7, if v == u32::MAX { 0 } else { v });
+            },
+            (Quick4, Arg::Imm(e)) => {
+                let v = self.value(e)?;
+                self.check(in_range(v, 0, 15))?;
+                self.set(0, 15, v);
+            },
+            (Quick8, Arg::Imm(e)) => {
+                let v = self.value(e)?;
+                self.check(in_range(v, -0x80, 0x7F))?;
+                self.set(0, 0xFF, v);
+            },
+            (PcRel8, Arg::Expr(e)) => {
+                // Displacements 0 and -1 select the 16- and 32-bit forms
+                let d = self.value(e)?.wrapping_sub(self.pc.wrapping_add(2));
+                self.check(in_range(d, -0x80, 0x7F) && d != 0 && d != u32::MAX)?;
+                self.set(0, 0xFF, d);
+            },
+            (PcRel16, Arg::Expr(e)) => {
+                let d = self.value(e)?.wrapping_sub(self.pc.wrapping_add(2));
+                self.check(in_range(d, -0x8000, 0x7FFF))?;
+                self.push16(d);
+            },
+            (PcRel32, Arg::Expr(e)) => {
+                let d = self.value(e)?.wrapping_sub(self.pc.wrapping_add(2));
+                self.push32(d);
+            },
+            _ if op.modes() & mode_of(arg) != 0 => {
+                self.ea(op, arg)?;
+            },
+            _ => return Err(EncodeError::IllegalMode),
+        }
+        Ok(())
+    }
+
+    fn ea(&mut self, op: Operand, arg: &Arg) -> Result<(), EncodeError> {
+        let (mode, reg) = match *arg {
+            Arg::DataReg       (r) => (0, r.num()),
+            Arg::AddrReg       (r) => (1, r.num()),
+            Arg::AddrRegInd    (r) => (2, r.num()),
+            Arg::AddrRegIndInc (r) => (3, r.num()),
+            Arg::AddrRegIndDec (r) => (4, r.num()),
+            Arg::AddrRegDisp (r, ref d) => {
+                let v = self.value(d)?;
+                self.check(in_range(v, -0x8000, 0x7FFF))?;
+                self.push16(v);
+                (5, r.num())
+            },
+            Arg::AddrRegIdx (r, ref d, ref x) => {
+                let v = self.value(d)?;
+                let w = self.brief(v, x)?;
+                self.push16(w);
+                (6, r.num())
+            },
+            Arg::AbsShort (ref a) => {
+                let v = self.value(a)?;
+                self.check(in_range(v, -0x8000, 0x7FFF))?;
+                self.push16(v);
+                (7, 0)
+            },
+            Arg::AbsLong (ref a) | Arg::Expr (ref a) => {
+                let v = self.value(a)?;
+                self.push32(v);
+                (7, 1)
+            },
+            Arg::PcDisp (_, ref t) => {
+                let d = self.value(t)?.wrapping_sub(self.here());
+                self.check(in_range(d, -0x8000, 0x7FFF))?;
+                self.push16(d);
+                (7, 2)
+            },
+            Arg::PcIdx (_, ref t, ref x) => {
+                let d = self.value(t)?.wrapping_sub(self.here());
+                let w = self.brief(d, x)?;
+                self.push16(w);
+                (7, 3)
+            },
+            Arg::Imm (ref e) if self.float => {
+                if self.size == 4 {
+                    let v = eval_single(e, self.lookup).map_err(EncodeError::Eval)?;
+                    self.push32(v.to_bits());
+                } else {
+                    let v    = eval_float(e, self.lookup).map_err(EncodeError::Eval)?;
+                    let bits = v.to_bits();
+                    self.push32((bits >> 32) as u32);
+                    self.push32( bits        as u32);
+                }
+                (7, 4)
+            },
+            Arg::Imm (ref e) => {
+                let v = self.value(e)?;
+                match self.size {
+                    1 => { self.check(in_range(v, -0x80,   0xFF  ))?; self.push16(v & 0xFF) },
+                    2 => { self.check(in_range(v, -0x8000, 0xFFFF))?; self.push16(v)        },
+                    _ => {                                             self.push32(v)        },
+                }
+                (7, 4)
+            },
+            _ => return Err(EncodeError::IllegalMode),
+        };
+
+        if op == Operand::Md_ipmdxnf___6 {
+            self.set(6, 7, mode);
+            self.set(9, 7, reg as u32);
+        } else {
+            self.set(3, 7, mode);
+            self.set(0, 7, reg as u32);
+        }
+        Ok(())
+    }
+
+    // Brief extension word: index register, size (always long), scale, and
+    // 8-bit displacement
+    fn brief(&self, disp: u32, index: &Index) -> Result<u32, EncodeError> {
+        self.check(in_range(disp, -0x80, 0x7F))?;
+
+        let scale = match self.value(&index.scale)? {
+            1 => 0,
+            2 => 1,
+            4 => 2,
+            _ => return Err(EncodeError::OutOfRange(self.index)),
+        };
+
+        let mut word = 0u32;
+        index.reg.encode(&mut word, 12);
+        Ok(word | 1 << 11 | scale << 9 | disp & 0xFF)
+    }
+
+    fn value(&self, expr: &Expr) -> Result<u32, EncodeError> {
+        eval(expr, self.lookup)
+            .map(|v| v.0)
+            .map_err(EncodeError::Eval)
+    }
+
+    #[inline]
+    fn check(&self, ok: bool) -> Result<(), EncodeError> {
+        if ok { Ok(()) } else { Err(EncodeError::OutOfRange(self.index)) }
+    }
+
+    #[inline]
+    fn set(&mut self, pos: u32, mask: u32, value: u32) {
+        self.opcode |= (value & mask) << pos;
+    }
+
+    // Address of the next extension word
+    #[inline]
+    fn here(&self) -> u32 {
+        self.pc.wrapping_add(2 * (self.words + self.ext.len() as u32))
+    }
+
+    #[inline]
+    fn push16(&mut self, value: u32) {
+        self.ext.push(value as u16);
+    }
+
+    #[inline]
+    fn push32(&mut self, value: u32) {
+        self.ext.push((value >> 16) as u16);
+        self.ext.push( value        as u16);
+    }
+}
+
+/// Returns whether `value`, interpreted as signed, is within `min..=max`.
+#[inline]
+pub fn in_range(value: u32, min: i32, max: i32) -> bool {
+    let v = value as i32;
+    min <= v && v <= max
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{IdentPool, Slot, SymbolTable};
+    use super::*;
+
+    fn enc_with(src: &str, pc: u32, features: u16) -> Result<(Instructions, Vec<u16>), EncodeError> {
+        let mut pool  = IdentPool::new();
+        let mut syms  = SymbolTable::new();
+        let     block = parse_moto(src, &mut pool, &mut syms).unwrap();
+        let     stmt  = &block.stmts[0];
+
+        let inst = match stmt.op {
+            Slot::Value(Op::Inst(i), _) => i,
+            ref op => panic!("unexpected op: {:?}", op),
+        };
+        let args: Vec<Arg> = stmt.args.iter()
+            .map(|a| a.value().unwrap().clone())
+            .collect();
+
+        let enc = select(inst, &args, features)?;
+        assert_eq!( size(enc, &args), enc_words(enc, &args, pc)?.len() as u32 * 2 );
+        Ok((enc.inst(), enc_words(enc, &args, pc)?))
+    }
+
+    fn enc_words(enc: Encoding, args: &[Arg], pc: u32) -> Result<Vec<u16>, EncodeError> {
+        encode(enc, args, pc, &|_| None)
+    }
+
+    fn enc(src: &str) -> Vec<u16> {
+        enc_with(src, 0x1000, ISA_A | HWDIV).unwrap().1
+    }
+
+    #[test]
+    pub fn encode_general_modes() {
+        assert_eq!( enc(" move.l d0,d1"),                  [0x2200] );
+        assert_eq!( enc(" move.l #$12345678,(a0)"),        [0x20BC, 0x1234, 0x5678] );
+        assert_eq!( enc(" move.w (a1)+,-(sp)"),            [0x3F19] );
+        assert_eq!( enc(" move.b #-1,d7"),                 [0x1E3C, 0x00FF] );
+        assert_eq!( enc(" move.l (4,a0,d1.l*4),d0"),       [0x2030, 0x1C04] );
+        assert_eq!( enc(" lea (8,a0),a1"),                 [0x43E8, 0x0008] );
+        assert_eq!( enc(" jsr ($1234).w"),                 [0x4EB8, 0x1234] );
+        assert_eq!( enc(" jmp ($12345678).l"),             [0x4EF9, 0x1234, 0x5678] );
+        assert_eq!( enc(" lea ($1010,pc),a0"),             [0x41FA, 0x000E] );
+        assert_eq!( enc(" tst.l ($0FF0,pc,a0.l*2)"),       [0x4ABB, 0x8AEE] );
+    }
+
+    #[test]
+    pub fn encode_special_operands() {
+        assert_eq!( enc(" addq.l #8,d0"),                  [0x5080] );
+        assert_eq!( enc(" subq.l #1,(a0)"),                [0x5390] );
+        assert_eq!( enc(" moveq.l #-1,d0"),                [0x70FF] );
+        assert_eq!( enc(" trap #15"),                      [0x4E4F] );
+        assert_eq!( enc(" link.w fp,#-8"),                 [0x4E56, 0xFFF8] );
+        assert_eq!( enc(" btst.b #3,(a0)"),                [0x0810, 0x0003] );
+        assert_eq!( enc(" movem.l d0-d1/a6,(sp)"),         [0x48D7, 0x4003] );
+        assert_eq!( enc(" movem.l (4,sp),d2"),             [0x4CEF, 0x0004, 0x0004] );
+        assert_eq!( enc(" rems.l d1,d2:d3"),               [0x4C41, 0x3802] );
+        assert_eq!( enc(" divs.l (a0),d4"),                [0x4C50, 0x4804] );
+        assert_eq!( enc(" move.w sr,d0"),                  [0x40C0] );
+        assert_eq!( enc(" move.b #4,ccr"),                 [0x44FC, 0x0004] );
+    }
+
+    #[test]
+    pub fn encode_fpu() {
+        let fpu = |src| enc_with(src, 0x1000, FPU).unwrap().1;
+        assert_eq!( fpu(" fmove.d #3.14159,fp0"),          [0xF23C, 0x5400, 0x4009, 0x21F9, 0xF01B, 0x866E] );
+        assert_eq!( fpu(" fmove.s #-1.5,fp7"),             [0xF23C, 0x4780, 0xBFC0, 0x0000] );
+        assert_eq!( fpu(" fmove.s #2,fp1"),                [0xF23C, 0x4480, 0x4000, 0x0000] );
+        assert_eq!( fpu(" fmove.s d0,fp1"),                [0xF200, 0x4480] );
+        assert_eq!( fpu(" fmove.d (8,a0),fp2"),            [0xF228, 0x5500, 0x0008] );
+        assert_eq!( fpu(" fmove.d fp2,-(sp)"),             [0xF227, 0x7500] );
+        assert_eq!( fpu(" fmove.d fp1,fp3"),               [0xF200, 0x0580] );
+        assert_eq!(
+            enc_with(" fmove.d d0,fp0", 0x1000, FPU).unwrap_err(),
+            EncodeError::IllegalMode
+        );
+    }
+
+    #[test]
+    pub fn encode_branches() {
+        assert_eq!( enc(" bra.b $1010"),                   [0x600E] );
+        assert_eq!( enc(" bne.w $0FF0"),                   [0x6600, 0xFFEE] );
+        assert_eq!( enc(" bsr.w $1002"),                   [0x6100, 0x0000] );
+
+        let far = enc_with(" beq.l $20000", 0x1000, ISA_B).unwrap().1;
+        assert_eq!( far, [0x67FF, 0x0001, 0xEFFE] );
+    }
+
+    #[test]
+    pub fn encode_address_variant() {
+        let (inst, words) = enc_with(" add.l d0,a0", 0, ISA_A).unwrap();
+        assert_eq!( inst,  Instructions::ADDAL );
+        assert_eq!( words, [0xD1C0] );
+    }
+
+    #[test]
+    pub fn encode_errors() {
+        let err = |src| enc_with(src, 0x1000, ISA_A).unwrap_err();
+        assert_eq!( err(" move.l (8,a0),(4,a1,d0.l)"), EncodeError::IllegalMode     );
+        assert_eq!( err(" rems.l d1,d2:d3"),           EncodeError::Unsupported(HWDIV) );
+        assert_eq!( err(" addq.l #9,d0"),              EncodeError::OutOfRange(0)   );
+        assert_eq!( err(" bra.b $1001"),               EncodeError::OutOfRange(0)   );
+        assert_eq!( err(" bra.b $1100"),               EncodeError::OutOfRange(0)   );
+        assert_eq!( err(" move.l ($8000,a0),d0"),      EncodeError::OutOfRange(0)   );
+        assert_eq!( err(" cpushl bc,(a0)"),            EncodeError::NoEncoding      );
+        assert_eq!( err(" mvs.b d0,d1"),               EncodeError::Unsupported(ISA_B_UP) );
+    }
+}
@@ -15,11 +15,14 @@
 // along with cfda.  If not, see <http://www.gnu.org/licenses/>.
 
 use super::flags::*;
-use super::{Operand, Operand as O};
+use super::{Instructions, Instructions as I, Operand, Operand as O};
 
 /// ColdFire 1-word instruction encoding.
 #[derive(Clone, Copy, Debug)]
 pub struct WordEncoding {
+    /// Instruction encoded.
+    pub inst: Instructions,
+
     /// Values of required bits in opword.
     pub bits: u16,
  
@@ -36,6 +39,9 @@ pub struct WordEncoding {
 /// ColdFire 2-word instruction encoding.
 #[derive(Clone, Copy, Debug)]
 pub struct LongEncoding {
+    /// Instruction encoded.
+    pub inst: Instructions,
+
     /// Values of required bits in opword and extension word.
     /// The extension word portion occupies the upper 16 bits.
     pub bits: u32,
@@ -54,11 +60,12 @@ pub struct LongEncoding {
 macro_rules! encodings {
     {
         $array:ident: [$type:ident] <$operands:ident, $indexes:ident> =
-        $( $name:ident $bits:tt $mask:tt [$($operand:ident),*] $flags:expr; )*
+        $( $name:ident $inst:ident $bits:tt $mask:tt [$($operand:ident),*] $flags:expr; )*
     } =>
     {
         pub static $array: [$type; count!($($name)*)] = [$(
             $type {
+                inst:     I::$inst,
                 bits:     opcode!($bits),
                 mask:     opcode!($mask),
                 operands: $operands!($($operand),*),
@@ -96,25 +103,402 @@ macro_rules! operands5 {
 encodings! {
     WORD_ENCODINGS: [WordEncoding] <operands2, WordEncodings> =
     
-//  NAME       WORD      MASK      OPERANDS                    FLAGS
-//  ---------  --------  --------  --------------------------  -----
-    Addl0      0o150200  0o170700  [MdaipmdxnfDXI0, DataReg9]  ISA_A_UP;
-    Addl1      0o150600  0o170700  [DataReg9, M__ipmdxnf___0]  ISA_A_UP;
-//  ---------  --------  --------  --------------------------  -----
-    Byterevl0  0o001300  0o177770  [DataReg0]                  ISA_C;
-//  ---------  --------  --------  --------------------------  -----
+//  NAME        INST      WORD      MASK      OPERANDS                          FLAGS
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Addl0       ADDL      0o150200  0o170700  [MdaipmdxnfDXI0, DataReg9]        ISA_A_UP;
+    Addl1       ADDL      0o150600  0o170700  [DataReg9, M__ipmdxnf___0]        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Addal0      ADDAL     0o150700  0o170700  [MdaipmdxnfDXI0, AddrReg9]        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Addil0      ADDIL     0o003200  0o177770  [Imm32, DataReg0]                 ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Addql0      ADDQL     0o050200  0o170700  [Quick3, Mdaipmdxnf___0]          ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Addxl0      ADDXL     0o150600  0o170770  [DataReg0, DataReg9]              ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Andl0       ANDL      0o140200  0o170700  [Md_ipmdxnfDXI0, DataReg9]        ISA_A_UP;
+    Andl1       ANDL      0o140600  0o170700  [DataReg9, M__ipmdxnf___0]        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Andil0      ANDIL     0o001200  0o177770  [Imm32, DataReg0]                 ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Asll0       ASLL      0o160600  0o170770  [Quick3, DataReg0]                ISA_A_UP;
+    Asll1       ASLL      0o160640  0o170770  [DataReg9, DataReg0]              ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Asrl0       ASRL      0o160200  0o170770  [Quick3, DataReg0]                ISA_A_UP;
+    Asrl1       ASRL      0o160240  0o170770  [DataReg9, DataReg0]              ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Bchgb0      BCHGB     0o000500  0o170700  [DataReg9, M__ipmdxnf___0]        ISA_A_UP;
+    Bchgb1      BCHGB     0o004100  0o177700  [Imm8, M__ipmd______0]            ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Bchgl0      BCHGL     0o000500  0o170770  [DataReg9, DataReg0]              ISA_A_UP;
+    Bchgl1      BCHGL     0o004100  0o177770  [Imm8, DataReg0]                  ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Bclrb0      BCLRB     0o000600  0o170700  [DataReg9, M__ipmdxnf___0]        ISA_A_UP;
+    Bclrb1      BCLRB     0o004200  0o177700  [Imm8, M__ipmd______0]            ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Bclrl0      BCLRL     0o000600  0o170770  [DataReg9, DataReg0]              ISA_A_UP;
+    Bclrl1      BCLRL     0o004200  0o177770  [Imm8, DataReg0]                  ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Bsetb0      BSETB     0o000700  0o170700  [DataReg9, M__ipmdxnf___0]        ISA_A_UP;
+    Bsetb1      BSETB     0o004300  0o177700  [Imm8, M__ipmd______0]            ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Bsetl0      BSETL     0o000700  0o170770  [DataReg9, DataReg0]              ISA_A_UP;
+    Bsetl1      BSETL     0o004300  0o177770  [Imm8, DataReg0]                  ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Btstb0      BTSTB     0o000400  0o170700  [DataReg9, M__ipmdxnf___0]        ISA_A_UP;
+    Btstb1      BTSTB     0o004000  0o177700  [Imm8, M__ipmd______0]            ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Btstl0      BTSTL     0o000400  0o170770  [DataReg9, DataReg0]              ISA_A_UP;
+    Btstl1      BTSTL     0o004000  0o177770  [Imm8, DataReg0]                  ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Bitrevl0    BITREVL   0o000300  0o177770  [DataReg0]                        ISA_A2 | ISA_C;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Byterevl0   BYTEREVL  0o001300  0o177770  [DataReg0]                        ISA_A2 | ISA_C;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Clrb0       CLRB      0o041000  0o177700  [Md_ipmdxnf___0]                  ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Clrw0       CLRW      0o041100  0o177700  [Md_ipmdxnf___0]                  ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Clrl0       CLRL      0o041200  0o177700  [Md_ipmdxnf___0]                  ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Cmpb0       CMPB      0o130000  0o170700  [Md_ipmdxnfDXI0, DataReg9]        ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Cmpw0       CMPW      0o130100  0o170700  [MdaipmdxnfDXI0, DataReg9]        ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Cmpl0       CMPL      0o130200  0o170700  [MdaipmdxnfDXI0, DataReg9]        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Cmpaw0      CMPAW     0o130300  0o170700  [MdaipmdxnfDXI0, AddrReg9]        ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Cmpal0      CMPAL     0o130700  0o170700  [MdaipmdxnfDXI0, AddrReg9]        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Cmpib0      CMPIB     0o006000  0o177770  [Imm8, DataReg0]                  ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Cmpiw0      CMPIW     0o006100  0o177770  [Imm16, DataReg0]                 ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Cmpil0      CMPIL     0o006200  0o177770  [Imm32, DataReg0]                 ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Divsw0      DIVSW     0o100700  0o170700  [Md_ipmdxnfDXI0, DataReg9]        HWDIV;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Divuw0      DIVUW     0o100300  0o170700  [Md_ipmdxnfDXI0, DataReg9]        HWDIV;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Eorl0       EORL      0o130600  0o170700  [DataReg9, Md_ipmdxnf___0]        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Eoril0      EORIL     0o005200  0o177770  [Imm32, DataReg0]                 ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Extw0       EXTW      0o044200  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Extl0       EXTL      0o044300  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Extbl0      EXTBL     0o044700  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Ff1l0       FF1L      0o002300  0o177770  [DataReg0]                        ISA_A2 | ISA_C;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Halt0       HALT      0o045310  0o177777  []                                ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Illegal0    ILLEGAL   0o045374  0o177777  []                                ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Jmp0        JMP       0o047300  0o177700  [M__i__dxnfDX_0]                  ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Jsr0        JSR       0o047200  0o177700  [M__i__dxnfDX_0]                  ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Leal0       LEAL      0o040700  0o170700  [M__i__dxnfDX_0, AddrReg9]        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Linkw0      LINKW     0o047120  0o177770  [AddrReg0, Imm16]                 ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Lsll0       LSLL      0o160610  0o170770  [Quick3, DataReg0]                ISA_A_UP;
+    Lsll1       LSLL      0o160650  0o170770  [DataReg9, DataReg0]              ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Lsrl0       LSRL      0o160210  0o170770  [Quick3, DataReg0]                ISA_A_UP;
+    Lsrl1       LSRL      0o160250  0o170770  [DataReg9, DataReg0]              ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Mov3ql0     MOV3QL    0o120500  0o170700  [Quick3Mov, Mdaipmdxnf___0]       ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Moveb0      MOVEB     0o010000  0o170000  [Md_ipmdxnfDXI0, Md_ipmdxnf___6]  ISA_A_UP;
+    Moveb1      MOVEB     0o042300  0o177700  [Md__________I0, Ccr]             ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Movew0      MOVEW     0o030000  0o170000  [MdaipmdxnfDXI0, Md_ipmdxnf___6]  ISA_A_UP;
+    Movew1      MOVEW     0o041300  0o177770  [Ccr, DataReg0]                   ISA_A_UP;
+    Movew2      MOVEW     0o040300  0o177770  [Sr, DataReg0]                    ISA_A_UP;
+    Movew3      MOVEW     0o043300  0o177700  [Md__________I0, Sr]              ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Movel0      MOVEL     0o020000  0o170000  [MdaipmdxnfDXI0, Md_ipmdxnf___6]  ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Moveaw0     MOVEAW    0o030100  0o170700  [MdaipmdxnfDXI0, AddrReg9]        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Moveal0     MOVEAL    0o020100  0o170700  [MdaipmdxnfDXI0, AddrReg9]        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Moveql0     MOVEQL    0o070000  0o170400  [Quick8, DataReg9]                ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Mulsw0      MULSW     0o140700  0o170700  [Md_ipmdxnfDXI0, DataReg9]        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Muluw0      MULUW     0o140300  0o170700  [Md_ipmdxnfDXI0, DataReg9]        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Mvsb0       MVSB      0o070400  0o170700  [Md_ipmdxnfDXI0, DataReg9]        ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Mvsw0       MVSW      0o070500  0o170700  [MdaipmdxnfDXI0, DataReg9]        ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Mvzb0       MVZB      0o070600  0o170700  [Md_ipmdxnfDXI0, DataReg9]        ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Mvzw0       MVZW      0o070700  0o170700  [MdaipmdxnfDXI0, DataReg9]        ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Negl0       NEGL      0o042200  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Negxl0      NEGXL     0o040200  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Nop0        NOP       0o047161  0o177777  []                                ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Notl0       NOTL      0o043200  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Orl0        ORL       0o100200  0o170700  [Md_ipmdxnfDXI0, DataReg9]        ISA_A_UP;
+    Orl1        ORL       0o100600  0o170700  [DataReg9, M__ipmdxnf___0]        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Oril0       ORIL      0o000200  0o177770  [Imm32, DataReg0]                 ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Swapw0      SWAPW     0o044100  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Peal0       PEAL      0o044100  0o177700  [M__i__dxnfDX_0]                  ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Pulse0      PULSE     0o045314  0o177777  []                                ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Rte0        RTE       0o047163  0o177777  []                                ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Rts0        RTS       0o047165  0o177777  []                                ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Satsl0      SATSL     0o046200  0o177770  [DataReg0]                        ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Subl0       SUBL      0o110200  0o170700  [MdaipmdxnfDXI0, DataReg9]        ISA_A_UP;
+    Subl1       SUBL      0o110600  0o170700  [DataReg9, M__ipmdxnf___0]        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Subal0      SUBAL     0o110700  0o170700  [MdaipmdxnfDXI0, AddrReg9]        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Subil0      SUBIL     0o002200  0o177770  [Imm32, DataReg0]                 ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Subql0      SUBQL     0o050600  0o170700  [Quick3, Mdaipmdxnf___0]          ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Subxl0      SUBXL     0o110600  0o170770  [DataReg0, DataReg9]              ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Stop0       STOP      0o047162  0o177777  [Imm16]                           ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Tasb0       TASB      0o045300  0o177700  [M__ipmdxnf___0]                  ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Tpf0        TPF       0o050774  0o177777  []                                ISA_A_UP;
+    Tpfw0       TPFW      0o050772  0o177777  [Imm16]                           ISA_A_UP;
+    Tpfl0       TPFL      0o050773  0o177777  [Imm32]                           ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Trap0       TRAP      0o047100  0o177760  [Quick4]                          ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Tstb0       TSTB      0o045000  0o177700  [Md_ipmdxnfDXI0]                  ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Tstw0       TSTW      0o045100  0o177700  [MdaipmdxnfDXI0]                  ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Tstl0       TSTL      0o045200  0o177700  [MdaipmdxnfDXI0]                  ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Unlk0       UNLK      0o047130  0o177770  [AddrReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Brab0       BRAB      0o060000  0o177400  [PcRel8]                          ISA_A_UP;
+    Braw0       BRAW      0o060000  0o177777  [PcRel16]                         ISA_A_UP;
+    Bral0       BRAL      0o060377  0o177777  [PcRel32]                         ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Bsrb0       BSRB      0o060400  0o177400  [PcRel8]                          ISA_A_UP;
+    Bsrw0       BSRW      0o060400  0o177777  [PcRel16]                         ISA_A_UP;
+    Bsrl0       BSRL      0o060777  0o177777  [PcRel32]                         ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Bhib0       BHIB      0o061000  0o177400  [PcRel8]                          ISA_A_UP;
+    Bhiw0       BHIW      0o061000  0o177777  [PcRel16]                         ISA_A_UP;
+    Bhil0       BHIL      0o061377  0o177777  [PcRel32]                         ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Blsb0       BLSB      0o061400  0o177400  [PcRel8]                          ISA_A_UP;
+    Blsw0       BLSW      0o061400  0o177777  [PcRel16]                         ISA_A_UP;
+    Blsl0       BLSL      0o061777  0o177777  [PcRel32]                         ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Bhsb0       BHSB      0o062000  0o177400  [PcRel8]                          ISA_A_UP;
+    Bhsw0       BHSW      0o062000  0o177777  [PcRel16]                         ISA_A_UP;
+    Bhsl0       BHSL      0o062377  0o177777  [PcRel32]                         ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Blob0       BLOB      0o062400  0o177400  [PcRel8]                          ISA_A_UP;
+    Blow0       BLOW      0o062400  0o177777  [PcRel16]                         ISA_A_UP;
+    Blol0       BLOL      0o062777  0o177777  [PcRel32]                         ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Bneb0       BNEB      0o063000  0o177400  [PcRel8]                          ISA_A_UP;
+    Bnew0       BNEW      0o063000  0o177777  [PcRel16]                         ISA_A_UP;
+    Bnel0       BNEL      0o063377  0o177777  [PcRel32]                         ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Beqb0       BEQB      0o063400  0o177400  [PcRel8]                          ISA_A_UP;
+    Beqw0       BEQW      0o063400  0o177777  [PcRel16]                         ISA_A_UP;
+    Beql0       BEQL      0o063777  0o177777  [PcRel32]                         ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Bvcb0       BVCB      0o064000  0o177400  [PcRel8]                          ISA_A_UP;
+    Bvcw0       BVCW      0o064000  0o177777  [PcRel16]                         ISA_A_UP;
+    Bvcl0       BVCL      0o064377  0o177777  [PcRel32]                         ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Bvsb0       BVSB      0o064400  0o177400  [PcRel8]                          ISA_A_UP;
+    Bvsw0       BVSW      0o064400  0o177777  [PcRel16]                         ISA_A_UP;
+    Bvsl0       BVSL      0o064777  0o177777  [PcRel32]                         ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Bplb0       BPLB      0o065000  0o177400  [PcRel8]                          ISA_A_UP;
+    Bplw0       BPLW      0o065000  0o177777  [PcRel16]                         ISA_A_UP;
+    Bpll0       BPLL      0o065377  0o177777  [PcRel32]                         ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Bmib0       BMIB      0o065400  0o177400  [PcRel8]                          ISA_A_UP;
+    Bmiw0       BMIW      0o065400  0o177777  [PcRel16]                         ISA_A_UP;
+    Bmil0       BMIL      0o065777  0o177777  [PcRel32]                         ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Bgeb0       BGEB      0o066000  0o177400  [PcRel8]                          ISA_A_UP;
+    Bgew0       BGEW      0o066000  0o177777  [PcRel16]                         ISA_A_UP;
+    Bgel0       BGEL      0o066377  0o177777  [PcRel32]                         ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Bltb0       BLTB      0o066400  0o177400  [PcRel8]                          ISA_A_UP;
+    Bltw0       BLTW      0o066400  0o177777  [PcRel16]                         ISA_A_UP;
+    Bltl0       BLTL      0o066777  0o177777  [PcRel32]                         ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Bgtb0       BGTB      0o067000  0o177400  [PcRel8]                          ISA_A_UP;
+    Bgtw0       BGTW      0o067000  0o177777  [PcRel16]                         ISA_A_UP;
+    Bgtl0       BGTL      0o067377  0o177777  [PcRel32]                         ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Bleb0       BLEB      0o067400  0o177400  [PcRel8]                          ISA_A_UP;
+    Blew0       BLEW      0o067400  0o177777  [PcRel16]                         ISA_A_UP;
+    Blel0       BLEL      0o067777  0o177777  [PcRel32]                         ISA_B_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Stb0        STB       0o050300  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Sfb0        SFB       0o050700  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Shib0       SHIB      0o051300  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Slsb0       SLSB      0o051700  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Shsb0       SHSB      0o052300  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Slob0       SLOB      0o052700  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Sneb0       SNEB      0o053300  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Seqb0       SEQB      0o053700  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Svcb0       SVCB      0o054300  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Svsb0       SVSB      0o054700  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Splb0       SPLB      0o055300  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Smib0       SMIB      0o055700  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Sgeb0       SGEB      0o056300  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Sltb0       SLTB      0o056700  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Sgtb0       SGTB      0o057300  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
+    Sleb0       SLEB      0o057700  0o177770  [DataReg0]                        ISA_A_UP;
+//  ----------  --------  --------  --------  --------------------------------  -----
 //  ...
 }
 
 encodings! {
     LONG_ENCODINGS: [LongEncoding] <operands5, LongEncodings> =
     
-//  NAME       WORD                  MASK                  OPERANDS                     FLAGS
-//  ---------  --------------------  --------------------  ---------------------------  -----
-    Mulul0     (0o046000, 0o000000)  (0o177700, 0o107777)  [Md_ipmd______0, DataReg28]  ISA_A_UP;
-//  ---------  --------------------  --------------------  ---------------------------  -----
-    Mulsl0     (0o046000, 0o004000)  (0o177700, 0o107777)  [Md_ipmd______0, DataReg28]  ISA_A_UP;
-//  ---------  --------------------  --------------------  ---------------------------  -----
+//  NAME       INST    WORD                  MASK                  OPERANDS                         FLAGS
+//  ---------  ------  --------------------  --------------------  -------------------------------  -----
+    Divsl0     DIVSL   (0o046100, 0o004000)  (0o177700, 0o107770)  [Md_ipmd______0, DataRegQ]       HWDIV;
+//  ---------  ------  --------------------  --------------------  -------------------------------  -----
+    Divul0     DIVUL   (0o046100, 0o000000)  (0o177700, 0o107770)  [Md_ipmd______0, DataRegQ]       HWDIV;
+//  ---------  ------  --------------------  --------------------  -------------------------------  -----
+    Moveml0    MOVEML  (0o044300, 0o000000)  (0o177700, 0o000000)  [RegList16, M__i__d______0]      ISA_A_UP;
+    Moveml1    MOVEML  (0o046300, 0o000000)  (0o177700, 0o000000)  [M__i__d______0, RegList16]      ISA_A_UP;
+//  ---------  ------  --------------------  --------------------  -------------------------------  -----
+    Mulul0     MULUL   (0o046000, 0o000000)  (0o177700, 0o107777)  [Md_ipmd______0, DataReg28]      ISA_A_UP;
+//  ---------  ------  --------------------  --------------------  -------------------------------  -----
+    Mulsl0     MULSL   (0o046000, 0o004000)  (0o177700, 0o107777)  [Md_ipmd______0, DataReg28]      ISA_A_UP;
+//  ---------  ------  --------------------  --------------------  -------------------------------  -----
+    Remsl0     REMSL   (0o046100, 0o004000)  (0o177700, 0o107770)  [Md_ipmd______0, DataRegPair16]  HWDIV;
+//  ---------  ------  --------------------  --------------------  -------------------------------  -----
+    Remul0     REMUL   (0o046100, 0o000000)  (0o177700, 0o107770)  [Md_ipmd______0, DataRegPair16]  HWDIV;
+//  ---------  ------  --------------------  --------------------  -------------------------------  -----
+    Fmoves0    FMOVES  (0o171000, 0o042000)  (0o177700, 0o176177)  [Md_ipmd__D_I0, FpReg23]         FPU;
+    Fmoves1    FMOVES  (0o171000, 0o062000)  (0o177700, 0o176177)  [FpReg23, Md_ipmd______0]        FPU;
+//  ---------  ------  --------------------  --------------------  -------------------------------  -----
+    Fmoved0    FMOVED  (0o171000, 0o000000)  (0o177777, 0o160177)  [FpReg26, FpReg23]               FPU;
+    Fmoved1    FMOVED  (0o171000, 0o052000)  (0o177700, 0o176177)  [M__ipmd__D_I0, FpReg23]         FPU;
+    Fmoved2    FMOVED  (0o171000, 0o072000)  (0o177700, 0o176177)  [FpReg23, M__ipmd______0]        FPU;
+//  ---------  ------  --------------------  --------------------  -------------------------------  -----
 //  ...
 }
 
+// -----------------------------------------------------------------------------
+// Encoding References
+
+/// Reference to a ColdFire instruction encoding of either length.
+#[derive(Clone, Copy, Debug)]
+pub enum Encoding {
+    /// 1-word encoding.
+    Word(&'static WordEncoding),
+
+    /// 2-word encoding.
+    Long(&'static LongEncoding),
+}
+
+impl Encoding {
+    /// Returns the instruction encoded.
+    #[inline]
+    pub fn inst(self) -> Instructions {
+        match self {
+            Encoding::Word(e) => e.inst,
+            Encoding::Long(e) => e.inst,
+        }
+    }
+
+    /// Returns the values of required bits in the opword and, for 2-word
+    /// encodings, the extension word in the upper 16 bits.
+    #[inline]
+    pub fn bits(self) -> u32 {
+        match self {
+            Encoding::Word(e) => e.bits as u32,
+            Encoding::Long(e) => e.bits,
+        }
+    }
+
+    /// Returns the mask of required bits, laid out as for `bits`.
+    #[inline]
+    pub fn mask(self) -> u32 {
+        match self {
+            Encoding::Word(e) => e.mask as u32,
+            Encoding::Long(e) => e.mask,
+        }
+    }
+
+    /// Returns the flags (arity, hardware support).
+    #[inline]
+    pub fn flags(self) -> CfFlags {
+        match self {
+            Encoding::Word(e) => e.flags,
+            Encoding::Long(e) => e.flags,
+        }
+    }
+
+    /// Returns the operand kinds used by the encoding.
+    #[inline]
+    pub fn operands(self) -> &'static [Operand] {
+        let ops: &'static [Operand] = match self {
+            Encoding::Word(e) => &e.operands,
+            Encoding::Long(e) => &e.operands,
+        };
+        &ops[..self.flags().arity()]
+    }
+
+    /// Returns the number of words occupied by the opcode, excluding operand
+    /// extension words.
+    #[inline]
+    pub fn words(self) -> u32 {
+        match self {
+            Encoding::Word(_) => 1,
+            Encoding::Long(_) => 2,
+        }
+    }
+}
+
+/// Returns the encodings of the given instruction, in table order.
+pub fn encodings_for(inst: Instructions) -> impl Iterator<Item=Encoding> {
+    let words = WORD_ENCODINGS.iter().map(Encoding::Word);
+    let longs = LONG_ENCODINGS.iter().map(Encoding::Long);
+    words.chain(longs).filter(move |e| e.inst() == inst)
+}
@@ -44,6 +44,33 @@ pub const ISA_A_UP:     u16 = ISA_A | ISA_A2 | ISA_B | ISA_C;
 pub const ISA_A2_UP:    u16 =         ISA_A2 | ISA_B | ISA_C;
 pub const ISA_B_UP:     u16 =                  ISA_B | ISA_C;
 
+static FEATURE_NAMES: [(u16, &str); 11] = [
+    (ISA_A,  "ISA_A"),
+    (ISA_A2, "ISA_A+"),
+    (ISA_B,  "ISA_B"),
+    (ISA_C,  "ISA_C"),
+    (HWDIV,  "HWDIV"),
+    (FPU,    "FPU"),
+    (MAC,    "MAC"),
+    (EMAC,   "EMAC"),
+    (EMAC_B, "EMAC_B"),
+    (MMU,    "MMU"),
+    (USP,    "USP"),
+];
+
+/// Returns the names of the given features, separated by `" or "`.
+pub fn feature_names(features: u16) -> String {
+    feature_list(features).join(" or ")
+}
+
+/// Returns the names of the given features.
+pub fn feature_list(features: u16) -> Vec<&'static str> {
+    FEATURE_NAMES.iter()
+        .filter(|&&(f, _)| features & f != 0)
+        .map(|&(_, n)| n)
+        .collect()
+}
+
 impl CfFlags {
     #[inline]
     pub const fn new(arity: usize, features: u16) -> CfFlags {
@@ -0,0 +1,765 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt::{self, Display, Formatter, Write};
+use crate::ast::*;
+use crate::mem::{BE, LE};
+use super::*;
+
+/// Assembly language syntaxes.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Syntax {
+    /// Motorola syntax: `dc.b` directives, `$` hexadecimal, and operands
+    /// such as `(8,a0)`.
+    Moto,
+
+    /// GNU assembler syntax: `.byte` directives, `0x` hexadecimal, and
+    /// operands such as `8(%a0)`.
+    Gas,
+}
+
+/// Radixes in which to display immediate values.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Radix {
+    /// Decimal for values from -9 to 9, hexadecimal otherwise.
+    Mixed,
+
+    /// Decimal.
+    Decimal,
+
+    /// Hexadecimal.
+    Hex,
+}
+
+/// Options for displaying assembly.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct FmtOptions {
+    /// Syntax of directives and operands.
+    pub syntax: Syntax,
+
+    /// Whether to name a6 and a7 as `fp` and `sp`.
+    pub reg_aliases: bool,
+
+    /// Radix of immediate values.  Other values use `Radix::Mixed`.
+    pub radix: Radix,
+
+    /// Whether to write mnemonics in upper case.
+    pub upper_case: bool,
+
+    /// Column at which operations begin.  Labels begin in column 0.
+    pub op_column: usize,
+
+    /// Column at which arguments begin, or 0 to follow the operation with
+    /// one space.  An operation that reaches the column is followed by one
+    /// space.
+    pub arg_column: usize,
+
+    /// Whether to spell 8-bit branches `.s` rather than `.b`.
+    pub short_branch: bool,
+
+    /// Column at which trailing comments begin, or 0 to follow the
+    /// statement with one space.  A statement that reaches the column is
+    /// followed by one space.
+    pub comment_column: usize,
+}
+
+impl FmtOptions {
+    /// Returns the default options for the given syntax.
+    pub const fn new(syntax: Syntax) -> Self {
+        FmtOptions {
+            syntax,
+            reg_aliases:    true,
+            radix:          Radix::Mixed,
+            upper_case:     false,
+            op_column:      8,
+            arg_column:     0,
+            short_branch:   false,
+            comment_column: 0,
+        }
+    }
+}
+
+/// Wrapper to display a node with given options, naming identifiers from an
+/// `IdentPool`.
+#[derive(Clone, Copy, Debug)]
+pub struct Styled<'a, T: ?Sized> {
+    node: &'a T,
+    pool: &'a IdentPool,
+    opts: FmtOptions,
+}
+
+/// Returns a wrapper that displays `node` with the given options.
+#[inline]
+pub fn styled<'a, T: ?Sized>(node: &'a T, pool: &'a IdentPool, opts: FmtOptions) -> Styled<'a, T> {
+    Styled { node, pool, opts }
+}
+
+/// Returns a wrapper that displays `node` in Motorola syntax.
+#[inline]
+pub fn moto<'a, T: ?Sized>(node: &'a T, pool: &'a IdentPool) -> Styled<'a, T> {
+    styled(node, pool, FmtOptions::new(Syntax::Moto))
+}
+
+/// Returns a wrapper that displays `node` in GNU assembler syntax.
+#[inline]
+pub fn gas<'a, T: ?Sized>(node: &'a T, pool: &'a IdentPool) -> Styled<'a, T> {
+    styled(node, pool, FmtOptions::new(Syntax::Gas))
+}
+
+impl<'a, T: ?Sized> Styled<'a, T> {
+    // Returns a wrapper for a child node in the same style.
+    #[inline]
+    fn child<U: ?Sized>(&self, node: &'a U) -> Styled<'a, U> {
+        Styled { node, pool: self.pool, opts: self.opts }
+    }
+
+    // Returns a wrapper for a child value that is not an immediate.
+    #[inline]
+    fn value<U: ?Sized>(&self, node: &'a U) -> Styled<'a, U> {
+        Styled { node, pool: self.pool, opts: self.opts.mixed() }
+    }
+
+    // Returns the name of a register, prefixed as the syntax requires.
+    #[inline]
+    fn reg(&self, name: &'static str) -> RegName {
+        RegName(name, self.opts.syntax)
+    }
+
+    #[inline]
+    fn addr_reg(&self, r: AddrReg) -> RegName {
+        self.reg(if self.opts.reg_aliases { r.name() } else { ADDR_REG_NUM_NAMES[r as usize] })
+    }
+
+    #[inline]
+    fn index_reg(&self, r: IndexReg) -> RegName {
+        match r {
+            IndexReg::Data(r) => self.reg(r.name()),
+            IndexReg::Addr(r) => self.addr_reg(r),
+        }
+    }
+
+    #[inline]
+    fn list_reg(&self, n: u8) -> RegName {
+        match n {
+            0..=7 => self.reg(DataReg::with_num(n).unwrap().name()),
+            _     => self.addr_reg(AddrReg::with_num(n - 8).unwrap()),
+        }
+    }
+}
+
+impl FmtOptions {
+    // Options for values that are not immediates.
+    #[inline]
+    fn mixed(self) -> Self {
+        FmtOptions { radix: Radix::Mixed, ..self }
+    }
+}
+
+static ADDR_REG_NUM_NAMES: [&str; 8] = [
+    "a0", "a1", "a2", "a3", "a4", "a5", "a6", "a7"
+];
+
+// Register name, with a % prefix in GNU syntax
+struct RegName (&'static str, Syntax);
+
+impl Display for RegName {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.1 == Syntax::Gas {
+            f.write_char('%')?;
+        }
+        f.write_str(self.0)
+    }
+}
+
+/// Displays a block, one statement per line, with each label on a line of
+/// its own.  Comments and blank lines attached to statements are kept.
+impl<'a> Display for Styled<'a, CfBlock> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // GNU syntax comments are delimited: a `|` would parse as an operator
+        let (open, close) = match self.opts.syntax {
+            Syntax::Moto => (";",  ""),
+            Syntax::Gas  => ("/*", " */"),
+        };
+
+        for stmt in &self.node.stmts {
+            for t in &stmt.trivia.leading {
+                match *t {
+                    Trivium::Blank          => writeln!(f)?,
+                    Trivium::Comment(ref c) => writeln!(f, "{}{}{}", open, c, close)?,
+                }
+            }
+
+            let mut line = String::new();
+            for (n, &label) in stmt.labels.iter().enumerate() {
+                if n > 0 { line.push('\n') }
+                write!(line, "{}:", self.pool.source_name(label))?;
+            }
+            if stmt.op != Slot::Value(Op::Asm(AsmOp::Empty), None) {
+                if !line.is_empty() { line.push('\n') }
+                write!(line, "{:2$}{}", "", self.child(stmt), self.opts.op_column)?;
+            }
+            if let Some(ref c) = stmt.trivia.trailing {
+                let width = line.len() - line.rfind('\n').map_or(0, |n| n + 1);
+                let pad   = match width {
+                    0 => 0,
+                    _ => self.opts.comment_column.saturating_sub(width).max(1),
+                };
+                write!(line, "{:4$}{}{}{}", "", open, c, close, pad)?;
+            }
+            if !line.is_empty() {
+                writeln!(f, "{}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Displays the operation and arguments of a statement, without labels.
+/// The statement is assumed to begin at the operation column.
+impl<'a> Display for Styled<'a, CfStmt> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let stmt   = self.node;
+        let syntax = self.opts.syntax;
+        let (op, mut name) = match stmt.op {
+            Slot::Ident    (i,    _) => (None,    self.pool.source_name(i)),
+            Slot::Resolved (_, o, _) => (Some(o), op_name(o, &self.opts)),
+            Slot::Value    (   o, _) => (Some(o), op_name(o, &self.opts)),
+        };
+
+        // Directives without an equivalent in the syntax are rewritten:
+        // Motorola spells .asciz as dc.b with a zero byte after each string,
+        // and GNU spells ds.w and larger as .zero with a scaled count, and
+        // wide strings other than .string16 and .string32 as code units.
+        let asm = match op {
+            Some(Op::Asm(a)) => Some(a),
+            _                => None,
+        };
+        let units = match asm {
+            Some(a @ AsmOp::Chars(e)) | Some(a @ AsmOp::CharsZ(e))
+                if syntax == Syntax::Gas && !gas_string(a, &stmt.args) => Some(e),
+            _ => None,
+        };
+        if let Some(e) = units {
+            name = if e.unit_size() == 2 { ".short" } else { ".long" };
+        }
+        let zero_term = matches!(asm, Some(AsmOp::CharsZ(e)) if e == StrEncoding::UTF8)
+                     && syntax == Syntax::Moto;
+        let scale     = match asm {
+            Some(a) if a.is_zero() && syntax == Syntax::Gas => a.unit_size().unwrap_or(1),
+            _                                               => 1,
+        };
+
+        for c in name.chars() {
+            f.write_char(if self.opts.upper_case { c.to_ascii_uppercase() } else { c })?;
+        }
+
+        let width = self.opts.arg_column.saturating_sub(self.opts.op_column);
+        let mut sep = " ";
+        let mut pad = width.saturating_sub(name.len()).max(1);
+        for arg in &stmt.args {
+            if let (Some(e), Some(s)) = (units, text_of(arg)) {
+                let mut bytes = vec![];
+                e.encode(s, &mut bytes);
+                if let Some(AsmOp::CharsZ(_)) = asm {
+                    e.terminate(&mut bytes);
+                }
+                for unit in bytes.chunks(e.unit_size()) {
+                    write!(f, "{:1$}", sep, pad)?;
+                    let v = unit.iter().fold(0, |v, &b| v << 8 | b as i64);
+                    fmt_int(v, &self.opts, f)?;
+                    sep = ",";
+                    pad = 1;
+                }
+                continue
+            }
+            write!(f, "{:1$}", sep, pad)?;
+            match *arg {
+                Slot::Ident    (i,    _)  => f.write_str(self.pool.source_name(i))?,
+                Slot::Resolved (i, _, _)  => f.write_str(self.pool.source_name(i))?,
+                Slot::Value    (Arg::Expr(ref e), _) if scale > 1 => {
+                    let prec = binary_op(BinaryOp::Mul).1;
+                    fmt_expr(e, prec, self.pool, &self.opts.mixed(), f)?
+                },
+                Slot::Value    (ref a, _) => self.child(a).fmt(f)?,
+            }
+            if scale > 1 {
+                write!(f, "*{}", scale)?;
+            }
+            if zero_term {
+                f.write_str(",0")?;
+            }
+            sep = ",";
+            pad = 1;
+        }
+        Ok(())
+    }
+}
+
+fn op_name(op: Op, opts: &FmtOptions) -> &'static str {
+    let a = match op {
+        Op::Inst(i) if opts.short_branch => return short_name(i),
+        Op::Inst(i)                      => return i.name(),
+        Op::Asm(a)                       => a,
+    };
+    match opts.syntax {
+        Syntax::Moto => match a {
+            AsmOp::Empty   => "",
+            AsmOp::Origin  => "org",
+            AsmOp::Skip    => "dcb.b",
+            AsmOp::Zero8   => "ds.b",
+            AsmOp::Zero16  => "ds.w",
+            AsmOp::Zero32  => "ds.l",
+            AsmOp::Zero64  => "ds.q",
+            AsmOp::Data8   => "dc.b",
+            AsmOp::Data16  => "dc.w",
+            AsmOp::Data32  => "dc.l",
+            AsmOp::Data64  => "dc.q",
+            AsmOp::Float32 => "dc.s",
+            AsmOp::Float64 => "dc.d",
+            AsmOp::Chars   (e) if e == StrEncoding::UTF8 => "dc.b",
+            AsmOp::CharsZ  (e) if e == StrEncoding::UTF8 => "dc.b",
+            AsmOp::Chars   (e) => str_directive(e, false),
+            AsmOp::CharsZ  (e) => str_directive(e, true),
+            AsmOp::Public  => "xdef",
+            AsmOp::Section => "section",
+        },
+        Syntax::Gas => match a {
+            AsmOp::Empty   => "",
+            AsmOp::Origin  => ".org",
+            AsmOp::Skip    => ".skip",
+            AsmOp::Zero8   => ".zero",
+            AsmOp::Zero16  => ".zero",
+            AsmOp::Zero32  => ".zero",
+            AsmOp::Zero64  => ".zero",
+            AsmOp::Data8   => ".byte",
+            AsmOp::Data16  => ".short",
+            AsmOp::Data32  => ".long",
+            AsmOp::Data64  => ".quad",
+            AsmOp::Float32 => ".float",
+            AsmOp::Float64 => ".double",
+            AsmOp::Chars   (_) => ".ascii",
+            AsmOp::CharsZ  (e) => match e.encoding {
+                CharEncoding::Utf8  => ".asciz",
+                CharEncoding::Utf16 => ".string16",
+                CharEncoding::Utf32 => ".string32",
+            },
+            AsmOp::Public  => ".globl",
+            AsmOp::Section => ".section",
+        },
+    }
+}
+
+// Returns the mnemonic of an 8-bit branch spelled with `.s`, or the usual
+// mnemonic of any other instruction.
+fn short_name(inst: Instructions) -> &'static str {
+    ALIASES.iter()
+        .find(|a| a.1 == inst as usize && a.0.ends_with(".s"))
+        .map_or(inst.name(), |a| a.0)
+}
+
+// Determines whether GNU syntax has a string directive for `op` with `args`.
+// Wide strings have only .string16 and .string32, which are zero-terminated
+// in the target byte order, and which widen each byte of their text.
+fn gas_string(op: AsmOp, args: &[Slot<Arg>]) -> bool {
+    match op {
+        AsmOp::Chars  (e) => e.encoding == CharEncoding::Utf8,
+        AsmOp::CharsZ (e) => e.encoding == CharEncoding::Utf8 || (e.order == BE && args.iter().all(|a| {
+            text_of(a).is_none_or(|s| s.chars().all(|c| c <= '\u{FF}'))
+        })),
+        _                 => false,
+    }
+}
+
+// Returns the text of a quoted literal argument.
+fn text_of(arg: &Slot<Arg>) -> Option<&str> {
+    match *arg {
+        Slot::Value(Arg::Expr(Expr::LitStr (ref s, _)), _) => Some(s),
+        Slot::Value(Arg::Expr(Expr::LitChar(ref s, _)), _) => Some(s),
+        _                                                  => None,
+    }
+}
+
+const UTF16BE: StrEncoding = StrEncoding::new(CharEncoding::Utf16, BE);
+const UTF16LE: StrEncoding = StrEncoding::new(CharEncoding::Utf16, LE);
+const UTF32BE: StrEncoding = StrEncoding::new(CharEncoding::Utf32, BE);
+const UTF32LE: StrEncoding = StrEncoding::new(CharEncoding::Utf32, LE);
+
+// String directives by encoding: unterminated and zero-terminated
+static STR_DIRECTIVES: [(StrEncoding, &str, &str); 5] = [
+    (StrEncoding::UTF8, ".ascii",     ".asciz"    ),
+    (UTF16BE,           ".ascii16be", ".asciz16be"),
+    (UTF16LE,           ".ascii16le", ".asciz16le"),
+    (UTF32BE,           ".ascii32be", ".asciz32be"),
+    (UTF32LE,           ".ascii32le", ".asciz32le"),
+];
+
+/// Returns the name of the directive that emits strings in encoding `enc`,
+/// each followed by a zero code unit if `terminated`.
+pub fn str_directive(enc: StrEncoding, terminated: bool) -> &'static str {
+    // UTF-8 directives serve any byte order
+    let enc = if enc.encoding == CharEncoding::Utf8 { StrEncoding::UTF8 } else { enc };
+    STR_DIRECTIVES.iter()
+        .find(|d| d.0 == enc)
+        .map_or(".ascii", |d| if terminated { d.2 } else { d.1 })
+}
+
+/// Returns the string directive with the given lowercase name, if any.  The
+/// GNU names `.string16` and `.string32` denote big-endian strings, as on the
+/// target.
+pub fn str_directive_with_name(name: &str) -> Option<AsmOp> {
+    match name {
+        ".string"   => return Some(AsmOp::CharsZ(StrEncoding::UTF8)),
+        ".string16" => return Some(AsmOp::CharsZ(UTF16BE)),
+        ".string32" => return Some(AsmOp::CharsZ(UTF32BE)),
+        _           => (),
+    }
+    STR_DIRECTIVES.iter().find_map(|&(e, n, z)| {
+        if name == n { return Some(AsmOp::Chars(e)) }
+        if name == z { return Some(AsmOp::CharsZ(e)) }
+        None
+    })
+}
+
+impl<'a> Display for Styled<'a, Arg> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let e   = |e| self.value(e);
+        let a   = |r| self.addr_reg(r);
+        let pc  = self.reg("pc");
+        let gas = self.opts.syntax == Syntax::Gas;
+        match *self.node {
+            Arg::DataReg       (r)                   => write!(f, "{}", self.reg(r.name())),
+            Arg::AddrReg       (r)                   => write!(f, "{}", a(r)),
+            Arg::AddrRegInd    (r)                   => write!(f, "({})", a(r)),
+            Arg::AddrRegIndInc (r)                   => write!(f, "({})+", a(r)),
+            Arg::AddrRegIndDec (r)                   => write!(f, "-({})", a(r)),
+            Arg::AddrRegDisp   (r, ref d) if gas     => write!(f, "{}({})", e(d), a(r)),
+            Arg::AddrRegDisp   (r, ref d)            => write!(f, "({},{})", e(d), a(r)),
+            Arg::AddrRegIdx    (r, ref d, ref x) if gas
+                                                     => write!(f, "{}({},{})", e(d), a(r), self.child(x)),
+            Arg::AddrRegIdx    (r, ref d, ref x)     => write!(f, "({},{},{})", e(d), a(r), self.child(x)),
+            Arg::PcDisp        (_, ref d) if gas     => write!(f, "{}({})", e(d), pc),
+            Arg::PcDisp        (_, ref d)            => write!(f, "({},{})", e(d), pc),
+            Arg::PcIdx         (_, ref d, ref x) if gas
+                                                     => write!(f, "{}({},{})", e(d), pc, self.child(x)),
+            Arg::PcIdx         (_, ref d, ref x)     => write!(f, "({},{},{})", e(d), pc, self.child(x)),
+            Arg::AbsShort      (ref v)               => write!(f, "({}).w", e(v)),
+            Arg::AbsLong       (ref v)               => write!(f, "({}).l", e(v)),
+            Arg::Imm           (ref v)               => write!(f, "#{}", self.child(v)),
+            Arg::Expr          (ref v)               => write!(f, "{}", e(v)),
+            Arg::DataRegPair   (p)                   => write!(f, "{}:{}", self.reg(p.0.name()), self.reg(p.1.name())),
+            Arg::RegList       (l)                   => {
+                // Maximal runs of adjacent registers as ranges: d0-d3/a6
+                let mut sep = "";
+                for (lo, hi) in l.runs() {
+                    write!(f, "{}{}", sep, self.list_reg(lo))?;
+                    if hi != lo {
+                        write!(f, "-{}", self.list_reg(hi))?;
+                    }
+                    sep = "/";
+                }
+                Ok(())
+            },
+            Arg::CcrReg        (_)                   => write!(f, "{}", self.reg("ccr")),
+            Arg::SrReg         (_)                   => write!(f, "{}", self.reg("sr")),
+            Arg::FpReg         (r)                   => write!(f, "{}", self.reg(r.name())),
+            Arg::Cache         (c)                   => write!(f, "{}", self.reg(match c {
+                Cache::Inst => "ic",
+                Cache::Data => "dc",
+                Cache::Both => "bc",
+            })),
+        }
+    }
+}
+
+impl<'a> Display for Styled<'a, Index> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}.l", self.index_reg(self.node.reg))?;
+        if self.node.scale != Expr::LitInt(1, None) {
+            write!(f, "*{}", self.value(&self.node.scale))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Display for Styled<'a, Expr> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        fmt_expr(self.node, 0, self.pool, &self.opts, f)
+    }
+}
+
+// Formats an expression, parenthesized if its precedence is below `prec`.
+fn fmt_expr(expr: &Expr, prec: u8, pool: &IdentPool, opts: &FmtOptions, f: &mut Formatter) -> fmt::Result {
+    match *expr {
+        Expr::Ident   (i,     _) => f.write_str(pool.source_name(i)),
+        Expr::Resolved(i, _,  _) => f.write_str(pool.source_name(i)),
+        Expr::LitInt  (n,     _) => fmt_int(n, opts, f),
+        Expr::LitFloat(x,     _) => write!(f, "{}", x),
+        Expr::LitStr  (ref s, _) => fmt_quoted(s, '"',  f),
+        Expr::LitChar (ref s, _) => fmt_quoted(s, '\'', f),
+        Expr::Unary   (ref u   ) => {
+            f.write_str(match u.op {
+                UnaryOp::Pos    => "+",
+                UnaryOp::Neg    => "-",
+                UnaryOp::BitNot => "~",
+                UnaryOp::LogNot => "!",
+            })?;
+            fmt_slot(&u.expr, UNARY_PREC, pool, opts, f)
+        },
+        Expr::Binary  (ref b   ) => {
+            let (op, p) = binary_op(b.op);
+            if p < prec { f.write_char('(')?; }
+            fmt_slot(&b.lhs, p, pool, opts, f)?;
+            f.write_str(op)?;
+            fmt_slot(&b.rhs, p + 1, pool, opts, f)?;
+            if p < prec { f.write_char(')')?; }
+            Ok(())
+        },
+    }
+}
+
+fn fmt_slot(slot: &Slot<Expr>, prec: u8, pool: &IdentPool, opts: &FmtOptions, f: &mut Formatter) -> fmt::Result {
+    match *slot {
+        Slot::Ident    (i,        _) => f.write_str(pool.source_name(i)),
+        Slot::Resolved (i, _,     _) => f.write_str(pool.source_name(i)),
+        Slot::Value    (   ref e, _) => fmt_expr(e, prec, pool, opts, f),
+    }
+}
+
+const UNARY_PREC: u8 = 11;
+
+// Operator text and precedence, as in the expression parser
+fn binary_op(op: BinaryOp) -> (&'static str, u8) {
+    match op {
+        BinaryOp::Mul    => ("*",   10),
+        BinaryOp::Div    => ("/",   10),
+        BinaryOp::Mod    => ("%",   10),
+        BinaryOp::Add    => ("+",    9),
+        BinaryOp::Sub    => ("-",    9),
+        BinaryOp::Shl    => ("<<",   8),
+        BinaryOp::Shr    => (">>",   8),
+        BinaryOp::ShrS   => ("->>",  8),
+        BinaryOp::Lt     => ("<",    7),
+        BinaryOp::LtEq   => ("<=",   7),
+        BinaryOp::Gt     => (">",    7),
+        BinaryOp::GtEq   => (">=",   7),
+        BinaryOp::Eq     => ("==",   6),
+        BinaryOp::NotEq  => ("!=",   6),
+        BinaryOp::BitAnd => ("&",    5),
+        BinaryOp::BitXor => ("^",    4),
+        BinaryOp::BitOr  => ("|",    3),
+        BinaryOp::LogAnd => ("&&",   2),
+        BinaryOp::LogOr  => ("||",   1),
+    }
+}
+
+// Integer in the radix of the options: by default, small values in decimal
+// and others in hexadecimal
+fn fmt_int(n: i64, opts: &FmtOptions, f: &mut Formatter) -> fmt::Result {
+    let (sign, mag) = match (opts.radix, n) {
+        (Radix::Decimal, _) | (Radix::Mixed, -9..=9)
+                            => return write!(f, "{}", n),
+        (_, i64::MIN..=-1)  => ("-", n.unsigned_abs()),
+        _                   => ("",  n as u64),
+    };
+    match opts.syntax {
+        Syntax::Moto => write!(f, "{}${:X}",  sign, mag),
+        Syntax::Gas  => write!(f, "{}0x{:X}", sign, mag),
+    }
+}
+
+fn fmt_quoted(s: &str, quote: char, f: &mut Formatter) -> fmt::Result {
+    f.write_char(quote)?;
+    for c in s.chars() {
+        match c {
+            '\0'                    => f.write_str("\\0")?,
+            '\x08'                  => f.write_str("\\b")?,
+            '\t'                    => f.write_str("\\t")?,
+            '\n'                    => f.write_str("\\n")?,
+            '\x0C'                  => f.write_str("\\f")?,
+            '\r'                    => f.write_str("\\r")?,
+            '\\'                    => f.write_str("\\\\")?,
+            _ if c == quote         => { f.write_char('\\')?; f.write_char(c)? },
+            '\x20'..='\x7E'         => f.write_char(c)?,
+            '\u{80}'..='\u{FF}'
+                | '\0'..='\x1F'
+                | '\x7F'            => write!(f, "\\x{:02X}", c as u32)?,
+            _                       => f.write_char(c)?,
+        }
+    }
+    f.write_char(quote)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::SymbolTable;
+    use super::*;
+
+    fn round_trip(src: &str) -> String {
+        let mut pool  = IdentPool::new();
+        let mut syms  = SymbolTable::new();
+        let     block = parse_moto(src, &mut pool, &mut syms).unwrap();
+        moto(&block, &pool).to_string()
+    }
+
+    #[test]
+    pub fn fmt_moto_block() {
+        let src = "start:\n\
+                   \x20       move.l (8,a0),d0\n\
+                   \x20       lea.l (start+2*(x-1),pc,d1.l*4),a1\n\
+                   \x20       movem.l d0-d2/fp,-(sp)\n\
+                   \x20       add.l #-$1234,(x).l\n\
+                   \x20       dc.b \"a\\\"b\\n\",'c',$FF\n";
+        assert_eq!( round_trip(src), src );
+        assert_eq!( round_trip(&round_trip(src)), src );
+    }
+
+    #[test]
+    pub fn fmt_directives() {
+        let mut pool  = IdentPool::new();
+        let mut syms  = SymbolTable::new();
+        let     block = parse_moto(
+            " .section .data\n .globl x\n .asciz \"a\",\"b\"\n ds.w n+1\n .quad $100\n",
+            &mut pool, &mut syms
+        ).unwrap();
+
+        assert_eq!(
+            gas(&block, &pool).to_string(),
+            "        .section .data\n\
+             \x20       .globl x\n\
+             \x20       .asciz \"a\",\"b\"\n\
+             \x20       .zero (n+1)*2\n\
+             \x20       .quad 0x100\n"
+        );
+        assert_eq!(
+            moto(&block, &pool).to_string(),
+            "        section .data\n\
+             \x20       xdef x\n\
+             \x20       dc.b \"a\",0,\"b\",0\n\
+             \x20       ds.w n+1\n\
+             \x20       dc.q $100\n"
+        );
+    }
+
+    #[test]
+    pub fn fmt_gas_operands() {
+        let src = "start:\n\
+                   \x20       move.l 8(%a0),%d0\n\
+                   \x20       lea.l start+2(%pc,%d1.l*4),%a1\n\
+                   \x20       movem.l %d0-%d2/%fp,-(%sp)\n\
+                   \x20       rems.l (%a0)+,%d1:%d2\n\
+                   \x20       move.w %sr,%d0\n\
+                   \x20       fmove.d %fp0,(x).w\n\
+                   \x20       cpushl %bc,(%a0)\n";
+        let mut pool  = IdentPool::new();
+        let mut syms  = SymbolTable::new();
+        let     block = parse_moto(src, &mut pool, &mut syms).unwrap();
+        assert_eq!( gas(&block, &pool).to_string(), src );
+        assert_eq!(
+            moto(&block.stmts[1], &pool).to_string(),
+            "lea.l (start+2,pc,d1.l*4),a1"
+        );
+    }
+
+    #[test]
+    pub fn fmt_options() {
+        let mut pool  = IdentPool::new();
+        let mut syms  = SymbolTable::new();
+        let     block = parse_moto(
+            "loop:\n move.l #255,(8,fp)\n movem.l d0/a6-a7,-(sp)\n bne.b loop\n dc.w 255\n",
+            &mut pool, &mut syms
+        ).unwrap();
+
+        let opts = FmtOptions {
+            reg_aliases:  false,
+            radix:        Radix::Decimal,
+            upper_case:   true,
+            op_column:    4,
+            arg_column:   12,
+            short_branch: true,
+            ..FmtOptions::new(Syntax::Moto)
+        };
+        assert_eq!(
+            styled(&block, &pool, opts).to_string(),
+            "loop:\n\
+             \x20   MOVE.L  #255,(8,a6)\n\
+             \x20   MOVEM.L d0/a6-a7,-(a7)\n\
+             \x20   BNE.S   loop\n\
+             \x20   DC.W    $FF\n"
+        );
+
+        let opts = FmtOptions { radix: Radix::Hex, ..FmtOptions::new(Syntax::Gas) };
+        assert_eq!(
+            styled(&block.stmts[0], &pool, opts).to_string(),
+            "move.l #0xFF,8(%fp)"
+        );
+    }
+
+    #[test]
+    pub fn fmt_trivia() {
+        let src =
+            "* Count to ten\n\
+             \n\
+             start   moveq   #0,d0           ; counter\n\
+             loop:                           ; top of loop\n\
+             \x20       addq.l  #1,d0\n\
+             \x20       cmpi.l  #10,d0\n\
+             \x20       bne.s   loop\n\
+             ; done\n";
+        let mut pool  = IdentPool::new();
+        let mut syms  = SymbolTable::new();
+        let     block = parse_moto(src, &mut pool, &mut syms).unwrap();
+
+        let opts = FmtOptions { arg_column: 16, comment_column: 32, ..FmtOptions::new(Syntax::Moto) };
+        let text = styled(&block, &pool, opts).to_string();
+        assert_eq!(
+            text,
+            "; Count to ten\n\
+             \n\
+             start:\n\
+             \x20       moveq.l #0,d0           ; counter\n\
+             loop:                           ; top of loop\n\
+             \x20       addq.l  #1,d0\n\
+             \x20       cmpi.l  #$A,d0\n\
+             \x20       bne.b   loop\n\
+             ; done\n"
+        );
+
+        // Formatting is stable
+        let mut pool2 = IdentPool::new();
+        let     again = parse_moto(&text, &mut pool2, &mut SymbolTable::new()).unwrap();
+        assert_eq!( styled(&again, &pool2, opts).to_string(), text );
+
+        // Comments in GNU syntax parse back
+        let opts = FmtOptions { comment_column: 32, ..FmtOptions::new(Syntax::Gas) };
+        let text = styled(&block, &pool, opts).to_string();
+        let mut pool2 = IdentPool::new();
+        let     again = parse_moto(&text, &mut pool2, &mut SymbolTable::new()).unwrap();
+        assert_eq!( moto(&again, &pool2).to_string(), moto(&block, &pool).to_string() );
+        assert_eq!( styled(&again, &pool2, opts).to_string(), text );
+
+        assert_eq!(
+            gas(&block.stmts[0], &pool).to_string(),
+            "moveq.l #0,%d0"
+        );
+        assert_eq!(
+            gas(&CfBlock { stmts: block.stmts.into_iter().take(1).collect() }, &pool).to_string(),
+            "/* Count to ten */\n\
+             \n\
+             start:\n\
+             \x20       moveq.l #0,%d0 /* counter */\n"
+        );
+    }
+}
@@ -14,24 +14,253 @@
 // You should have received a copy of the GNU General Public License
 // along with cfda.  If not, see <http://www.gnu.org/licenses/>.
 
+use crate::ast::{Expr, Slot};
+use super::{Arg, CfStmt};
+
 /// ColdFire instruction specification.
 #[derive(Clone, Copy, Debug)]
 pub struct Instruction {
     /// Preferred mnemonic.
     pub name: &'static str,
+
+    /// Effect on control flow.
+    pub flow: Flow,
+
+    /// Condition tested, for a conditional branch or `Scc` instruction.
+    pub cond: Option<Condition>,
+}
+
+impl Instruction {
+    /// Returns whether execution never continues to the next instruction:
+    /// whether the instruction is an unconditional branch, an indirect
+    /// jump, a return, or a halt.
+    pub fn is_terminator(&self) -> bool {
+        matches!(self.flow, Flow::Branch | Flow::IndirectJump | Flow::Return | Flow::Halt)
+    }
+
+    /// Returns the effect of `stmt`, a statement of this instruction, on
+    /// control flow.  This is the flow of the instruction, except that a
+    /// `jmp` to an absolute or PC-relative address, whose target is known
+    /// without execution, is an unconditional branch.
+    pub fn flow_of(&self, stmt: &CfStmt) -> Flow {
+        match self.flow {
+            Flow::IndirectJump if static_target(stmt).is_some() => Flow::Branch,
+            flow                                                => flow,
+        }
+    }
+
+    /// Returns the target address of `stmt`, a statement of this
+    /// instruction, if the statement is a branch, call, or jump whose
+    /// target is an address known without execution: a branch displacement,
+    /// an absolute address, or a PC-relative address.
+    pub fn branch_target(&self, stmt: &CfStmt) -> Option<u32> {
+        match self.flow_of(stmt) {
+            Flow::Branch | Flow::CondBranch | Flow::Call => static_target(stmt),
+            _                                            => None,
+        }
+    }
+
+    /// Returns the operation size indicated by the mnemonic suffix, if any.
+    pub fn size(&self) -> Option<Size> {
+        match self.name.rsplit_once('.')?.1 {
+            "b" => Some(Size::Byte),
+            "w" => Some(Size::Word),
+            "l" => Some(Size::Long),
+            "s" => Some(Size::Single),
+            "d" => Some(Size::Double),
+            _   => None,
+        }
+    }
+}
+
+// Returns the address of the first argument of `stmt`, if it is a branch
+// displacement, an absolute address, or a PC-relative address.
+fn static_target(stmt: &CfStmt) -> Option<u32> {
+    match *stmt.args.first()? {
+        Slot::Value(Arg::Expr    (   Expr::LitInt(t, _)), _)
+      | Slot::Value(Arg::AbsShort(   Expr::LitInt(t, _)), _)
+      | Slot::Value(Arg::AbsLong (   Expr::LitInt(t, _)), _)
+      | Slot::Value(Arg::PcDisp  (_, Expr::LitInt(t, _)), _) => Some(t as u32),
+        _                                                    => None,
+    }
+}
+
+/// Effects of ColdFire instructions on control flow.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Flow {
+    /// Continues to the next instruction.
+    Fallthrough,
+
+    /// Continues to the target: `bra`.
+    Branch,
+
+    /// Continues to the target if a condition holds, or else to the next
+    /// instruction: `bcc`.
+    CondBranch,
+
+    /// Continues to the target, which may return to the next instruction:
+    /// `bsr`, `jsr`.
+    Call,
+
+    /// Returns from a subroutine or exception: `rts`, `rte`.
+    Return,
+
+    /// Continues to the address of the effective address operand, which
+    /// may be known only at run time: `jmp`.  See `Instruction::flow_of`
+    /// for a `jmp` whose target is known.
+    IndirectJump,
+
+    /// Raises an exception whose handler may return to the next
+    /// instruction: `trap`.
+    Trap,
+
+    /// Halts the processor, or raises an exception that does not return:
+    /// `halt`, `illegal`.
+    Halt,
+
+    /// Stops the processor until an interrupt, whose handler may return to
+    /// the next instruction: `stop`.
+    Stop,
+}
+
+/// ColdFire condition codes, in the order of their encodings.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Condition {
+    /// True.
+    T,
+    /// False.
+    F,
+    /// High (unsigned greater than).
+    Hi,
+    /// Low or same (unsigned less than or equal).
+    Ls,
+    /// High or same (unsigned greater than or equal); carry clear.
+    Hs,
+    /// Low (unsigned less than); carry set.
+    Lo,
+    /// Not equal.
+    Ne,
+    /// Equal.
+    Eq,
+    /// Overflow clear.
+    Vc,
+    /// Overflow set.
+    Vs,
+    /// Plus.
+    Pl,
+    /// Minus.
+    Mi,
+    /// Greater than or equal (signed).
+    Ge,
+    /// Less than (signed).
+    Lt,
+    /// Greater than (signed).
+    Gt,
+    /// Less than or equal (signed).
+    Le,
+}
+
+/// ColdFire operation sizes.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Size {
+    /// 8 bits.
+    Byte,
+
+    /// 16 bits.
+    Word,
+
+    /// 32 bits.
+    Long,
+
+    /// 32-bit single-precision floating-point.
+    Single,
+
+    /// 64-bit double-precision floating-point.
+    Double,
+}
+
+impl Size {
+    /// Returns the number of bytes in a value of this size.
+    #[inline]
+    pub fn bytes(self) -> u32 {
+        match self {
+            Size::Byte   => 1,
+            Size::Word   => 2,
+            Size::Long   => 4,
+            Size::Single => 4,
+            Size::Double => 8,
+        }
+    }
+
+    /// Returns whether values of this size are floating-point.
+    #[inline]
+    pub fn is_float(self) -> bool {
+        matches!(self, Size::Single | Size::Double)
+    }
 }
 
 macro_rules! instructions {
-    { $( $id:ident: $name:expr; )* } =>
+    { $( $id:ident: $name:expr $(=> $flow:ident $(($cond:ident))?)?; )* } =>
     {
         pub static INSTRUCTIONS: [Instruction; count!($($id)*)] = [$(
             Instruction {
-                name: $name
+                name: $name,
+                flow: flow!($($flow)?),
+                cond: cond!($($($cond)?)?),
             }
         ),*];
 
+        /// Identifiers of ColdFire instructions, in `INSTRUCTIONS` order.
+        #[allow(clippy::upper_case_acronyms)]
+        #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
         pub enum Instructions { $($id),* }
+
+        static INSTRUCTION_IDS: [Instructions; count!($($id)*)] = [$(
+            Instructions::$id
+        ),*];
+    }
+}
+
+impl Instructions {
+    /// Returns the instruction with the given index in `INSTRUCTIONS`, if any.
+    #[inline]
+    pub fn with_index(index: usize) -> Option<Self> {
+        INSTRUCTION_IDS.get(index).copied()
     }
+
+    /// Returns the instruction with the given mnemonic or alias, if any.
+    /// Mnemonics are case-insensitive.
+    pub fn with_name(name: &str) -> Option<Self> {
+        let index = INSTRUCTIONS.iter()
+            .position(|i| i.name.eq_ignore_ascii_case(name))
+            .or_else(|| ALIASES.iter()
+                .find(|a| a.0.eq_ignore_ascii_case(name))
+                .map(|a| a.1)
+            )?;
+        Self::with_index(index)
+    }
+
+    /// Returns the specification of the instruction.
+    #[inline]
+    pub fn info(self) -> &'static Instruction {
+        &INSTRUCTIONS[self as usize]
+    }
+
+    /// Returns the preferred mnemonic of the instruction.
+    #[inline]
+    pub fn name(self) -> &'static str {
+        self.info().name
+    }
+}
+
+macro_rules! flow {
+    {          } => { Flow::Fallthrough };
+    { $f:ident } => { Flow::$f };
+}
+
+macro_rules! cond {
+    {          } => { None };
+    { $c:ident } => { Some(Condition::$c) };
 }
 
 macro_rules! aliases {
@@ -45,6 +274,9 @@ macro_rules! aliases {
 
 // ColdFire instructions table
 // Source: ColdFire Family Programmer’s Reference Manual, Rev. 3
+//
+// An entry `ID: "name" => Flow(Cond);` gives the effect on control flow and
+// the condition tested.  An entry without `=>` falls through untested.
 
 instructions! {
     // Integer Instructions
@@ -90,11 +322,11 @@ instructions! {
     EXTL:     "ext.l"     ;
     EXTBL:    "extb.l"    ;
     FF1L:     "ff1.l"     ;
-    HALT:     "halt"      ;
-    ILLEGAL:  "illegal"   ;
+    HALT:     "halt"      => Halt;
+    ILLEGAL:  "illegal"   => Halt;
     INTOUCH:  "intouch"   ;
-    JMP:      "jmp"       ;
-    JSR:      "jsr"       ;
+    JMP:      "jmp"       => IndirectJump;
+    JSR:      "jsr"       => Call;
     LEAL:     "lea.l"     ;
     LINKW:    "link.w"    ;
     LSLL:     "lsl.l"     ;
@@ -126,8 +358,8 @@ instructions! {
     PULSE:    "pulse"     ;
     REMSL:    "rems.l"    ;
     REMUL:    "remu.l"    ;
-    RTE:      "rte"       ;
-    RTS:      "rts"       ;
+    RTE:      "rte"       => Return;
+    RTS:      "rts"       => Return;
     SATSL:    "sats.l"    ;
     SUBL:     "sub.l"     ;
     SUBAL:    "suba.l"    ;
@@ -135,13 +367,13 @@ instructions! {
     SUBQL:    "subq.l"    ;
     SUBXL:    "subx.l"    ;
     SWAPW:    "swap.w"    ;
-    STOP:     "stop"      ;
+    STOP:     "stop"      => Stop;
     STRLDSR:  "strldsr"   ;
     TASB:     "tas.b"     ;
     TPF:      "tpf"       ;
     TPFW:     "tpf.w"     ;
     TPFL:     "tpf.l"     ;
-    TRAP:     "trap"      ;
+    TRAP:     "trap"      => Trap;
     TSTB:     "tst.b"     ;
     TSTW:     "tst.w"     ;
     TSTL:     "tst.l"     ;
@@ -152,83 +384,87 @@ instructions! {
     WDEBUGL:  "wdebug.l"  ;
 
     // Branch Instructions - Unconditional
-    BRAB:     "bra.b"     ;
-    BRAW:     "bra.w"     ;
-    BRAL:     "bra.l"     ;
-    BSRB:     "bsr.b"     ;
-    BSRW:     "bsr.w"     ;
-    BSRL:     "bsr.l"     ;
+    BRAB:     "bra.b"     => Branch;
+    BRAW:     "bra.w"     => Branch;
+    BRAL:     "bra.l"     => Branch;
+    BSRB:     "bsr.b"     => Call;
+    BSRW:     "bsr.w"     => Call;
+    BSRL:     "bsr.l"     => Call;
     // Branch Instructions - Equative
-    BEQB:     "beq.b"     ;
-    BEQW:     "beq.w"     ;
-    BEQL:     "beq.l"     ;
-    BNEB:     "bne.b"     ;
-    BNEW:     "bne.w"     ;
-    BNEL:     "bne.l"     ;
+    BEQB:     "beq.b"     => CondBranch(Eq);
+    BEQW:     "beq.w"     => CondBranch(Eq);
+    BEQL:     "beq.l"     => CondBranch(Eq);
+    BNEB:     "bne.b"     => CondBranch(Ne);
+    BNEW:     "bne.w"     => CondBranch(Ne);
+    BNEL:     "bne.l"     => CondBranch(Ne);
     // Branch Instructions - Unsigned Relative
-    BLOB:     "blo.b"     ; // bcs.b
-    BLOW:     "blo.w"     ; // bcs.w
-    BLOL:     "blo.l"     ; // bcs.l
-    BLSB:     "bls.b"     ;
-    BLSW:     "bls.w"     ;
-    BLSL:     "bls.l"     ;
-    BHIB:     "bhi.b"     ;
-    BHIW:     "bhi.w"     ;
-    BHIL:     "bhi.l"     ;
-    BHSB:     "bhs.b"     ; // bcc.b
-    BHSW:     "bhs.w"     ; // bcc.w
-    BHSL:     "bhs.l"     ; // bcc.l
+    BLOB:     "blo.b"     => CondBranch(Lo); // bcs.b
+    BLOW:     "blo.w"     => CondBranch(Lo); // bcs.w
+    BLOL:     "blo.l"     => CondBranch(Lo); // bcs.l
+    BLSB:     "bls.b"     => CondBranch(Ls);
+    BLSW:     "bls.w"     => CondBranch(Ls);
+    BLSL:     "bls.l"     => CondBranch(Ls);
+    BHIB:     "bhi.b"     => CondBranch(Hi);
+    BHIW:     "bhi.w"     => CondBranch(Hi);
+    BHIL:     "bhi.l"     => CondBranch(Hi);
+    BHSB:     "bhs.b"     => CondBranch(Hs); // bcc.b
+    BHSW:     "bhs.w"     => CondBranch(Hs); // bcc.w
+    BHSL:     "bhs.l"     => CondBranch(Hs); // bcc.l
     // Branch Instructions - Signed Relative
-    BLTB:     "blt.b"     ;
-    BLTW:     "blt.w"     ;
-    BLTL:     "blt.l"     ;
-    BLEB:     "ble.b"     ;
-    BLEW:     "ble.w"     ;
-    BLEL:     "ble.l"     ;
-    BGEB:     "bge.b"     ;
-    BGEW:     "bge.w"     ;
-    BGEL:     "bge.l"     ;
-    BGTB:     "bgt.b"     ;
-    BGTW:     "bgt.w"     ;
-    BGTL:     "bgt.l"     ;
+    BLTB:     "blt.b"     => CondBranch(Lt);
+    BLTW:     "blt.w"     => CondBranch(Lt);
+    BLTL:     "blt.l"     => CondBranch(Lt);
+    BLEB:     "ble.b"     => CondBranch(Le);
+    BLEW:     "ble.w"     => CondBranch(Le);
+    BLEL:     "ble.l"     => CondBranch(Le);
+    BGEB:     "bge.b"     => CondBranch(Ge);
+    BGEW:     "bge.w"     => CondBranch(Ge);
+    BGEL:     "bge.l"     => CondBranch(Ge);
+    BGTB:     "bgt.b"     => CondBranch(Gt);
+    BGTW:     "bgt.w"     => CondBranch(Gt);
+    BGTL:     "bgt.l"     => CondBranch(Gt);
     // Branch Instructions - Flags
-    BPLB:     "bpl.b"     ;
-    BPLW:     "bpl.w"     ;
-    BPLL:     "bpl.l"     ;
-    BMIB:     "bmi.b"     ;
-    BMIW:     "bmi.w"     ;
-    BMIL:     "bmi.l"     ;
-    BVCB:     "bvc.b"     ;
-    BVCW:     "bvc.w"     ;
-    BVCL:     "bvc.l"     ;
-    BVSB:     "bvs.b"     ;
-    BVSW:     "bvs.w"     ;
-    BVSL:     "bvs.l"     ;
+    BPLB:     "bpl.b"     => CondBranch(Pl);
+    BPLW:     "bpl.w"     => CondBranch(Pl);
+    BPLL:     "bpl.l"     => CondBranch(Pl);
+    BMIB:     "bmi.b"     => CondBranch(Mi);
+    BMIW:     "bmi.w"     => CondBranch(Mi);
+    BMIL:     "bmi.l"     => CondBranch(Mi);
+    BVCB:     "bvc.b"     => CondBranch(Vc);
+    BVCW:     "bvc.w"     => CondBranch(Vc);
+    BVCL:     "bvc.l"     => CondBranch(Vc);
+    BVSB:     "bvs.b"     => CondBranch(Vs);
+    BVSW:     "bvs.w"     => CondBranch(Vs);
+    BVSL:     "bvs.l"     => CondBranch(Vs);
 
     // Set to Condition - Unconditional
-    STB:      "st.b"      ;
-    SFB:      "sf.b"      ;
+    STB:      "st.b"      => Fallthrough(T);
+    SFB:      "sf.b"      => Fallthrough(F);
     // Set to Condition - Equative
-    SEQB:     "seq.b"     ;
-    SNEB:     "sne.b"     ;
+    SEQB:     "seq.b"     => Fallthrough(Eq);
+    SNEB:     "sne.b"     => Fallthrough(Ne);
     // Set to Condition - Unsigned Relative
-    SLOB:     "slo.b"     ; // scs.b
-    SLSB:     "sls.b"     ;
-    SHIB:     "shi.b"     ;
-    SHSB:     "shs.b"     ; // scc.b
+    SLOB:     "slo.b"     => Fallthrough(Lo); // scs.b
+    SLSB:     "sls.b"     => Fallthrough(Ls);
+    SHIB:     "shi.b"     => Fallthrough(Hi);
+    SHSB:     "shs.b"     => Fallthrough(Hs); // scc.b
     // Set to Condition - Signed Relative
-    SLTB:     "slt.b"     ;
-    SLEB:     "sle.b"     ;
-    SGEB:     "sge.b"     ;
-    SGTB:     "sgt.b"     ;
+    SLTB:     "slt.b"     => Fallthrough(Lt);
+    SLEB:     "sle.b"     => Fallthrough(Le);
+    SGEB:     "sge.b"     => Fallthrough(Ge);
+    SGTB:     "sgt.b"     => Fallthrough(Gt);
     // Set to Condition - Flags
-    SPLB:     "spl.b"     ;
-    SMIB:     "smi.b"     ;
-    SVCB:     "svc.b"     ;
-    SVSB:     "svs.b"     ;
+    SPLB:     "spl.b"     => Fallthrough(Pl);
+    SMIB:     "smi.b"     => Fallthrough(Mi);
+    SVCB:     "svc.b"     => Fallthrough(Vc);
+    SVSB:     "svs.b"     => Fallthrough(Vs);
+
+    // Floating-Point Instructions
+    FMOVES:   "fmove.s"   ;
+    FMOVED:   "fmove.d"   ;
 
     // TODO: Multiply-Accumulate Instructions
-    // TODO: Floating-Point Instructions
+    // TODO: Remaining Floating-Point Instructions
 }
 
 aliases! {
@@ -270,3 +506,69 @@ aliases! {
     "bz.l"  => BEQL;
 }
 
+#[cfg(test)]
+mod tests {
+    use crate::ast::Stmt;
+    use super::*;
+    use super::super::{AddrReg, Op, PcReg};
+
+    fn stmt(inst: Instructions, arg: Arg) -> CfStmt {
+        Stmt::new(vec![], Slot::Value(Op::Inst(inst), None), vec![Slot::Value(arg, None)])
+    }
+
+    #[test]
+    pub fn flow() {
+        use Instructions::*;
+        assert_eq!( MOVEL.info().flow, Flow::Fallthrough );
+        assert_eq!( BRAB .info().flow, Flow::Branch );
+        assert_eq!( BNEW .info().flow, Flow::CondBranch );
+        assert_eq!( BSRL .info().flow, Flow::Call );
+        assert_eq!( JSR  .info().flow, Flow::Call );
+        assert_eq!( RTE  .info().flow, Flow::Return );
+        assert_eq!( JMP  .info().flow, Flow::IndirectJump );
+        assert_eq!( TRAP .info().flow, Flow::Trap );
+        assert_eq!( HALT .info().flow, Flow::Halt );
+        assert_eq!( STOP .info().flow, Flow::Stop );
+
+        let terminators: Vec<_> = INSTRUCTIONS.iter()
+            .filter(|i| i.is_terminator())
+            .map(|i| i.name)
+            .collect();
+        assert_eq!( terminators, ["halt", "illegal", "jmp", "rte", "rts", "bra.b", "bra.w", "bra.l"] );
+
+        // A jmp to a known address is a branch
+        let flow = |arg| JMP.info().flow_of(&stmt(JMP, arg));
+        assert_eq!( flow(Arg::AbsLong(Expr::LitInt(0x400, None))),              Flow::Branch );
+        assert_eq!( flow(Arg::PcDisp(PcReg, Expr::LitInt(0x400, None))),        Flow::Branch );
+        assert_eq!( flow(Arg::AddrRegDisp(AddrReg::A0, Expr::LitInt(4, None))), Flow::IndirectJump );
+    }
+
+    #[test]
+    pub fn conditions() {
+        use Instructions::*;
+        assert_eq!( BHSB.info().cond, Some(Condition::Hs) );
+        assert_eq!( BLEL.info().cond, Some(Condition::Le) );
+        assert_eq!( SFB .info().cond, Some(Condition::F) );
+        assert_eq!( SVSB.info().cond, Some(Condition::Vs) );
+        assert_eq!( BRAB.info().cond, None );
+        assert_eq!( TSTL.info().cond, None );
+
+        // Conditions agree with mnemonics
+        for i in INSTRUCTIONS.iter().filter(|i| i.cond.is_some()) {
+            let cc = format!("{:?}", i.cond.unwrap()).to_lowercase();
+            assert_eq!( &i.name[1..i.name.len() - 2], cc );
+        }
+    }
+
+    #[test]
+    pub fn branch_targets() {
+        use Instructions::*;
+        let target = |inst: Instructions, arg| inst.info().branch_target(&stmt(inst, arg));
+        assert_eq!( target(BEQB, Arg::Expr(Expr::LitInt(0x400, None))),          Some(0x400) );
+        assert_eq!( target(JSR,  Arg::AbsLong(Expr::LitInt(0x1234, None))),      Some(0x1234) );
+        assert_eq!( target(JMP,  Arg::AbsShort(Expr::LitInt(-2, None))),         Some(0xFFFF_FFFE) );
+        assert_eq!( target(JMP,  Arg::PcDisp(PcReg, Expr::LitInt(0x500, None))), Some(0x500) );
+        assert_eq!( target(JMP,  Arg::AddrRegInd(AddrReg::A0)),                  None );
+        assert_eq!( target(LEAL, Arg::PcDisp(PcReg, Expr::LitInt(0x500, None))), None );
+    }
+}
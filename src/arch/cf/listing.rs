@@ -0,0 +1,133 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt::{self, Display, Formatter};
+use crate::ast::*;
+use super::*;
+
+/// A listing of assembled code: the address, bytes, and Motorola-syntax text
+/// of each statement.  Statements rewritten by optimisation are annotated
+/// with their original text, and a summary follows the last statement.
+#[derive(Clone, Copy, Debug)]
+pub struct Listing<'a> {
+    block: &'a CfBlock,
+    asm:   &'a Assembly,
+    pool:  &'a IdentPool,
+}
+
+// Bytes per listing line
+const LINE_BYTES: usize = 8;
+
+impl<'a> Listing<'a> {
+    /// Creates a listing of `block` as assembled into `asm`.
+    pub fn new(block: &'a CfBlock, asm: &'a Assembly, pool: &'a IdentPool) -> Self {
+        Self { block, asm, pool }
+    }
+}
+
+impl<'a> Display for Listing<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut opts = self.asm.optimizations.iter().peekable();
+
+        for (n, stmt) in self.block.stmts.iter().enumerate() {
+            let addr = self.asm.addrs[n];
+
+            for &label in &stmt.labels {
+                writeln!(f, "{:08X}  {:20}{}:", addr, "", self.pool.source_name(label))?;
+            }
+            if stmt.op == Slot::Value(Op::Asm(AsmOp::Empty), None) {
+                continue
+            }
+
+            let mut lines = self.asm.stmt_bytes(n).chunks(LINE_BYTES);
+            write!(f, "{:08X}  {:20}    {}", addr, Hex(lines.next().unwrap_or(&[])), moto(stmt, self.pool))?;
+
+            if let Some(o) = opts.next_if(|o| o.stmt == n) {
+                let orig = CfStmt::new(vec![], Slot::Value(Op::Inst(o.inst), None), o.args.clone());
+                write!(f, "  ; was {}", moto(&orig, self.pool))?;
+            }
+            writeln!(f)?;
+
+            for (i, bytes) in lines.enumerate() {
+                let addr = addr.wrapping_add(((i + 1) * LINE_BYTES) as u32);
+                writeln!(f, "{:08X}  {}", addr, Hex(bytes))?;
+            }
+        }
+
+        let opts = &self.asm.optimizations;
+        if !opts.is_empty() {
+            let saved: u32 = opts.iter().map(|o| o.saved).sum();
+            writeln!(f, "; {} instruction(s) optimised, {} byte(s) saved", opts.len(), saved)?;
+        }
+        Ok(())
+    }
+}
+
+// Bytes in hexadecimal, grouped into words
+struct Hex<'a>(&'a [u8]);
+
+impl<'a> Display for Hex<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut s = String::new();
+        for (i, b) in self.0.iter().enumerate() {
+            if i > 0 && i % 2 == 0 { s.push(' ') }
+            s.push_str(&format!("{:02X}", b));
+        }
+        f.pad(&s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn listing_optimized() {
+        let mut pool  = IdentPool::new();
+        let mut syms  = SymbolTable::new();
+        let mut block = parse_moto(
+            "start   move.l  #3,d0\n\
+             \x20       add.l   #4,a0\n\
+             \x20       move.l  #2,(a1)\n\
+             \x20       bra     start\n\
+             \x20       dc.b    'Hello, world'\n",
+            &mut pool, &mut syms
+        ).unwrap();
+
+        let opts = AsmOptions { optimize: true, ..AsmOptions::new(0x1000) };
+        let asm  = assemble(&mut block, &mut syms, &opts).unwrap();
+
+        assert_eq!(
+            Listing::new(&block, &asm, &pool).to_string(),
+            "00001000                      start:\n\
+             00001000  7003                    moveq.l #3,d0  ; was move.l #3,d0\n\
+             00001002  5888                    addq.l #4,a0  ; was add.l #4,a0\n\
+             00001004  22BC 0000 0002          move.l #2,(a1)\n\
+             0000100A  60F4                    bra.b start\n\
+             0000100C  4865 6C6C 6F2C 2077     dc.b 'Hello, world'\n\
+             00001014  6F72 6C64\n\
+             ; 2 instruction(s) optimised, 8 byte(s) saved\n"
+        );
+
+        let opts = AsmOptions { features: ISA_B, ..opts };
+        let asm  = assemble(&mut block, &mut syms, &opts).unwrap();
+        assert_eq!( words(asm.stmt_bytes(2)), [0xA551] );
+    }
+
+    fn words(bytes: &[u8]) -> Vec<u16> {
+        bytes.chunks(2).map(|c| (c[0] as u16) << 8 | c[1] as u16).collect()
+    }
+}
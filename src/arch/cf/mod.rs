@@ -14,19 +14,43 @@
 // You should have received a copy of the GNU General Public License
 // along with cfda.  If not, see <http://www.gnu.org/licenses/>.
 
+mod assemble;
 mod ast;
 mod decode;
+mod diag;
+mod disasm;
+mod encode;
 mod encoding;
 mod flags;
+mod fmt;
 mod inst;
+mod listing;
+mod moto;
+mod object;
 mod operand;
+mod optimize;
+mod reloc;
+mod traverse;
+mod vectors;
 
+pub use self::assemble::*;
 pub use self::ast::*;
 pub use self::decode::*;
+pub use self::diag::*;
+pub use self::disasm::*;
+pub use self::encode::*;
 pub use self::encoding::*;
 pub use self::flags::*;
+pub use self::fmt::*;
 pub use self::inst::*;
+pub use self::listing::*;
+pub use self::moto::*;
+pub use self::object::*;
 pub use self::operand::*;
+pub use self::optimize::*;
+pub use self::reloc::*;
+pub use self::traverse::*;
+pub use self::vectors::*;
 
 use super::Arch;
 
@@ -41,6 +65,6 @@ impl Arch for Cf {
     type Arg = Arg;
 }
 
-type CfBlock = Block<Cf>;
-type CfStmt  = Stmt <Cf>;
+pub type CfBlock = Block<Cf>;
+pub type CfStmt  = Stmt <Cf>;
 
@@ -0,0 +1,975 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::ast::{AsmOp, Expr, Fold, Ident, IdentPool, LocalLabels, Slot, Span, Stmt, SymbolTable, Trivium};
+use crate::ast::is_local_name;
+use crate::parse::{into_expr, lex_line, ParseError, Token, Tokens};
+use super::*;
+
+/// Parses ColdFire assembly source in Motorola syntax.
+///
+/// Identifiers are interned in `pool`, and label definitions are recorded in
+/// `syms`.  On success, returns the parsed block.  Otherwise, returns every
+/// error encountered; parsing continues with the next line after an error.
+///
+/// The syntax is that of the ColdFire Family Programmer's Reference Manual:
+///
+/// ```text
+/// loop:   move.l  (8,a0,d1.l*4),d0    ; load table entry
+///         addq.l  #1,d1
+///         cmpi.l  #$10,d1
+///         bne.s   loop
+/// table   dc.l    $DEADBEEF,%1010,@17
+/// ```
+///
+/// A label begins in the first column, optionally followed by `:`.  Labels
+/// elsewhere require the `:`.  A `*` in the first column or a `;` or `/*`
+/// anywhere outside a literal begins a comment.
+///
+/// GNU-style numeric local labels are supported: `1:` may be defined any
+/// number of times, and `1b` and `1f` refer to its nearest definition
+/// backward and forward.  Symbols named `.L` and numeric local labels are
+/// private to the source and cannot be made public.
+///
+/// Each statement records the source locations of its operation and
+/// arguments.  Comments and blank lines are kept as trivia of the statement
+/// that follows or, for a trailing comment, of the statement on the same
+/// line.  A line with labels and a comment but no operation becomes an empty
+/// statement, so that the comment stays with the labels.  Trivia after the
+/// last statement belongs to a final empty statement.
+///
+/// GNU-style operands, such as `8(%a0)` and `%d0-%d2/%fp`, are also
+/// accepted, so that the output of `gas` parses.
+pub fn parse_moto(src: &str, pool: &mut IdentPool, syms: &mut SymbolTable)
+    -> Result<CfBlock, Vec<ParseError>>
+{
+    let mut parser = MotoParser {
+        pool,
+        syms,
+        stmts:  vec![],
+        labels: vec![],
+        locals: LocalLabels::new(),
+        trivia: vec![],
+        errors: vec![],
+    };
+
+    for (n, text) in src.lines().enumerate() {
+        if let Err(e) = parser.line(text, n + 1) {
+            parser.errors.push(e);
+        }
+    }
+
+    parser.finish()
+}
+
+struct MotoParser<'a> {
+    pool:   &'a mut IdentPool,
+    syms:   &'a mut SymbolTable,
+    stmts:  Vec<CfStmt>,
+    labels: Vec<Ident>,
+    locals: LocalLabels,
+    trivia: Vec<Trivium>,
+    errors: Vec<ParseError>,
+}
+
+impl<'a> MotoParser<'a> {
+    fn finish(mut self) -> Result<CfBlock, Vec<ParseError>> {
+        if !self.labels.is_empty() || !self.trivia.is_empty() {
+            // Labels or trivia at end of input
+            self.push(Stmt::new(vec![], Slot::Value(Op::Asm(AsmOp::Empty), None), vec![]));
+        }
+
+        if self.errors.is_empty() {
+            Ok(CfBlock { stmts: self.stmts })
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    // Adds a statement, with the pending labels and trivia.
+    fn push(&mut self, mut stmt: CfStmt) {
+        stmt.labels         = std::mem::take(&mut self.labels);
+        stmt.trivia.leading = std::mem::take(&mut self.trivia);
+        self.stmts.push(stmt);
+    }
+
+    fn line(&mut self, text: &str, line: usize) -> Result<(), ParseError> {
+        if let Some(comment) = text.strip_prefix('*') {
+            self.trivia.push(Trivium::Comment(comment.to_string()));
+            return Ok(())
+        }
+
+        let lexed = lex_line(text, line)?;
+        let mut t = Tokens::new(&lexed.tokens, line, lexed.end);
+
+        if t.at_end() {
+            self.trivia.push(match lexed.comment {
+                Some(c) => Trivium::Comment(c.to_string()),
+                None    => Trivium::Blank,
+            });
+            return Ok(())
+        }
+
+        // Label in first column, with optional colon
+        let first_col = !text.starts_with(char::is_whitespace);
+        if first_col {
+            if let Some(Token::Ident(name)) = t.peek() {
+                self.label(&mut t, name)?;
+                t.eat(&Token::Colon);
+            }
+        }
+
+        // Labels elsewhere, with required colon
+        loop {
+            match (t.peek(), t.peek2()) {
+                (Some(Token::Ident(name)), Some(&Token::Colon)) => self.label(&mut t, name)?,
+                (Some(&Token::Int (n)   ), Some(&Token::Colon)) => self.label(&mut t, &n.to_string())?,
+                _                                               => break,
+            }
+            t.next();
+        }
+
+        if t.at_end() {
+            if let Some(c) = lexed.comment {
+                // Labels with a comment: keep them together
+                let mut stmt = Stmt::new(vec![], Slot::Value(Op::Asm(AsmOp::Empty), None), vec![]);
+                stmt.trivia.trailing = Some(c.to_string());
+                self.push(stmt);
+            }
+            return Ok(()) // labels apply to next statement
+        }
+
+        // Operation
+        let start = t.offset();
+        let name  = match t.peek() {
+            Some(Token::Ident(name)) => { t.next(); name },
+            _ => return Err(t.error("expected label or operation")),
+        };
+        let span = Some(t.span_from(start));
+        let op   = match lookup_op(name) {
+            Some(op) => Slot::Value(op, span),
+            None     => Slot::Ident(self.pool.intern(name), span),
+        };
+
+        // Arguments
+        let data = matches!(op, Slot::Value(Op::Asm(_), _));
+        let mut args = vec![];
+        if !t.at_end() {
+            loop {
+                let arg_start = t.offset();
+                let arg = if data { self.data_arg(&mut t)? } else { self.arg(&mut t)? };
+                args.push(arg.with_span(t.span_from(arg_start)));
+                if !t.eat(&Token::Comma) { break }
+            }
+            if !t.at_end() {
+                return Err(t.error("expected ',' or end of line"))
+            }
+        }
+
+        if op == Slot::Value(Op::Asm(AsmOp::Public), None) {
+            let local = args.iter().find_map(|a| {
+                a.ident().filter(|&i| is_local_name(self.pool.name(i))).zip(a.span())
+            });
+            if let Some((i, span)) = local {
+                return Err(ParseError::new(line, span.col as usize, format!(
+                    "local symbol '{}' cannot be made public", self.pool.source_name(i)
+                )))
+            }
+        }
+
+        // References to numeric local labels
+        let mut refs = LocalRefs {
+            locals:    &self.locals,
+            pool:      self.pool,
+            syms:      self.syms,
+            undefined: None,
+        };
+        let mut stmt = refs.fold_stmt(Stmt::new(vec![], op, args));
+        if let Some((name, span)) = refs.undefined {
+            let col = span.map_or(start + 1, |s| s.col as usize);
+            return Err(ParseError::new(line, col, format!(
+                "'{}' refers to a local label not defined before it", name
+            )))
+        }
+
+        stmt.span            = Some(t.span_from(start));
+        stmt.trivia.trailing = lexed.comment.map(str::to_string);
+        self.push(stmt);
+        Ok(())
+    }
+
+    // Label definition.
+    fn label(&mut self, t: &mut Tokens, name: &str) -> Result<(), ParseError> {
+        let span  = Span::new(t.line() as u32, t.offset() as u32 + 1, name.len() as u32);
+
+        if LocalLabels::reference(name).is_some() {
+            return Err(t.error(format!("'{}' is a local label reference, not a label", name)))
+        }
+        let ident = match LocalLabels::number(name) {
+            Some(n) => self.locals.define(n, self.pool),
+            None    => self.pool.intern(name),
+        };
+
+        if let Err(prev) = self.syms.define(ident, span) {
+            return Err(t.error(format!(
+                "'{}' is already defined at line {}", name, prev.line
+            )))
+        }
+
+        t.next();
+        self.labels.push(ident);
+        Ok(())
+    }
+
+    // Argument to a pseudo-operation: a plain expression.
+    fn data_arg(&mut self, t: &mut Tokens) -> Result<Slot<Arg>, ParseError> {
+        Ok(expr_arg(t.expr(self.pool)?))
+    }
+
+    // Argument to an instruction: an addressing mode.
+    fn arg(&mut self, t: &mut Tokens) -> Result<Slot<Arg>, ParseError> {
+        match t.peek() {
+            // Immediate
+            Some(&Token::Hash) => {
+                t.next();
+                let expr = into_expr(t.expr(self.pool)?);
+                return Ok(Slot::Value(Arg::Imm(expr), None))
+            },
+
+            // Address register indirect with pre-decrement
+            Some(&Token::Minus) => {
+                let mark = t.mark();
+                t.next();
+                if t.eat(&Token::LParen) {
+                    if let Some(Reg::Addr(r)) = reg_token(t.peek()) {
+                        t.next();
+                        if t.eat(&Token::RParen) {
+                            return Ok(Slot::Value(Arg::AddrRegIndDec(r), None))
+                        }
+                    }
+                }
+                t.reset(mark);
+            },
+
+            // Parenthesized modes
+            Some(&Token::LParen) => {
+                return self.paren_arg(t)
+            },
+
+            // Registers
+            Some(&Token::Ident(_)) => {
+                if let Some(arg) = self.reg_arg(t)? {
+                    return Ok(Slot::Value(arg, None))
+                }
+            },
+
+            _ => (),
+        }
+
+        // Expression, possibly followed by a displacement mode or size
+        let expr = t.expr(self.pool)?;
+        self.expr_tail(t, expr)
+    }
+
+    // Modes beginning with '('.
+    fn paren_arg(&mut self, t: &mut Tokens) -> Result<Slot<Arg>, ParseError> {
+        let start = t.offset();
+        t.next(); // (
+
+        // (An) (An)+ (An,Xi)
+        if let Some(Reg::Addr(r)) = reg_token(t.peek()) {
+            t.next();
+            if t.eat(&Token::Comma) {
+                let index = self.index(t)?;
+                t.expect(&Token::RParen, "')'")?;
+                return Ok(Slot::Value(Arg::AddrRegIdx(r, Expr::LitInt(0, None), index), None))
+            }
+            t.expect(&Token::RParen, "')'")?;
+            if t.eat(&Token::Plus) {
+                return Ok(Slot::Value(Arg::AddrRegIndInc(r), None))
+            }
+            return Ok(Slot::Value(Arg::AddrRegInd(r), None))
+        }
+
+        // (d,An) (d,An,Xi) (d,PC) (d,PC,Xi)
+        let disp = t.expr(self.pool)?;
+        if t.eat(&Token::Comma) {
+            let arg = self.base_arg(t, into_expr(disp))?;
+            t.expect(&Token::RParen, "')'")?;
+            return Ok(Slot::Value(arg, None))
+        }
+        t.expect(&Token::RParen, "',' or ')'")?;
+
+        // (xxx).W (xxx).L
+        if let Some(arg) = abs_size(t, &disp)? {
+            return Ok(Slot::Value(arg, None))
+        }
+
+        // Parenthesized leading subexpression
+        let expr = t.expr_from(disp, start, self.pool)?;
+        self.expr_tail(t, expr)
+    }
+
+    // Modes following an expression: d(An) d(An,Xi) d(PC) d(PC,Xi) xxx.W xxx.L
+    fn expr_tail(&mut self, t: &mut Tokens, expr: Slot<Expr>) -> Result<Slot<Arg>, ParseError> {
+        if t.eat(&Token::LParen) {
+            let arg = self.base_arg(t, into_expr(expr))?;
+            t.expect(&Token::RParen, "')'")?;
+            return Ok(Slot::Value(arg, None))
+        }
+
+        if let Some(arg) = abs_size(t, &expr)? {
+            return Ok(Slot::Value(arg, None))
+        }
+
+        Ok(expr_arg(expr))
+    }
+
+    // Base register and optional index of a displacement mode.
+    fn base_arg(&mut self, t: &mut Tokens, disp: Expr) -> Result<Arg, ParseError> {
+        let base = reg_token(t.peek());
+        match base {
+            Some(Reg::Addr(_)) | Some(Reg::Pc) => { t.next(); },
+            _ => return Err(t.error("expected address register or pc")),
+        }
+
+        let index = if t.eat(&Token::Comma) { Some(self.index(t)?) } else { None };
+
+        Ok(match (base, index) {
+            (Some(Reg::Addr(r)), None   ) => Arg::AddrRegDisp (r,     disp       ),
+            (Some(Reg::Addr(r)), Some(i)) => Arg::AddrRegIdx  (r,     disp, i    ),
+            (_,                  None   ) => Arg::PcDisp      (PcReg, disp       ),
+            (_,                  Some(i)) => Arg::PcIdx       (PcReg, disp, i    ),
+        })
+    }
+
+    // Index register with optional size and scale: Xn[.L][*scale]
+    fn index(&mut self, t: &mut Tokens) -> Result<Index, ParseError> {
+        let name = match t.peek() {
+            Some(Token::Ident(name)) => name,
+            _ => return Err(t.error("expected index register")),
+        };
+
+        let (name, size) = match name.find('.') {
+            Some(n) => (&name[..n], Some(&name[n + 1..])),
+            None    => (&name[..],  None),
+        };
+
+        let reg = match reg(name) {
+            Some(Reg::Data(r)) => IndexReg::Data(r),
+            Some(Reg::Addr(r)) => IndexReg::Addr(r),
+            _ => return Err(t.error("expected index register")),
+        };
+
+        match size {
+            None                                      => (),
+            Some(s) if s.eq_ignore_ascii_case("l")    => (),
+            Some(s) if s.eq_ignore_ascii_case("w")    => {
+                return Err(t.error("word-sized index registers are not supported on ColdFire"))
+            },
+            Some(_) => return Err(t.error("invalid index register size")),
+        }
+        t.next();
+
+        let scale = if t.eat(&Token::Star) {
+            into_expr(t.expr(self.pool)?)
+        } else {
+            Expr::LitInt(1, None)
+        };
+
+        Ok(Index { reg, scale })
+    }
+
+    // Register direct modes, register pairs, and register lists.
+    fn reg_arg(&mut self, t: &mut Tokens) -> Result<Option<Arg>, ParseError> {
+        let r = match reg_token(t.peek()) {
+            Some(r) => r,
+            None    => return Ok(cache_token(t.peek()).map(|c| { t.next(); Arg::Cache(c) })),
+        };
+        t.next();
+
+        match (r, t.peek()) {
+            (Reg::Data(a), Some(&Token::Colon)) => {
+                t.next();
+                match reg_token(t.next()) {
+                    Some(Reg::Data(b)) => Ok(Some(Arg::DataRegPair(DataRegPair(a, b)))),
+                    _ => Err(t.error("expected data register")),
+                }
+            },
+            (Reg::Data(_), Some(&Token::Minus)) | (Reg::Data(_), Some(&Token::Slash)) |
+            (Reg::Addr(_), Some(&Token::Minus)) | (Reg::Addr(_), Some(&Token::Slash)) => {
+                self.reg_list(t, r).map(Some)
+            },
+            (Reg::Data(r), _) => Ok(Some(Arg::DataReg(r))),
+            (Reg::Addr(r), _) => Ok(Some(Arg::AddrReg(r))),
+            (Reg::Fp(r),   _) => Ok(Some(Arg::FpReg(r))),
+            (Reg::Ccr,     _) => Ok(Some(Arg::CcrReg(CcrReg))),
+            (Reg::Sr,      _) => Ok(Some(Arg::SrReg(SrReg))),
+            (Reg::Pc,      _) => Err(t.error("pc is not valid here")),
+        }
+    }
+
+    // Register list: d0-d3/a0/a2-a4
+    fn reg_list(&mut self, t: &mut Tokens, first: Reg) -> Result<Arg, ParseError> {
+        let mut list = RegList::default();
+        let mut r    = first;
+
+        loop {
+            let lo = list_num(r).ok_or_else(|| t.error("expected data or address register"))?;
+            let mut hi = lo;
+
+            if t.eat(&Token::Minus) {
+                hi = reg_token(t.next()).and_then(list_num)
+                    .ok_or_else(|| t.error("expected data or address register"))?;
+                if hi < lo {
+                    return Err(t.error("register range is backwards"))
+                }
+            }
+
+            for n in lo..=hi {
+                list.0 |= 1 << n;
+            }
+
+            if !t.eat(&Token::Slash) { break }
+            r = reg_token(t.next()).ok_or_else(|| t.error("expected data or address register"))?;
+        }
+
+        Ok(Arg::RegList(list))
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+
+// Registers recognized in operands.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Reg {
+    Data(DataReg),
+    Addr(AddrReg),
+    Fp(FpReg),
+    Pc,
+    Ccr,
+    Sr,
+}
+
+// Rewrites references to numeric local labels, such as `1b`, as references
+// to the label instances, recording each as an alias in the symbol table.
+struct LocalRefs<'a> {
+    locals:    &'a LocalLabels,
+    pool:      &'a mut IdentPool,
+    syms:      &'a mut SymbolTable,
+    undefined: Option<(String, Option<Span>)>,
+}
+
+impl<'a> Fold<Cf> for LocalRefs<'a> {
+    fn fold_ident(&mut self, node: Ident, span: Option<Span>) -> Ident {
+        let (n, forward) = match LocalLabels::reference(self.pool.name(node)) {
+            Some(r) => r,
+            None    => return node,
+        };
+        match self.locals.refer(n, forward, self.pool) {
+            Some((reference, label)) => {
+                self.syms.alias(reference, label);
+                reference
+            },
+            None => {
+                self.undefined = Some((self.pool.name(node).to_string(), span));
+                node
+            },
+        }
+    }
+}
+
+// Register by name, with an optional % prefix as in GNU syntax.
+fn reg(name: &str) -> Option<Reg> {
+    let name  = name.strip_prefix('%').unwrap_or(name);
+    let lower = name.to_ascii_lowercase();
+    let bytes = lower.as_bytes();
+
+    if bytes.len() == 2 && (b'0'..=b'7').contains(&bytes[1]) {
+        let n = bytes[1] - b'0';
+        match bytes[0] {
+            b'd' => return DataReg::with_num(n).map(Reg::Data),
+            b'a' => return AddrReg::with_num(n).map(Reg::Addr),
+            _    => (),
+        }
+    }
+
+    if bytes.len() == 3 && bytes.starts_with(b"fp") && (b'0'..=b'7').contains(&bytes[2]) {
+        return FpReg::with_num(bytes[2] - b'0').map(Reg::Fp)
+    }
+
+    match &lower[..] {
+        "sp"  => Some(Reg::Addr(AddrReg::SP)),
+        "fp"  => Some(Reg::Addr(AddrReg::FP)),
+        "pc"  => Some(Reg::Pc),
+        "ccr" => Some(Reg::Ccr),
+        "sr"  => Some(Reg::Sr),
+        _     => None,
+    }
+}
+
+fn reg_token(token: Option<&Token>) -> Option<Reg> {
+    match token {
+        Some(Token::Ident(name)) => reg(name),
+        _                             => None,
+    }
+}
+
+fn cache_token(token: Option<&Token>) -> Option<Cache> {
+    let name = match token {
+        Some(Token::Ident(name)) => name.strip_prefix('%').unwrap_or(name).to_ascii_lowercase(),
+        _                             => return None,
+    };
+    match &name[..] {
+        "ic" => Some(Cache::Inst),
+        "dc" => Some(Cache::Data),
+        "bc" => Some(Cache::Both),
+        _    => None,
+    }
+}
+
+// Bit number of a register within a register list.
+fn list_num(r: Reg) -> Option<u8> {
+    match r {
+        Reg::Data(r) => Some(r.num()),
+        Reg::Addr(r) => Some(r.num() + 8),
+        _            => None,
+    }
+}
+
+// Absolute modes with explicit size: xxx.W xxx.L
+fn abs_size(t: &mut Tokens, expr: &Slot<Expr>) -> Result<Option<Arg>, ParseError> {
+    let size = match t.peek() {
+        Some(Token::Ident(s)) if s.eq_ignore_ascii_case(".w") => false,
+        Some(Token::Ident(s)) if s.eq_ignore_ascii_case(".l") => true,
+        _                                                          => return Ok(None),
+    };
+    t.next();
+    let expr = into_expr(expr.clone());
+    Ok(Some(if size { Arg::AbsLong(expr) } else { Arg::AbsShort(expr) }))
+}
+
+// Converts a parsed expression into an argument slot.  A lone identifier
+// becomes an unresolved slot.
+fn expr_arg(expr: Slot<Expr>) -> Slot<Arg> {
+    match expr {
+        Slot::Ident(i, s) => Slot::Ident(i, s),
+        expr              => {
+            let span = expr.span();
+            Slot::Value(Arg::Expr(into_expr(expr)), span)
+        },
+    }
+}
+
+// Looks up an operation by mnemonic.  Size-less mnemonics default to word
+// size if possible, as is customary in Motorola syntax, except that branches
+// take their shortest form, from which the assembler grows them as needed.
+fn lookup_op(name: &str) -> Option<Op> {
+    let lower = name.to_ascii_lowercase();
+
+    // Motorola directives, and GNU equivalents as emitted by the disassembler
+    let asm = match &lower[..] {
+        "org"     | ".org"                  => Some(AsmOp::Origin),
+        "dcb.b"   | ".skip"   | ".space"    => Some(AsmOp::Skip),
+        "ds.b"    | ".zero"                 => Some(AsmOp::Zero8),
+        "ds.w"    | "ds"                    => Some(AsmOp::Zero16),
+        "ds.l"                              => Some(AsmOp::Zero32),
+        "ds.q"                              => Some(AsmOp::Zero64),
+        "dc.b"    | ".byte"                 => Some(AsmOp::Data8),
+        "dc.w"    | "dc"      | ".short"
+                  | ".word"                 => Some(AsmOp::Data16),
+        "dc.l"    | ".long"                 => Some(AsmOp::Data32),
+        "dc.q"    | ".quad"                 => Some(AsmOp::Data64),
+        "dc.s"    | ".float"  | ".single"   => Some(AsmOp::Float32),
+        "dc.d"    | ".double"               => Some(AsmOp::Float64),
+        "xdef"    | "public"  | ".globl"
+                  | ".global"               => Some(AsmOp::Public),
+        "section" | ".section"              => Some(AsmOp::Section),
+        _                                   => str_directive_with_name(&lower),
+    };
+    if let Some(op) = asm {
+        return Some(Op::Asm(op))
+    }
+
+    if let Some(inst) = Instructions::with_name(&lower) {
+        return Some(Op::Inst(inst))
+    }
+
+    if !lower.contains('.') {
+        if let Some(inst) = Instructions::with_name(&(lower.clone() + ".b")) {
+            if branch_family(inst).is_some() {
+                return Some(Op::Inst(inst))
+            }
+        }
+        for suffix in &[".w", ".l", ".b"] {
+            if let Some(inst) = Instructions::with_name(&(lower.clone() + suffix)) {
+                return Some(Op::Inst(inst))
+            }
+        }
+    }
+
+    None
+}
+
+// -----------------------------------------------------------------------------
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::AddrReg::*;
+    use super::DataReg::*;
+    use super::Instructions::*;
+
+    fn parse(src: &str) -> (CfBlock, IdentPool) {
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        let block = parse_moto(src, &mut pool, &mut syms).unwrap();
+        (block, pool)
+    }
+
+    fn args(src: &str) -> Vec<Slot<Arg>> {
+        let (mut block, _) = parse(src);
+        assert_eq!( block.stmts.len(), 1 );
+        block.stmts.pop().unwrap().args
+    }
+
+    fn val(arg: Arg) -> Slot<Arg> {
+        Slot::Value(arg, None)
+    }
+
+    #[test]
+    pub fn parse_labels_and_ops() {
+        let (block, pool) = parse(
+            "start   moveq   #0,d0\n\
+             * comment line\n\
+             \n\
+             loop:\n\
+             again:  addq.l  #1,d0   ; count\n\
+             \x20       bra.s   loop\n\
+             end:\n"
+        );
+
+        let s = &block.stmts;
+        assert_eq!( s.len(), 4 );
+
+        let label_names = |i: usize| s[i].labels.iter()
+            .map(|&l| pool.name(l).to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!( label_names(0), ["start"] );
+        assert_eq!( s[0].op, Slot::Value(Op::Inst(MOVEQL), None) );
+        assert_eq!( label_names(1), ["loop", "again"] );
+        assert_eq!( s[1].op, Slot::Value(Op::Inst(ADDQL), None) );
+        assert_eq!( label_names(2), Vec::<String>::new() );
+        assert_eq!( s[2].op, Slot::Value(Op::Inst(BRAB), None) );
+        assert_eq!( s[2].args, [Slot::Ident(s[1].labels[0], None)] );
+        assert_eq!( label_names(3), ["end"] );
+        assert_eq!( s[3].op, Slot::Value(Op::Asm(AsmOp::Empty), None) );
+    }
+
+    #[test]
+    pub fn parse_default_size() {
+        let (block, _) = parse(" move d0,d1\n lea (a0),a1\n seq d0\n bne x\n");
+        assert_eq!( block.stmts[0].op, Slot::Value(Op::Inst(MOVEW), None) );
+        assert_eq!( block.stmts[1].op, Slot::Value(Op::Inst(LEAL), None)  );
+        assert_eq!( block.stmts[2].op, Slot::Value(Op::Inst(SEQB), None)  );
+        assert_eq!( block.stmts[3].op, Slot::Value(Op::Inst(BNEB), None)  );
+    }
+
+    #[test]
+    pub fn parse_unknown_op() {
+        let (block, pool) = parse(" frobnicate d0\n");
+        let id = block.stmts[0].op.ident().unwrap();
+        assert_eq!( pool.name(id), "frobnicate" );
+    }
+
+    #[test]
+    pub fn parse_register_modes() {
+        assert_eq!(
+            args(" move.l d0,a1\n"),
+            [val(Arg::DataReg(D0)), val(Arg::AddrReg(A1))]
+        );
+        assert_eq!(
+            args(" move.l (a0)+,-(sp)\n"),
+            [val(Arg::AddrRegIndInc(A0)), val(Arg::AddrRegIndDec(A7))]
+        );
+        assert_eq!(
+            args(" move.l (fp),d0\n"),
+            [val(Arg::AddrRegInd(A6)), val(Arg::DataReg(D0))]
+        );
+    }
+
+    #[test]
+    pub fn parse_displacement_modes() {
+        let idx = |r, s| Index { reg: r, scale: Expr::LitInt(s, None) };
+        assert_eq!(
+            args(" move.l (8,a0),4(a1)\n"),
+            [
+                val(Arg::AddrRegDisp(A0, Expr::LitInt(8, None))),
+                val(Arg::AddrRegDisp(A1, Expr::LitInt(4, None))),
+            ]
+        );
+        assert_eq!(
+            args(" move.l (8,a0,d1.l*4),(a1,a2)\n"),
+            [
+                val(Arg::AddrRegIdx(A0, Expr::LitInt(8, None), idx(IndexReg::Data(D1), 4))),
+                val(Arg::AddrRegIdx(A1, Expr::LitInt(0, None), idx(IndexReg::Addr(A2), 1))),
+            ]
+        );
+        assert_eq!(
+            args(" lea ($10,pc),a0\n"),
+            [val(Arg::PcDisp(PcReg, Expr::LitInt(16, None))), val(Arg::AddrReg(A0))]
+        );
+        assert_eq!(
+            args(" move.w 2(pc,d0*2),d1\n"),
+            [
+                val(Arg::PcIdx(PcReg, Expr::LitInt(2, None), idx(IndexReg::Data(D0), 2))),
+                val(Arg::DataReg(D1)),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn parse_absolute_and_immediate() {
+        assert_eq!(
+            args(" move.l $1234.w,($FFFF0000).l\n"),
+            [
+                val(Arg::AbsShort(Expr::LitInt(0x1234, None))),
+                val(Arg::AbsLong(Expr::LitInt(0xFFFF0000, None))),
+            ]
+        );
+        assert_eq!(
+            args(" move.l #-1,(2+2)*4\n"),
+            [
+                val(Arg::Imm(Expr::unary(
+                    crate::ast::UnaryOp::Neg, Slot::Value(Expr::LitInt(1, None), None)
+                ))),
+                val(Arg::Expr(Expr::binary(
+                    crate::ast::BinaryOp::Mul,
+                    Slot::Value(Expr::binary(
+                        crate::ast::BinaryOp::Add,
+                        Slot::Value(Expr::LitInt(2, None), None),
+                        Slot::Value(Expr::LitInt(2, None), None),
+                    ), None),
+                    Slot::Value(Expr::LitInt(4, None), None),
+                ))),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn parse_special_modes() {
+        assert_eq!(
+            args(" movem.l d0-d2/a0/a5-a6,-(sp)\n"),
+            [val(Arg::RegList(RegList(0b_0110_0001_0000_0111))), val(Arg::AddrRegIndDec(A7))]
+        );
+        assert_eq!(
+            args(" rems.l #3,d1:d2\n")[1],
+            val(Arg::DataRegPair(DataRegPair(D1, D2)))
+        );
+        assert_eq!(
+            args(" move.w sr,d0\n")[0],
+            val(Arg::SrReg(SrReg))
+        );
+        assert_eq!(
+            args(" cpushl bc,(a0)\n")[0],
+            val(Arg::Cache(Cache::Both))
+        );
+        assert_eq!(
+            args(" movem.l %d0-%d1/%fp,-(%sp)\n"),
+            [val(Arg::RegList(RegList(0b_0100_0000_0000_0011))), val(Arg::AddrRegIndDec(A7))]
+        );
+        assert_eq!(
+            args(" move.l 8(%a0,%d1.l*4),%d0\n")[0],
+            val(Arg::AddrRegIdx(A0, Expr::LitInt(8, None), Index {
+                reg:   IndexReg::Data(D1),
+                scale: Expr::LitInt(4, None),
+            }))
+        );
+        assert_eq!(
+            args(" fmove.d fp1,fp7\n"),
+            [val(Arg::FpReg(FpReg::FP1)), val(Arg::FpReg(FpReg::FP7))]
+        );
+        assert_eq!(
+            args(" fmove.d #1.0e-3,fp0\n")[0],
+            val(Arg::Imm(Expr::LitFloat(crate::ast::Float::new(0.001), None)))
+        );
+    }
+
+    #[test]
+    pub fn parse_data() {
+        let (block, _) = parse("tbl dc.l $DEADBEEF,tbl\n ds.b 16\n dc.b 'OK',0\n");
+        let s = &block.stmts;
+        assert_eq!( s[0].op, Slot::Value(Op::Asm(AsmOp::Data32), None) );
+        assert_eq!( s[0].args, [val(Arg::Expr(Expr::LitInt(0xDEADBEEF, None))), Slot::Ident(s[0].labels[0], None)] );
+        assert_eq!( s[1].op, Slot::Value(Op::Asm(AsmOp::Zero8), None) );
+        assert_eq!( s[2].args, [val(Arg::Expr(Expr::LitChar("OK".into(), None))), val(Arg::Expr(Expr::LitInt(0, None)))] );
+    }
+
+    #[test]
+    pub fn parse_directives() {
+        let (block, pool) = parse(
+            " .section .text\n org $400\n .globl start\n .asciz \"hi\"\n .quad 1\n dcb.b 4,$FF\n"
+        );
+        let ops: Vec<_> = block.stmts.iter().map(|s| s.op).collect();
+        assert_eq!(
+            ops,
+            [
+                Slot::Value(Op::Asm(AsmOp::Section), None),
+                Slot::Value(Op::Asm(AsmOp::Origin), None),
+                Slot::Value(Op::Asm(AsmOp::Public), None),
+                Slot::Value(Op::Asm(AsmOp::CharsZ(crate::ast::StrEncoding::UTF8)), None),
+                Slot::Value(Op::Asm(AsmOp::Data64), None),
+                Slot::Value(Op::Asm(AsmOp::Skip), None),
+            ]
+        );
+        assert_eq!( block.stmts[0].args, [Slot::Ident(pool.get(".text").unwrap(), None)] );
+        assert_eq!( block.stmts[5].args.len(), 2 );
+    }
+
+    #[test]
+    pub fn parse_label_definitions() {
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        let errs = parse_moto("a nop\n b: nop\n a: nop\n", &mut pool, &mut syms).unwrap_err();
+
+        let a = pool.get("a").unwrap();
+        let b = pool.get("b").unwrap();
+        assert_eq!( syms.get(a).unwrap().span, Some(Span::new(1, 1, 1)) );
+        assert_eq!( syms.get(b).unwrap().span, Some(Span::new(2, 2, 1)) );
+        assert_eq!( errs.len(), 1 );
+        assert_eq!( errs[0].to_string(), "3:2: 'a' is already defined at line 1" );
+    }
+
+    #[test]
+    pub fn parse_local_labels() {
+        let src = "1:      subq.l #1,d0\n\
+                   \x20       bne.b 1b\n\
+                   \x20       bra.b 1f\n\
+                   1:\n\
+                   .Lx:    bra.b 1b\n";
+        let mut pool  = IdentPool::new();
+        let mut syms  = SymbolTable::new();
+        let mut block = parse_moto(src, &mut pool, &mut syms).unwrap();
+        let s = &block.stmts;
+
+        // Each definition is a new instance, and each reference its nearest
+        assert_ne!( s[0].labels[0], s[3].labels[0] );
+        assert_eq!( syms.target(s[1].args[0].ident().unwrap()), s[0].labels[0] );
+        assert_eq!( syms.target(s[2].args[0].ident().unwrap()), s[3].labels[0] );
+        assert_eq!( syms.target(s[3].args[0].ident().unwrap()), s[3].labels[0] );
+        assert_eq!(
+            moto(&block, &pool).to_string(),
+            "1:\n\
+             \x20       subq.l #1,d0\n\
+             \x20       bne.b 1b\n\
+             \x20       bra.b 1f\n\
+             1:\n\
+             .Lx:\n\
+             \x20       bra.b 1b\n"
+        );
+
+        let asm = assemble(&mut block, &mut syms, &AsmOptions::new(0)).unwrap();
+        assert_eq!( asm.bytes, [0x53, 0x80, 0x66, 0xFC, 0x60, 0x00, 0x00, 0x02, 0x60, 0xFE] );
+
+        let errs = parse_moto(" bra 1b\n xdef .Lx\n1b: nop\n", &mut pool, &mut syms).unwrap_err();
+        assert_eq!( errs[0].to_string(), "1:6: '1b' refers to a local label not defined before it" );
+        assert_eq!( errs[1].to_string(), "2:7: local symbol '.Lx' cannot be made public" );
+        assert_eq!( errs[2].to_string(), "3:1: '1b' is a local label reference, not a label" );
+    }
+
+    #[test]
+    pub fn parse_spans_and_trivia() {
+        let (block, _) = parse(
+            "; setup\n\
+             \x20 move.l  (4,a0,d1.l*2),-(sp) ; save\n\
+             \n\
+             \x20 dc.w    1+2,x\n\
+             * end\n"
+        );
+        let s = &block.stmts;
+        assert_eq!( s.len(), 3 );
+
+        assert_eq!( s[0].span,        Some(Span::new(2,  3, 27)) );
+        assert_eq!( s[0].op.span(),   Some(Span::new(2,  3,  6)) );
+        assert_eq!( s[0].arg_span(0), Some(Span::new(2, 11, 13)) );
+        assert_eq!( s[0].arg_span(1), Some(Span::new(2, 25,  5)) );
+        assert_eq!( s[0].trivia.leading,  [Trivium::Comment(" setup".into())] );
+        assert_eq!( s[0].trivia.trailing, Some(" save".into()) );
+
+        assert_eq!( s[1].arg_span(0), Some(Span::new(4, 11, 3)) );
+        assert_eq!( s[1].arg_span(1), Some(Span::new(4, 15, 1)) );
+        assert_eq!( s[1].arg_span(2), None );
+        assert_eq!( s[1].trivia.leading, [Trivium::Blank] );
+        let e = match s[1].args[0] {
+            Slot::Value(Arg::Expr(ref e), _) => e,
+            ref arg                          => panic!("unexpected argument: {:?}", arg),
+        };
+        assert_eq!( e.span(), Some(Span::new(4, 11, 3)) );
+        match *e {
+            Expr::Binary(ref b) => {
+                assert_eq!( b.lhs.span(), Some(Span::new(4, 11, 1)) );
+                assert_eq!( b.rhs.span(), Some(Span::new(4, 13, 1)) );
+            },
+            _ => panic!("expected binary expression"),
+        }
+
+        assert_eq!( s[2].op, Slot::Value(Op::Asm(AsmOp::Empty), None) );
+        assert_eq!( s[2].span, None );
+        assert_eq!( s[2].trivia.leading, [Trivium::Comment(" end".into())] );
+    }
+
+    #[test]
+    pub fn parse_preprocessed() {
+        use std::collections::HashMap;
+        use std::path::Path;
+        use crate::parse::preprocess_str;
+
+        let src = "COUNT   equ     2\n\
+                   \x20       .macro  clear reg\n\
+                   \x20       moveq   #0,\\reg\n\
+                   \x20       .endm\n\
+                   \x20       .irp    r, d0, d1\n\
+                   \x20       clear   \\r\n\
+                   \x20       .endr\n\
+                   \x20       frob    #COUNT\n";
+        let pp = preprocess_str(src, Path::new("t.s"), &mut HashMap::new(), &[]).unwrap();
+
+        let (block, pool) = parse(&pp.text);
+        let s = &block.stmts;
+        assert_eq!( s.len(), 3 );
+        assert_eq!( s[0].op,   Slot::Value(Op::Inst(MOVEQL), None) );
+        assert_eq!( s[1].args, [val(Arg::Imm(Expr::LitInt(0, None))), val(Arg::DataReg(D1))] );
+        assert_eq!( pool.name(s[2].op.ident().unwrap()), "frob" );
+        assert_eq!( s[2].args, [val(Arg::Imm(Expr::LitInt(2, None)))] );
+
+        let line = s[2].span.unwrap().line as usize;
+        assert_eq!( pp.origin(line), Some((Path::new("t.s"), 8)) );
+    }
+
+    #[test]
+    pub fn parse_errors() {
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        let errs = parse_moto(" move.l (8,d0),d1\n nop\n move.l d0 d1\n", &mut pool, &mut syms)
+            .unwrap_err();
+        assert_eq!( errs.len(), 2 );
+        assert_eq!( (errs[0].line, errs[0].col), (1, 12) );
+        assert_eq!( errs[1].line, 3 );
+    }
+}
@@ -0,0 +1,699 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use crate::ast::*;
+use crate::mem::{BE, Image, Load};
+use crate::obj::elf::*;
+use crate::obj::flat::*;
+use crate::obj::uimage::UImage;
+use super::*;
+
+// Name of the section of statements before any .section directive
+const DEFAULT_SECTION: &str = ".text";
+
+/// Returns a relocatable ELF object for `block`, assembled as `asm` with
+/// `AsmOptions::relocatable` for a target with the given `features`.
+///
+/// Chunks of the same section (see `Chunks`) are joined, each aligned to 4
+/// bytes.  A section holds code if it contains an instruction; otherwise it
+/// holds writable data, or read-only data if named `.rodata`.  A section
+/// named `.bss` reserves space if its content is all zero.
+///
+/// Labels become symbols, except local ones (see `is_local_name`), which
+/// relocations refer to by section and offset.  Other symbols with values
+/// are absolute, and global symbols without values are undefined.
+pub fn elf_object(block: &CfBlock, asm: &Assembly, syms: &SymbolTable, pool: &IdentPool, features: u16)
+    -> ElfFile
+{
+    let chunks = Chunks::of(block);
+    let mut elf = ElfFile::new(ET_REL, EM_68K, elf_flags(features));
+
+    // Join chunks into sections: chunk n is at `base[n]` in `section[n]`
+    let count = asm.sections.len() + 1;
+    let end   = asm.origin.wrapping_add(asm.bytes.len() as u32);
+    let start = |n: usize| match n {
+        0 => asm.origin,
+        _ => asm.addrs[asm.sections[n - 1].0],
+    };
+    let mut datas:   Vec<Vec<u8>> = vec![];
+    let mut codes:   Vec<bool>    = vec![];
+    let mut section: Vec<usize>   = vec![];
+    let mut base:    Vec<u32>     = vec![];
+    for n in 0..count {
+        let (lo, hi) = (start(n), if n + 1 < count { start(n + 1) } else { end });
+        let bytes = &asm.bytes[lo.wrapping_sub(asm.origin) as usize..hi.wrapping_sub(asm.origin) as usize];
+        let stmts = chunks.stmts.iter().zip(&block.stmts).filter(|s| *s.0 == n).map(|s| s.1);
+        let code  = stmts.clone().any(|s| matches!(s.op.value_copy(), Some(Op::Inst(_))));
+        let used  = n > 0 || !bytes.is_empty() || stmts.clone().any(|s| !s.labels.is_empty());
+
+        let name = match n {
+            0 => DEFAULT_SECTION,
+            _ => pool.name(asm.sections[n - 1].1),
+        };
+        let index = match elf.section_index(name) {
+            Some(i)       => i,
+            None if !used => { section.push(0); base.push(0); continue },
+            None          => {
+                elf.sections.push(ElfSection::progbits(name, 0, 4, vec![]));
+                datas.push(vec![]);
+                codes.push(false);
+                elf.sections.len() - 1
+            },
+        };
+        let data = &mut datas[index];
+        data.resize(data.len().div_ceil(4) * 4, 0);
+        section.push(index);
+        base.push(data.len() as u32);
+        data.extend_from_slice(bytes);
+        codes[index] |= code;
+    }
+    for (i, data) in datas.into_iter().enumerate() {
+        let s     = &mut elf.sections[i];
+        let flags = if codes[i] {
+            SHF_ALLOC | SHF_EXECINSTR
+        } else if s.name.starts_with(".rodata") {
+            SHF_ALLOC
+        } else {
+            SHF_ALLOC | SHF_WRITE
+        };
+        *s = if s.name.starts_with(".bss") && !codes[i] && data.iter().all(|&b| b == 0) {
+            ElfSection::nobits(&s.name, flags, 4, data.len() as u32)
+        } else {
+            ElfSection::progbits(&s.name, flags, 4, data)
+        };
+    }
+
+    // Section symbols, then others
+    for i in 0..elf.sections.len() {
+        elf.symbols.push(ElfSymbol::section(i));
+    }
+    let offset = |ident: Ident, chunk: usize| {
+        syms.value(ident).unwrap_or(0).wrapping_sub(start(chunk)).wrapping_add(base[chunk])
+    };
+    let mut index = HashMap::new();
+    for (ident, sym) in syms.iter() {
+        let name = pool.name(ident);
+        if is_local_name(name) {
+            continue
+        }
+        let (value, place) = match (chunks.labels.get(&ident), sym.value) {
+            (Some(&c), _)                                 => (offset(ident, c), ElfSymbolSection::Section(section[c])),
+            (None, Some(v))                               => (v, ElfSymbolSection::Absolute),
+            (None, None) if sym.binding != Binding::Local => (0, ElfSymbolSection::Undefined),
+            (None, None)                                  => continue,
+        };
+        index.insert(ident, elf.symbols.len());
+        elf.symbols.push(ElfSymbol {
+            name:    name.to_string(),
+            value,
+            size:    0,
+            kind:    STT_NOTYPE,
+            binding: elf_binding(sym.binding),
+            section: place,
+        });
+    }
+
+    // Relocations
+    for r in &asm.relocs {
+        let c = chunks.stmts[r.stmt];
+        let (symbol, addend) = match r.sym.map(|i| syms.target(i)) {
+            None => (None, r.addend),
+            Some(t) => match (chunks.labels.get(&t), index.get(&t)) {
+                (_,         Some(&i)) => (Some(i), r.addend),
+                (Some(&lc), None    ) => (Some(section[lc]), r.addend.wrapping_add(offset(t, lc) as i32)),
+                (None,      None    ) => {
+                    index.insert(t, elf.symbols.len());
+                    elf.symbols.push(ElfSymbol {
+                        name:    pool.name(t).to_string(),
+                        value:   0,
+                        size:    0,
+                        kind:    STT_NOTYPE,
+                        binding: STB_GLOBAL,
+                        section: ElfSymbolSection::Undefined,
+                    });
+                    (Some(elf.symbols.len() - 1), r.addend)
+                },
+            },
+        };
+        elf.sections[section[c]].relocs.push(ElfReloc {
+            offset: r.addr.wrapping_sub(start(c)).wrapping_add(base[c]),
+            kind:   elf_reloc_type(r.kind),
+            symbol,
+            addend,
+        });
+    }
+
+    elf
+}
+
+/// Returns the ELF header flags for a target with the given `features`.
+pub fn elf_flags(features: u16) -> u32 {
+    let isa = if features & ISA_C != 0 {
+        if features & HWDIV != 0 { EF_M68K_CF_ISA_C } else { EF_M68K_CF_ISA_C_NODIV }
+    } else if features & ISA_B != 0 {
+        if features & USP != 0 { EF_M68K_CF_ISA_B } else { EF_M68K_CF_ISA_B_NOUSP }
+    } else if features & ISA_A2 != 0 {
+        EF_M68K_CF_ISA_A_PLUS
+    } else if features & HWDIV != 0 {
+        EF_M68K_CF_ISA_A
+    } else {
+        EF_M68K_CF_ISA_A_NODIV
+    };
+    let mac = if features & EMAC_B != 0 {
+        EF_M68K_CF_EMAC_B
+    } else if features & EMAC != 0 {
+        EF_M68K_CF_EMAC
+    } else if features & MAC != 0 {
+        EF_M68K_CF_MAC
+    } else {
+        0
+    };
+    let float = if features & FPU != 0 { EF_M68K_CF_FLOAT } else { 0 };
+    isa | mac | float
+}
+
+/// Returns the ELF relocation type for a kind of field.
+pub fn elf_reloc_type(kind: RelocKind) -> u8 {
+    match kind {
+        RelocKind::Abs8  => R_68K_8,
+        RelocKind::Abs16 => R_68K_16,
+        RelocKind::Abs32 => R_68K_32,
+        RelocKind::Pc8   => R_68K_PC8,
+        RelocKind::Pc16  => R_68K_PC16,
+        RelocKind::Pc32  => R_68K_PC32,
+    }
+}
+
+fn elf_binding(binding: Binding) -> u8 {
+    match binding {
+        Binding::Local  => STB_LOCAL,
+        Binding::Global => STB_GLOBAL,
+        Binding::Weak   => STB_WEAK,
+    }
+}
+
+/// Returns the hardware features of a target with the given ELF header
+/// `flags`.  This is the inverse of `elf_flags`.
+pub fn elf_features(flags: u32) -> u16 {
+    let isa = match flags & 0x0F {
+        EF_M68K_CF_ISA_A_NODIV => ISA_A,
+        EF_M68K_CF_ISA_A       => ISA_A  | HWDIV,
+        EF_M68K_CF_ISA_A_PLUS  => ISA_A2 | HWDIV,
+        EF_M68K_CF_ISA_B_NOUSP => ISA_B  | HWDIV,
+        EF_M68K_CF_ISA_B       => ISA_B  | HWDIV | USP,
+        EF_M68K_CF_ISA_C       => ISA_C  | HWDIV | USP,
+        EF_M68K_CF_ISA_C_NODIV => ISA_C  | USP,
+        _                      => ISA_A,
+    };
+    let mac = match flags & 0x30 {
+        EF_M68K_CF_MAC    => MAC,
+        EF_M68K_CF_EMAC   => EMAC,
+        EF_M68K_CF_EMAC_B => EMAC | EMAC_B,
+        _                 => 0,
+    };
+    let float = if flags & EF_M68K_CF_FLOAT != 0 { FPU } else { 0 };
+    isa | mac | float
+}
+
+/// Adds the symbols of `elf` to `syms`, at the addresses given by
+/// `ElfFile::section_addrs`.
+///
+/// Undefined symbols have no value.  Function symbols become code symbols,
+/// and object symbols data symbols; untyped symbols, as `elf_object` writes,
+/// are code symbols if their section holds code.  Section and file symbols,
+/// common symbols, and symbols in sections that do not occupy memory are
+/// omitted.  If names repeat, as static symbols of different files may,
+/// the first prevails.
+pub fn elf_symbols(elf: &ElfFile, syms: &mut SymbolTable, pool: &mut IdentPool) {
+    let addrs = elf.section_addrs();
+    for sym in &elf.symbols {
+        if sym.name.is_empty() || sym.kind == STT_SECTION || sym.kind == STT_FILE {
+            continue
+        }
+        let value = match sym.section {
+            ElfSymbolSection::Undefined => None,
+            _ => match elf_symbol_addr(elf, &addrs, sym) {
+                Some(v) => Some(v),
+                None    => continue,
+            },
+        };
+        let ident = pool.intern(&sym.name);
+        if syms.get(ident).is_some_and(Symbol::is_defined) {
+            continue
+        }
+        if let Some(v) = value {
+            syms.set_value(ident, v);
+        }
+        if let ElfSymbolSection::Section(i) = sym.section {
+            syms.entry(ident).section = Some(pool.intern(&elf.sections[i].name));
+        }
+        syms.set_binding(ident, match sym.binding {
+            STB_GLOBAL => Binding::Global,
+            STB_WEAK   => Binding::Weak,
+            _          => Binding::Local,
+        });
+        syms.set_kind(ident, match sym.kind {
+            STT_FUNC   => SymbolKind::Code,
+            STT_OBJECT => SymbolKind::Data,
+            STT_NOTYPE => match sym.section {
+                ElfSymbolSection::Section(i) if elf.sections[i].is_code() => SymbolKind::Code,
+                _ => SymbolKind::Unknown,
+            },
+            _ => SymbolKind::Unknown,
+        });
+    }
+}
+
+/// Disassembles the memory image of `elf` (see `ElfFile::image`).
+///
+/// In a relocatable file, relocations against defined symbols are applied
+/// first, so that fields refer to their targets.
+pub fn disassemble_elf(elf: &ElfFile, dis: &mut Disassembler) -> Result<Vec<CfStmt>, ElfError> {
+    let mut image = elf.image()?;
+    if elf.file_type == ET_REL {
+        relocate_elf(elf, &mut image);
+    }
+    let mut stmts = dis.globals();
+    stmts.extend(dis.image(&image));
+    Ok(stmts)
+}
+
+// Returns the address of `sym`, given the address of each section.
+fn elf_symbol_addr(elf: &ElfFile, addrs: &[Option<u32>], sym: &ElfSymbol) -> Option<u32> {
+    match sym.section {
+        ElfSymbolSection::Absolute   => Some(sym.value),
+        ElfSymbolSection::Section(i) => {
+            let addr = addrs[i]?;
+            match elf.file_type {
+                ET_REL => Some(addr.wrapping_add(sym.value)),
+                _      => Some(sym.value),
+            }
+        },
+        _ => None,
+    }
+}
+
+// Applies to `image` the relocations of `elf` whose symbols have addresses.
+fn relocate_elf(elf: &ElfFile, image: &mut Image) {
+    let addrs = elf.section_addrs();
+    for (s, addr) in elf.sections.iter().zip(&addrs) {
+        let base = match *addr {
+            Some(a) => a,
+            None    => continue,
+        };
+        for r in &s.relocs {
+            let s = match r.symbol.map(|i| elf_symbol_addr(elf, &addrs, &elf.symbols[i])) {
+                Some(Some(s)) => s,
+                Some(None)    => continue,
+                None          => 0,
+            };
+            let p     = base.wrapping_add(r.offset);
+            let value = s.wrapping_add(r.addend as u32);
+            let (value, size) = match r.kind {
+                R_68K_32   => (value,                 4),
+                R_68K_16   => (value,                 2),
+                R_68K_8    => (value,                 1),
+                R_68K_PC32 => (value.wrapping_sub(p), 4),
+                R_68K_PC16 => (value.wrapping_sub(p), 2),
+                R_68K_PC8  => (value.wrapping_sub(p), 1),
+                _          => continue,
+            };
+            if let Some(field) = image.read_mut(p, size) {
+                field.copy_from_slice(&value.to_be_bytes()[4 - size..]);
+            }
+        }
+    }
+}
+
+/// Adds symbols for `flat`, loaded at `base`, to `syms`: the code symbol
+/// `_start` at the entry point and, if the file has a GOT, the data symbol
+/// `_GLOBAL_OFFSET_TABLE_` at the GOT.  Each nonzero entry of the GOT
+/// receives a data symbol `G_xxxx`, after the address that the entry holds
+/// once loaded.  Existing symbols prevail.
+///
+/// Returns the entry point, from which to disassemble even if an existing
+/// `_start` is elsewhere.
+pub fn flat_symbols(flat: &FlatFile, base: u32, syms: &mut SymbolTable, pool: &mut IdentPool) -> u32 {
+    let mut define = |name: &str, addr: u32, kind: SymbolKind| {
+        let ident = pool.intern(name);
+        if !syms.get(ident).is_some_and(Symbol::is_defined) {
+            syms.set_value(ident, addr);
+            syms.set_kind(ident, kind);
+        }
+    };
+    let entry = base.wrapping_add(flat.entry);
+    define("_start", entry, SymbolKind::Code);
+
+    let got = match flat.got_len() {
+        Some(n) => &flat.data[..4 * n],
+        None    => return entry,
+    };
+    let addr = base.wrapping_add(flat.data_addr());
+    define("_GLOBAL_OFFSET_TABLE_", addr, SymbolKind::Data);
+    for (i, entry) in got.chunks(4).enumerate() {
+        match u32::load(entry, BE).unwrap().0 {
+            0 => continue,
+            v => define(&format!("G_{:04X}", base.wrapping_add(v)), addr + 4 * i as u32, SymbolKind::Data),
+        }
+    }
+    entry
+}
+
+/// Disassembles the memory image of `flat`, loaded at `base` (see
+/// `FlatFile::image`).
+///
+/// To refer to GOT entries by name, add symbols with `flat_symbols`, and
+/// give the disassembler the address of the GOT with `with_data_base`.
+pub fn disassemble_flat(flat: &FlatFile, base: u32, dis: &mut Disassembler) -> Result<Vec<CfStmt>, FlatError> {
+    let image = flat.image(base)?;
+    let mut stmts = dis.globals();
+    stmts.extend(dis.image(&image));
+    Ok(stmts)
+}
+
+/// Adds a code symbol `_start` at the entry point of the U-Boot image `img`
+/// to `syms`.  An existing symbol prevails.
+///
+/// Returns the entry point, from which to disassemble even if an existing
+/// `_start` is elsewhere.
+pub fn uimage_symbols(img: &UImage, syms: &mut SymbolTable, pool: &mut IdentPool) -> u32 {
+    let start = pool.intern("_start");
+    if !syms.get(start).is_some_and(Symbol::is_defined) {
+        syms.set_value(start, img.entry);
+        syms.set_kind(start, SymbolKind::Code);
+    }
+    img.entry
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mem::RegionKind;
+    use super::*;
+
+    fn object(src: &str) -> (ElfFile, IdentPool) {
+        let mut pool  = IdentPool::new();
+        let mut syms  = SymbolTable::new();
+        let mut block = parse_moto(src, &mut pool, &mut syms).unwrap();
+        let mut opts  = AsmOptions::new(0);
+        opts.relocatable = true;
+        let asm = assemble(&mut block, &mut syms, &opts).unwrap();
+        (elf_object(&block, &asm, &syms, &pool, opts.features), pool)
+    }
+
+    #[test]
+    pub fn object_sections_and_relocs() {
+        let (elf, _) = object(
+            "        xdef    start\n\
+             start   lea     msg,a0\n\
+             \x20       jsr     puts\n\
+             .L1     bsr     helper\n\
+             \x20       bra     .L1\n\
+             \x20       section .data\n\
+             msg     dc.b    'hi',0\n\
+             \x20       dc.l    .L1+2,end-msg\n\
+             end\n\
+             \x20       section .bss\n\
+             buf     ds.b    16\n"
+        );
+
+        let names: Vec<&str> = elf.sections.iter().map(|s| &s.name[..]).collect();
+        assert_eq!( names, [".text", ".data", ".bss"] );
+        assert_eq!( elf.flags, EF_M68K_CF_ISA_A_NODIV );
+        assert_eq!( elf.sections[0].flags, SHF_ALLOC | SHF_EXECINSTR );
+        assert_eq!( elf.sections[1].flags, SHF_ALLOC | SHF_WRITE );
+        assert_eq!( elf.sections[2].kind, SHT_NOBITS );
+        assert_eq!( elf.sections[2].size, 16 );
+
+        // Relocated fields are zero
+        assert_eq!(
+            elf.sections[0].data[..18],
+            [0x41, 0xF9, 0, 0, 0, 0, 0x4E, 0xB9, 0, 0, 0, 0, 0x61, 0x00, 0, 0, 0x60, 0xFA]
+        );
+        assert_eq!( elf.sections[1].data, [b'h', b'i', 0, 0, 0, 0, 0, 0, 0, 0, 0, 12] );
+
+        // Relocations name symbols, or sections for local labels
+        let relocs: Vec<(usize, u32, u8, &str, i32)> = elf.sections.iter().enumerate()
+            .flat_map(|(i, s)| s.relocs.iter().map(move |r| (i, r)))
+            .map(|(i, r)| {
+                let sym = &elf.symbols[r.symbol.unwrap()];
+                let name = match (sym.kind, sym.section) {
+                    (STT_SECTION, ElfSymbolSection::Section(i)) => &elf.sections[i].name[..],
+                    _                                           => &sym.name[..],
+                };
+                (i, r.offset, r.kind, name, r.addend)
+            })
+            .collect();
+        assert_eq!( relocs, [
+            (0,  2, R_68K_32,   "msg",    0),
+            (0,  8, R_68K_32,   "puts",   0),
+            (0, 14, R_68K_PC16, "helper", 0),
+            (1,  4, R_68K_32,   ".text",  14),
+        ]);
+
+        // Local labels are omitted; undefined references are global
+        let sym = |name: &str| elf.symbols.iter().find(|s| s.name == name);
+        assert!( sym(".L1").is_none() );
+        assert_eq!( sym("start").unwrap().binding, STB_GLOBAL );
+        assert_eq!( sym("end").unwrap().value, 12 );
+        assert_eq!( sym("end").unwrap().section, ElfSymbolSection::Section(1) );
+        assert_eq!( sym("puts").unwrap().section, ElfSymbolSection::Undefined );
+        assert_eq!( sym("puts").unwrap().binding, STB_GLOBAL );
+    }
+
+    #[test]
+    pub fn object_errors() {
+        let mut pool  = IdentPool::new();
+        let mut syms  = SymbolTable::new();
+        let mut block = parse_moto(" moveq #ext,d0\n dc.l ext*2\n", &mut pool, &mut syms).unwrap();
+        let mut opts  = AsmOptions::new(0);
+        opts.relocatable = true;
+        let errs = assemble(&mut block, &mut syms, &opts).unwrap_err();
+        assert_eq!( errs.len(), 2 );
+        assert_eq!( errs[0].kind, AsmErrorKind::Relocation(0) );
+        assert_eq!( errs[1].message(&pool), "operand 1 cannot be relocated" );
+    }
+
+    #[test]
+    pub fn disassemble_object() {
+        let (elf, _) = object(
+            "        xdef    start\n\
+             start   lea     msg,a0\n\
+             \x20       jsr     puts\n\
+             \x20       bsr     helper\n\
+             helper  rts\n\
+             \x20       section .data\n\
+             msg     dc.b    'hi',0\n\
+             \x20       section .bss\n\
+             buf     ds.b    16\n"
+        );
+        let mut elf = ElfFile::read(&elf.write()).unwrap();
+        for s in &mut elf.symbols {
+            match &s.name[..] {
+                "start" => s.kind = STT_FUNC,
+                "msg"   => s.kind = STT_OBJECT,
+                _       => (),
+            }
+        }
+
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        elf_symbols(&elf, &mut syms, &mut pool);
+        let puts = pool.intern("puts");
+        assert_eq!( syms.value(puts), None );
+        assert_eq!( syms.get(puts).unwrap().binding, Binding::Global );
+
+        let kind = |name| syms.get(pool.get(name).unwrap()).unwrap().kind;
+        assert_eq!( kind("start"),  SymbolKind::Code );
+        assert_eq!( kind("msg"),    SymbolKind::Data );
+        assert_eq!( kind("helper"), SymbolKind::Code );
+        assert_eq!( kind("buf"),    SymbolKind::Unknown );
+
+        let mut d = Disassembler::new(&mut pool, &mut syms, elf_features(elf.flags));
+        let stmts = disassemble_elf(&elf, &mut d).unwrap();
+
+        // The relocation of msg applies; that of the undefined puts cannot
+        assert_eq!(
+            gas(&CfBlock { stmts }, &pool).to_string(),
+            "        .globl start\n\
+             \x20       .globl puts\n\
+             \x20       .section .text\n\
+             \x20       .org 0\n\
+             start:\n\
+             \x20       lea.l (0x14).l,%a0\n\
+             \x20       jsr (0).l\n\
+             \x20       bsr.w helper\n\
+             helper:\n\
+             \x20       rts\n\
+             \x20       .short 0\n\
+             \x20       .section .data\n\
+             \x20       .org 0x14\n\
+             msg:\n\
+             \x20       .long 0x68690000\n\
+             \x20       .section .bss\n\
+             \x20       .org 0x18\n\
+             buf:\n\
+             \x20       .zero 0x10\n"
+        );
+    }
+
+    #[test]
+    pub fn traverse_object() {
+        let (elf, _) = object(
+            "        xdef    start\n\
+             start   bsr     helper\n\
+             \x20       jmp     .L1\n\
+             \x20       dc.l    $12345678\n\
+             .L1     rts\n\
+             helper  moveq   #0,d0\n\
+             \x20       rts\n\
+             \x20       section .data\n\
+             msg     dc.b    'hi',0\n"
+        );
+        let elf = ElfFile::read(&elf.write()).unwrap();
+
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        elf_symbols(&elf, &mut syms, &mut pool);
+        let mut image = elf.image().unwrap();
+        relocate_elf(&elf, &mut image);
+
+        // Untyped symbols in .text seed traversal; the jump to the local
+        // label, which has no symbol, is followed
+        let seeds = seeds(None, &[], &syms, &[]);
+        assert_eq!( seeds, [0, 14] );
+        let insts = traverse(&mut image, &seeds, elf_features(elf.flags));
+        assert_eq!( insts, [0, 2, 12, 14, 16] );
+
+        let kinds: Vec<_> = image.blocks().iter()
+            .map(|b| (b.region.vma, b.region.len, b.region.kind))
+            .collect();
+        assert_eq!( kinds, [
+            ( 0, 8, RegionKind::Code),
+            ( 8, 4, RegionKind::Data),
+            (12, 6, RegionKind::Code),
+            (18, 2, RegionKind::Data),
+            (20, 3, RegionKind::Data),
+        ]);
+    }
+
+    #[test]
+    pub fn features_from_flags() {
+        for &features in &[ISA_A, ISA_A | HWDIV | MAC, ISA_A2 | HWDIV, ISA_B | HWDIV | EMAC, ISA_C | USP | FPU] {
+            assert_eq!( elf_flags(elf_features(elf_flags(features))), elf_flags(features) );
+        }
+        assert_eq!( elf_features(EF_M68K_CF_ISA_B | EF_M68K_CF_EMAC_B), ISA_B | HWDIV | USP | EMAC | EMAC_B );
+    }
+
+    #[test]
+    pub fn disassemble_flat_file() {
+        let flat = FlatFile {
+            entry: 0,
+            flags: FLAT_FLAG_RAM | FLAT_FLAG_GOTPIC,
+            text:  vec![
+                0x20, 0x6D, 0x00, 0x04,     // movea.l (4,a5),a0
+                0x4E, 0x90,                 // jsr (a0)
+                0x4E, 0x75,                 // rts
+                0x4E, 0x75,                 // rts
+                0x20, 0x2D, 0x00, 0x10,     // move.l (16,a5),d0
+                0x4E, 0x71,                 // nop
+            ],
+            data:  vec![
+                0x00, 0x00, 0x00, 0x1C,     // GOT: var
+                0x00, 0x00, 0x00, 0x08,     // GOT: func
+                0xFF, 0xFF, 0xFF, 0xFF,     // GOT end
+            ],
+            bss_size: 4,
+            ..FlatFile::default()
+        };
+
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        assert_eq!( flat_symbols(&flat, 0x1000, &mut syms, &mut pool), 0x1000 );
+        let got = syms.value(pool.intern("_GLOBAL_OFFSET_TABLE_"));
+        assert_eq!( got, Some(0x1010) );
+
+        let kind = |name| syms.get(pool.get(name).unwrap()).unwrap().kind;
+        assert_eq!( kind("_start"),                SymbolKind::Code );
+        assert_eq!( kind("_GLOBAL_OFFSET_TABLE_"), SymbolKind::Data );
+        assert_eq!( kind("G_1008"),                SymbolKind::Data );
+
+        let mut d = Disassembler::new(&mut pool, &mut syms, ISA_A).with_data_base(got);
+        let stmts = disassemble_flat(&flat, 0x1000, &mut d).unwrap();
+
+        // A displacement to an address without a symbol remains numeric
+        assert_eq!(
+            gas(&CfBlock { stmts }, &pool).to_string(),
+            "        .section .text\n\
+             \x20       .org 0x1000\n\
+             _start:\n\
+             \x20       movea.l G_1008-_GLOBAL_OFFSET_TABLE_(%a5),%a0\n\
+             \x20       jsr (%a0)\n\
+             \x20       rts\n\
+             \x20       rts\n\
+             \x20       move.l 0x10(%a5),%d0\n\
+             \x20       nop\n\
+             \x20       .section .got\n\
+             \x20       .org 0x1010\n\
+             _GLOBAL_OFFSET_TABLE_:\n\
+             G_101C:\n\
+             \x20       .long 0x101C\n\
+             G_1008:\n\
+             \x20       .long 0x1008,0xFFFFFFFF\n\
+             \x20       .section .bss\n\
+             \x20       .org 0x101C\n\
+             \x20       .zero 4\n"
+        );
+    }
+
+    #[test]
+    pub fn disassemble_uimage() {
+        use crate::obj::uimage::*;
+
+        let img = UImage {
+            load:  0x4000,
+            entry: 0x4004,
+            arch:  IH_ARCH_M68K,
+            kind:  IH_TYPE_STANDALONE,
+            data:  vec![0x4E, 0x71, 0x4E, 0x71, 0x4E, 0x75],
+            ..UImage::default()
+        };
+        let img = UImage::read(&img.write()).unwrap();
+
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        assert_eq!( uimage_symbols(&img, &mut syms, &mut pool), 0x4004 );
+
+        // An existing _start prevails, but the entry point is returned
+        let mut pool2 = IdentPool::new();
+        let mut syms2 = SymbolTable::new();
+        let     start = pool2.intern("_start");
+        syms2.set_value(start, 0x4000);
+        assert_eq!( uimage_symbols(&img, &mut syms2, &mut pool2), 0x4004 );
+        assert_eq!( syms2.value(start), Some(0x4000) );
+
+        let mut d = Disassembler::new(&mut pool, &mut syms, ISA_A);
+        let stmts = d.image(&img.image().unwrap());
+        assert_eq!(
+            gas(&CfBlock { stmts }, &pool).to_string(),
+            "        .org 0x4000\n\
+             \x20       nop\n\
+             \x20       nop\n\
+             _start:\n\
+             \x20       rts\n"
+        );
+    }
+}
@@ -14,11 +14,11 @@
 // You should have received a copy of the GNU General Public License
 // along with cfda.  If not, see <http://www.gnu.org/licenses/>.
 
-use crate::ast::Expr;
+use crate::ast::{Expr, Float};
 use crate::decode::{* /*, DecodeIndex as X*/};
 use crate::mem::{BE, Load};
 //use crate::num::Field;
-use super::{Arg, AddrReg, DataReg};
+use super::{Arg, AddrReg, CcrReg, DataReg, DataRegPair, FpReg, Index, IndexReg, PcReg, RegList, SrReg};
 
 /// ColdFire operand kinds and bit positions.
 #[allow(non_camel_case_types)]
@@ -46,7 +46,7 @@ pub enum Operand {
 
     /// Readable addressing mode (6 bits at 5:0)
     MdaipmdxnfDXI0,
-/*
+
     /// Writable addressing modes (6 bits at 5:0)
     Mdaipmdxnf___0,
 
@@ -58,19 +58,31 @@ pub enum Operand {
 
     /// Readable memory addressing modes without side effects (6 bits at 5:0)
     M__i__dxnfDX_0,
-*/
+
     /// Writable memory addressing modes (6 bits at 5:0)
     M__ipmdxnf___0,
 
+    /// Writable memory modes for bit ops with immediate (6 bits at 5:0)
+    M__ipmd______0,
+
     /// Source modes for op with extension word (6 bits at 5:0)
     Md_ipmd______0,
-/*
+
     /// Address register indirect mode, potentially displaced (movem) (6 bits at 5:0)
     M__i__d______0,
 
     /// Data register or immediate modes (move to ccr/sr) (6 bits at 5:0)
     Md__________I0,
-*/
+
+    /// FPU single-precision source modes (6 bits at 5:0)
+    Md_ipmd__D_I0,
+
+    /// FPU double-precision source modes (6 bits at 5:0)
+    M__ipmd__D_I0,
+
+    /// Writable data addressing modes, move destination (6 bits at 11:6)
+    Md_ipmdxnf___6,
+
     /// Data register (3 bits at 2:0)
     DataReg0,
 
@@ -79,15 +91,24 @@ pub enum Operand {
 
     /// Data register (3 bits at 30:28)
     DataReg28,
-/*
-    /// Data register, same one as previous operand (3 bits)
-    DataRegSame?,
 
-    /// Data register, different one from prevous operand (3 bits)
-    DataRegDiff?,
-*/
+    /// Data register, same one in two places (3 bits at 30:28 and 18:16)
+    DataRegQ,
+
+    /// Data register pair, remainder:dividend (3 bits at 18:16 and 30:28)
+    DataRegPair16,
+
+    /// Address register (3 bits at 2:0)
+    AddrReg0,
+
     /// Address register (3 bits at 11:9)
     AddrReg9,
+
+    /// Floating-point data register (3 bits at 25:23)
+    FpReg23,
+
+    /// Floating-point data register (3 bits at 28:26)
+    FpReg26,
 /*
     /// Data or address register (4 bits at ?)
     NormalReg?,
@@ -97,73 +118,423 @@ pub enum Operand {
 
     /// Debug control register (5 bits at ?)
     DbgReg?,
-
+*/
     /// Condition code register (implicit)
     Ccr,
 
-    /// Condition code register (implicit)
+    /// Status register (implicit)
     Sr,
-
+/*
     /// User stack pointer (implicit)
     Usp,
-
-    /// Data/address register list (16 bits in extension word)
-    RegList,
-
+*/
+    /// Data/address register list (16 bits at 31:16)
+    RegList16,
+/*
     /// Cache selector (2 bits)
     CacheSel?,
 */
+    /// Immediate (8 bits in extension word)
+    Imm8,
+
     /// Immediate (16 bits in extension words)
     Imm16,
 
     /// Immediate (32 bits in extension words)
     Imm32,
-/*
-    /// Quick immediate (3 bits unsigned; 0 => 8; at ?)
-    Quick3?,
 
-    /// Quick immediate (4 bits unsigned at ?)
-    Quick4?,
+    /// Quick immediate (3 bits unsigned; 0 => 8; at 11:9)
+    Quick3,
+
+    /// Quick immediate (3 bits unsigned; 0 => -1; at 11:9)
+    Quick3Mov,
 
-    /// Quick immediate (8 bits signed at ?)
-    Quick8?,
+    /// Quick immediate (4 bits unsigned at 3:0)
+    Quick4,
 
-    /// PC-relative immediate offset (8 bits signed at ?)
-    PcRel8?,
+    /// Quick immediate (8 bits signed at 7:0)
+    Quick8,
+
+    /// PC-relative immediate offset (8 bits signed at 7:0)
+    PcRel8,
 
     /// PC-relative immediate offset (16 bits signed in extension word)
     PcRel16,
 
     /// PC-relative immediate offset (32 bits signed in extension words)
     PcRel32,
-*/
 }
 
-impl Decode<[u8], u16> for Operand {
+// General addressing mode bits, as in the operand names above
+pub const MODE_DATA:            u16 = 1 <<  0;  // d "data"
+pub const MODE_ADDR:            u16 = 1 <<  1;  // a "address"
+pub const MODE_ADDR_IND:        u16 = 1 <<  2;  // i "indirect"
+pub const MODE_ADDR_IND_INC:    u16 = 1 <<  3;  // p "plus"
+pub const MODE_ADDR_IND_DEC:    u16 = 1 <<  4;  // m "minus"
+pub const MODE_ADDR_DISP:       u16 = 1 <<  5;  // d "displacement"
+pub const MODE_ADDR_DISP_IDX:   u16 = 1 <<  6;  // x "index"
+pub const MODE_ABS_NEAR:        u16 = 1 <<  7;  // n "near"
+pub const MODE_ABS_FAR:         u16 = 1 <<  8;  // f "far"
+pub const MODE_PC_DISP:         u16 = 1 <<  9;  // D "Displacement"
+pub const MODE_PC_DISP_IDX:     u16 = 1 << 10;  // X "indeX"
+pub const MODE_IMM:             u16 = 1 << 11;  // I "Immediate"
+
+// Syntax of each general addressing mode
+static MODE_FORMS: [(u16, &str); 12] = [
+    (MODE_DATA,          "Dn"),
+    (MODE_ADDR,          "An"),
+    (MODE_ADDR_IND,      "(An)"),
+    (MODE_ADDR_IND_INC,  "(An)+"),
+    (MODE_ADDR_IND_DEC,  "-(An)"),
+    (MODE_ADDR_DISP,     "(d16,An)"),
+    (MODE_ADDR_DISP_IDX, "(d8,An,Xi)"),
+    (MODE_ABS_NEAR,      "(xxx).W"),
+    (MODE_ABS_FAR,       "(xxx).L"),
+    (MODE_PC_DISP,       "(d16,PC)"),
+    (MODE_PC_DISP_IDX,   "(d8,PC,Xi)"),
+    (MODE_IMM,           "#<data>"),
+];
+
+const MODES_ALL:    u16 = 0b_1111_1111_1111;
+const MODES_D:      u16 = MODE_DATA;
+const MODES_A:      u16 = MODE_ADDR;
+const MODES_IPM:    u16 = MODE_ADDR_IND | MODE_ADDR_IND_INC | MODE_ADDR_IND_DEC;
+const MODES_DX:     u16 = MODE_ADDR_DISP | MODE_ADDR_DISP_IDX;
+const MODES_NF:     u16 = MODE_ABS_NEAR | MODE_ABS_FAR;
+const MODES_PC:     u16 = MODE_PC_DISP | MODE_PC_DISP_IDX;
+
+/// Returns the general addressing mode bit for the argument, or 0 if the
+/// argument does not use a general addressing mode.  A bare expression
+/// could be either absolute mode.
+pub fn mode_of(arg: &Arg) -> u16 {
+    match *arg {
+        Arg::DataReg       (..) => MODE_DATA,
+        Arg::AddrReg       (..) => MODE_ADDR,
+        Arg::AddrRegInd    (..) => MODE_ADDR_IND,
+        Arg::AddrRegIndInc (..) => MODE_ADDR_IND_INC,
+        Arg::AddrRegIndDec (..) => MODE_ADDR_IND_DEC,
+        Arg::AddrRegDisp   (..) => MODE_ADDR_DISP,
+        Arg::AddrRegIdx    (..) => MODE_ADDR_DISP_IDX,
+        Arg::AbsShort      (..) => MODE_ABS_NEAR,
+        Arg::AbsLong       (..) => MODE_ABS_FAR,
+        Arg::PcDisp        (..) => MODE_PC_DISP,
+        Arg::PcIdx         (..) => MODE_PC_DISP_IDX,
+        Arg::Imm           (..) => MODE_IMM,
+        Arg::Expr          (..) => MODES_NF,
+        _                       => 0,
+    }
+}
+
+impl Operand {
+    /// Returns the general addressing modes accepted by the operand, or 0 if
+    /// the operand is not a general addressing mode.
+    pub fn modes(self) -> u16 {
+        use Operand::*;
+        match self {
+            MdaipmdxnfDXI0 => MODES_ALL,
+            Mdaipmdxnf___0 => MODES_D | MODES_A | MODES_IPM | MODES_DX | MODES_NF,
+            Md_ipmdxnfDXI0 => MODES_ALL & !MODES_A,
+            Md_ipmdxnf___0 => MODES_D | MODES_IPM | MODES_DX | MODES_NF,
+            M__i__dxnfDX_0 => MODE_ADDR_IND | MODES_DX | MODES_NF | MODES_PC,
+            M__ipmdxnf___0 => MODES_IPM | MODES_DX | MODES_NF,
+            M__ipmd______0 => MODES_IPM | MODE_ADDR_DISP,
+            Md_ipmd______0 => MODES_D | MODES_IPM | MODE_ADDR_DISP,
+            M__i__d______0 => MODE_ADDR_IND | MODE_ADDR_DISP,
+            Md__________I0 => MODES_D | MODE_IMM,
+            Md_ipmd__D_I0  => MODES_D | MODES_IPM | MODE_ADDR_DISP | MODE_PC_DISP | MODE_IMM,
+            M__ipmd__D_I0  => MODES_IPM | MODE_ADDR_DISP | MODE_PC_DISP | MODE_IMM,
+            Md_ipmdxnf___6 => MODES_D | MODES_IPM | MODES_DX | MODES_NF,
+            _              => 0,
+        }
+    }
+
+    /// Returns the assembly syntax of each form of argument accepted by the
+    /// operand, in the notation of the ColdFire Programmer's Reference Manual.
+    pub fn forms(self) -> Vec<&'static str> {
+        use Operand::*;
+        match self {
+            None                        => vec![],
+            DataReg0 | DataReg9
+                | DataReg28 | DataRegQ  => vec!["Dn"],
+            DataRegPair16               => vec!["Dw:Dx"],
+            AddrReg0 | AddrReg9         => vec!["An"],
+            FpReg23 | FpReg26           => vec!["FPn"],
+            Ccr                         => vec!["CCR"],
+            Sr                          => vec!["SR"],
+            RegList16                   => vec!["<list>"],
+            Imm8 | Imm16 | Imm32
+                | Quick3 | Quick3Mov
+                | Quick4 | Quick8       => vec!["#<data>"],
+            PcRel8 | PcRel16 | PcRel32  => vec!["<label>"],
+            _ => MODE_FORMS.iter()
+                .filter(|&&(m, _)| self.modes() & m != 0)
+                .map(|&(_, s)| s)
+                .collect(),
+        }
+    }
+
+    /// Returns the inclusive range of values that the operand can encode for
+    /// the argument, if limited, given the operation size in bytes.  For
+    /// displacement modes, the range is that of the displacement.
+    pub fn range(self, arg: &Arg, size: u32) -> Option<(i32, i32)> {
+        use Operand::*;
+        match self {
+            Imm8                    => Some((  -0x80,   0xFF)),
+            Imm16                   => Some((-0x8000, 0xFFFF)),
+            Quick3                  => Some((      1,      8)),
+            Quick3Mov               => Some((     -1,      7)),
+            Quick4                  => Some((      0,     15)),
+            Quick8 | PcRel8         => Some((  -0x80,   0x7F)),
+            PcRel16                 => Some((-0x8000, 0x7FFF)),
+            _ if self.modes() == 0  => Option::None,
+            _ => match *arg {
+                Arg::AddrRegDisp (..)
+                    | Arg::AbsShort (..)
+                    | Arg::PcDisp   (..)    => Some((-0x8000, 0x7FFF)),
+                Arg::AddrRegIdx  (..)
+                    | Arg::PcIdx    (..)    => Some((  -0x80,   0x7F)),
+                Arg::Imm (..) if size == 1  => Some((  -0x80,   0xFF)),
+                Arg::Imm (..) if size == 2  => Some((-0x8000, 0xFFFF)),
+                _                           => Option::None,
+            },
+        }
+    }
+
+    /// Returns whether the operand can represent the argument.
+    pub fn accepts(self, arg: &Arg) -> bool {
+        use Operand::*;
+        match self {
+            None                        => false,
+            DataReg0 | DataReg9
+                | DataReg28 | DataRegQ  => matches!(*arg, Arg::DataReg(_)),
+            DataRegPair16               => matches!(*arg, Arg::DataRegPair(_)),
+            AddrReg0 | AddrReg9         => matches!(*arg, Arg::AddrReg(_)),
+            FpReg23 | FpReg26           => matches!(*arg, Arg::FpReg(_)),
+            Ccr                         => matches!(*arg, Arg::CcrReg(_)),
+            Sr                          => matches!(*arg, Arg::SrReg(_)),
+            RegList16                   => matches!(*arg, Arg::RegList(_)
+                                                    | Arg::DataReg(_)
+                                                    | Arg::AddrReg(_)),
+            Imm8 | Imm16 | Imm32
+                | Quick3 | Quick3Mov
+                | Quick4 | Quick8       => matches!(*arg, Arg::Imm(_)),
+            PcRel8 | PcRel16 | PcRel32  => matches!(*arg, Arg::Expr(_)),
+            _                           => self.modes() & mode_of(arg) != 0,
+        }
+    }
+
+    /// Returns the number of extension words the operand occupies beyond
+    /// the opcode, given the argument and the operation size in bytes.
+    pub fn ext_words(self, arg: &Arg, size: u32) -> u32 {
+        use Operand::*;
+        match self {
+            Imm8 | Imm16 | PcRel16 => 1,
+            Imm32 | PcRel32        => 2,
+            _ if self.modes() == 0 => 0,
+            _ => match *arg {
+                Arg::AddrRegDisp (..) => 1,
+                Arg::AddrRegIdx  (..) => 1,
+                Arg::AbsShort    (..) => 1,
+                Arg::AbsLong     (..) => 2,
+                Arg::Expr        (..) => 2,
+                Arg::PcDisp      (..) => 1,
+                Arg::PcIdx       (..) => 1,
+                Arg::Imm         (..) => size.div_ceil(2),
+                _                     => 0,
+            },
+        }
+    }
+}
+
+/// Context for decoding an operand.
+#[derive(Clone, Copy, Debug)]
+pub struct OperandContext {
+    /// Opword, plus extension word in upper 16 bits.
+    pub opcode: u32,
+
+    /// Address of the opword.
+    pub pc: u32,
+
+    /// Address of the machine code being decoded.
+    pub here: u32,
+
+    /// Operation size in bytes.
+    pub size: u32,
+
+    /// Whether the operation size is floating-point.
+    pub float: bool,
+}
+
+impl Decode<[u8], OperandContext> for Operand {
     type Output = Arg;
 
-    fn decode<'a>(&self, buf: &'a [u8], ctx: &u16) -> Option<(Arg, &'a [u8])> {
-        match *self {
-            Operand::DataReg0 => {
-                Some(( Arg::DataReg(DataReg::decode(*ctx, 0)), buf ))
+    fn decode<'a>(&self, buf: &'a [u8], ctx: &OperandContext) -> Option<(Arg, &'a [u8])> {
+        let op = ctx.opcode;
+        let arg = match *self {
+            Operand::None          => return None,
+            Operand::DataReg0      => Arg::DataReg(DataReg::decode(op,  0u8)),
+            Operand::DataReg9      => Arg::DataReg(DataReg::decode(op,  9u8)),
+            Operand::DataReg28     => Arg::DataReg(DataReg::decode(op, 28u8)),
+            Operand::DataRegQ      => {
+                let r = DataReg::decode(op, 28u8);
+                if r != DataReg::decode(op, 16u8) { return None }
+                Arg::DataReg(r)
             },
-            Operand::DataReg9 => {
-                Some(( Arg::DataReg(DataReg::decode(*ctx, 9)), buf ))
+            Operand::DataRegPair16 => Arg::DataRegPair(DataRegPair(
+                DataReg::decode(op, 16u8),
+                DataReg::decode(op, 28u8),
+            )),
+            Operand::AddrReg0      => Arg::AddrReg(AddrReg::decode(op, 0u8)),
+            Operand::AddrReg9      => Arg::AddrReg(AddrReg::decode(op, 9u8)),
+            Operand::FpReg23       => Arg::FpReg(FpReg::decode(op, 23u8)),
+            Operand::FpReg26       => Arg::FpReg(FpReg::decode(op, 26u8)),
+            Operand::Ccr           => Arg::CcrReg(CcrReg),
+            Operand::Sr            => Arg::SrReg(SrReg),
+            Operand::RegList16     => match (op >> 16) as u16 {
+                0    => return None,
+                list => Arg::RegList(RegList(list)),
             },
-            Operand::AddrReg9 => {
-                Some(( Arg::AddrReg(AddrReg::decode(*ctx, 9)), buf ))
+            Operand::Imm8 => {
+                let (ext, buf) = u16::load(buf, BE)?;
+                if ext > 0xFF { return None }
+                return Some(( imm(ext as i64), buf ))
             },
             Operand::Imm16 => {
                 let (ext, buf) = u16::load(buf, BE)?;
-                Some(( Arg::Imm(Expr::LitInt(ext as i64)), buf ))
+                return Some(( imm(ext as i64), buf ))
             },
             Operand::Imm32 => {
                 let (ext, buf) = u32::load(buf, BE)?;
-                Some(( Arg::Imm(Expr::LitInt(ext as i64)), buf ))
+                return Some(( imm(ext as i64), buf ))
             },
-            _ => None
-        }
+            Operand::Quick3 => {
+                match op >> 9 & 7 { 0 => imm(8), n => imm(n as i64) }
+            },
+            Operand::Quick3Mov => {
+                match op >> 9 & 7 { 0 => imm(-1), n => imm(n as i64) }
+            },
+            Operand::Quick4 => imm((op & 0xF) as i64),
+            Operand::Quick8 => imm(op as u8 as i8 as i64),
+            Operand::PcRel8 => {
+                // Displacements 0 and -1 select the 16- and 32-bit forms
+                let d = op as u8 as i8 as u32;
+                if d == 0 || d == u32::MAX { return None }
+                target(ctx.pc.wrapping_add(2).wrapping_add(d))
+            },
+            Operand::PcRel16 => {
+                let (d, buf) = u16::load(buf, BE)?;
+                let t = ctx.pc.wrapping_add(2).wrapping_add(d as i16 as u32);
+                return Some(( target(t), buf ))
+            },
+            Operand::PcRel32 => {
+                let (d, buf) = u32::load(buf, BE)?;
+                let t = ctx.pc.wrapping_add(2).wrapping_add(d);
+                return Some(( target(t), buf ))
+            },
+            _ => {
+                let (mode, reg) = if *self == Operand::Md_ipmdxnf___6 {
+                    (op >> 6 & 7, op >> 9 & 7)
+                } else {
+                    (op >> 3 & 7, op & 7)
+                };
+                let (arg, buf) = decode_ea(mode, reg as u8, buf, ctx)?;
+                if self.modes() & mode_of(&arg) == 0 { return None }
+                return Some(( arg, buf ))
+            },
+        };
+        Some(( arg, buf ))
+    }
+}
+
+// Decodes a general addressing mode.
+fn decode_ea<'a>(mode: u32, reg: u8, buf: &'a [u8], ctx: &OperandContext)
+    -> Option<(Arg, &'a [u8])>
+{
+    let an = AddrReg::decode(reg, 0u8);
+    let arg = match mode {
+        0 => Arg::DataReg(DataReg::decode(reg, 0u8)),
+        1 => Arg::AddrReg(an),
+        2 => Arg::AddrRegInd(an),
+        3 => Arg::AddrRegIndInc(an),
+        4 => Arg::AddrRegIndDec(an),
+        5 => {
+            let (d, buf) = u16::load(buf, BE)?;
+            return Some(( Arg::AddrRegDisp(an, Expr::LitInt(d as i16 as i64, None)), buf ))
+        },
+        6 => {
+            let (w, buf) = u16::load(buf, BE)?;
+            let (d, x)   = decode_brief(w)?;
+            return Some(( Arg::AddrRegIdx(an, Expr::LitInt(d as i64, None), x), buf ))
+        },
+        _ => match reg {
+            0 => {
+                // Sign-extended to a 32-bit address
+                let (a, buf) = u16::load(buf, BE)?;
+                return Some(( Arg::AbsShort(addr(a as i16 as u32)), buf ))
+            },
+            1 => {
+                let (a, buf) = u32::load(buf, BE)?;
+                return Some(( Arg::AbsLong(addr(a)), buf ))
+            },
+            2 => {
+                let (d, buf) = u16::load(buf, BE)?;
+                let t = ctx.here.wrapping_add(d as i16 as u32);
+                return Some(( Arg::PcDisp(PcReg, addr(t)), buf ))
+            },
+            3 => {
+                let (w, buf) = u16::load(buf, BE)?;
+                let (d, x)   = decode_brief(w)?;
+                let t = ctx.here.wrapping_add(d as i32 as u32);
+                return Some(( Arg::PcIdx(PcReg, addr(t), x), buf ))
+            },
+            4 if ctx.float => {
+                let x = match ctx.size {
+                    4 => { let (v, b) = f32::load(buf, BE)?; (Float::single(v), b) },
+                    _ => { let (v, b) = f64::load(buf, BE)?; (Float::new (v), b) },
+                };
+                return Some(( Arg::Imm(Expr::LitFloat(x.0, None)), x.1 ))
+            },
+            4 => {
+                let (v, buf) = match ctx.size {
+                    1 => { let (v, b) = u16::load(buf, BE)?; if v > 0xFF { return None } (v as u32, b) },
+                    2 => { let (v, b) = u16::load(buf, BE)?; (v as u32, b) },
+                    _ => u32::load(buf, BE)?,
+                };
+                return Some(( imm(v as i64), buf ))
+            },
+            _ => return None,
+        },
+    };
+    Some(( arg, buf ))
+}
+
+// Decodes a brief extension word: index register, size (always long), scale,
+// and 8-bit displacement.
+fn decode_brief(word: u16) -> Option<(i8, Index)> {
+    let scale = word >> 9 & 3;
+    if word & 1 << 11 == 0 || word & 1 << 8 != 0 || scale == 3 {
+        return None
     }
+    let index = Index {
+        reg:   IndexReg::decode(word, 12),
+        scale: Expr::LitInt(1 << scale, None),
+    };
+    Some(( word as u8 as i8, index ))
+}
+
+#[inline]
+fn imm(value: i64) -> Arg {
+    Arg::Imm(Expr::LitInt(value, None))
+}
+
+#[inline]
+fn addr(value: u32) -> Expr {
+    Expr::LitInt(value as i64, None)
+}
+
+#[inline]
+fn target(value: u32) -> Arg {
+    Arg::Expr(addr(value))
 }
 
 /*
@@ -215,19 +586,6 @@ static MODES_7X: [DecodeIndex<Mode>; 8] = [
     /*[77]*/ X::Empty,
 ];
 
-const MODE_DATA:            u16 = 1 <<  0;  // d "data"
-const MODE_ADDR:            u16 = 1 <<  1;  // a "address"
-const MODE_ADDR_IND:        u16 = 1 <<  2;  // i "indirect"
-const MODE_ADDR_IND_INC:    u16 = 1 <<  3;  // p "plus"
-const MODE_ADDR_IND_DEC:    u16 = 1 <<  4;  // m "minus"
-const MODE_ADDR_DISP:       u16 = 1 <<  5;  // d "displacement"
-const MODE_ADDR_DISP_IDX:   u16 = 1 <<  6;  // x "index"
-const MODE_ABS_NEAR:        u16 = 1 <<  7;  // n "near"
-const MODE_ABS_FAR:         u16 = 1 <<  8;  // f "far"
-const MODE_PC_DISP:         u16 = 1 <<  9;  // D "Displacement"
-const MODE_PC_DISP_IDX:     u16 = 1 << 10;  // X "indeX"
-const MODE_IMM:             u16 = 1 << 11;  // I "Immediate"
-
 fn mode_data() {}
 fn mode_addr() {}
 fn mode_addr_ind() {}
@@ -0,0 +1,218 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::ast::*;
+use super::*;
+
+/// A statement rewritten by the optimisation pass.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Optimization {
+    /// Index of the statement.
+    pub stmt: usize,
+
+    /// Instruction before optimisation.
+    pub inst: Instructions,
+
+    /// Arguments before optimisation.
+    pub args: Vec<Slot<Arg>>,
+
+    /// Number of bytes saved, or 0 if the original was not encodable.
+    pub saved: u32,
+}
+
+/// Rewrites the instruction in `stmt` to a smaller equivalent encoding
+/// supported by `features`, if one exists.  The function `lookup` provides
+/// values of symbols in immediate arguments and displacements.  Returns the
+/// number of bytes saved, or `None` if the statement is unchanged.
+///
+/// Rewrites preserve the effect on registers, memory, and condition codes:
+///
+/// - `move.l #q,Dn` becomes `moveq.l` for q in -128..127;
+/// - `move.x #0,<ea>` becomes `clr.x`;
+/// - `move.l #q,<ea>` becomes `mov3q.l` for q of -1 or 1..7, except to An;
+/// - `add.l`, `adda.l`, `addi.l`, and the `sub` forms with `#q` for q in
+///   1..8 become `addq.l` or `subq.l` of the same kind;
+/// - those with `#q` for q in -8..-1 become the opposite kind with `#-q`
+///   only for an An destination, as C and X would differ for others;
+/// - other 16-bit `adda.l` or `suba.l` immediates become `lea (d16,An),An`;
+/// - a zero displacement `(0,An)` becomes `(An)`.
+pub fn optimize<F>(stmt: &mut CfStmt, features: u16, lookup: &F) -> Option<u32>
+where
+    F: Fn(Ident) -> Option<u32>
+{
+    let inst = match stmt.op {
+        Slot::Value(Op::Inst(i), _) => i,
+        _                           => return None,
+    };
+
+    // Only fully valued arguments are considered.
+    let args: Vec<Arg> = stmt.args.iter()
+        .map(|a| match *a { Slot::Value(ref v, _) => Some(v.clone()), _ => None })
+        .collect::<Option<_>>()?;
+
+    let old_size = select(inst, &args, features).map(|e| size(e, &args)).ok();
+
+    let mut best = None;
+    let mut size_ = old_size;
+    let mut try_form = |inst: Instructions, args: Vec<Arg>| {
+        let enc = match select(inst, &args, features) {
+            Ok(enc) => enc,
+            Err(_)  => return false,
+        };
+        let new = size(enc, &args);
+        if size_.is_some_and(|s| new >= s) {
+            return false
+        }
+        size_ = Some(new);
+        best  = Some((enc.inst(), args));
+        true
+    };
+
+    let stripped = strip_zero_disp(&args, lookup);
+    let args = if stripped != args && try_form(inst, stripped.clone()) { stripped } else { args };
+
+    for (inst, args) in candidates(inst, &args, lookup) {
+        if try_form(inst, args) {
+            break
+        }
+    }
+
+    let (inst, args) = best?;
+    let spans: Vec<_> = stmt.args.iter().map(Slot::span).collect();
+    stmt.op   = Slot::Value(Op::Inst(inst), stmt.op.span());
+    stmt.args = args.into_iter().enumerate()
+        .map(|(i, a)| Slot::Value(a, spans.get(i).copied().flatten()))
+        .collect();
+    Some(old_size.zip(size_).map_or(0, |(o, n)| o - n))
+}
+
+fn strip_zero_disp<F>(args: &[Arg], lookup: &F) -> Vec<Arg>
+where
+    F: Fn(Ident) -> Option<u32>
+{
+    args.iter().map(|a| match *a {
+        Arg::AddrRegDisp(r, ref d) if eval(d, lookup).is_ok_and(|v| v.0 == 0) => {
+            Arg::AddrRegInd(r)
+        },
+        _ => a.clone(),
+    }).collect()
+}
+
+// Returns equivalent forms of an instruction with an immediate source, in
+// order of preference.
+fn candidates<F>(inst: Instructions, args: &[Arg], lookup: &F) -> Vec<(Instructions, Vec<Arg>)>
+where
+    F: Fn(Ident) -> Option<u32>
+{
+    use Instructions::*;
+
+    let (src, dst) = match args {
+        [Arg::Imm(e), dst] => (e, dst),
+        _                  => return vec![],
+    };
+    let v = match eval(src, lookup) {
+        Ok(v)  => v.0 as i32,
+        Err(_) => return vec![],
+    };
+
+    let imm  = || Arg::Imm(src.clone());
+    let neg  = || Arg::Imm(Expr::LitInt(-(v as i64), None));
+    let an   = match *dst { Arg::AddrReg(r) => Some(r), _ => None };
+    let dn   = matches!(*dst, Arg::DataReg(_));
+    let with = |i, a| vec![(i, vec![a, dst.clone()])];
+
+    let mut c = vec![];
+    match inst {
+        MOVEL | MOVEW | MOVEB if an.is_none() => {
+            if inst == MOVEL && dn && (-0x80..=0x7F).contains(&v) {
+                c.extend(with(MOVEQL, imm()));
+            }
+            if v == 0 {
+                let clr = match inst { MOVEB => CLRB, MOVEW => CLRW, _ => CLRL };
+                c.push((clr, vec![dst.clone()]));
+            }
+            if inst == MOVEL && (v == -1 || (1..=7).contains(&v)) {
+                c.extend(with(MOV3QL, imm()));
+            }
+        },
+        ADDL | ADDAL | ADDIL | SUBL | SUBAL | SUBIL => {
+            let add = matches!(inst, ADDL | ADDAL | ADDIL);
+            let (same, other) = if add { (ADDQL, SUBQL) } else { (SUBQL, ADDQL) };
+            if (1..=8).contains(&v) {
+                c.extend(with(same, imm()));
+            } else if an.is_some() && (-8..=-1).contains(&v) {
+                // Only An destinations leave the flags unaffected.
+                c.extend(with(other, neg()));
+            }
+            if let Some(r) = an {
+                let d = if add { v as i64 } else { -(v as i64) };
+                if (-0x8000..=0x7FFF).contains(&d) {
+                    c.extend(with(LEAL, Arg::AddrRegDisp(r, Expr::LitInt(d, None))));
+                }
+            }
+        },
+        _ => (),
+    }
+    c
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opt(src: &str, features: u16) -> (String, Option<u32>) {
+        let mut pool  = IdentPool::new();
+        let mut syms  = SymbolTable::new();
+        let mut block = parse_moto(src, &mut pool, &mut syms).unwrap();
+        let     saved = optimize(&mut block.stmts[0], features, &|_| None);
+        (moto(&block.stmts[0], &pool).to_string(), saved)
+    }
+
+    #[test]
+    pub fn optimize_immediates() {
+        assert_eq!( opt(" move.l #3,d0",       ISA_A), ("moveq.l #3,d0".into(),     Some(4)) );
+        assert_eq!( opt(" move.l #-128,d7",    ISA_A), ("moveq.l #-$80,d7".into(),  Some(4)) );
+        assert_eq!( opt(" move.l #$1000,d0",   ISA_A), ("move.l #$1000,d0".into(),  None   ) );
+        assert_eq!( opt(" move.w #0,(a0)",     ISA_A), ("clr.w (a0)".into(),        Some(2)) );
+        assert_eq!( opt(" move.l #5,(4,a1)",   ISA_A), ("move.l #5,(4,a1)".into(),  None   ) );
+        assert_eq!( opt(" move.l #5,(4,a1)",   ISA_B), ("mov3q.l #5,(4,a1)".into(), Some(0)) );
+        assert_eq!( opt(" move.l #-1,(a1)+",   ISA_B), ("mov3q.l #-1,(a1)+".into(), Some(4)) );
+        assert_eq!( opt(" move.l #5,a1",       ISA_B), ("move.l #5,a1".into(),      None   ) );
+        assert_eq!( opt(" move.b #3,d0",       ISA_A), ("move.b #3,d0".into(),      None   ) );
+    }
+
+    #[test]
+    pub fn optimize_arithmetic() {
+        assert_eq!( opt(" add.l #4,a0",        ISA_A), ("addq.l #4,a0".into(),      Some(4)) );
+        assert_eq!( opt(" adda.l #-2,sp",      ISA_A), ("subq.l #2,sp".into(),      Some(4)) );
+        assert_eq!( opt(" sub.l #8,d1",        ISA_A), ("subq.l #8,d1".into(),      Some(4)) );
+        assert_eq!( opt(" add.l #100,a0",      ISA_A), ("lea.l ($64,a0),a0".into(), Some(2)) );
+        assert_eq!( opt(" suba.l #100,a2",     ISA_A), ("lea.l (-$64,a2),a2".into(),Some(2)) );
+        assert_eq!( opt(" add.l #100,d0",      ISA_A), ("add.l #$64,d0".into(),     None   ) );
+        assert_eq!( opt(" add.l #4,(a0)",      ISA_A), ("addq.l #4,(a0)".into(),    Some(0)) );
+        assert_eq!( opt(" add.l #-1,d0",       ISA_A), ("add.l #-1,d0".into(),      None   ) );
+        assert_eq!( opt(" sub.l #-8,(a0)",     ISA_A), ("sub.l #-8,(a0)".into(),    None   ) );
+        assert_eq!( opt(" adda.l #-1,a0",      ISA_A), ("subq.l #1,a0".into(),      Some(4)) );
+    }
+
+    #[test]
+    pub fn optimize_displacement() {
+        assert_eq!( opt(" lea (0,a0),a1",      ISA_A), ("lea.l (a0),a1".into(),     Some(2)) );
+        assert_eq!( opt(" move.l d0,(0,a0)",   ISA_A), ("move.l d0,(a0)".into(),    Some(2)) );
+        assert_eq!( opt(" move.l #1,(0,a0)",   ISA_A), ("move.l #1,(a0)".into(),    Some(0)) );
+        assert_eq!( opt(" move.l #1,(0,a0)",   ISA_B), ("mov3q.l #1,(a0)".into(),   Some(0)) );
+    }
+}
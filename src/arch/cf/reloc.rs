@@ -0,0 +1,347 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use crate::ast::*;
+use super::*;
+
+/// Kinds of relocated fields.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum RelocKind {
+    /// An 8-bit absolute value.
+    Abs8,
+
+    /// A 16-bit absolute value.
+    Abs16,
+
+    /// A 32-bit absolute value.
+    Abs32,
+
+    /// An 8-bit displacement from the field.
+    Pc8,
+
+    /// A 16-bit displacement from the field.
+    Pc16,
+
+    /// A 32-bit displacement from the field.
+    Pc32,
+}
+
+impl RelocKind {
+    /// Returns the size of the field in bytes.
+    pub fn size(self) -> u32 {
+        match self {
+            RelocKind::Abs8  | RelocKind::Pc8  => 1,
+            RelocKind::Abs16 | RelocKind::Pc16 => 2,
+            RelocKind::Abs32 | RelocKind::Pc32 => 4,
+        }
+    }
+
+    /// Returns whether the field is a displacement from itself.
+    pub fn is_pc_relative(self) -> bool {
+        matches!(self, RelocKind::Pc8 | RelocKind::Pc16 | RelocKind::Pc32)
+    }
+}
+
+/// A field whose value depends on an address not known until link time.
+///
+/// The field holds the value of the symbol `sym`, or 0 if none, plus
+/// `addend`, less the address of the field if the field is PC-relative.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Reloc {
+    /// Index of the statement containing the field.
+    pub stmt: usize,
+
+    /// Address of the field.
+    pub addr: u32,
+
+    /// Kind of field.
+    pub kind: RelocKind,
+
+    /// Symbol on whose value the field depends, if any.
+    pub sym: Option<Ident>,
+
+    /// Constant added to the value of the symbol.
+    pub addend: i32,
+}
+
+/// The division of a block into chunks for relocatable output.
+///
+/// Statements before the first `.section` directive form chunk 0, and each
+/// directive begins a new chunk.  Labels of a directive belong to the chunk
+/// it ends.  Chunks may be moved apart by the linker, even chunks of the
+/// same section.
+#[derive(Clone, Default, Debug)]
+pub struct Chunks {
+    /// Chunk of each statement.
+    pub stmts: Vec<usize>,
+
+    /// Chunk of each label defined in the block.
+    pub labels: HashMap<Ident, usize>,
+}
+
+impl Chunks {
+    /// Divides `block` into chunks.
+    pub fn of(block: &CfBlock) -> Self {
+        let mut chunks = Chunks::default();
+        let mut chunk  = 0;
+        for stmt in &block.stmts {
+            for &label in &stmt.labels {
+                chunks.labels.insert(label, chunk);
+            }
+            if stmt.op.value_copy() == Some(Op::Asm(AsmOp::Section)) {
+                chunk += 1;
+            }
+            chunks.stmts.push(chunk);
+        }
+        chunks
+    }
+}
+
+/// What the value of an expression depends on at link time.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum RelocTarget {
+    /// Nothing: the value is known.
+    Absolute,
+
+    /// The address of a label in the given chunk.
+    Label(Ident, usize),
+
+    /// The value of a symbol defined elsewhere.
+    External(Ident),
+}
+
+/// Returns what the value of `expr` depends on at link time, or `None` if
+/// the value is not an address plus a constant.
+///
+/// Labels of the same chunk cancel where their coefficients sum to zero, so
+/// that `end-start` is absolute.  Symbols with values that are not labels
+/// are absolute.
+pub fn reloc_target(expr: &Expr, chunks: &Chunks, syms: &SymbolTable) -> Option<RelocTarget> {
+    let mut terms = vec![];
+    if !terms_of(expr, 1, chunks, syms, &mut terms) {
+        return None
+    }
+
+    // Sum coefficients by chunk for labels, and by symbol for others
+    let mut sums: Vec<(Result<usize, Ident>, i32, Ident)> = vec![];
+    for (ident, coef) in terms {
+        let key = chunks.labels.get(&ident).copied().ok_or(ident);
+        match sums.iter_mut().find(|s| s.0 == key) {
+            Some(s) => {
+                s.1 += coef;
+                if coef > 0 { s.2 = ident }
+            },
+            None => sums.push((key, coef, ident)),
+        }
+    }
+    sums.retain(|s| s.1 != 0);
+
+    match sums[..] {
+        []                      => Some(RelocTarget::Absolute),
+        [(Ok(chunk), 1, ident)] => Some(RelocTarget::Label(ident, chunk)),
+        [(Err(_),    1, ident)] => Some(RelocTarget::External(ident)),
+        _                       => None,
+    }
+}
+
+// Collects the labels and undefined symbols of `expr`, each with its
+// coefficient.  Returns false if a label or undefined symbol is an operand
+// of something other than addition, subtraction, or negation.
+fn terms_of(expr: &Expr, sign: i32, chunks: &Chunks, syms: &SymbolTable, terms: &mut Vec<(Ident, i32)>) -> bool {
+    let absolute = |slot: &Slot<Expr>| {
+        let mut inner = vec![];
+        terms_of_slot(slot, 1, chunks, syms, &mut inner) && inner.is_empty()
+    };
+    match *expr {
+        Expr::Ident(i, _) | Expr::Resolved(i, _, _) => {
+            term(i, sign, chunks, syms, terms);
+            true
+        },
+        Expr::Unary(ref u) => match u.op {
+            UnaryOp::Pos => terms_of_slot(&u.expr,  sign, chunks, syms, terms),
+            UnaryOp::Neg => terms_of_slot(&u.expr, -sign, chunks, syms, terms),
+            _            => absolute(&u.expr),
+        },
+        Expr::Binary(ref b) => match b.op {
+            BinaryOp::Add => terms_of_slot(&b.lhs, sign, chunks, syms, terms)
+                          && terms_of_slot(&b.rhs, sign, chunks, syms, terms),
+            BinaryOp::Sub => terms_of_slot(&b.lhs, sign, chunks, syms, terms)
+                          && terms_of_slot(&b.rhs, -sign, chunks, syms, terms),
+            _             => absolute(&b.lhs) && absolute(&b.rhs),
+        },
+        _ => true,
+    }
+}
+
+fn terms_of_slot(slot: &Slot<Expr>, sign: i32, chunks: &Chunks, syms: &SymbolTable, terms: &mut Vec<(Ident, i32)>) -> bool {
+    match *slot {
+        Slot::Ident    (i,        _) => { term(i, sign, chunks, syms, terms); true },
+        Slot::Resolved (i, _,     _) => { term(i, sign, chunks, syms, terms); true },
+        Slot::Value    (   ref e, _) => terms_of(e, sign, chunks, syms, terms),
+    }
+}
+
+fn term(ident: Ident, sign: i32, chunks: &Chunks, syms: &SymbolTable, terms: &mut Vec<(Ident, i32)>) {
+    let ident = syms.target(ident);
+    if chunks.labels.contains_key(&ident) || syms.value(ident).is_none() {
+        terms.push((ident, sign));
+    }
+}
+
+/// Returns relocations for the arguments of statement `n`, an operation
+/// `op` located at `addr`, and replaces each relocated expression with a
+/// value that leaves its field zero.
+///
+/// A PC-relative reference to a label in the same chunk needs no
+/// relocation.  Returns an error for an argument whose value depends on an
+/// address but whose field cannot be relocated, such as an immediate in the
+/// opword.
+pub fn relocate(
+    n: usize, op: Op, args: &mut [Arg], addr: u32, features: u16,
+    chunks: &Chunks, syms: &SymbolTable,
+) -> Result<Vec<Reloc>, AsmErrorKind> {
+    let chunk = chunks.stmts[n];
+    let mut r = Relocator { n, chunk, chunks, syms, relocs: vec![] };
+    match op {
+        Op::Inst(inst) => {
+            // An instruction that cannot be encoded is reported by encoding.
+            if let Ok(enc) = select(inst, args, features) {
+                r.inst(enc, args, addr)?;
+            }
+        },
+        Op::Asm(op) => r.data(op, args, addr)?,
+    }
+    Ok(r.relocs)
+}
+
+struct Relocator<'a> {
+    n:      usize,
+    chunk:  usize,
+    chunks: &'a Chunks,
+    syms:   &'a SymbolTable,
+    relocs: Vec<Reloc>,
+}
+
+impl<'a> Relocator<'a> {
+    fn inst(&mut self, enc: Encoding, args: &mut [Arg], addr: u32) -> Result<(), AsmErrorKind> {
+        let op_size = enc.inst().info().size();
+        let bytes   = op_size.map_or(4, Size::bytes);
+        let mut here = addr.wrapping_add(2 * enc.words());
+
+        for (i, (&op, arg)) in enc.operands().iter().zip(args.iter_mut()).enumerate() {
+            let ext  = op.ext_words(arg, bytes);
+            let base = here;
+            here = here.wrapping_add(2 * ext);
+
+            let pc = matches!(op, Operand::PcRel8 | Operand::PcRel16 | Operand::PcRel32)
+                  || matches!(arg, Arg::PcDisp(..) | Arg::PcIdx(..));
+            let (expr, kind) = match *arg {
+                Arg::Expr(ref mut e) if op == Operand::PcRel16 => (e, Some(RelocKind::Pc16)),
+                Arg::Expr(ref mut e) if op == Operand::PcRel32 => (e, Some(RelocKind::Pc32)),
+                Arg::Expr(ref mut e) if op == Operand::PcRel8  => (e, None),
+                Arg::Expr        (ref mut e)       => (e, Some(RelocKind::Abs32)),
+                Arg::AbsLong     (ref mut e)       => (e, Some(RelocKind::Abs32)),
+                Arg::AbsShort    (ref mut e)       => (e, Some(RelocKind::Abs16)),
+                Arg::AddrRegDisp (_, ref mut e)    => (e, Some(RelocKind::Abs16)),
+                Arg::PcDisp      (_, ref mut e)    => (e, Some(RelocKind::Pc16)),
+                Arg::AddrRegIdx  (_, ref mut e, _) => (e, None),
+                Arg::PcIdx       (_, ref mut e, _) => (e, None),
+                Arg::Imm         (ref mut e) if op_size.is_some_and(Size::is_float) => (e, None),
+                Arg::Imm         (ref mut e)       => (e, match ext {
+                    1 if op == Operand::Imm8 || bytes == 1 => Some(RelocKind::Abs8),
+                    1                                       => Some(RelocKind::Abs16),
+                    2                                       => Some(RelocKind::Abs32),
+                    _                                       => None,
+                }),
+                _ => continue,
+            };
+
+            let target = reloc_target(expr, self.chunks, self.syms)
+                .ok_or(AsmErrorKind::Relocation(i))?;
+            let sym = match target {
+                RelocTarget::Absolute                       if !pc => continue,
+                RelocTarget::Label(_, c) if c == self.chunk &&  pc => continue,
+                RelocTarget::Absolute                              => None,
+                RelocTarget::Label(l, _) | RelocTarget::External(l) => Some(l),
+            };
+            let kind = kind.filter(|_| ext > 0).ok_or(AsmErrorKind::Relocation(i))?;
+
+            // Branch displacements are from the word after the opword;
+            // others are from their own extension word.
+            let field  = if kind == RelocKind::Abs8 { base.wrapping_add(1) } else { base };
+            let origin = if op == Operand::PcRel16 || op == Operand::PcRel32 {
+                addr.wrapping_add(2)
+            } else {
+                field
+            };
+            let value  = self.constant(expr, sym);
+            let addend = match kind.is_pc_relative() {
+                true  => value.wrapping_add(field.wrapping_sub(origin)),
+                false => value,
+            };
+            *expr = Expr::LitInt(if kind.is_pc_relative() { origin as i64 } else { 0 }, expr.span());
+            self.relocs.push(Reloc { stmt: self.n, addr: field, kind, sym, addend: addend as i32 });
+        }
+        Ok(())
+    }
+
+    fn data(&mut self, op: AsmOp, args: &mut [Arg], addr: u32) -> Result<(), AsmErrorKind> {
+        let kind = match op {
+            AsmOp::Data8   => Some(RelocKind::Abs8),
+            AsmOp::Data16  => Some(RelocKind::Abs16),
+            AsmOp::Data32  => Some(RelocKind::Abs32),
+            AsmOp::Data64 | AsmOp::Float32 | AsmOp::Float64 => None,
+            _              => return Ok(()),
+        };
+        let unit = op.unit_size().unwrap_or(1) as u32;
+
+        let mut field = addr;
+        for (i, arg) in args.iter_mut().enumerate() {
+            let expr = match *arg {
+                Arg::Expr(Expr::LitStr (ref s, _)) | Arg::Expr(Expr::LitChar(ref s, _)) if unit == 1 => {
+                    field = field.wrapping_add(s.len() as u32);
+                    continue
+                },
+                Arg::Expr(ref mut e) => e,
+                _                    => continue, // reported by emission
+            };
+            let here = field;
+            field = field.wrapping_add(unit);
+
+            let sym = match reloc_target(expr, self.chunks, self.syms) {
+                Some(RelocTarget::Absolute)    => continue,
+                Some(RelocTarget::Label(l, _)) => l,
+                Some(RelocTarget::External(l)) => l,
+                None                           => return Err(AsmErrorKind::Relocation(i)),
+            };
+            let kind   = kind.ok_or(AsmErrorKind::Relocation(i))?;
+            let addend = self.constant(expr, Some(sym));
+            *expr = Expr::LitInt(0, expr.span());
+            self.relocs.push(Reloc { stmt: self.n, addr: here, kind, sym: Some(sym), addend: addend as i32 });
+        }
+        Ok(())
+    }
+
+    // Returns the value of `expr` with the symbol `sym` taken as zero.
+    fn constant(&self, expr: &Expr, sym: Option<Ident>) -> u32 {
+        let lookup = |i| match Some(self.syms.target(i)) == sym {
+            true  => Some(0),
+            false => self.syms.value(i).or(Some(0)),
+        };
+        eval(expr, &lookup).map_or(0, |v| v.0)
+    }
+}
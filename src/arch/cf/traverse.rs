@@ -0,0 +1,170 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::BTreeMap;
+use crate::ast::*;
+use crate::mem::{Image, RegionKind};
+use super::*;
+
+/// Returns the addresses from which to traverse code: the `entry` point,
+/// if any, such as `flat_symbols` and `uimage_symbols` return, the handlers
+/// of `vectors`, the values of the code symbols in `syms`, and the `extra`
+/// addresses given by the user, without duplicates.
+///
+/// Symbols of other kinds, such as data objects, GOT entries, and generated
+/// labels, do not seed traversal.
+pub fn seeds(entry: Option<u32>, vectors: &[Vector], syms: &SymbolTable, extra: &[u32]) -> Vec<u32> {
+    let mut seeds: Vec<u32> = entry.into_iter()
+        .chain(vectors.iter().filter(|v| v.is_handler()).map(|v| v.target))
+        .chain(syms.iter().filter_map(|(_, s)| s.value.filter(|_| s.kind == SymbolKind::Code)))
+        .chain(extra.iter().copied())
+        .collect();
+    seeds.sort_unstable();
+    seeds.dedup();
+    seeds
+}
+
+/// Classifies the code regions of `image` by recursive traversal from the
+/// addresses `seeds`, and returns the address of each instruction reached,
+/// in order.
+///
+/// Traversal decodes instructions supported by `features` from each seed
+/// that lies in a code region, following the target of each branch, call,
+/// and jump whose target is known (see `Instruction::branch_target`).  It
+/// continues past each instruction but a terminator, such as `bra`, `jmp`,
+/// `rts`, `rte`, `illegal`, or `halt`, and stops at bytes that do not
+/// decode or lie outside a code region.  Execution resumes after `stop`,
+/// once an interrupt handler returns, so traversal continues past it.  The
+/// bytes of code regions that no instruction reaches become data regions.
+pub fn traverse(image: &mut Image, seeds: &[u32], features: u16) -> Vec<u32> {
+    let mut insts = BTreeMap::new();
+    let mut work: Vec<u32> = seeds.iter().rev().copied().collect();
+
+    while let Some(mut pc) = work.pop() {
+        while !insts.contains_key(&pc) {
+            let (stmt, len) = match decode_code(image, pc, features) {
+                Some(d) => d,
+                None    => break,
+            };
+            insts.insert(pc, len);
+            let inst = match stmt.op {
+                Slot::Value(Op::Inst(i), _) => i.info(),
+                _                           => break,
+            };
+            work.extend(inst.branch_target(&stmt));
+            if inst.is_terminator() {
+                break
+            }
+            pc = pc.wrapping_add(len);
+        }
+    }
+
+    // Bytes of code regions outside of instructions become data
+    let mut gaps = vec![];
+    for b in image.blocks().iter().filter(|b| b.region.kind == RegionKind::Code) {
+        let mut next = b.region.vma as u64;
+        for (&a, &len) in insts.range(b.region.vma..).take_while(|(&a, _)| b.region.contains(a)) {
+            if a as u64 > next {
+                gaps.push((next, a as u64));
+            }
+            next = next.max(a as u64 + len as u64);
+        }
+        if next < b.region.end_vma() {
+            gaps.push((next, b.region.end_vma()));
+        }
+    }
+    for (start, end) in gaps {
+        image.set_kind(start as u32, (end - start) as u32, RegionKind::Data);
+    }
+
+    insts.into_keys().collect()
+}
+
+// Decodes the instruction at `pc` in a code region of `image`
+fn decode_code(image: &Image, pc: u32, features: u16) -> Option<(CfStmt, u32)> {
+    let b = image.block_at(pc).filter(|b| b.region.kind == RegionKind::Code)?;
+    if pc & 1 != 0 {
+        return None
+    }
+    decode_at(b.bytes.get((pc - b.region.vma) as usize..)?, pc, features)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::obj::raw::*;
+    use super::*;
+
+    #[test]
+    pub fn traverse_code() {
+        let bytes = [
+            0x61, 0x0C,                 // 400: bsr.b 0x40E
+            0x4A, 0x80,                 // 402: tst.l d0
+            0x67, 0x02,                 // 404: beq.b 0x408
+            0x4E, 0x75,                 // 406: rts
+            0x4E, 0xF9, 0, 0, 4, 0x18,  // 408: jmp (0x418).l
+            0x20, 0x3A, 0x00, 0x04,     // 40E: move.l (0x414,pc),d0
+            0x4E, 0x75,                 // 412: rts
+            0x12, 0x34,                 // 414: (data)
+            0x56, 0x78,                 // 416: (data)
+            0x4E, 0x71,                 // 418: nop
+            0x4E, 0x72, 0x27, 0x00,     // 41A: stop #0x2700
+            0x60, 0xF8,                 // 41E: bra.b 0x418
+            0x4E, 0x75,                 // 420: (unreached)
+        ];
+        let mut image = raw_image(&bytes, 0x400);
+
+        let insts = traverse(&mut image, &[0x400], ISA_A);
+        assert_eq!( insts, [0x400, 0x402, 0x404, 0x406, 0x408, 0x40E, 0x412, 0x418, 0x41A, 0x41E] );
+
+        let kinds: Vec<_> = image.blocks().iter()
+            .map(|b| (b.region.vma, b.region.len, b.region.kind))
+            .collect();
+        assert_eq!( kinds, [
+            (0x400, 0x14, RegionKind::Code),
+            (0x414, 0x04, RegionKind::Data),
+            (0x418, 0x08, RegionKind::Code),
+            (0x420, 0x02, RegionKind::Data),
+        ]);
+
+        // Seeds outside of code, and code already reached, add nothing
+        let again = traverse(&mut image, &[0x400, 0x414, 0x500], ISA_A);
+        assert_eq!( again, insts );
+        assert_eq!( image.blocks().len(), 4 );
+    }
+
+    #[test]
+    pub fn seed_addresses() {
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        let mut define = |name, value, kind| {
+            let ident = pool.intern(name);
+            syms.set_value(ident, value);
+            syms.set_kind(ident, kind);
+        };
+        define("f",      0x500, SymbolKind::Code);
+        define("g",      0x400, SymbolKind::Code);
+        define("table",  0x800, SymbolKind::Data);
+        define("G_0900", 0x810, SymbolKind::Data);
+        define("L_0520", 0x520, SymbolKind::Unknown);
+        let vectors = [
+            Vector { number: 0, addr: 0, target: 0x2000_0000 },
+            Vector { number: 1, addr: 4, target: 0x400 },
+            Vector { number: 2, addr: 8, target: 0x600 },
+        ];
+        // Data symbols and generated labels do not seed traversal
+        assert_eq!( seeds(Some(0x400), &vectors, &syms, &[0x700]), [0x400, 0x500, 0x600, 0x700] );
+    }
+}
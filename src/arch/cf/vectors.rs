@@ -0,0 +1,229 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use crate::ast::*;
+use crate::mem::{BE, Image, Load, RegionKind};
+
+/// Number of vectors in a ColdFire exception vector table.
+pub const VECTOR_COUNT: usize = 256;
+
+// Vector numbers
+pub const VEC_INITIAL_SP:        u8 =  0;
+pub const VEC_INITIAL_PC:        u8 =  1;
+pub const VEC_ACCESS_ERROR:      u8 =  2;
+pub const VEC_ADDRESS_ERROR:     u8 =  3;
+pub const VEC_ILLEGAL:           u8 =  4;
+pub const VEC_DIVIDE_BY_ZERO:    u8 =  5;
+pub const VEC_PRIVILEGE:         u8 =  8;
+pub const VEC_TRACE:             u8 =  9;
+pub const VEC_LINE_A:            u8 = 10;
+pub const VEC_LINE_F:            u8 = 11;
+pub const VEC_DEBUG:             u8 = 12;
+pub const VEC_PC_BREAKPOINT:     u8 = 13;
+pub const VEC_FORMAT_ERROR:      u8 = 14;
+pub const VEC_UNINITIALIZED:     u8 = 15;
+pub const VEC_SPURIOUS:          u8 = 24;
+pub const VEC_AUTOVECTOR:        u8 = 25;  // Level 1; levels 2-7 follow
+pub const VEC_TRAP:              u8 = 32;  // trap #0; traps #1-#15 follow
+pub const VEC_FP_UNORDERED:      u8 = 48;
+pub const VEC_FP_INEXACT:        u8 = 49;
+pub const VEC_FP_DIVIDE_BY_ZERO: u8 = 50;
+pub const VEC_FP_UNDERFLOW:      u8 = 51;
+pub const VEC_FP_OPERAND_ERROR:  u8 = 52;
+pub const VEC_FP_OVERFLOW:       u8 = 53;
+pub const VEC_FP_NAN:            u8 = 54;
+pub const VEC_FP_DENORMAL:       u8 = 55;
+pub const VEC_UNSUPPORTED:       u8 = 61;
+pub const VEC_INTERRUPT:         u8 = 64;  // Source 0 of the interrupt controller
+
+/// A vector of a ColdFire exception vector table.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Vector {
+    /// Vector number.
+    pub number: u8,
+
+    /// Address of the vector within the table.
+    pub addr: u32,
+
+    /// Content of the vector: the initial stack pointer for vector 0, and
+    /// otherwise the address of a handler.
+    pub target: u32,
+}
+
+impl Vector {
+    /// Returns the name of the vector (see `vector_name`).
+    pub fn name(&self) -> String {
+        vector_name(self.number)
+    }
+
+    /// Returns whether the vector holds the address of code: any vector
+    /// but the initial stack pointer.
+    pub fn is_handler(&self) -> bool {
+        self.number != VEC_INITIAL_SP
+    }
+}
+
+/// Returns the name of ColdFire exception vector `n`, such as
+/// `access_error`, `trap_3`, `autovector_7`, or `interrupt_12`, the last
+/// for source 12 of the interrupt controller.  Vector 1, the initial
+/// program counter, is `reset`.
+pub fn vector_name(n: u8) -> String {
+    let name = match n {
+        VEC_INITIAL_SP        => "initial_sp",
+        VEC_INITIAL_PC        => "reset",
+        VEC_ACCESS_ERROR      => "access_error",
+        VEC_ADDRESS_ERROR     => "address_error",
+        VEC_ILLEGAL           => "illegal_instruction",
+        VEC_DIVIDE_BY_ZERO    => "divide_by_zero",
+        VEC_PRIVILEGE         => "privilege_violation",
+        VEC_TRACE             => "trace",
+        VEC_LINE_A            => "line_a",
+        VEC_LINE_F            => "line_f",
+        VEC_DEBUG             => "debug_interrupt",
+        VEC_PC_BREAKPOINT     => "pc_breakpoint",
+        VEC_FORMAT_ERROR      => "format_error",
+        VEC_UNINITIALIZED     => "uninitialized_interrupt",
+        VEC_SPURIOUS          => "spurious_interrupt",
+        VEC_FP_UNORDERED      => "fp_unordered",
+        VEC_FP_INEXACT        => "fp_inexact",
+        VEC_FP_DIVIDE_BY_ZERO => "fp_divide_by_zero",
+        VEC_FP_UNDERFLOW      => "fp_underflow",
+        VEC_FP_OPERAND_ERROR  => "fp_operand_error",
+        VEC_FP_OVERFLOW       => "fp_overflow",
+        VEC_FP_NAN            => "fp_nan",
+        VEC_FP_DENORMAL       => "fp_denormal",
+        VEC_UNSUPPORTED       => "unsupported_instruction",
+        25..=31 => return format!("autovector_{}", n - VEC_AUTOVECTOR + 1),
+        32..=47 => return format!("trap_{}",       n - VEC_TRAP),
+        64..    => return format!("interrupt_{}",  n - VEC_INTERRUPT),
+        _       => return format!("reserved_{}",   n),
+    };
+    name.into()
+}
+
+/// Reads the exception vector table at `base` in `image`: address 0, or the
+/// address that VBR holds.  Returns the vectors in order, up to 256, ending
+/// early where the content of the image ends.
+pub fn read_vectors(image: &Image, base: u32) -> Vec<Vector> {
+    (0..VECTOR_COUNT)
+        .map_while(|n| {
+            let addr = base.checked_add(4 * n as u32)?;
+            let (target, _) = u32::load(image.read(addr, 4)?, BE)?;
+            Some(Vector { number: n as u8, addr, target })
+        })
+        .collect()
+}
+
+/// Analyzes the exception vector table at `base` in `image` (see
+/// `read_vectors`), and returns its vectors.
+///
+/// The table becomes a vector region of `image`, which disassembles as one
+/// `.long` directive per vector.  Each handler within the content of
+/// `image` receives a code symbol in `syms`, unless it has one: the name of
+/// its vector, or `handler_xxxx`, after its address, if several vectors
+/// share it.  A name that already belongs to another address is not reused.
+pub fn analyze_vectors(image: &mut Image, base: u32, syms: &mut SymbolTable, pool: &mut IdentPool)
+    -> Vec<Vector>
+{
+    let vectors = read_vectors(image, base);
+    image.set_kind(base, 4 * vectors.len() as u32, RegionKind::Vectors);
+
+    let mut shares = HashMap::new();
+    for v in vectors.iter().filter(|v| v.is_handler()) {
+        *shares.entry(v.target).or_insert(0) += 1;
+    }
+    for v in vectors.iter().filter(|v| v.is_handler()) {
+        let t = v.target;
+        if t & 1 != 0 || image.read(t, 2).is_none() || !syms.at(t).is_empty() {
+            continue
+        }
+        let name = match shares[&t] {
+            1 => v.name(),
+            _ => format!("handler_{:04X}", t),
+        };
+        let ident = pool.intern(&name);
+        if !syms.get(ident).is_some_and(Symbol::is_defined) {
+            syms.set_value(ident, t);
+            syms.set_kind(ident, SymbolKind::Code);
+        }
+    }
+    vectors
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::obj::raw::*;
+    use super::*;
+
+    #[test]
+    pub fn names() {
+        assert_eq!( vector_name(0),   "initial_sp" );
+        assert_eq!( vector_name(1),   "reset" );
+        assert_eq!( vector_name(2),   "access_error" );
+        assert_eq!( vector_name(4),   "illegal_instruction" );
+        assert_eq!( vector_name(6),   "reserved_6" );
+        assert_eq!( vector_name(25),  "autovector_1" );
+        assert_eq!( vector_name(31),  "autovector_7" );
+        assert_eq!( vector_name(32),  "trap_0" );
+        assert_eq!( vector_name(47),  "trap_15" );
+        assert_eq!( vector_name(63),  "reserved_63" );
+        assert_eq!( vector_name(64),  "interrupt_0" );
+        assert_eq!( vector_name(255), "interrupt_191" );
+    }
+
+    #[test]
+    pub fn analyze() {
+        // Vectors to a default handler, but for reset and trap #0
+        let mut bytes = vec![];
+        for n in 0..VECTOR_COUNT {
+            let v: u32 = match n as u8 {
+                VEC_INITIAL_SP => 0x2000_1000,
+                VEC_INITIAL_PC => 0x400,
+                VEC_TRAP       => 0x402,
+                _              => 0x404,
+            };
+            bytes.extend_from_slice(&v.to_be_bytes());
+        }
+        bytes.extend_from_slice(&[
+            0x4E, 0x71,     // reset: nop
+            0x4E, 0x73,     // trap_0: rte
+            0x4E, 0x73,     // handler_0404: rte
+        ]);
+        let mut image = raw_image(&bytes, 0);
+
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        let vectors  = analyze_vectors(&mut image, 0, &mut syms, &mut pool);
+        assert_eq!( vectors.len(), VECTOR_COUNT );
+        assert_eq!( vectors[2], Vector { number: 2, addr: 8, target: 0x404 } );
+        assert_eq!( vectors[2].name(), "access_error" );
+
+        let name = |addr| syms.at(addr).iter().map(|&i| pool.name(i)).collect::<Vec<_>>();
+        assert_eq!( name(0x400), ["reset"] );
+        assert_eq!( name(0x402), ["trap_0"] );
+        assert_eq!( name(0x404), ["handler_0404"] );
+        assert_eq!( name(0x2000_1000), Vec::<&str>::new() );
+        assert_eq!( syms.get(syms.at(0x404)[0]).unwrap().kind, SymbolKind::Code );
+
+        let kinds: Vec<_> = image.blocks().iter().map(|b| (b.region.vma, b.region.kind)).collect();
+        assert_eq!( kinds, [(0, RegionKind::Vectors), (0x400, RegionKind::Code)] );
+
+        // A short image holds a partial table
+        let image = raw_image(&bytes[..10], 0);
+        assert_eq!( read_vectors(&image, 0).len(), 2 );
+    }
+}
@@ -14,9 +14,10 @@
 // You should have received a copy of the GNU General Public License
 // along with cfda.  If not, see <http://www.gnu.org/licenses/>.
 
-mod cf;
+pub mod cf;
 
 use std::fmt::Debug;
+use crate::ast::Expr;
 
 /// An instruction set architecture.
 pub trait Arch {
@@ -24,6 +25,19 @@ pub trait Arch {
     type Op: Debug;
 
     /// An operation argument or operand in the architecture.
-    type Arg: Debug;
+    type Arg: Debug + Argument;
+}
+
+/// Trait for operation arguments, giving architecture-independent passes
+/// access to their contents.
+pub trait Argument: Sized {
+    /// Creates an argument representing the value of a symbol.
+    fn from_value(value: u32) -> Self;
+
+    /// Calls `f` with each expression in the argument.
+    fn each_expr<F: FnMut(&Expr)>(&self, f: F);
+
+    /// Calls `f` with a mutable reference to each expression in the argument.
+    fn each_expr_mut<F: FnMut(&mut Expr)>(&mut self, f: F);
 }
 
@@ -0,0 +1,127 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use super::StrEncoding;
+
+/// An assembler pseudo-operation.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum AsmOp {
+    /// No operation; the statement only defines labels.
+    Empty,
+
+    /// Set the location counter (`.org`).
+    Origin,
+
+    /// Skip bytes, filled with an optional value (`.skip`).
+    Skip,
+
+    /// Reserve zeroed bytes (`ds.b`).
+    Zero8,
+
+    /// Reserve zeroed 16-bit words (`ds.w`).
+    Zero16,
+
+    /// Reserve zeroed 32-bit words (`ds.l`).
+    Zero32,
+
+    /// Reserve zeroed 64-bit words (`ds.q`).
+    Zero64,
+
+    /// Emit bytes (`dc.b`).
+    Data8,
+
+    /// Emit 16-bit words (`dc.w`).
+    Data16,
+
+    /// Emit 32-bit words (`dc.l`).
+    Data32,
+
+    /// Emit 64-bit words (`dc.q`).
+    Data64,
+
+    /// Emit single-precision floating-point values (`.float`).
+    Float32,
+
+    /// Emit double-precision floating-point values (`.double`).
+    Float64,
+
+    /// Emit characters in the given encoding (`.ascii`).
+    Chars(StrEncoding),
+
+    /// Emit characters in the given encoding, each string followed by a zero
+    /// code unit (`.asciz`).
+    CharsZ(StrEncoding),
+
+    /// Make symbols visible to other modules (`.globl`).
+    Public,
+
+    /// Begin or continue a section (`.section`).
+    Section,
+}
+
+impl AsmOp {
+    /// Returns the size, in bytes, of each data unit the operation emits or
+    /// reserves, or `None` if the operation emits no data.
+    #[inline]
+    pub fn unit_size(self) -> Option<usize> {
+        match self {
+            AsmOp::Empty                  => None,
+            AsmOp::Origin                 => None,
+            AsmOp::Public                 => None,
+            AsmOp::Section                => None,
+            AsmOp::Skip                   => Some(1),
+            AsmOp::Chars  (e)             => Some(e.unit_size()),
+            AsmOp::CharsZ (e)             => Some(e.unit_size()),
+            AsmOp::Zero8  | AsmOp::Data8  => Some(1),
+            AsmOp::Zero16 | AsmOp::Data16 => Some(2),
+            AsmOp::Zero32 | AsmOp::Data32 => Some(4),
+            AsmOp::Zero64 | AsmOp::Data64 => Some(8),
+            AsmOp::Float32                => Some(4),
+            AsmOp::Float64                => Some(8),
+        }
+    }
+
+    /// Returns whether the operation reserves zeroed data units.
+    #[inline]
+    pub fn is_zero(self) -> bool {
+        matches!(self, AsmOp::Zero8 | AsmOp::Zero16 | AsmOp::Zero32 | AsmOp::Zero64)
+    }
+
+    /// Returns the encoding of strings emitted by the operation, if it emits
+    /// only strings.
+    #[inline]
+    pub fn str_encoding(self) -> Option<StrEncoding> {
+        match self {
+            AsmOp::Chars(e) | AsmOp::CharsZ(e) => Some(e),
+            _                                  => None,
+        }
+    }
+
+    /// Returns whether the operation emits data units given by its arguments.
+    #[inline]
+    pub fn is_data(self) -> bool {
+        matches!(self,
+            AsmOp::Data8 | AsmOp::Data16 | AsmOp::Data32 | AsmOp::Data64 |
+            AsmOp::Float32 | AsmOp::Float64
+        )
+    }
+
+    /// Returns whether the operation emits floating-point values.
+    #[inline]
+    pub fn is_float(self) -> bool {
+        matches!(self, AsmOp::Float32 | AsmOp::Float64)
+    }
+}
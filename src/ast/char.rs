@@ -0,0 +1,203 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::mem::{ByteOrder, Save};
+
+/// An assembly character literal.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Char {
+    /// Character value.
+    pub value: char,
+
+    /// Encoding of the character in memory.
+    pub encoding: CharEncoding,
+}
+
+/// Encodings of characters in memory.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum CharEncoding {
+    /// UTF-8: one to four bytes.
+    Utf8,
+
+    /// UTF-16: one or two 16-bit units.
+    Utf16,
+
+    /// UTF-32: one 32-bit unit.
+    Utf32,
+}
+
+/// An encoding and byte order for string literals in memory.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct StrEncoding {
+    /// Encoding of each character.
+    pub encoding: CharEncoding,
+
+    /// Order of bytes within each code unit.  Irrelevant for UTF-8.
+    pub order: ByteOrder,
+}
+
+impl Char {
+    /// Creates a character literal with the given value and encoding.
+    #[inline]
+    pub fn new(value: char, encoding: CharEncoding) -> Self {
+        Self { value, encoding }
+    }
+
+    /// Returns the number of bytes occupied by the encoded character.
+    #[inline]
+    pub fn encoded_len(&self) -> usize {
+        match self.encoding {
+            CharEncoding::Utf8  => self.value.len_utf8(),
+            CharEncoding::Utf16 => self.value.len_utf16() * 2,
+            CharEncoding::Utf32 => 4,
+        }
+    }
+
+    /// Encodes the character into `dst` with code units in byte order
+    /// `order`.  Returns the number of bytes written, or `None` if `dst` is
+    /// too small.
+    pub fn encode(&self, dst: &mut [u8], order: ByteOrder) -> Option<usize> {
+        let len = self.encoded_len();
+        if dst.len() < len {
+            return None
+        }
+        match self.encoding {
+            CharEncoding::Utf8 => {
+                self.value.encode_utf8(dst);
+            },
+            CharEncoding::Utf16 => {
+                let mut buf = [0u16; 2];
+                let mut dst = &mut dst[..];
+                for unit in self.value.encode_utf16(&mut buf) {
+                    dst = unit.save(dst, order)?;
+                }
+            },
+            CharEncoding::Utf32 => {
+                (self.value as u32).save(dst, order)?;
+            },
+        }
+        Some(len)
+    }
+}
+
+impl CharEncoding {
+    /// Returns the size in bytes of a code unit.
+    #[inline]
+    pub fn unit_size(self) -> usize {
+        match self {
+            CharEncoding::Utf8  => 1,
+            CharEncoding::Utf16 => 2,
+            CharEncoding::Utf32 => 4,
+        }
+    }
+}
+
+impl StrEncoding {
+    /// UTF-8, for which byte order is irrelevant.
+    pub const UTF8: Self = Self { encoding: CharEncoding::Utf8, order: ByteOrder::BigEndian };
+
+    /// Creates a string encoding with the given character encoding and byte
+    /// order.
+    #[inline]
+    pub const fn new(encoding: CharEncoding, order: ByteOrder) -> Self {
+        Self { encoding, order }
+    }
+
+    /// Returns the size in bytes of a code unit.
+    #[inline]
+    pub fn unit_size(self) -> usize {
+        self.encoding.unit_size()
+    }
+
+    /// Returns the number of bytes occupied by the encoded string `s`.
+    pub fn len(self, s: &str) -> usize {
+        match self.encoding {
+            CharEncoding::Utf8  => s.len(),
+            CharEncoding::Utf16 => s.encode_utf16().count() * 2,
+            CharEncoding::Utf32 => s.chars().count() * 4,
+        }
+    }
+
+    /// Appends the encoded string `s` to `dst`.
+    pub fn encode(self, s: &str, dst: &mut Vec<u8>) {
+        let start = dst.len();
+        dst.resize(start + self.len(s), 0);
+
+        let mut buf = &mut dst[start..];
+        for c in s.chars() {
+            let c = Char::new(c, self.encoding);
+            let n = c.encode(buf, self.order).unwrap();
+            buf = &mut buf[n..];
+        }
+    }
+
+    /// Appends a zero code unit to `dst`.
+    #[inline]
+    pub fn terminate(self, dst: &mut Vec<u8>) {
+        dst.resize(dst.len() + self.unit_size(), 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mem::{BE, LE};
+    use super::*;
+
+    fn enc(value: char, encoding: CharEncoding, order: ByteOrder) -> Vec<u8> {
+        let mut b = [0u8; 4];
+        let     c = Char::new(value, encoding);
+        let     n = c.encode(&mut b, order).unwrap();
+        assert_eq!( n, c.encoded_len() );
+        b[..n].to_vec()
+    }
+
+    #[test]
+    pub fn encode_utf8() {
+        assert_eq!( enc('a',         CharEncoding::Utf8, BE), [0x61] );
+        assert_eq!( enc('\u{211D9}', CharEncoding::Utf8, LE), [0xF0, 0xA1, 0x87, 0x99] );
+    }
+
+    #[test]
+    pub fn encode_utf16() {
+        assert_eq!( enc('a',         CharEncoding::Utf16, BE), [0x00, 0x61] );
+        assert_eq!( enc('a',         CharEncoding::Utf16, LE), [0x61, 0x00] );
+        assert_eq!( enc('\u{211D9}', CharEncoding::Utf16, BE), [0xD8, 0x44, 0xDD, 0xD9] );
+        assert_eq!( enc('\u{211D9}', CharEncoding::Utf16, LE), [0x44, 0xD8, 0xD9, 0xDD] );
+    }
+
+    #[test]
+    pub fn encode_utf32() {
+        assert_eq!( enc('a',         CharEncoding::Utf32, BE), [0x00, 0x00, 0x00, 0x61] );
+        assert_eq!( enc('\u{211D9}', CharEncoding::Utf32, BE), [0x00, 0x02, 0x11, 0xD9] );
+        assert_eq!( enc('\u{211D9}', CharEncoding::Utf32, LE), [0xD9, 0x11, 0x02, 0x00] );
+    }
+
+    #[test]
+    pub fn encode_too_small() {
+        let mut b = [0u8; 1];
+        assert_eq!( Char::new('a', CharEncoding::Utf16).encode(&mut b, BE), None );
+    }
+
+    #[test]
+    pub fn encode_str() {
+        let mut b = vec![];
+        let     e = StrEncoding::new(CharEncoding::Utf16, BE);
+        e.encode("Hé", &mut b);
+        e.terminate(&mut b);
+        assert_eq!( b, [0x00, 0x48, 0x00, 0xE9, 0x00, 0x00] );
+        assert_eq!( e.len("Hé"), 4 );
+    }
+}
@@ -0,0 +1,322 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::num::Wrapping;
+use crate::num::Cast;
+use super::{BinaryOp, Expr, Ident, IdentPool, Slot, UnaryOp};
+
+/// A value computed by expression evaluation.
+///
+/// Evaluation uses 32-bit two's complement arithmetic that wraps on overflow.
+/// Operators that distinguish signedness (`*`, `/`, `%`, `->>`, and the
+/// relational operators) treat their operands as signed.
+pub type Value = Wrapping<u32>;
+
+/// An error encountered during expression evaluation.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum EvalError {
+    /// The expression references a symbol that has no value.
+    Undefined(Ident),
+
+    /// The expression divides by zero.
+    DivideByZero,
+
+    /// The expression contains a string literal, which has no numeric value.
+    NotNumeric,
+
+    /// The expression contains a character literal too long to fit in 32
+    /// bits.
+    CharTooLong,
+
+    /// The expression contains a floating-point literal where an integer is
+    /// required.
+    NotInteger,
+}
+
+impl EvalError {
+    /// Returns a description of the error, using `pool` to name symbols.
+    pub fn message(&self, pool: &IdentPool) -> String {
+        match *self {
+            EvalError::Undefined(i)  => format!("undefined symbol '{}'", pool.source_name(i)),
+            EvalError::DivideByZero  => "division by zero".to_string(),
+            EvalError::NotNumeric    => "string literal is not a numeric value".to_string(),
+            EvalError::CharTooLong   => "character literal is longer than 4 bytes".to_string(),
+            EvalError::NotInteger    => "floating-point literal is not an integer value".to_string(),
+        }
+    }
+}
+
+/// Evaluates an expression.  The function `lookup` provides symbol values.
+pub fn eval<F>(expr: &Expr, lookup: &F) -> Result<Value, EvalError>
+where
+    F: Fn(Ident) -> Option<u32>
+{
+    match *expr {
+        Expr::Ident   (    i,    _) => lookup(i).map(Wrapping).ok_or(EvalError::Undefined(i)),
+        Expr::Resolved(_,  v,    _) => Ok(Wrapping(v).cast()),
+        Expr::LitInt  (    i,    _) => Ok(Wrapping(i).cast()),
+        Expr::LitFloat(_,        _) => Err(EvalError::NotInteger),
+        Expr::LitStr  (_,        _) => Err(EvalError::NotNumeric),
+        Expr::LitChar (ref s,    _) => eval_chars(s),
+        Expr::Unary   (ref u      ) => eval_unary(u.op, eval_slot(&u.expr, lookup)?),
+        Expr::Binary  (ref b      ) => {
+            let lhs = eval_slot(&b.lhs, lookup)?;
+            let rhs = eval_slot(&b.rhs, lookup)?;
+            eval_binary(b.op, lhs, rhs)
+        },
+    }
+}
+
+/// Evaluates an expression slot.  An unresolved identifier takes its value
+/// from `lookup`; a resolved identifier takes its resolved value.
+pub fn eval_slot<F>(slot: &Slot<Expr>, lookup: &F) -> Result<Value, EvalError>
+where
+    F: Fn(Ident) -> Option<u32>
+{
+    match *slot {
+        Slot::Ident    (i,        _) => lookup(i).map(Wrapping).ok_or(EvalError::Undefined(i)),
+        Slot::Resolved (_, ref e, _) => eval(e, lookup),
+        Slot::Value    (   ref e, _) => eval(e, lookup),
+    }
+}
+
+/// Evaluates an expression as a floating-point value.  The function `lookup`
+/// provides symbol values.
+///
+/// Floating-point literals combine with `+`, `-`, `*`, and `/`.  Any other
+/// expression evaluates as an integer, which is then taken as signed.
+pub fn eval_float<F>(expr: &Expr, lookup: &F) -> Result<f64, EvalError>
+where
+    F: Fn(Ident) -> Option<u32>
+{
+    let slot = |s: &Slot<Expr>| match *s {
+        Slot::Value    (   ref e, _) => eval_float(e, lookup),
+        Slot::Resolved (_, ref e, _) => eval_float(e, lookup),
+        Slot::Ident    (_,        _) => eval_slot(s, lookup).map(|v| v.0 as i32 as f64),
+    };
+
+    match *expr {
+        Expr::LitFloat(f, _) => Ok(f.value()),
+        Expr::Unary(ref u) if is_float(expr) => match u.op {
+            UnaryOp::Pos => slot(&u.expr),
+            UnaryOp::Neg => slot(&u.expr).map(|x| -x),
+            _            => Err(EvalError::NotInteger),
+        },
+        Expr::Binary(ref b) if is_float(expr) => {
+            let x = slot(&b.lhs)?;
+            let y = slot(&b.rhs)?;
+            match b.op {
+                BinaryOp::Add => Ok(x + y),
+                BinaryOp::Sub => Ok(x - y),
+                BinaryOp::Mul => Ok(x * y),
+                BinaryOp::Div => Ok(x / y),
+                _             => Err(EvalError::NotInteger),
+            }
+        },
+        _ => eval(expr, lookup).map(|v| v.0 as i32 as f64),
+    }
+}
+
+/// Evaluates an expression as a single-precision value.  The function
+/// `lookup` provides symbol values.
+///
+/// A floating-point literal, alone or negated, takes its single-precision
+/// value, which is rounded once from its decimal form.  Any other expression
+/// evaluates as by `eval_float`, and the result is rounded to single
+/// precision.
+pub fn eval_single<F>(expr: &Expr, lookup: &F) -> Result<f32, EvalError>
+where
+    F: Fn(Ident) -> Option<u32>
+{
+    let literal = |s: &Slot<Expr>| match *s {
+        Slot::Value(Expr::LitFloat(f, _), _) => Some(f.single_value()),
+        _                                    => None,
+    };
+
+    let x = match *expr {
+        Expr::LitFloat(f, _)  => Some(f.single_value()),
+        Expr::Unary(ref u) => match u.op {
+            UnaryOp::Pos => literal(&u.expr),
+            UnaryOp::Neg => literal(&u.expr).map(|x| -x),
+            _            => None,
+        },
+        _ => None,
+    };
+    match x {
+        Some(x) => Ok(x),
+        None    => eval_float(expr, lookup).map(|x| x as f32),
+    }
+}
+
+// Determines whether the expression contains a floating-point literal.
+fn is_float(expr: &Expr) -> bool {
+    let slot = |s: &Slot<Expr>| s.value().is_some_and(is_float);
+    match *expr {
+        Expr::LitFloat (_,    _) => true,
+        Expr::Unary    (ref u  ) => slot(&u.expr),
+        Expr::Binary   (ref b  ) => slot(&b.lhs) || slot(&b.rhs),
+        _                        => false,
+    }
+}
+
+// Character literals pack their UTF-8 bytes big-endian: 'AB' = $4142.
+fn eval_chars(s: &str) -> Result<Value, EvalError> {
+    if s.len() > 4 {
+        return Err(EvalError::CharTooLong)
+    }
+    Ok(s.bytes().fold(Wrapping(0), |v, b| v << 8 | Wrapping(b as u32)))
+}
+
+fn eval_unary(op: UnaryOp, x: Value) -> Result<Value, EvalError> {
+    Ok(match op {
+        UnaryOp::Pos    =>  x,
+        UnaryOp::Neg    => -x,
+        UnaryOp::BitNot => !x,
+        UnaryOp::LogNot => truth(x.0 == 0),
+    })
+}
+
+fn eval_binary(op: BinaryOp, x: Value, y: Value) -> Result<Value, EvalError> {
+    use BinaryOp::*;
+
+    let sx: Wrapping<i32> = x.cast();
+    let sy: Wrapping<i32> = y.cast();
+
+    Ok(match op {
+        // Arithmetic
+        Mul    => (sx * sy).cast(),
+        Div    => { nonzero(y)?; (sx / sy).cast() },
+        Mod    => { nonzero(y)?; (sx % sy).cast() },
+        Add    => x + y,
+        Sub    => x - y,
+        // Bitwise
+        Shl    => if y.0 < 32 { x << y.0 as usize } else { Wrapping(0) },
+        Shr    => if y.0 < 32 { x >> y.0 as usize } else { Wrapping(0) },
+        ShrS   => (sx >> y.0.min(31) as usize).cast(),
+        BitAnd => x & y,
+        BitOr  => x | y,
+        BitXor => x ^ y,
+        // Relational: true is -1, as in GAS
+        Lt     => relation(sx <  sy),
+        LtEq   => relation(sx <= sy),
+        Gt     => relation(sx >  sy),
+        GtEq   => relation(sx >= sy),
+        Eq     => relation(x  == y ),
+        NotEq  => relation(x  != y ),
+        // Logical: true is 1, as in GAS
+        LogAnd => truth(x.0 != 0 && y.0 != 0),
+        LogOr  => truth(x.0 != 0 || y.0 != 0),
+    })
+}
+
+#[inline]
+fn nonzero(x: Value) -> Result<(), EvalError> {
+    if x.0 != 0 { Ok(()) } else { Err(EvalError::DivideByZero) }
+}
+
+#[inline]
+fn truth(b: bool) -> Value {
+    Wrapping(b as u32)
+}
+
+#[inline]
+fn relation(b: bool) -> Value {
+    Wrapping((b as u32).wrapping_neg())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Float;
+    use super::*;
+
+    fn int(i: i64) -> Slot<Expr> {
+        Slot::Value(Expr::LitInt(i, None), None)
+    }
+
+    fn bin(op: BinaryOp, x: i64, y: i64) -> Result<u32, EvalError> {
+        let expr = Expr::binary(op, int(x), int(y));
+        eval(&expr, &|_| None).map(|v| v.0)
+    }
+
+    #[test]
+    pub fn eval_arithmetic() {
+        assert_eq!( bin(BinaryOp::Add, 2, 3),            Ok(5)           );
+        assert_eq!( bin(BinaryOp::Sub, 2, 3),            Ok(0xFFFF_FFFF) );
+        assert_eq!( bin(BinaryOp::Mul, -4, 3),           Ok(-12i32 as u32) );
+        assert_eq!( bin(BinaryOp::Div, -7, 2),           Ok(-3i32 as u32) );
+        assert_eq!( bin(BinaryOp::Mod, -7, 2),           Ok(-1i32 as u32) );
+        assert_eq!( bin(BinaryOp::Div, 1, 0),            Err(EvalError::DivideByZero) );
+        assert_eq!( bin(BinaryOp::Add, 0xFFFF_FFFF, 2),  Ok(1)           );
+        assert_eq!( bin(BinaryOp::Div, i32::MIN as i64, -1), Ok(i32::MIN as u32) );
+    }
+
+    #[test]
+    pub fn eval_shifts() {
+        assert_eq!( bin(BinaryOp::Shl,  1, 31),          Ok(0x8000_0000) );
+        assert_eq!( bin(BinaryOp::Shl,  1, 32),          Ok(0)           );
+        assert_eq!( bin(BinaryOp::Shr,  -16, 2),         Ok(0x3FFF_FFFC) );
+        assert_eq!( bin(BinaryOp::ShrS, -16, 2),         Ok(-4i32 as u32) );
+        assert_eq!( bin(BinaryOp::ShrS, -16, 40),        Ok(0xFFFF_FFFF) );
+    }
+
+    #[test]
+    pub fn eval_relational_and_logical() {
+        assert_eq!( bin(BinaryOp::Lt,     -1, 1),        Ok(0xFFFF_FFFF) );
+        assert_eq!( bin(BinaryOp::Gt,     -1, 1),        Ok(0)           );
+        assert_eq!( bin(BinaryOp::Eq,      5, 5),        Ok(0xFFFF_FFFF) );
+        assert_eq!( bin(BinaryOp::LogAnd,  5, 0),        Ok(0)           );
+        assert_eq!( bin(BinaryOp::LogOr,   5, 0),        Ok(1)           );
+
+        let not = Expr::unary(UnaryOp::LogNot, int(0));
+        assert_eq!( eval(&not, &|_| None), Ok(Wrapping(1)) );
+    }
+
+    #[test]
+    pub fn eval_symbols_and_literals() {
+        let a = Ident::new(0);
+        let b = Ident::new(1);
+        let lookup = |i| if i == a { Some(0x100) } else { None };
+
+        let expr = Expr::binary(BinaryOp::Add, Slot::Ident(a, None), int(4));
+        assert_eq!( eval(&expr, &lookup), Ok(Wrapping(0x104)) );
+
+        let expr = Expr::binary(BinaryOp::Add, Slot::Resolved(b, Expr::LitInt(8, None), None), int(4));
+        assert_eq!( eval(&expr, &lookup), Ok(Wrapping(12)) );
+
+        assert_eq!( eval(&Expr::Ident(b, None), &lookup), Err(EvalError::Undefined(b)) );
+        assert_eq!( eval(&Expr::LitChar("AB".into(), None), &lookup), Ok(Wrapping(0x4142)) );
+        assert_eq!( eval(&Expr::LitChar("ABCDE".into(), None), &lookup), Err(EvalError::CharTooLong) );
+        assert_eq!( eval(&Expr::LitStr("A".into(), None), &lookup), Err(EvalError::NotNumeric) );
+        assert_eq!( eval(&Expr::LitFloat(Float::new(1.0), None), &lookup), Err(EvalError::NotInteger) );
+    }
+
+    #[test]
+    pub fn eval_floats() {
+        let x = |v: f64| Slot::Value(Expr::LitFloat(Float::new(v), None), None);
+
+        let expr = Expr::binary(BinaryOp::Mul, x(1.5), int(-2));
+        assert_eq!( eval_float(&expr, &|_| None), Ok(-3.0) );
+
+        let expr = Expr::unary(UnaryOp::Neg, x(0.0));
+        assert_eq!( eval_float(&expr, &|_| None).map(f64::to_bits), Ok((-0.0f64).to_bits()) );
+
+        let expr = Expr::binary(BinaryOp::Sub, int(1), int(3));
+        assert_eq!( eval_float(&expr, &|_| None), Ok(-2.0) );
+
+        let expr = Expr::binary(BinaryOp::Shl, x(1.0), int(3));
+        assert_eq!( eval_float(&expr, &|_| None), Err(EvalError::NotInteger) );
+    }
+}
@@ -0,0 +1,166 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt::{self, Display, Formatter};
+use std::num::ParseFloatError;
+use std::str::FromStr;
+
+/// A floating-point literal.
+///
+/// The literal holds the bits of its IEEE 754 double-precision value, and of
+/// its single-precision value, each rounded directly from the decimal form
+/// of the literal.  Rounding a decimal to double precision and then to
+/// single precision can differ from rounding it to single precision once.
+///
+/// Equality and hashing are bit-exact: `-0.0` differs from `0.0`, and a NaN
+/// equals itself if its payload is the same.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Float {
+    bits:   u64,
+    single: u32,
+}
+
+impl Float {
+    /// Creates a literal with the given value.
+    #[inline]
+    pub fn new(value: f64) -> Self {
+        Float { bits: value.to_bits(), single: (value as f32).to_bits() }
+    }
+
+    /// Creates a literal with the shortest decimal value that converts to
+    /// the given single-precision value, so that `0.1f32` formats as `0.1`
+    /// rather than as its exact double-precision expansion.
+    pub fn single(value: f32) -> Self {
+        // Debug output is the shortest representation that round-trips.
+        let x: f64 = format!("{:?}", value).parse().unwrap_or(value as f64);
+        Float { bits: x.to_bits(), single: value.to_bits() }
+    }
+
+    /// Creates a literal with the given double-precision bits.
+    #[inline]
+    pub fn from_bits(bits: u64) -> Self {
+        Float::new(f64::from_bits(bits))
+    }
+
+    /// Returns the double-precision bits of the literal.
+    #[inline]
+    pub const fn bits(self) -> u64 {
+        self.bits
+    }
+
+    /// Returns the value of the literal.
+    #[inline]
+    pub fn value(self) -> f64 {
+        f64::from_bits(self.bits)
+    }
+
+    /// Returns the value of the literal in single precision.
+    #[inline]
+    pub fn single_value(self) -> f32 {
+        f32::from_bits(self.single)
+    }
+}
+
+impl From<f64> for Float {
+    #[inline]
+    fn from(value: f64) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<f32> for Float {
+    /// Converts a single-precision value exactly.
+    #[inline]
+    fn from(value: f32) -> Self {
+        Float { bits: (value as f64).to_bits(), single: value.to_bits() }
+    }
+}
+
+/// Parses a decimal literal, such as `3.14159` or `6.02e23`, rounding to the
+/// nearest double-precision value and to the nearest single-precision value.
+impl FromStr for Float {
+    type Err = ParseFloatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let x: f64 = s.parse()?;
+        let y: f32 = s.parse()?;
+        Ok(Float { bits: x.to_bits(), single: y.to_bits() })
+    }
+}
+
+/// Formats the shortest decimal literal that parses to the same value.  The
+/// literal always contains `.` or `e`, to distinguish it from an integer.
+/// Non-finite values format as `inf`, `-inf`, and `NaN`.
+impl Display for Float {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // Debug output is the shortest representation that round-trips.
+        write!(f, "{:?}", self.value())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    use super::*;
+
+    fn hash(x: Float) -> u64 {
+        let mut h = DefaultHasher::new();
+        x.hash(&mut h);
+        h.finish()
+    }
+
+    #[test]
+    pub fn float_bit_exact() {
+        assert_ne!( Float::new(0.0), Float::new(-0.0) );
+        assert_eq!( Float::new(f64::NAN), Float::new(f64::NAN) );
+        assert_eq!( hash(Float::new(1.5)), hash(Float::new(1.5)) );
+        assert_eq!( Float::from(0.1f32).value(), 0.1f32 as f64 );
+    }
+
+    #[test]
+    pub fn float_single() {
+        assert_eq!( Float::single(0.1).to_string(),     "0.1" );
+        assert_eq!( Float::single(-1.5).to_string(),    "-1.5" );
+        assert_eq!( Float::single(f32::MAX).value() as f32, f32::MAX );
+        assert_eq!( Float::single(-0.0).bits(), (-0.0f64).to_bits() );
+        assert_eq!( Float::single(0.1).single_value(), 0.1f32 );
+    }
+
+    #[test]
+    pub fn float_parse_single() {
+        // Halfway between 1.0 and the next single-precision value, plus a
+        // little: the double-precision value is exactly halfway, which then
+        // rounds to even, down to 1.0.
+        let x: Float = "1.0000000596046448".parse().unwrap();
+        assert_eq!( x.value(), 1.0 + 2f64.powi(-24) );
+        assert_eq!( x.value() as f32, 1.0 );
+        assert_eq!( x.single_value(), 1.0 + f32::EPSILON );
+        assert_eq!( Float::new(x.value()).single_value(), 1.0 );
+    }
+
+    #[test]
+    pub fn float_round_trip() {
+        for &v in &[2.71, 0.1, -2.5, 100.0, 1e300, 6.02e23, 1e-7, 5e-324, f64::MAX, -0.0] {
+            let s = Float::new(v).to_string();
+            assert!( s.contains('.') || s.contains('e'), "{}", s );
+            assert_eq!( s.parse::<Float>().unwrap(), Float::new(v) );
+        }
+        assert_eq!( Float::new(0.001).to_string(),   "0.001" );
+        assert_eq!( Float::new(100.0).to_string(),   "100.0" );
+        assert_eq!( Float::new(1e300).to_string(),   "1e300" );
+    }
+}
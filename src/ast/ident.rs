@@ -14,9 +14,125 @@
 // You should have received a copy of the GNU General Public License
 // along with cfda.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::HashMap;
+use super::INSTANCE_MARK;
+
 /// An assembly identifier.
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+///
+/// An identifier is an index into an `IdentPool`, which maps it to and from
+/// its name.  Identifiers from different pools are not comparable.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub struct Ident (usize);
 
-// TODO: Ident-to-string pool
+impl Ident {
+    /// Creates an identifier with the given pool index.
+    #[inline]
+    pub const fn new(index: usize) -> Self {
+        Ident(index)
+    }
+
+    /// Returns the pool index of the identifier.
+    #[inline]
+    pub const fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// A pool of identifier names.
+///
+/// Interning a name yields an `Ident`; interning the same name again yields
+/// the same `Ident`.  The pool maps identifiers back to their names.
+#[derive(Clone, Default, Debug)]
+pub struct IdentPool {
+    names: Vec<Box<str>>,
+    map:   HashMap<Box<str>, Ident>,
+}
+
+impl IdentPool {
+    /// Creates an empty pool.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the identifier for `name`, adding `name` to the pool if
+    /// necessary.
+    pub fn intern(&mut self, name: &str) -> Ident {
+        if let Some(&ident) = self.map.get(name) {
+            return ident
+        }
+        let ident = Ident(self.names.len());
+        self.names.push(name.into());
+        self.map.insert(name.into(), ident);
+        ident
+    }
+
+    /// Returns the identifier for `name`, if `name` is in the pool.
+    #[inline]
+    pub fn get(&self, name: &str) -> Option<Ident> {
+        self.map.get(name).copied()
+    }
+
+    /// Returns the name of `ident`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ident` is not from this pool.
+    #[inline]
+    pub fn name(&self, ident: Ident) -> &str {
+        &self.names[ident.0]
+    }
+
+    /// Returns the name of `ident` as written in source: the name less the
+    /// instance suffix, if any, of a numeric local label or reference.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ident` is not from this pool.
+    #[inline]
+    pub fn source_name(&self, ident: Ident) -> &str {
+        let name = self.name(ident);
+        name.split(INSTANCE_MARK).next().unwrap_or(name)
+    }
+
+    /// Returns the number of identifiers in the pool.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Returns whether the pool is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Returns an iterator over the identifiers in the pool and their names,
+    /// in the order in which they were interned.
+    pub fn iter(&self) -> impl Iterator<Item=(Ident, &str)> {
+        self.names.iter().enumerate().map(|(n, s)| (Ident(n), &s[..]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn intern() {
+        let mut pool = IdentPool::new();
+        let a = pool.intern("a");
+        let b = pool.intern("b");
+        assert_ne!( a, b );
+        assert_eq!( pool.intern("a"), a );
+        assert_eq!( pool.len(), 2 );
+    }
 
+    #[test]
+    pub fn lookup() {
+        let mut pool = IdentPool::new();
+        let a = pool.intern("alpha");
+        assert_eq!( pool.name(a), "alpha" );
+        assert_eq!( pool.get("alpha"), Some(a) );
+        assert_eq!( pool.get("beta"), None );
+    }
+}
@@ -0,0 +1,132 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use super::{Ident, IdentPool};
+
+/// Character that separates the name of a numeric local label from its
+/// instance number in the name of an identifier, as in GNU `as`.
+pub const INSTANCE_MARK: char = '\x02';
+
+/// Returns whether `name` denotes a symbol private to its source: a
+/// GNU-style `.L` symbol or an instance of a numeric local label.  Such
+/// symbols are never visible to other modules.
+pub fn is_local_name(name: &str) -> bool {
+    name.starts_with(".L") || name.contains(INSTANCE_MARK)
+}
+
+/// Numeric local labels, such as `1:`, and references to them, such as `1b`
+/// and `1f`.
+///
+/// A numeric local label may be defined any number of times.  A reference
+/// `1b` refers to the nearest definition of `1` at or before it, and `1f` to
+/// the nearest definition after it.  Each definition is a distinct instance
+/// whose identifier is named `1`, then `INSTANCE_MARK`, then the instance
+/// number.  Each reference has an identifier named `1b` or `1f` with the
+/// same suffix, which a symbol table aliases to the instance.
+#[derive(Clone, Default, Debug)]
+pub struct LocalLabels {
+    counts: HashMap<u32, u32>,
+}
+
+impl LocalLabels {
+    /// Creates an empty set of labels.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of the numeric local label `name`, if `name` is
+    /// one: a string of decimal digits.
+    pub fn number(name: &str) -> Option<u32> {
+        if name.is_empty() || !name.bytes().all(|b| b.is_ascii_digit()) {
+            return None
+        }
+        name.parse().ok()
+    }
+
+    /// Returns the label number of the reference `name`, and whether the
+    /// reference is forward, if `name` is a reference such as `1b` or `1f`.
+    pub fn reference(name: &str) -> Option<(u32, bool)> {
+        let forward = match name.as_bytes().last()? {
+            b'b' => false,
+            b'f' => true,
+            _    => return None,
+        };
+        Some((Self::number(&name[..name.len() - 1])?, forward))
+    }
+
+    /// Defines the next instance of label `n` and returns its identifier.
+    pub fn define(&mut self, n: u32, pool: &mut IdentPool) -> Ident {
+        let count = self.counts.entry(n).or_insert(0);
+        *count += 1;
+        pool.intern(&format!("{}{}{}", n, INSTANCE_MARK, *count - 1))
+    }
+
+    /// Returns the identifiers of a reference to label `n`, backward or
+    /// `forward`, and of the instance to which it refers.  Returns `None`
+    /// for a backward reference to a label not yet defined.
+    pub fn refer(&self, n: u32, forward: bool, pool: &mut IdentPool) -> Option<(Ident, Ident)> {
+        let count = self.counts.get(&n).copied().unwrap_or(0);
+        let (instance, dir) = match forward {
+            true               => (count,     'f'),
+            false if count > 0 => (count - 1, 'b'),
+            false              => return None,
+        };
+        let reference = pool.intern(&format!("{}{}{}{}", n, dir, INSTANCE_MARK, instance));
+        let label     = pool.intern(&format!("{}{}{}",   n,      INSTANCE_MARK, instance));
+        Some((reference, label))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn parse_names() {
+        assert_eq!( LocalLabels::number("12"),     Some(12) );
+        assert_eq!( LocalLabels::number("1a"),     None );
+        assert_eq!( LocalLabels::number(""),       None );
+        assert_eq!( LocalLabels::reference("3b"),  Some((3, false)) );
+        assert_eq!( LocalLabels::reference("3f"),  Some((3, true)) );
+        assert_eq!( LocalLabels::reference("b"),   None );
+        assert_eq!( LocalLabels::reference("x3f"), None );
+    }
+
+    #[test]
+    pub fn instances() {
+        let mut pool   = IdentPool::new();
+        let mut locals = LocalLabels::new();
+
+        assert_eq!( locals.refer(1, false, &mut pool), None );
+
+        let (fwd, a) = locals.refer(1, true, &mut pool).unwrap();
+        assert_eq!( locals.define(1, &mut pool), a );
+        let (back, b) = locals.refer(1, false, &mut pool).unwrap();
+        assert_eq!( b, a );
+
+        let c = locals.define(1, &mut pool);
+        assert_ne!( c, a );
+        assert_eq!( locals.refer(1, false, &mut pool).unwrap().1, c );
+
+        assert_eq!( pool.source_name(a),    "1" );
+        assert_eq!( pool.source_name(fwd),  "1f" );
+        assert_eq!( pool.source_name(back), "1b" );
+        assert!( is_local_name(pool.name(c)) );
+        assert!( is_local_name(".Lloop") );
+        assert!( !is_local_name("loop") );
+    }
+}
@@ -14,11 +14,31 @@
 // You should have received a copy of the GNU General Public License
 // along with cfda.  If not, see <http://www.gnu.org/licenses/>.
 
+mod asm_op;
+mod char;
+mod eval;
+mod float;
 mod ident;
+mod local;
 mod node;
+mod resolve;
 mod slot;
+mod span;
+mod symbol;
+mod trivia;
+mod visit;
 
+pub use self::asm_op::*;
+pub use self::char::*;
+pub use self::eval::*;
+pub use self::float::*;
 pub use self::ident::*;
+pub use self::local::*;
 pub use self::node::*;
+pub use self::resolve::*;
 pub use self::slot::*;
+pub use self::span::*;
+pub use self::symbol::*;
+pub use self::trivia::*;
+pub use self::visit::*;
 
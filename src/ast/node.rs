@@ -14,9 +14,10 @@
 // You should have received a copy of the GNU General Public License
 // along with cfda.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::hash::{Hash, Hasher};
 use crate::arch::Arch;
-use super::Ident;
-use super::Slot;
+use super::{Float, Ident};
+use super::{Slot, Span, Trivia};
 
 /// A block of assembly statements.
 #[derive(/*Clone, PartialEq, Eq,*/ Debug)]
@@ -36,27 +37,61 @@ pub struct Stmt<A: Arch> {
 
     /// Arguments to the operation.
     pub args: Vec<Slot<A::Arg>>,
+
+    /// Source location of the operation and arguments, if parsed from source.
+    pub span: Option<Span>,
+
+    /// Comments and blank lines attached to the statement.
+    pub trivia: Trivia,
+}
+
+impl<A: Arch> Stmt<A> {
+    /// Creates a statement without source location or trivia.
+    pub fn new(labels: Vec<Ident>, op: Slot<A::Op>, args: Vec<Slot<A::Arg>>) -> Self {
+        Stmt {
+            labels,
+            op,
+            args,
+            span:   None,
+            trivia: Trivia::default(),
+        }
+    }
+
+    /// Returns the source location of the argument at `index`, if known.
+    #[inline]
+    pub fn arg_span(&self, index: usize) -> Option<Span> {
+        self.args.get(index).and_then(Slot::span)
+    }
 }
 
 /// An assembly expression.
-#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+///
+/// Atoms record their source location, if parsed from source, as do operator
+/// expressions.  The source location does not participate in comparison or
+/// hashing.
+#[derive(Clone, Debug)]
 pub enum Expr {
     // Atoms -----
 
     /// An identifier.
-    Ident(Ident),
+    Ident(Ident, Option<Span>),
+
+    /// An identifier resolved to the value of its symbol.  Resolution
+    /// produces this in place of an identifier that is a whole expression,
+    /// which has no slot to hold the value.
+    Resolved(Ident, i64, Option<Span>),
 
     /// An integer literal.
-    LitInt(i64),
+    LitInt(i64, Option<Span>),
 
-    // /// A floating-point literal.
-    // LitFloat(f64), // TODO: hash not implemented
+    /// A floating-point literal.
+    LitFloat(Float, Option<Span>),
 
     /// A string literal.
-    LitStr(String),
+    LitStr(String, Option<Span>),
 
     /// A character literal.
-    LitChar(String),
+    LitChar(String, Option<Span>),
 
     // Composite -----
 
@@ -68,18 +103,144 @@ pub enum Expr {
 }
 
 /// An assembly unary operator expression.
-#[derive(Clone, PartialEq, Eq, Hash, Debug)]
-pub struct Unary { 
-    op:   UnaryOp,
-    expr: Box<Slot<Expr>>,
+///
+/// The source location does not participate in comparison or hashing.
+#[derive(Clone, Debug)]
+pub struct Unary {
+    /// Operator.
+    pub op: UnaryOp,
+
+    /// Operand.
+    pub expr: Box<Slot<Expr>>,
+
+    /// Source location, if parsed from source.
+    pub span: Option<Span>,
 }
 
 /// An assembly binary operator expression.
-#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+///
+/// The source location does not participate in comparison or hashing.
+#[derive(Clone, Debug)]
 pub struct Binary {
-    op:  BinaryOp,
-    lhs: Box<Slot<Expr>>,
-    rhs: Box<Slot<Expr>>,
+    /// Operator.
+    pub op: BinaryOp,
+
+    /// Left operand.
+    pub lhs: Box<Slot<Expr>>,
+
+    /// Right operand.
+    pub rhs: Box<Slot<Expr>>,
+
+    /// Source location, if parsed from source.
+    pub span: Option<Span>,
+}
+
+impl PartialEq for Unary {
+    fn eq(&self, other: &Self) -> bool {
+        self.op == other.op && self.expr == other.expr
+    }
+}
+
+impl Eq for Unary { }
+
+impl Hash for Unary {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.op  .hash(state);
+        self.expr.hash(state);
+    }
+}
+
+impl PartialEq for Binary {
+    fn eq(&self, other: &Self) -> bool {
+        self.op == other.op && self.lhs == other.lhs && self.rhs == other.rhs
+    }
+}
+
+impl Eq for Binary { }
+
+impl Hash for Binary {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.op .hash(state);
+        self.lhs.hash(state);
+        self.rhs.hash(state);
+    }
+}
+
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Expr::Ident    (a,    _), Expr::Ident    (b,    _)) => a == b,
+            (Expr::Resolved (a, x, _), Expr::Resolved (b, y, _)) => a == b && x == y,
+            (Expr::LitInt   (x,    _), Expr::LitInt   (y,    _)) => x == y,
+            (Expr::LitFloat (x,    _), Expr::LitFloat (y,    _)) => x == y,
+            (Expr::LitStr   (x,    _), Expr::LitStr   (y,    _)) => x == y,
+            (Expr::LitChar  (x,    _), Expr::LitChar  (y,    _)) => x == y,
+            (Expr::Unary    (x      ), Expr::Unary    (y      )) => x == y,
+            (Expr::Binary   (x      ), Expr::Binary   (y      )) => x == y,
+            _                                                    => false,
+        }
+    }
+}
+
+impl Eq for Expr { }
+
+impl Hash for Expr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match *self {
+            Expr::Ident    (ref i,        _) => i.hash(state),
+            Expr::Resolved (ref i, ref v, _) => { i.hash(state); v.hash(state) },
+            Expr::LitInt   (ref v,        _) => v.hash(state),
+            Expr::LitFloat (ref v,        _) => v.hash(state),
+            Expr::LitStr   (ref v,        _) => v.hash(state),
+            Expr::LitChar  (ref v,        _) => v.hash(state),
+            Expr::Unary    (ref u          ) => u.hash(state),
+            Expr::Binary   (ref b          ) => b.hash(state),
+        }
+    }
+}
+
+impl Expr {
+    /// Creates a unary operator expression.
+    pub fn unary(op: UnaryOp, expr: Slot<Expr>) -> Self {
+        Expr::Unary(Unary { op, expr: Box::new(expr), span: None })
+    }
+
+    /// Creates a binary operator expression.
+    pub fn binary(op: BinaryOp, lhs: Slot<Expr>, rhs: Slot<Expr>) -> Self {
+        Expr::Binary(Binary { op, lhs: Box::new(lhs), rhs: Box::new(rhs), span: None })
+    }
+
+    /// Returns the source location of the expression, if known.
+    #[inline]
+    pub fn span(&self) -> Option<Span> {
+        match *self {
+            Expr::Ident    (_,    s) => s,
+            Expr::Resolved (_, _, s) => s,
+            Expr::LitInt   (_,    s) => s,
+            Expr::LitFloat (_,    s) => s,
+            Expr::LitStr   (_,    s) => s,
+            Expr::LitChar  (_,    s) => s,
+            Expr::Unary    (ref u  ) => u.span,
+            Expr::Binary   (ref b  ) => b.span,
+        }
+    }
+
+    /// Sets the source location of the expression.
+    #[inline]
+    pub fn with_span(mut self, span: Span) -> Self {
+        match self {
+            Expr::Ident    (_,    ref mut s) => *s     = Some(span),
+            Expr::Resolved (_, _, ref mut s) => *s     = Some(span),
+            Expr::LitInt   (_,    ref mut s) => *s     = Some(span),
+            Expr::LitFloat (_,    ref mut s) => *s     = Some(span),
+            Expr::LitStr   (_,    ref mut s) => *s     = Some(span),
+            Expr::LitChar  (_,    ref mut s) => *s     = Some(span),
+            Expr::Unary    (ref mut u      ) => u.span = Some(span),
+            Expr::Binary   (ref mut b      ) => b.span = Some(span),
+        }
+        self
+    }
 }
 
 /// An assembly unary operator.
@@ -104,6 +265,7 @@ pub enum BinaryOp {
     Shl,        // <<
     Shr,        // >>
     ShrS,       // ->> (arithmetic)
+    BitAnd,     // &
     BitOr,      // |
     BitXor,     // ^
     // Relational
@@ -0,0 +1,187 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::arch::{Arch, Argument};
+use super::{Block, Expr, Ident, Slot, Span, SymbolTable};
+
+/// A reference to an identifier that could not be resolved.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Unresolved {
+    /// The unresolved identifier.
+    pub ident: Ident,
+
+    /// Index of the statement containing the reference.
+    pub stmt: usize,
+
+    /// Whether the identifier is in the operation position, such as an
+    /// unknown mnemonic, rather than in an argument.
+    pub is_op: bool,
+
+    /// Source location of the reference, if known.
+    pub span: Option<Span>,
+}
+
+/// Resolves identifiers in `block` to their values in `syms`.
+///
+/// Every `Slot::Ident` argument or subexpression whose symbol has a value
+/// becomes `Slot::Resolved` with that value, and every other identifier
+/// expression becomes `Expr::Resolved`.  References resolved previously are
+/// updated with the current value, so the pass can be repeated as symbol
+/// values change.  Returns the references that remain unresolved, in order
+/// of appearance.
+pub fn resolve<A: Arch>(block: &mut Block<A>, syms: &SymbolTable) -> Vec<Unresolved> {
+    let mut unresolved = vec![];
+
+    for (n, stmt) in block.stmts.iter_mut().enumerate() {
+        // Operations do not take values from the symbol table.
+        if let Slot::Ident(ident, _) = stmt.op {
+            unresolved.push(Unresolved { ident, stmt: n, is_op: true, span: stmt.op.span() });
+        }
+
+        let mut report = |ident, span| {
+            unresolved.push(Unresolved { ident, stmt: n, is_op: false, span })
+        };
+        for arg in stmt.args.iter_mut() {
+            match *arg {
+                Slot::Ident(i, s) | Slot::Resolved(i, _, s) => match syms.value(i) {
+                    Some(v) => *arg = Slot::Resolved(i, A::Arg::from_value(v), s),
+                    None    => report(i, s),
+                },
+                Slot::Value(ref mut a, _) => {
+                    a.each_expr_mut(|e| resolve_expr(e, syms, &mut report));
+                },
+            }
+        }
+    }
+
+    unresolved
+}
+
+fn resolve_expr<F>(expr: &mut Expr, syms: &SymbolTable, report: &mut F)
+where
+    F: FnMut(Ident, Option<Span>)
+{
+    match *expr {
+        Expr::Ident(i, s) | Expr::Resolved(i, _, s) => match syms.value(i) {
+            Some(v) => *expr = Expr::Resolved(i, v as i64, s),
+            None    => report(i, s),
+        },
+        Expr::Unary(ref mut u) => {
+            resolve_slot(&mut u.expr, syms, report);
+        },
+        Expr::Binary(ref mut b) => {
+            resolve_slot(&mut b.lhs, syms, report);
+            resolve_slot(&mut b.rhs, syms, report);
+        },
+        _ => (),
+    }
+}
+
+fn resolve_slot<F>(slot: &mut Slot<Expr>, syms: &SymbolTable, report: &mut F)
+where
+    F: FnMut(Ident, Option<Span>)
+{
+    match *slot {
+        Slot::Ident(i, s) | Slot::Resolved(i, _, s) => match syms.value(i) {
+            Some(v) => *slot = Slot::Resolved(i, Expr::LitInt(v as i64, s), s),
+            None    => report(i, s),
+        },
+        Slot::Value(ref mut e, _) => {
+            resolve_expr(e, syms, report)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::cf::*;
+    use crate::ast::{eval, IdentPool};
+    use super::*;
+
+    #[test]
+    pub fn resolve_block() {
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        let mut block = parse_moto(
+            "a   bra.s   b\n\
+             \x20   move.l  (c+b*2,a0),d0\n\
+             \x20   frob    d0\n\
+             \x20   dc.l    a,(c),4\n",
+            &mut pool, &mut syms
+        ).unwrap();
+
+        let a = pool.get("a").unwrap();
+        let b = pool.get("b").unwrap();
+        let c = pool.get("c").unwrap();
+        let frob = pool.get("frob").unwrap();
+        syms.set_value(a, 0x400);
+        syms.set_value(b, 0x10);
+
+        let unresolved = resolve(&mut block, &syms);
+        assert_eq!(
+            unresolved,
+            [
+                Unresolved { ident: c,    stmt: 1, is_op: false, span: Some(Span::new(2, 14,  1)) },
+                Unresolved { ident: frob, stmt: 2, is_op: true,  span: Some(Span::new(3,  5,  4)) },
+                Unresolved { ident: c,    stmt: 3, is_op: false, span: Some(Span::new(4, 15,  3)) },
+            ]
+        );
+
+        let s = &block.stmts;
+        assert_eq!( s[0].args[0], Slot::Resolved(b, Arg::Expr(Expr::LitInt(0x10, None)), None) );
+        assert_eq!( s[3].args[0], Slot::Resolved(a, Arg::Expr(Expr::LitInt(0x400, None)), None) );
+
+        // Update after value change, and evaluate without the table
+        syms.set_value(b, 0x20);
+        syms.set_value(c, 1);
+        assert!( resolve(&mut block, &syms).iter().all(|u| u.is_op) );
+
+        let disp = match block.stmts[1].args[0] {
+            Slot::Value(Arg::AddrRegDisp(_, ref e), _) => e.clone(),
+            ref arg => panic!("unexpected argument: {:?}", arg),
+        };
+        assert_eq!( eval(&disp, &|_| None).unwrap().0, 0x41 );
+    }
+
+    #[test]
+    pub fn resolve_bare_ident() {
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        let mut block = parse_moto(
+            " move.l #a,d0\n\
+             \x20lea (a,a0),a1\n",
+            &mut pool, &mut syms
+        ).unwrap();
+
+        let a = pool.get("a").unwrap();
+        syms.set_value(a, 0x400);
+        assert_eq!( resolve(&mut block, &syms), [] );
+
+        let s = &block.stmts;
+        assert_eq!( s[0].args[0], Slot::Value(Arg::Imm(Expr::Resolved(a, 0x400, None)), None) );
+        assert_eq!( s[1].args[0], Slot::Value(Arg::AddrRegDisp(AddrReg::A0, Expr::Resolved(a, 0x400, None)), None) );
+        assert_eq!( moto(&block, &pool).to_string(), "        move.l #a,d0\n        lea.l (a,a0),a1\n" );
+
+        // Update after value change, and evaluate without the table
+        syms.set_value(a, 0x500);
+        resolve(&mut block, &syms);
+        let imm = match block.stmts[0].args[0] {
+            Slot::Value(Arg::Imm(ref e), _) => e.clone(),
+            ref arg => panic!("unexpected argument: {:?}", arg),
+        };
+        assert_eq!( eval(&imm, &|_| None).unwrap().0, 0x500 );
+    }
+}
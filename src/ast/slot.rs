@@ -14,19 +14,23 @@
 // You should have received a copy of the GNU General Public License
 // along with cfda.  If not, see <http://www.gnu.org/licenses/>.
 
-use super::Ident;
+use std::hash::{Hash, Hasher};
+use super::{Ident, Span};
 
 /// A slot containing a value and/or an identifier that resolves to that value.
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+///
+/// Each slot records its source location, if parsed from source.  The source
+/// location does not participate in comparison or hashing.
+#[derive(Clone, Copy, Debug)]
 pub enum Slot<V> {
     /// Unresolved identifier.
-    Ident(Ident),
+    Ident(Ident, Option<Span>),
 
     /// Identifier resolved to a value.
-    Resolved(Ident, V),
+    Resolved(Ident, V, Option<Span>),
 
     /// Value resolved without an identifier.
-    Value(V),
+    Value(V, Option<Span>),
 }
 
 impl<V> Slot<V> {
@@ -34,9 +38,9 @@ impl<V> Slot<V> {
     #[inline]
     pub fn ident(&self) -> Option<Ident> {
         match *self {
-            Slot::Ident    (i   ) => Some(i),
-            Slot::Resolved (i, _) => Some(i),
-            Slot::Value    (   _) => None,
+            Slot::Ident    (i,    _) => Some(i),
+            Slot::Resolved (i, _, _) => Some(i),
+            Slot::Value    (   _, _) => None,
         }
     }
 
@@ -44,9 +48,9 @@ impl<V> Slot<V> {
     #[inline]
     pub fn value(&self) -> Option<&V> {
         match *self {
-            Slot::Ident    (_       ) => None,
-            Slot::Resolved (_, ref v) => Some(v),
-            Slot::Value    (   ref v) => Some(v),
+            Slot::Ident    (_,        _) => None,
+            Slot::Resolved (_, ref v, _) => Some(v),
+            Slot::Value    (   ref v, _) => Some(v),
         }
     }
 
@@ -54,10 +58,54 @@ impl<V> Slot<V> {
     #[inline]
     pub fn value_copy(&self) -> Option<V> where V: Copy {
         match *self {
-            Slot::Ident    (_   ) => None,
-            Slot::Resolved (_, v) => Some(v),
-            Slot::Value    (   v) => Some(v),
+            Slot::Ident    (_,    _) => None,
+            Slot::Resolved (_, v, _) => Some(v),
+            Slot::Value    (   v, _) => Some(v),
         }
     }
+
+    /// Returns the source location of the slot, if known.
+    #[inline]
+    pub fn span(&self) -> Option<Span> {
+        match *self {
+            Slot::Ident    (_,    s) => s,
+            Slot::Resolved (_, _, s) => s,
+            Slot::Value    (   _, s) => s,
+        }
+    }
+
+    /// Sets the source location of the slot.
+    #[inline]
+    pub fn with_span(mut self, span: Span) -> Self {
+        match self {
+            Slot::Ident    (_,    ref mut s) => *s = Some(span),
+            Slot::Resolved (_, _, ref mut s) => *s = Some(span),
+            Slot::Value    (   _, ref mut s) => *s = Some(span),
+        }
+        self
+    }
 }
 
+impl<V: PartialEq> PartialEq for Slot<V> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Slot::Ident    (a,    _), Slot::Ident    (b,    _)) => a == b,
+            (Slot::Resolved (a, x, _), Slot::Resolved (b, y, _)) => a == b && x == y,
+            (Slot::Value    (   x, _), Slot::Value    (   y, _)) => x == y,
+            _                                                    => false,
+        }
+    }
+}
+
+impl<V: Eq> Eq for Slot<V> { }
+
+impl<V: Hash> Hash for Slot<V> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match *self {
+            Slot::Ident    (ref i,        _) => i.hash(state),
+            Slot::Resolved (ref i, ref v, _) => { i.hash(state); v.hash(state) },
+            Slot::Value    (       ref v, _) => v.hash(state),
+        }
+    }
+}
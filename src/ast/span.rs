@@ -0,0 +1,36 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+/// A region of assembly source text.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Span {
+    /// Line number, starting from 1.
+    pub line: u32,
+
+    /// Column number, starting from 1.
+    pub col: u32,
+
+    /// Length, in bytes.
+    pub len: u32,
+}
+
+impl Span {
+    /// Creates a new `Span`.
+    #[inline]
+    pub const fn new(line: u32, col: u32, len: u32) -> Self {
+        Self { line, col, len }
+    }
+}
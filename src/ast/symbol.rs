@@ -0,0 +1,303 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{BTreeMap, HashMap};
+use super::{Ident, IdentPool, Span};
+
+/// Visibility of a symbol outside its defining module.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Binding {
+    /// Visible only within the defining module.
+    Local,
+
+    /// Visible to other modules.
+    Global,
+
+    /// Visible to other modules, but overridable by a global symbol.
+    Weak,
+}
+
+/// Kind of entity that a symbol names.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SymbolKind {
+    /// Not known.
+    Unknown,
+
+    /// Executable code, such as a function or an entry point.
+    Code,
+
+    /// Data, such as a variable or a table.
+    Data,
+}
+
+/// Information about a symbol.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Symbol {
+    /// Value, typically an address, if known.
+    pub value: Option<u32>,
+
+    /// Section containing the symbol, or `None` for the default section or
+    /// for an absolute symbol.
+    pub section: Option<Ident>,
+
+    /// Visibility outside the defining module.
+    pub binding: Binding,
+
+    /// Kind of entity that the symbol names.
+    pub kind: SymbolKind,
+
+    /// Location of the definition in source, if known.
+    pub span: Option<Span>,
+}
+
+impl Symbol {
+    /// Creates a local symbol of unknown kind, with no value, section, or
+    /// definition.
+    pub fn new() -> Self {
+        Self {
+            value:   None,
+            section: None,
+            binding: Binding::Local,
+            kind:    SymbolKind::Unknown,
+            span:    None,
+        }
+    }
+
+    /// Returns whether the symbol has been defined in source or given a
+    /// value.
+    #[inline]
+    pub fn is_defined(&self) -> bool {
+        self.value.is_some() || self.span.is_some()
+    }
+}
+
+impl Default for Symbol {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A table of symbols, keyed by identifier.
+///
+/// Besides lookup by identifier, the table supports lookup of symbols by
+/// value, so that a disassembler can find or generate a label for an address.
+#[derive(Clone, Default, Debug)]
+pub struct SymbolTable {
+    syms:    BTreeMap<Ident, Symbol>,
+    by_addr: HashMap<u32, Vec<Ident>>,
+    aliases: HashMap<Ident, Ident>,
+}
+
+impl SymbolTable {
+    /// Creates an empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns information about the symbol `ident`, if present.
+    #[inline]
+    pub fn get(&self, ident: Ident) -> Option<&Symbol> {
+        self.syms.get(&self.target(ident))
+    }
+
+    /// Returns the value of the symbol `ident`, if known.
+    #[inline]
+    pub fn value(&self, ident: Ident) -> Option<u32> {
+        self.syms.get(&self.target(ident))?.value
+    }
+
+    /// Makes `ident` an alias of the symbol `target`, so that lookups of
+    /// `ident` yield `target`.  References to numeric local labels are
+    /// aliases of the label instances to which they refer.
+    pub fn alias(&mut self, ident: Ident, target: Ident) {
+        self.aliases.insert(ident, target);
+    }
+
+    /// Returns the symbol of which `ident` is an alias, or `ident` itself if
+    /// it is not an alias.
+    #[inline]
+    pub fn target(&self, ident: Ident) -> Ident {
+        self.aliases.get(&ident).copied().unwrap_or(ident)
+    }
+
+    /// Returns whether the table contains no symbols.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.syms.is_empty()
+    }
+
+    /// Returns the number of symbols in the table.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.syms.len()
+    }
+
+    /// Returns an iterator over the symbols in the table, in identifier order.
+    pub fn iter(&self) -> impl Iterator<Item=(Ident, &Symbol)> {
+        self.syms.iter().map(|(&i, s)| (i, s))
+    }
+
+    /// Returns the symbol `ident`, adding it to the table if necessary.
+    ///
+    /// This and the other methods that modify a symbol act on the target of
+    /// an alias, as lookups do.
+    pub fn entry(&mut self, ident: Ident) -> &mut Symbol {
+        self.syms.entry(self.target(ident)).or_default()
+    }
+
+    /// Records the definition of symbol `ident` at `span` in source.
+    ///
+    /// If the symbol already has a definition in source, this method leaves
+    /// the table unchanged and returns the existing definition as an error.
+    pub fn define(&mut self, ident: Ident, span: Span) -> Result<(), Span> {
+        let sym = self.entry(ident);
+        match sym.span {
+            Some(prev) => Err(prev),
+            None       => { sym.span = Some(span); Ok(()) },
+        }
+    }
+
+    /// Sets the value of symbol `ident`, adding the symbol if necessary.
+    pub fn set_value(&mut self, ident: Ident, value: u32) {
+        let ident = self.target(ident);
+        let sym   = self.syms.entry(ident).or_default();
+        if let Some(old) = sym.value.replace(value) {
+            if old == value { return }
+            if let Some(v) = self.by_addr.get_mut(&old) {
+                v.retain(|&i| i != ident);
+            }
+        }
+        self.by_addr.entry(value).or_default().push(ident);
+    }
+
+    /// Sets the binding of symbol `ident`, adding the symbol if necessary.
+    pub fn set_binding(&mut self, ident: Ident, binding: Binding) {
+        self.entry(ident).binding = binding;
+    }
+
+    /// Sets the kind of symbol `ident`, adding the symbol if necessary.
+    pub fn set_kind(&mut self, ident: Ident, kind: SymbolKind) {
+        self.entry(ident).kind = kind;
+    }
+
+    /// Returns the symbols whose value is `value`, in the order in which
+    /// they acquired that value.
+    pub fn at(&self, value: u32) -> &[Ident] {
+        self.by_addr.get(&value).map_or(&[], |v| &v[..])
+    }
+
+    /// Returns a label for address `addr`.  If no symbol has that address,
+    /// this method generates a label named `L_xxxx`, where `xxxx` is the
+    /// address in hexadecimal, and adds it to the table.  If a symbol of
+    /// that name exists already, the generated name gains a suffix, as in
+    /// `L_xxxx_1`.
+    pub fn label(&mut self, addr: u32, pool: &mut IdentPool) -> Ident {
+        if let Some(&ident) = self.at(addr).first() {
+            return ident
+        }
+        let name      = format!("L_{:04X}", addr);
+        let mut ident = pool.intern(&name);
+        let mut n     = 0;
+        while self.get(ident).is_some() {
+            n    += 1;
+            ident = pool.intern(&format!("{}_{}", name, n));
+        }
+        self.set_value(ident, addr);
+        ident
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn define_twice() {
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        let a = pool.intern("a");
+
+        assert_eq!( syms.define(a, Span::new(1, 1, 1)), Ok(()) );
+        assert_eq!( syms.define(a, Span::new(2, 1, 1)), Err(Span::new(1, 1, 1)) );
+        assert!( syms.get(a).unwrap().is_defined() );
+    }
+
+    #[test]
+    pub fn value_lookup() {
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        let a = pool.intern("a");
+        let b = pool.intern("b");
+
+        syms.set_value(a, 0x100);
+        syms.set_value(b, 0x100);
+        assert_eq!( syms.at(0x100), [a, b] );
+
+        syms.set_value(a, 0x200);
+        assert_eq!( syms.at(0x100), [b] );
+        assert_eq!( syms.at(0x200), [a] );
+        assert_eq!( syms.value(a), Some(0x200) );
+    }
+
+    #[test]
+    pub fn alias_lookup() {
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        let a = pool.intern("a");
+        let b = pool.intern("b");
+
+        syms.alias(b, a);
+        syms.set_value(a, 0x100);
+        assert_eq!( syms.target(b), a );
+        assert_eq!( syms.value(b), Some(0x100) );
+        assert_eq!( syms.get(b), syms.get(a) );
+        assert_eq!( syms.len(), 1 );
+
+        // Changes through an alias apply to its target
+        syms.set_value(b, 0x200);
+        syms.set_binding(b, Binding::Global);
+        assert_eq!( syms.define(b, Span::new(1, 1, 1)), Ok(()) );
+        assert_eq!( syms.at(0x200), [a] );
+        assert_eq!( syms.get(a).unwrap().binding, Binding::Global );
+        assert_eq!( syms.get(a).unwrap().span, Some(Span::new(1, 1, 1)) );
+        assert_eq!( syms.len(), 1 );
+    }
+
+    #[test]
+    pub fn generated_label() {
+        let mut pool = IdentPool::new();
+        let mut syms = SymbolTable::new();
+        let start = pool.intern("start");
+        syms.set_value(start, 0x400);
+
+        assert_eq!( syms.label(0x400, &mut pool), start );
+
+        let gen = syms.label(0xFFE0_0010, &mut pool);
+        assert_eq!( pool.name(gen), "L_FFE00010" );
+        assert_eq!( syms.label(0xFFE0_0010, &mut pool), gen );
+
+        let gen = syms.label(0x12, &mut pool);
+        assert_eq!( pool.name(gen), "L_0012" );
+
+        // A symbol that has the generated name keeps its value
+        let user = pool.intern("L_0500");
+        syms.set_value(user, 0x600);
+        let gen = syms.label(0x500, &mut pool);
+        assert_eq!( pool.name(gen), "L_0500_1" );
+        assert_eq!( syms.value(gen),  Some(0x500) );
+        assert_eq!( syms.value(user), Some(0x600) );
+    }
+}
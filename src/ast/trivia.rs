@@ -0,0 +1,45 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+/// Source text that does not affect the meaning of a statement, kept so that
+/// reformatted source does not lose it.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Trivia {
+    /// Blank lines and whole-line comments preceding the statement.
+    pub leading: Vec<Trivium>,
+
+    /// Text of the comment following the statement on the same line, if any,
+    /// without the comment character.
+    pub trailing: Option<String>,
+}
+
+/// A line of source text preceding a statement.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Trivium {
+    /// A blank line.
+    Blank,
+
+    /// A whole-line comment, without the comment character.
+    Comment(String),
+}
+
+impl Trivia {
+    /// Returns whether there is no trivia.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.leading.is_empty() && self.trailing.is_none()
+    }
+}
@@ -0,0 +1,412 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::mem::replace;
+use crate::arch::{Arch, Argument};
+use super::{Block, Expr, Ident, Slot, Span, Stmt};
+
+// Traversal order, for both traits: each statement's labels, then its
+// operation, then its arguments, left to right; within an expression,
+// operands left to right.
+//
+// The value of a `Slot::Resolved` or `Expr::Resolved` is derived from its
+// identifier, so only the identifier is visited or folded; the value is kept
+// as is.  Folding keeps the source location of each node.
+
+// -----------------------------------------------------------------------------
+// Visitor
+
+/// Trait for read-only traversal of an assembly block.
+///
+/// Each method visits one kind of node.  The provided methods continue into
+/// the children of the node via the matching `walk_*` function, so that an
+/// implementation overrides only the methods for the nodes it cares about,
+/// calling `walk_*` to continue.  An error stops the traversal.
+pub trait Visitor<A: Arch, E = ()> {
+    fn visit_block(&mut self, node: &Block<A>) -> Result<(), E> {
+        walk_block(self, node)
+    }
+
+    fn visit_stmt(&mut self, node: &Stmt<A>) -> Result<(), E> {
+        walk_stmt(self, node)
+    }
+
+    /// Visits a label defined by a statement.
+    fn visit_label(&mut self, node: Ident) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_op(&mut self, node: &Slot<A::Op>) -> Result<(), E> {
+        walk_op(self, node)
+    }
+
+    fn visit_arg(&mut self, node: &Slot<A::Arg>) -> Result<(), E> {
+        walk_arg(self, node)
+    }
+
+    fn visit_expr(&mut self, node: &Expr) -> Result<(), E> {
+        walk_expr(self, node)
+    }
+
+    /// Visits an operand of a unary or binary expression.
+    fn visit_subexpr(&mut self, node: &Slot<Expr>) -> Result<(), E> {
+        walk_subexpr(self, node)
+    }
+
+    /// Visits a reference to an identifier: an unknown operation, an
+    /// argument, or an expression.  `span` is the source location of the
+    /// reference, if known.
+    fn visit_ident(&mut self, node: Ident, span: Option<Span>) -> Result<(), E> {
+        Ok(())
+    }
+}
+
+/// Visits the statements of a block.
+pub fn walk_block<A, E, V>(v: &mut V, node: &Block<A>) -> Result<(), E>
+where
+    A: Arch, V: Visitor<A, E> + ?Sized
+{
+    node.stmts.iter().try_for_each(|s| v.visit_stmt(s))
+}
+
+/// Visits the labels, operation, and arguments of a statement.
+pub fn walk_stmt<A, E, V>(v: &mut V, node: &Stmt<A>) -> Result<(), E>
+where
+    A: Arch, V: Visitor<A, E> + ?Sized
+{
+    node.labels.iter().try_for_each(|&l| v.visit_label(l))?;
+    v.visit_op(&node.op)?;
+    node.args.iter().try_for_each(|a| v.visit_arg(a))
+}
+
+/// Visits the identifier, if any, of an operation.
+pub fn walk_op<A, E, V>(v: &mut V, node: &Slot<A::Op>) -> Result<(), E>
+where
+    A: Arch, V: Visitor<A, E> + ?Sized
+{
+    match node.ident() {
+        Some(i) => v.visit_ident(i, node.span()),
+        None    => Ok(()),
+    }
+}
+
+/// Visits the identifier of an argument, or the expressions within it.
+pub fn walk_arg<A, E, V>(v: &mut V, node: &Slot<A::Arg>) -> Result<(), E>
+where
+    A: Arch, V: Visitor<A, E> + ?Sized
+{
+    match *node {
+        Slot::Ident    (i,        s) => v.visit_ident(i, s),
+        Slot::Resolved (i, _,     s) => v.visit_ident(i, s),
+        Slot::Value    (   ref a, _) => {
+            let mut result = Ok(());
+            a.each_expr(|e| if result.is_ok() { result = v.visit_expr(e) });
+            result
+        },
+    }
+}
+
+/// Visits the identifier or operands of an expression.
+pub fn walk_expr<A, E, V>(v: &mut V, node: &Expr) -> Result<(), E>
+where
+    A: Arch, V: Visitor<A, E> + ?Sized
+{
+    match *node {
+        Expr::Ident    (i,    s) => v.visit_ident(i, s),
+        Expr::Resolved (i, _, s) => v.visit_ident(i, s),
+        Expr::Unary    (ref u  ) => v.visit_subexpr(&u.expr),
+        Expr::Binary   (ref b  ) => { v.visit_subexpr(&b.lhs)?; v.visit_subexpr(&b.rhs) },
+        _                        => Ok(()),
+    }
+}
+
+/// Visits the identifier or expression of an operand.
+pub fn walk_subexpr<A, E, V>(v: &mut V, node: &Slot<Expr>) -> Result<(), E>
+where
+    A: Arch, V: Visitor<A, E> + ?Sized
+{
+    match *node {
+        Slot::Ident    (i,        s) => v.visit_ident(i, s),
+        Slot::Resolved (i, _,     s) => v.visit_ident(i, s),
+        Slot::Value    (   ref e, _) => v.visit_expr(e),
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Fold
+
+/// Trait for rewriting an assembly block.
+///
+/// Each method takes one node by value and returns its replacement.  The
+/// provided methods rebuild the node from its folded children via the
+/// matching `fold_*_children` function, so that an implementation overrides
+/// only the methods for the nodes it rewrites.
+pub trait Fold<A: Arch> {
+    fn fold_block(&mut self, node: Block<A>) -> Block<A> {
+        fold_block_children(self, node)
+    }
+
+    fn fold_stmt(&mut self, node: Stmt<A>) -> Stmt<A> {
+        fold_stmt_children(self, node)
+    }
+
+    /// Folds a label defined by a statement.
+    fn fold_label(&mut self, node: Ident) -> Ident {
+        node
+    }
+
+    fn fold_op(&mut self, node: Slot<A::Op>) -> Slot<A::Op> {
+        fold_op_children(self, node)
+    }
+
+    fn fold_arg(&mut self, node: Slot<A::Arg>) -> Slot<A::Arg> {
+        fold_arg_children(self, node)
+    }
+
+    fn fold_expr(&mut self, node: Expr) -> Expr {
+        fold_expr_children(self, node)
+    }
+
+    /// Folds an operand of a unary or binary expression.
+    fn fold_subexpr(&mut self, node: Slot<Expr>) -> Slot<Expr> {
+        fold_subexpr_children(self, node)
+    }
+
+    /// Folds a reference to an identifier: an unknown operation, an
+    /// argument, or an expression.  `span` is the source location of the
+    /// reference, if known.
+    fn fold_ident(&mut self, node: Ident, span: Option<Span>) -> Ident {
+        node
+    }
+}
+
+/// Folds the statements of a block.
+pub fn fold_block_children<A, F>(f: &mut F, node: Block<A>) -> Block<A>
+where
+    A: Arch, F: Fold<A> + ?Sized
+{
+    Block { stmts: node.stmts.into_iter().map(|s| f.fold_stmt(s)).collect() }
+}
+
+/// Folds the labels, operation, and arguments of a statement.  Source
+/// locations and trivia are kept.
+pub fn fold_stmt_children<A, F>(f: &mut F, node: Stmt<A>) -> Stmt<A>
+where
+    A: Arch, F: Fold<A> + ?Sized
+{
+    let labels = node.labels.into_iter().map(|l| f.fold_label(l)).collect();
+    let op     = f.fold_op(node.op);
+    let args   = node.args.into_iter().map(|a| f.fold_arg(a)).collect();
+    Stmt { labels, op, args, ..node }
+}
+
+/// Folds the identifier, if any, of an operation.
+pub fn fold_op_children<A, F>(f: &mut F, node: Slot<A::Op>) -> Slot<A::Op>
+where
+    A: Arch, F: Fold<A> + ?Sized
+{
+    match node {
+        Slot::Ident    (i,    s) => Slot::Ident    (f.fold_ident(i, s),    s),
+        Slot::Resolved (i, o, s) => Slot::Resolved (f.fold_ident(i, s), o, s),
+        Slot::Value    (   o, s) => Slot::Value    (                    o, s),
+    }
+}
+
+/// Folds the identifier of an argument, or the expressions within it.
+pub fn fold_arg_children<A, F>(f: &mut F, node: Slot<A::Arg>) -> Slot<A::Arg>
+where
+    A: Arch, F: Fold<A> + ?Sized
+{
+    match node {
+        Slot::Ident    (i,        s) => Slot::Ident    (f.fold_ident(i, s),    s),
+        Slot::Resolved (i, a,     s) => Slot::Resolved (f.fold_ident(i, s), a, s),
+        Slot::Value    (   mut a, s) => {
+            a.each_expr_mut(|e| {
+                let expr = replace(e, Expr::LitInt(0, None));
+                *e = f.fold_expr(expr);
+            });
+            Slot::Value(a, s)
+        },
+    }
+}
+
+/// Folds the identifier or operands of an expression.
+pub fn fold_expr_children<A, F>(f: &mut F, node: Expr) -> Expr
+where
+    A: Arch, F: Fold<A> + ?Sized
+{
+    match node {
+        Expr::Ident    (i,    s) => Expr::Ident    (f.fold_ident(i, s),    s),
+        Expr::Resolved (i, v, s) => Expr::Resolved (f.fold_ident(i, s), v, s),
+        Expr::Unary    (mut u  ) => {
+            *u.expr = f.fold_subexpr(*u.expr);
+            Expr::Unary(u)
+        },
+        Expr::Binary   (mut b  ) => {
+            *b.lhs = f.fold_subexpr(*b.lhs);
+            *b.rhs = f.fold_subexpr(*b.rhs);
+            Expr::Binary(b)
+        },
+        expr => expr,
+    }
+}
+
+/// Folds the identifier or expression of an operand.
+pub fn fold_subexpr_children<A, F>(f: &mut F, node: Slot<Expr>) -> Slot<Expr>
+where
+    A: Arch, F: Fold<A> + ?Sized
+{
+    match node {
+        Slot::Ident    (i,    s) => Slot::Ident    (f.fold_ident(i, s),    s),
+        Slot::Resolved (i, e, s) => Slot::Resolved (f.fold_ident(i, s), e, s),
+        Slot::Value    (   e, s) => Slot::Value    (f.fold_expr(e),        s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::cf::*;
+    use crate::ast::{eval, BinaryOp, IdentPool, SymbolTable};
+    use super::*;
+
+    fn parse(src: &str, pool: &mut IdentPool) -> CfBlock {
+        parse_moto(src, pool, &mut SymbolTable::new()).unwrap()
+    }
+
+    // Collects labels and identifier references in order.
+    #[derive(Default)]
+    struct Idents {
+        labels: Vec<Ident>,
+        refs:   Vec<Ident>,
+        spans:  Vec<Option<Span>>,
+    }
+
+    impl Visitor<Cf> for Idents {
+        fn visit_label(&mut self, node: Ident) -> Result<(), ()> {
+            self.labels.push(node);
+            Ok(())
+        }
+
+        fn visit_ident(&mut self, node: Ident, span: Option<Span>) -> Result<(), ()> {
+            self.refs .push(node);
+            self.spans.push(span);
+            Ok(())
+        }
+    }
+
+    #[test]
+    pub fn visit_block() {
+        let mut pool  = IdentPool::new();
+        let     block = parse(
+            "a   bra.s   b\n\
+             \x20   move.l  (c+-b*2,a0),d0\n\
+             d:  frob    e\n",
+            &mut pool
+        );
+        let id = |s| pool.get(s).unwrap();
+
+        let mut v = Idents::default();
+        v.visit_block(&block).unwrap();
+        assert_eq!( v.labels, [id("a"), id("d")] );
+        assert_eq!( v.refs,   [id("b"), id("c"), id("b"), id("frob"), id("e")] );
+        assert_eq!(
+            v.spans,
+            [
+                Some(Span::new(1, 13, 1)),
+                Some(Span::new(2, 14, 1)),
+                Some(Span::new(2, 17, 1)),
+                Some(Span::new(3,  5, 4)),
+                Some(Span::new(3, 13, 1)),
+            ]
+        );
+    }
+
+    // Stops at the first reference to a given identifier, reporting the
+    // number of identifiers seen before it.
+    struct Find (Ident, usize);
+
+    impl Visitor<Cf, usize> for Find {
+        fn visit_ident(&mut self, node: Ident, _: Option<Span>) -> Result<(), usize> {
+            if node == self.0 { return Err(self.1) }
+            self.1 += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    pub fn visit_stops_on_error() {
+        let mut pool  = IdentPool::new();
+        let     block = parse(" dc.l 1,w-1,(2*x),y\n", &mut pool);
+        let     x     = pool.get("x").unwrap();
+        let     z     = pool.intern("z");
+        assert_eq!( Find(x, 0).visit_block(&block), Err(1) );
+        assert_eq!( Find(z, 0).visit_block(&block), Ok(())  );
+    }
+
+    // Renames identifiers and folds constant sums.
+    struct Rewrite {
+        from: Ident,
+        to:   Ident,
+    }
+
+    impl Fold<Cf> for Rewrite {
+        fn fold_ident(&mut self, node: Ident, _: Option<Span>) -> Ident {
+            if node == self.from { self.to } else { node }
+        }
+
+        fn fold_expr(&mut self, node: Expr) -> Expr {
+            match fold_expr_children(self, node) {
+                Expr::Binary(ref b) if b.op == BinaryOp::Add => {
+                    match (b.lhs.value(), b.rhs.value()) {
+                        (Some(&Expr::LitInt(x, _)), Some(&Expr::LitInt(y, _))) => Expr::LitInt(x + y, b.span),
+                        _ => Expr::Binary(b.clone()),
+                    }
+                },
+                expr => expr,
+            }
+        }
+    }
+
+    #[test]
+    pub fn fold_block() {
+        let mut pool  = IdentPool::new();
+        let     block = parse(
+            "x   move.l  (x+(1+2),a0),d0\n\
+             \x20   dc.l    x,2+3\n",
+            &mut pool
+        );
+        let x = pool.get("x").unwrap();
+        let y = pool.intern("y");
+
+        let block = Rewrite { from: x, to: y }.fold_block(block);
+        assert_eq!(
+            moto(&block, &pool).to_string(),
+            "x:\n\
+             \x20       move.l (y+3,a0),d0\n\
+             \x20       dc.l y,5\n"
+        );
+        let disp = match block.stmts[0].args[0] {
+            Slot::Value(Arg::AddrRegDisp(_, ref e), _) => e.clone(),
+            ref arg => panic!("unexpected argument: {:?}", arg),
+        };
+        assert_eq!( eval(&disp, &|i| if i == y { Some(4) } else { None }).unwrap().0, 7 );
+
+        // Source locations are kept
+        assert_eq!( block.stmts[0].arg_span(0), Some(Span::new(1, 13, 12)) );
+        assert_eq!( block.stmts[1].arg_span(0), Some(Span::new(2, 13,  1)) );
+        assert_eq!( disp.span(), Some(Span::new(1, 14, 7)) );
+    }
+}
@@ -0,0 +1,190 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt::{self, Display, Formatter};
+use crate::ast::Span;
+use crate::parse::ParseError;
+
+/// Severities of diagnostics.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Severity {
+    /// The source cannot be assembled.
+    Error,
+
+    /// The source can be assembled, but is suspect.
+    Warning,
+}
+
+impl Severity {
+    /// Returns the name of the severity, as it appears in rendered output.
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Error   => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// A message about assembly source, with the location to which it applies
+/// and any notes that explain it.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Diagnostic {
+    /// Severity.
+    pub severity: Severity,
+
+    /// Description of the problem.
+    pub msg: String,
+
+    /// Source location to which the diagnostic applies, if known.
+    pub span: Option<Span>,
+
+    /// Additional explanations.
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    /// Creates an error diagnostic.
+    pub fn error<S: Into<String>>(msg: S, span: Option<Span>) -> Self {
+        Self { severity: Severity::Error, msg: msg.into(), span, notes: vec![] }
+    }
+
+    /// Creates a warning diagnostic.
+    pub fn warning<S: Into<String>>(msg: S, span: Option<Span>) -> Self {
+        Self { severity: Severity::Warning, msg: msg.into(), span, notes: vec![] }
+    }
+
+    /// Adds a note to the diagnostic.
+    pub fn with_note<S: Into<String>>(mut self, note: S) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Returns a wrapper that displays the diagnostic for the source file
+    /// named `file` with text `src`, quoting the offending line.
+    pub fn render<'a>(&'a self, file: &'a str, src: &'a str) -> Rendered<'a> {
+        Rendered { diag: self, file, src }
+    }
+}
+
+impl From<ParseError> for Diagnostic {
+    fn from(e: ParseError) -> Self {
+        let span = Span::new(e.line as u32, e.col as u32, 0);
+        Diagnostic::error(e.msg, Some(span))
+    }
+}
+
+/// Wrapper to display a diagnostic with an excerpt of its source.
+///
+/// ```text
+/// boot.s:12:9: error: instruction is not available on the target
+///    12 |         rems.l  d1,d2:d3
+///       |         ^^^^^^^^^^^^^^^^
+///       = note: rems.l requires HWDIV
+/// ```
+///
+/// A span of zero length is marked with one caret.  Tabs in the quoted line
+/// are kept in the marker line, so that the carets align however tabs are
+/// displayed.
+#[derive(Clone, Copy, Debug)]
+pub struct Rendered<'a> {
+    diag: &'a Diagnostic,
+    file: &'a str,
+    src:  &'a str,
+}
+
+impl<'a> Display for Rendered<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let d = self.diag;
+
+        let span = match d.span {
+            Some(s) => s,
+            None    => {
+                writeln!(f, "{}: {}: {}", self.file, d.severity.name(), d.msg)?;
+                return self.notes(f, 0)
+            },
+        };
+        writeln!(f, "{}:{}:{}: {}: {}",
+            self.file, span.line, span.col, d.severity.name(), d.msg)?;
+
+        let num   = span.line.to_string();
+        let width = num.len().max(4);
+        let text  = match self.src.lines().nth((span.line as usize).saturating_sub(1)) {
+            Some(t) => t,
+            None    => return self.notes(f, width),
+        };
+        writeln!(f, " {:>2$} | {}", num, text, width)?;
+
+        // Marker: whitespace matching the text before the span, then carets
+        let start = (span.col as usize).saturating_sub(1).min(text.len());
+        let end   = (start + span.len as usize).min(text.len());
+        let lead: String = text.get(..start).unwrap_or("").chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let carets = text.get(start..end).map_or(0, |s| s.chars().count()).max(1);
+        writeln!(f, " {:3$} | {}{}", "", lead, "^".repeat(carets), width)?;
+
+        self.notes(f, width)
+    }
+}
+
+impl<'a> Rendered<'a> {
+    fn notes(&self, f: &mut Formatter, width: usize) -> fmt::Result {
+        for note in &self.diag.notes {
+            writeln!(f, " {:2$} = note: {}", "", note, width)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn render_excerpt() {
+        let src  = "start:\n\trems.l  d1,d2:d3 ; divide\n";
+        let diag = Diagnostic::error("instruction is not available on the target", Some(Span::new(2, 2, 16)))
+            .with_note("rems.l requires HWDIV");
+        assert_eq!(
+            diag.render("boot.s", src).to_string(),
+            "boot.s:2:2: error: instruction is not available on the target\n\
+             \x20   2 | \trems.l  d1,d2:d3 ; divide\n\
+             \x20     | \t^^^^^^^^^^^^^^^^\n\
+             \x20     = note: rems.l requires HWDIV\n"
+        );
+    }
+
+    #[test]
+    pub fn render_without_span() {
+        let diag = Diagnostic::warning("no statements", None).with_note("the file is empty");
+        assert_eq!(
+            diag.render("empty.s", "").to_string(),
+            "empty.s: warning: no statements\n\
+             \x20 = note: the file is empty\n"
+        );
+    }
+
+    #[test]
+    pub fn render_parse_error() {
+        let diag = Diagnostic::from(ParseError::new(1, 9, "expected expression"));
+        assert_eq!(
+            diag.render("a.s", " dc.b 1,\n").to_string(),
+            "a.s:1:9: error: expected expression\n\
+             \x20   1 |  dc.b 1,\n\
+             \x20     |         ^\n"
+        );
+    }
+}
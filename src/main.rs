@@ -20,11 +20,14 @@
 #[macro_use]
 mod util;
 
-mod arch;
-mod ast;
+pub mod arch;
+pub mod ast;
 mod decode;
+mod diag;
 mod mem;
 mod num;
+mod obj;
+mod parse;
 
 fn main() {
     println!("Hello, world!");
@@ -14,8 +14,6 @@
 // You should have received a copy of the GNU General Public License
 // along with cfda.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::mem::transmute;
-
 /// Trait for types that have a known byte order.
 pub trait ByteOrdered {
     /// Gets the byte order.
@@ -81,17 +79,13 @@ macro_rules! impl_endian_float {
         impl Endian for $t {
             #[inline]
             fn to_order(self, order: ByteOrder) -> Self {
-                let mut i: $i = unsafe { transmute(self) };
-                i = i.to_order(order);
-                unsafe { transmute(i) }
+                $t::from_bits(self.to_bits().to_order(order))
             }
 
             /// Converts a value from the given byte order to the target's byte order.
             #[inline]
             fn from_order(order: ByteOrder, x: Self) -> Self {
-                let mut i: $i = unsafe { transmute(x) };
-                i = $i::from_order(order, i);
-                unsafe { transmute(i) }
+                $t::from_bits($i::from_order(order, x.to_bits()))
             }
         }
     )*}
@@ -103,7 +97,6 @@ impl_endian_float! { f32:u32, f64:u64 }
 
 #[cfg(test)]
 mod tests {
-    use std::mem::transmute;
     use super::*;
 
     #[test]
@@ -127,8 +120,8 @@ mod tests {
         let be: f32 = 12.34.to_order(BE);
         let le: f32 = 12.34.to_order(LE);
 
-        let be: u32 = unsafe { transmute(be) };
-        let le: u32 = unsafe { transmute(le) };
+        let be: u32 = be.to_bits();
+        let le: u32 = le.to_bits();
 
         assert_eq!(be.swap_bytes(), le);
     }
@@ -138,8 +131,8 @@ mod tests {
         let be: f32 = f32::from_order(BE, 12.34);
         let le: f32 = f32::from_order(LE, 12.34);
 
-        let be: u32 = unsafe { transmute(be) };
-        let le: u32 = unsafe { transmute(le) };
+        let be: u32 = be.to_bits();
+        let le: u32 = le.to_bits();
 
         assert_eq!(be.swap_bytes(), le);
     }
@@ -0,0 +1,346 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use super::{Region, RegionKind};
+
+/// Content of a region of memory.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Block {
+    /// Addresses, length, and kind of the content.
+    pub region: Region,
+
+    /// Content at the start of the region.  Any bytes of the region beyond
+    /// the content are reserved and zero, as in `.bss`.
+    pub bytes: Vec<u8>,
+
+    /// Name of the section from which the content came, if any.
+    pub section: Option<String>,
+}
+
+impl Block {
+    /// Creates a block with content `bytes` at the start of `region`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the content is longer than the region.
+    pub fn new(region: Region, bytes: Vec<u8>) -> Self {
+        if bytes.len() as u64 > region.len as u64 {
+            panic!("Block content exceeds region length.")
+        }
+        Self { region, bytes, section: None }
+    }
+
+    /// Returns the length of the reserved space after the content.
+    #[inline]
+    pub fn reserved(&self) -> u32 {
+        self.region.len - self.bytes.len() as u32
+    }
+
+    // Returns whether the content of the block overlaps that of `other` at
+    // load addresses.
+    fn overlaps_lma(&self, other: &Block) -> bool {
+        let a = (self .region.lma as u64, self .region.lma as u64 + self .bytes.len() as u64);
+        let b = (other.region.lma as u64, other.region.lma as u64 + other.bytes.len() as u64);
+        a.0 < b.1 && b.0 < a.1
+    }
+}
+
+/// A memory image: blocks of content in regions of memory, with gaps
+/// between.
+///
+/// Blocks are in order of Virtual Memory Address, and their regions overlap
+/// neither there nor, where they have content, at Load Memory Addresses.
+#[derive(Clone, Default, PartialEq, Eq, Hash, Debug)]
+pub struct Image {
+    blocks: Vec<Block>,
+}
+
+impl Image {
+    /// Creates an empty image.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns whether the image contains no blocks.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Returns the number of bytes of content in the image, excluding
+    /// reserved space and gaps.
+    pub fn len(&self) -> usize {
+        self.blocks.iter().map(|b| b.bytes.len()).sum()
+    }
+
+    /// Returns the blocks of the image, in order of Virtual Memory Address.
+    #[inline]
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    /// Returns the content of each block that has any, with its Load Memory
+    /// Address, in order of that address.  This is what a programmer would
+    /// store in memory.
+    pub fn contents(&self) -> Vec<(u32, &[u8])> {
+        let mut contents: Vec<(u32, &[u8])> = self.blocks.iter()
+            .filter(|b| !b.bytes.is_empty())
+            .map(|b| (b.region.lma, &b.bytes[..]))
+            .collect();
+        contents.sort_by_key(|&(lma, _)| lma);
+        contents
+    }
+
+    /// Adds `block` to the image.  An empty region is ignored.
+    ///
+    /// If the block overlaps an existing block, this method leaves the image
+    /// unchanged and returns the region of the existing block as an error.
+    pub fn add(&mut self, block: Block) -> Result<(), Region> {
+        let r = block.region;
+        if r.len == 0 {
+            return Ok(())
+        }
+        let i = self.blocks.partition_point(|b| b.region.vma < r.vma);
+        let prev = i.checked_sub(1).map(|i| &self.blocks[i]);
+        let next = self.blocks.get(i);
+        if let Some(b) = prev.filter(|b| b.region.end_vma() > r.vma as u64) {
+            return Err(b.region)
+        }
+        if let Some(b) = next.filter(|b| (b.region.vma as u64) < r.end_vma()) {
+            return Err(b.region)
+        }
+        if let Some(b) = self.blocks.iter().find(|b| b.overlaps_lma(&block)) {
+            return Err(b.region)
+        }
+        self.blocks.insert(i, block);
+        Ok(())
+    }
+
+    /// Writes `bytes` at `addr` as code, replacing any bytes already there.
+    /// Bytes that would lie beyond the end of the address space are
+    /// discarded.
+    ///
+    /// This method suits loaders of formats that give only addresses and
+    /// bytes.  The blocks that the bytes overlap or abut join into one block
+    /// whose LMA and VMA are equal, and whose reserved space becomes zero
+    /// content.
+    pub fn write(&mut self, addr: u32, bytes: &[u8]) {
+        let room  = (1_u64 << 32) - addr as u64;
+        let bytes = &bytes[..bytes.len().min(room as usize)];
+        if bytes.is_empty() {
+            return
+        }
+        let end = addr as u64 + bytes.len() as u64;
+
+        // Blocks that overlap or abut the new bytes
+        let first = self.blocks.partition_point(|b| b.region.end_vma() < addr as u64);
+        let last  = self.blocks.partition_point(|b| (b.region.vma as u64) <= end);
+        let joins = &self.blocks[first..last];
+
+        let start = joins.first().map_or(addr, |b| b.region.vma.min(addr));
+        let stop  = joins.last ().map_or(end,  |b| b.region.end_vma().max(end));
+        let mut joined = vec![0; (stop - start as u64) as usize];
+        for b in joins {
+            let at = (b.region.vma - start) as usize;
+            joined[at..at + b.bytes.len()].copy_from_slice(&b.bytes);
+        }
+        let at = (addr - start) as usize;
+        joined[at..at + bytes.len()].copy_from_slice(bytes);
+
+        let region = Region::new(start, start, joined.len() as u32, RegionKind::Code);
+        self.blocks.splice(first..last, Some(Block::new(region, joined)));
+    }
+
+    /// Sets the kind of the `len` bytes at the Virtual Memory Address `vma`
+    /// to `kind`, splitting blocks where the kind changes.  Addresses outside
+    /// of any block are ignored.
+    pub fn set_kind(&mut self, vma: u32, len: u32, kind: RegionKind) {
+        let end = vma as u64 + len as u64;
+        let mut i = self.blocks.partition_point(|b| b.region.end_vma() <= vma as u64);
+        while let Some(b) = self.blocks.get(i).filter(|b| (b.region.vma as u64) < end) {
+            if b.region.kind == kind {
+                i += 1;
+                continue
+            }
+            let r = b.region;
+            if end < r.end_vma() {
+                self.split(i, (end - r.vma as u64) as u32);
+            }
+            if vma > r.vma {
+                self.split(i, vma - r.vma);
+                i += 1;
+            }
+            self.blocks[i].region.kind = kind;
+            i += 1;
+        }
+    }
+
+    // Splits block `i` into two blocks, the second of which begins `at`
+    // bytes into the region.
+    fn split(&mut self, i: usize, at: u32) {
+        let b     = &mut self.blocks[i];
+        let r     = b.region;
+        let bytes = b.bytes.split_off((at as usize).min(b.bytes.len()));
+        let mut rest = Block::new(Region::new(r.lma + at, r.vma + at, r.len - at, r.kind), bytes);
+        rest.section = b.section.clone();
+        b.region = Region::new(r.lma, r.vma, at, r.kind);
+        self.blocks.insert(i + 1, rest);
+    }
+
+    /// Returns the block whose region contains the Virtual Memory Address
+    /// `vma`, if any.
+    pub fn block_at(&self, vma: u32) -> Option<&Block> {
+        let i = self.blocks.partition_point(|b| b.region.end_vma() <= vma as u64);
+        self.blocks.get(i).filter(|b| b.region.contains(vma))
+    }
+
+    /// Returns the block whose content contains the Load Memory Address
+    /// `lma`, if any.
+    pub fn block_at_lma(&self, lma: u32) -> Option<&Block> {
+        self.blocks.iter().find(|b| {
+            lma >= b.region.lma && (lma as u64) < b.region.lma as u64 + b.bytes.len() as u64
+        })
+    }
+
+    /// Returns the `len` bytes of content at the Virtual Memory Address
+    /// `vma`, if the content of one block contains all of them.
+    pub fn read(&self, vma: u32, len: usize) -> Option<&[u8]> {
+        let b     = self.block_at(vma)?;
+        let start = (vma - b.region.vma) as usize;
+        b.bytes.get(start..start.checked_add(len)?)
+    }
+
+    /// Returns the `len` bytes of content at the Virtual Memory Address
+    /// `vma` for modification, if the content of one block contains all of
+    /// them.
+    pub fn read_mut(&mut self, vma: u32, len: usize) -> Option<&mut [u8]> {
+        let i     = self.blocks.partition_point(|b| b.region.end_vma() <= vma as u64);
+        let b     = self.blocks.get_mut(i).filter(|b| b.region.contains(vma))?;
+        let start = (vma - b.region.vma) as usize;
+        b.bytes.get_mut(start..start.checked_add(len)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::mem::RegionKind::*;
+
+    fn spans(image: &Image) -> Vec<(u32, usize)> {
+        image.blocks().iter().map(|b| (b.region.vma, b.bytes.len())).collect()
+    }
+
+    fn block(lma: u32, vma: u32, len: u32, bytes: &[u8]) -> Block {
+        Block::new(Region::new(lma, vma, len, Data), bytes.to_vec())
+    }
+
+    #[test]
+    pub fn write_with_gaps() {
+        let mut image = Image::new();
+        image.write(0x100, &[1, 2, 3, 4]);
+        image.write(0x200, &[5, 6]);
+        image.write(0x080, &[7]);
+        assert_eq!( spans(&image), [(0x080, 1), (0x100, 4), (0x200, 2)] );
+        assert_eq!( image.len(), 7 );
+        assert_eq!( image.read(0x101, 3), Some(&[2, 3, 4][..]) );
+        assert_eq!( image.read(0x101, 4), None );
+        assert_eq!( image.read(0x104, 1), None );
+    }
+
+    #[test]
+    pub fn write_joins_blocks() {
+        let mut image = Image::new();
+        image.write(0x100, &[1, 2]);
+        image.write(0x104, &[5, 6]);
+        image.write(0x102, &[3, 4]);
+        assert_eq!( spans(&image), [(0x100, 6)] );
+        assert_eq!( image.read(0x100, 6), Some(&[1, 2, 3, 4, 5, 6][..]) );
+
+        // Later bytes replace earlier ones
+        image.write(0x0FF, &[9, 9, 9]);
+        assert_eq!( spans(&image), [(0x0FF, 7)] );
+        assert_eq!( image.read(0x0FF, 7), Some(&[9, 9, 9, 3, 4, 5, 6][..]) );
+    }
+
+    #[test]
+    pub fn write_at_top() {
+        let mut image = Image::new();
+        image.write(0xFFFF_FFFE, &[1, 2, 3]);
+        assert_eq!( spans(&image), [(0xFFFF_FFFE, 2)] );
+        assert_eq!( image.blocks()[0].region.end_vma(), 1 << 32 );
+        assert_eq!( image.block_at(0xFFFF_FFFF).map(|b| b.region.vma), Some(0xFFFF_FFFE) );
+    }
+
+    #[test]
+    pub fn add_regions() {
+        let mut image = Image::new();
+        let text = block(0x0000, 0x0000, 0x100, &[0x4E, 0x75]);
+        let data = block(0x0100, 0x8000, 0x010, &[1, 2, 3, 4]);
+        let bss  = block(0x8010, 0x8010, 0x100, &[]);
+        assert_eq!( image.add(bss.clone()),  Ok(()) );
+        assert_eq!( image.add(data.clone()), Ok(()) );
+        assert_eq!( image.add(text.clone()), Ok(()) );
+        assert_eq!( spans(&image), [(0x0000, 2), (0x8000, 4), (0x8010, 0)] );
+        assert_eq!( image.blocks()[1].region.reloc(), 0x7F00 );
+        assert_eq!( image.blocks()[2].reserved(), 0x100 );
+
+        // Lookup by either address
+        assert_eq!( image.block_at(0x8003).map(|b| b.region.lma), Some(0x100) );
+        assert_eq!( image.block_at(0x0200), None );
+        assert_eq!( image.block_at_lma(0x0103).map(|b| b.region.vma), Some(0x8000) );
+        assert_eq!( image.block_at_lma(0x8010), None );
+        assert_eq!( image.read(0x8001, 3), Some(&[2, 3, 4][..]) );
+        assert_eq!( image.read(0x8020, 1), None );
+
+        image.read_mut(0x8000, 1).unwrap()[0] = 9;
+        assert_eq!( image.read(0x8000, 1), Some(&[9][..]) );
+
+        // Overlaps at either address
+        assert_eq!( image.add(block(0x9000, 0x80FF, 1, &[])),  Err(bss.region) );
+        assert_eq!( image.add(block(0x9000, 0x7FFF, 2, &[])),  Err(data.region) );
+        assert_eq!( image.add(block(0x0103, 0x9000, 1, &[0])), Err(data.region) );
+        assert_eq!( image.add(block(0x0104, 0x9000, 1, &[0])), Ok(()) );
+    }
+
+    #[test]
+    pub fn set_kinds() {
+        let mut image = Image::new();
+        image.add(block(0x1000, 0x100, 0x10, &[0; 8])).unwrap();
+        image.add(block(0x2000, 0x200, 0x10, &[1; 16])).unwrap();
+
+        // Within a block, and across a gap into another
+        image.set_kind(0x104, 4, Code);
+        image.set_kind(0x10C, 0x100, Strings);
+        let kinds: Vec<_> = image.blocks().iter()
+            .map(|b| (b.region.lma, b.region.vma, b.region.len, b.bytes.len(), b.region.kind))
+            .collect();
+        assert_eq!( kinds, [
+            (0x1000, 0x100, 4, 4, Data),
+            (0x1004, 0x104, 4, 4, Code),
+            (0x1008, 0x108, 4, 0, Data),
+            (0x100C, 0x10C, 4, 0, Strings),
+            (0x2000, 0x200, 12, 12, Strings),
+            (0x200C, 0x20C, 4, 4, Data),
+        ]);
+
+        // A kind already set leaves blocks as they are
+        image.set_kind(0x100, 4, Data);
+        assert_eq!( image.blocks().len(), 6 );
+        assert_eq!( image.read(0x20A, 4), None );
+        assert_eq!( image.read(0x20C, 4), Some(&[1; 4][..]) );
+    }
+}
@@ -15,6 +15,7 @@
 // along with cfda.  If not, see <http://www.gnu.org/licenses/>.
 
 use std::mem::size_of;
+use std::ptr;
 use crate::mem::{ByteOrder, Endian};
 
 /// Trait to load values from buffers of type `B` given formats of type `F`.
@@ -44,7 +45,7 @@ impl<T> Load<[u8]> for T where T: Copy {
     fn load(buf: &[u8], _: ()) -> Option<(T, &[u8])> {
         let size = size_of::<T>();
         if buf.len() >= size {
-            let val = unsafe { *(buf.as_ptr() as *const T) };
+            let val = unsafe { ptr::read_unaligned(buf.as_ptr() as *const T) };
             Some((val, &buf[size..]))
         } else {
             None
@@ -57,7 +58,7 @@ impl<T> Save<[u8]> for T where T: Copy {
     fn save<'a>(&self, buf: &'a mut [u8], _: ()) -> Option<&'a mut [u8]> {
         let size = size_of::<T>();
         if buf.len() >= size {
-            unsafe { *(buf.as_ptr() as *mut T) = *self };
+            unsafe { ptr::write_unaligned(buf.as_mut_ptr() as *mut T, *self) };
             Some(&mut buf[size..])
         } else {
             None
@@ -15,8 +15,12 @@
 // along with cfda.  If not, see <http://www.gnu.org/licenses/>.
 
 mod byte_order;
+mod image;
 mod load_save;
+mod region;
 
 pub use self::byte_order::*;
+pub use self::image::*;
 pub use self::load_save::*;
+pub use self::region::*;
 
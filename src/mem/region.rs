@@ -0,0 +1,170 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+/// A range of memory with content of one kind.
+///
+/// A region has two addresses: the Load Memory Address (LMA), at which its
+/// content is stored, such as in flash; and the Virtual Memory Address
+/// (VMA), at which the content is used, such as in RAM after startup code
+/// copies it there.  For most regions, the two are equal.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Region {
+    /// Load Memory Address (LMA).  LMA + relocation = VMA.
+    pub lma: u32,
+
+    /// Virtual Memory Address (VMA).  LMA + relocation = VMA.
+    pub vma: u32,
+
+    /// Length in bytes.
+    pub len: u32,
+
+    /// The kind of content within the region.
+    pub kind: RegionKind,
+}
+
+/// Kinds of content within a region.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum RegionKind {
+    /// Instructions.
+    Code,
+
+    /// Data other than text.
+    Data,
+
+    /// Text, such as null-terminated strings.
+    Strings,
+
+    /// A table of exception vectors: 32-bit addresses of handlers.
+    Vectors,
+}
+
+impl Region {
+    /// Creates a new `Region`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the region would extend beyond the end of the address
+    /// space at either address.
+    pub fn new(lma: u32, vma: u32, len: u32, kind: RegionKind) -> Self {
+        if lma.checked_add(len).is_none() && lma as u64 + len as u64 != 1 << 32 {
+            panic!("Region LMA would overflow.")
+        }
+        if vma.checked_add(len).is_none() && vma as u64 + len as u64 != 1 << 32 {
+            panic!("Region VMA would overflow.")
+        }
+        Self { lma, vma, len, kind }
+    }
+
+    /// Gets the relocation.  LMA + relocation = VMA.
+    #[inline]
+    pub fn reloc(&self) -> u32 {
+        self.vma.wrapping_sub(self.lma)
+    }
+
+    /// Gets the ending Load Memory Address: that after the last byte.  This
+    /// is `2^32` for a region that ends at the top of the address space.
+    #[inline]
+    pub fn end_lma(&self) -> u64 {
+        self.lma as u64 + self.len as u64
+    }
+
+    /// Gets the ending Virtual Memory Address: that after the last byte.
+    /// This is `2^32` for a region that ends at the top of the address
+    /// space.
+    #[inline]
+    pub fn end_vma(&self) -> u64 {
+        self.vma as u64 + self.len as u64
+    }
+
+    /// Returns whether the region contains the Virtual Memory Address `vma`.
+    #[inline]
+    pub fn contains(&self, vma: u32) -> bool {
+        vma >= self.vma && (vma as u64) < self.end_vma()
+    }
+
+    /// Returns whether the region contains the Load Memory Address `lma`.
+    #[inline]
+    pub fn contains_lma(&self, lma: u32) -> bool {
+        lma >= self.lma && (lma as u64) < self.end_lma()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::RegionKind::{Code as C};
+
+    #[test]
+    pub fn new() {
+        let region_a = Region::new(0x2000, 0x3000, 0x0100, C);
+        let region_b = Region { lma: 0x2000, vma: 0x3000, len: 0x0100, kind: C };
+        assert_eq!(region_a, region_b);
+    }
+
+    #[test]
+    pub fn new_at_top() {
+        let region = Region::new(0xFFFF_FF00, 0x3000, 0x0100, C);
+        assert_eq!(region.end_lma(), 1 << 32);
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn new_lma_out_of_range() {
+        Region::new(0xFFFF_FF00, 0x3000, 0x0101, C);
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn new_vma_out_of_range() {
+        Region::new(0x2000, 0xFFFF_FFFF, 0x0002, C);
+    }
+
+    #[test]
+    pub fn reloc_positive() {
+        let region = Region { lma: 0x2000, vma: 0x3000, len: 0x0100, kind: C };
+        let reloc  = region.reloc();
+        assert_eq!(reloc, 0x1000);
+    }
+
+    #[test]
+    pub fn reloc_negative() {
+        let region = Region { lma: 0x2000, vma: 0x1000, len: 0x0100, kind: C };
+        let reloc  = region.reloc();
+        assert_eq!(reloc, 0x1000u32.wrapping_neg());
+    }
+
+    #[test]
+    pub fn end_lma() {
+        let region = Region { lma: 0x2000, vma: 0x3000, len: 0x0100, kind: C };
+        let addr   = region.end_lma();
+        assert_eq!(addr, 0x2100);
+    }
+
+    #[test]
+    pub fn end_vma() {
+        let region = Region { lma: 0x2000, vma: 0x3000, len: 0x0100, kind: C };
+        let addr   = region.end_vma();
+        assert_eq!(addr, 0x3100);
+    }
+
+    #[test]
+    pub fn contains() {
+        let region = Region { lma: 0x2000, vma: 0x3000, len: 0x0100, kind: C };
+        assert!( region.contains(0x3000) && region.contains(0x30FF) );
+        assert!( !region.contains(0x2FFF) && !region.contains(0x3100) );
+        assert!( region.contains_lma(0x2000) && !region.contains_lma(0x3000) );
+    }
+}
@@ -0,0 +1,299 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+mod read;
+mod write;
+
+pub use self::read::*;
+
+// Identification
+pub const ELF_MAGIC:    [u8; 4] = *b"\x7FELF";
+pub const ELFCLASS32:   u8 = 1;
+pub const ELFDATA2MSB:  u8 = 2;
+pub const EV_CURRENT:   u8 = 1;
+
+// File types
+pub const ET_REL:       u16 = 1;
+pub const ET_EXEC:      u16 = 2;
+
+// Machines
+pub const EM_68K:       u16 = 4;
+
+// ColdFire flags, as in binutils
+pub const EF_M68K_CF_ISA_A_NODIV: u32 = 0x01;
+pub const EF_M68K_CF_ISA_A:       u32 = 0x02;
+pub const EF_M68K_CF_ISA_A_PLUS:  u32 = 0x03;
+pub const EF_M68K_CF_ISA_B_NOUSP: u32 = 0x04;
+pub const EF_M68K_CF_ISA_B:       u32 = 0x05;
+pub const EF_M68K_CF_ISA_C:       u32 = 0x06;
+pub const EF_M68K_CF_ISA_C_NODIV: u32 = 0x07;
+pub const EF_M68K_CF_MAC:         u32 = 0x10;
+pub const EF_M68K_CF_EMAC:        u32 = 0x20;
+pub const EF_M68K_CF_EMAC_B:      u32 = 0x30;
+pub const EF_M68K_CF_FLOAT:       u32 = 0x40;
+
+// Section types
+pub const SHT_NULL:     u32 = 0;
+pub const SHT_PROGBITS: u32 = 1;
+pub const SHT_SYMTAB:   u32 = 2;
+pub const SHT_STRTAB:   u32 = 3;
+pub const SHT_RELA:     u32 = 4;
+pub const SHT_NOBITS:   u32 = 8;
+pub const SHT_REL:      u32 = 9;
+
+// Section flags
+pub const SHF_WRITE:     u32 = 0x01;
+pub const SHF_ALLOC:     u32 = 0x02;
+pub const SHF_EXECINSTR: u32 = 0x04;
+pub const SHF_MERGE:     u32 = 0x10;
+pub const SHF_STRINGS:   u32 = 0x20;
+pub const SHF_INFO_LINK: u32 = 0x40;
+
+// Special section indexes
+pub const SHN_UNDEF:    u16 = 0;
+pub const SHN_ABS:      u16 = 0xFFF1;
+pub const SHN_COMMON:   u16 = 0xFFF2;
+
+// Symbol bindings
+pub const STB_LOCAL:    u8 = 0;
+pub const STB_GLOBAL:   u8 = 1;
+pub const STB_WEAK:     u8 = 2;
+
+// Symbol types
+pub const STT_NOTYPE:   u8 = 0;
+pub const STT_OBJECT:   u8 = 1;
+pub const STT_FUNC:     u8 = 2;
+pub const STT_SECTION:  u8 = 3;
+pub const STT_FILE:     u8 = 4;
+
+// Relocation types for EM_68K
+pub const R_68K_NONE:   u8 = 0;
+pub const R_68K_32:     u8 = 1;
+pub const R_68K_16:     u8 = 2;
+pub const R_68K_8:      u8 = 3;
+pub const R_68K_PC32:   u8 = 4;
+pub const R_68K_PC16:   u8 = 5;
+pub const R_68K_PC8:    u8 = 6;
+
+// Program header types
+pub const PT_NULL:      u32 = 0;
+pub const PT_LOAD:      u32 = 1;
+pub const PT_DYNAMIC:   u32 = 2;
+pub const PT_INTERP:    u32 = 3;
+pub const PT_NOTE:      u32 = 4;
+pub const PT_PHDR:      u32 = 6;
+
+// Program header flags
+pub const PF_X:         u32 = 0x1;
+pub const PF_W:         u32 = 0x2;
+pub const PF_R:         u32 = 0x4;
+
+// Sizes of structures
+pub const EHDR_SIZE:    usize = 52;
+pub const PHDR_SIZE:    usize = 32;
+pub const SHDR_SIZE:    usize = 40;
+pub const SYM_SIZE:     usize = 16;
+pub const RELA_SIZE:    usize = 12;
+pub const REL_SIZE:     usize = 8;
+
+/// An ELF32 file: its sections, symbols, and relocations.
+///
+/// The string tables, symbol table, and relocation sections of the file are
+/// not sections here; the writer generates them and the reader interprets
+/// them.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ElfFile {
+    /// File type: `ET_REL` or `ET_EXEC`.
+    pub file_type: u16,
+
+    /// Machine: `EM_68K` for ColdFire.
+    pub machine: u16,
+
+    /// Machine-specific flags.
+    pub flags: u32,
+
+    /// Address at which execution begins, or 0 if none.
+    pub entry: u32,
+
+    /// Sections with content or reserved space.
+    pub sections: Vec<ElfSection>,
+
+    /// Symbols, in no particular order.
+    pub symbols: Vec<ElfSymbol>,
+
+    /// Segments described by program headers.  The writer ignores these,
+    /// generating instead a `PT_LOAD` segment for each allocated section of
+    /// an executable file.
+    pub segments: Vec<ElfSegment>,
+}
+
+impl ElfFile {
+    /// Creates an empty file of the given type, for the given machine.
+    pub fn new(file_type: u16, machine: u16, flags: u32) -> Self {
+        Self { file_type, machine, flags, entry: 0, sections: vec![], symbols: vec![], segments: vec![] }
+    }
+
+    /// Returns the index of the section named `name`, if any.
+    pub fn section_index(&self, name: &str) -> Option<usize> {
+        self.sections.iter().position(|s| s.name == name)
+    }
+}
+
+/// A section with content (`SHT_PROGBITS`) or reserved space
+/// (`SHT_NOBITS`).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ElfSection {
+    /// Name, such as `.text`.
+    pub name: String,
+
+    /// Section type.
+    pub kind: u32,
+
+    /// Section flags.
+    pub flags: u32,
+
+    /// Address in memory, or 0 in a relocatable file.
+    pub addr: u32,
+
+    /// Required alignment in bytes.
+    pub align: u32,
+
+    /// Size in bytes.  For a section with content, the length of `data`.
+    pub size: u32,
+
+    /// Content, or empty for reserved space.
+    pub data: Vec<u8>,
+
+    /// Relocations to apply to the content.
+    pub relocs: Vec<ElfReloc>,
+}
+
+impl ElfSection {
+    /// Creates a section of type `SHT_PROGBITS` with the given content.
+    pub fn progbits(name: &str, flags: u32, align: u32, data: Vec<u8>) -> Self {
+        let size = data.len() as u32;
+        Self { name: name.into(), kind: SHT_PROGBITS, flags, addr: 0, align, size, data, relocs: vec![] }
+    }
+
+    /// Creates a section of type `SHT_NOBITS` that reserves `size` bytes.
+    pub fn nobits(name: &str, flags: u32, align: u32, size: u32) -> Self {
+        Self { name: name.into(), kind: SHT_NOBITS, flags, addr: 0, align, size, data: vec![], relocs: vec![] }
+    }
+
+    /// Returns whether the section holds code.
+    #[inline]
+    pub fn is_code(&self) -> bool {
+        self.flags & SHF_EXECINSTR != 0
+    }
+}
+
+/// Section to which a symbol belongs.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ElfSymbolSection {
+    /// Defined in another file.
+    Undefined,
+
+    /// An absolute value.
+    Absolute,
+
+    /// A common block; the value is its alignment.
+    Common,
+
+    /// Defined in the section with the given index into `ElfFile::sections`.
+    Section(usize),
+}
+
+/// A symbol.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ElfSymbol {
+    /// Name, or empty for a section symbol.
+    pub name: String,
+
+    /// Value: in a relocatable file, the offset within the section; in an
+    /// executable file, the address.
+    pub value: u32,
+
+    /// Size in bytes, or 0 if unknown.
+    pub size: u32,
+
+    /// Symbol type (`STT_*`).
+    pub kind: u8,
+
+    /// Binding (`STB_*`).
+    pub binding: u8,
+
+    /// Section to which the symbol belongs.
+    pub section: ElfSymbolSection,
+}
+
+impl ElfSymbol {
+    /// Creates the symbol that stands for the start of section `index`.
+    pub fn section(index: usize) -> Self {
+        Self {
+            name:    String::new(),
+            value:   0,
+            size:    0,
+            kind:    STT_SECTION,
+            binding: STB_LOCAL,
+            section: ElfSymbolSection::Section(index),
+        }
+    }
+}
+
+/// A relocation with explicit addend (`Elf32_Rela`).
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ElfReloc {
+    /// Offset of the field within the section.
+    pub offset: u32,
+
+    /// Relocation type (`R_68K_*`).
+    pub kind: u8,
+
+    /// Index into `ElfFile::symbols` of the symbol whose value the field
+    /// depends on, or `None` for none.
+    pub symbol: Option<usize>,
+
+    /// Constant to add to the value of the symbol.
+    pub addend: i32,
+}
+
+/// A segment described by a program header.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ElfSegment {
+    /// Segment type (`PT_*`).
+    pub kind: u32,
+
+    /// Offset of the content within the file.
+    pub offset: u32,
+
+    /// Address of the segment in memory.
+    pub vaddr: u32,
+
+    /// Physical address, at which the content is loaded.
+    pub paddr: u32,
+
+    /// Size of the content in the file.
+    pub file_size: u32,
+
+    /// Size in memory.  Bytes beyond the content are zero.
+    pub mem_size: u32,
+
+    /// Flags (`PF_*`).
+    pub flags: u32,
+
+    /// Required alignment in bytes.
+    pub align: u32,
+}
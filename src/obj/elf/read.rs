@@ -0,0 +1,525 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt::{self, Display, Formatter};
+use crate::mem::{BE, Block, ByteOrder, Image, Load, Region, RegionKind};
+use super::*;
+
+/// An error encountered while reading an ELF file.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ElfError {
+    /// The file does not begin with the ELF magic number.
+    NotElf,
+
+    /// The file is not big-endian ELF32 of the current version.
+    Unsupported,
+
+    /// A structure at the given offset extends past the end of the file.
+    Truncated(usize),
+
+    /// The section header with the given index is invalid.
+    BadSection(usize),
+
+    /// The symbol with the given index is invalid.
+    BadSymbol(usize),
+
+    /// The section with the given index into `ElfFile::sections` overlaps
+    /// another in memory, or extends beyond the end of the address space.
+    Overlap(usize),
+}
+
+impl Display for ElfError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            ElfError::NotElf        => write!(f, "not an ELF file"),
+            ElfError::Unsupported   => write!(f, "not a big-endian ELF32 file"),
+            ElfError::Truncated(o)  => write!(f, "file truncated at offset 0x{:X}", o),
+            ElfError::BadSection(i) => write!(f, "invalid section header {}", i),
+            ElfError::BadSymbol(i)  => write!(f, "invalid symbol {}", i),
+            ElfError::Overlap(i)    => write!(f, "section {} overlaps another", i),
+        }
+    }
+}
+
+impl ElfFile {
+    /// Reads a big-endian ELF32 file from `bytes`.
+    ///
+    /// Sections other than the symbol table, string tables, and relocation
+    /// sections become `sections`, in order.  Symbols follow the order of
+    /// the symbol table, omitting its initial null entry.  Relocations,
+    /// whether `Elf32_Rela` or `Elf32_Rel`, attach to the sections they
+    /// modify; the addend of an `Elf32_Rel` is the content of its field.
+    pub fn read(bytes: &[u8]) -> Result<Self, ElfError> {
+        if bytes.len() < 16 || bytes[..4] != ELF_MAGIC {
+            return Err(ElfError::NotElf)
+        }
+        if bytes[4..7] != [ELFCLASS32, ELFDATA2MSB, EV_CURRENT] {
+            return Err(ElfError::Unsupported)
+        }
+        let h: Header = load(bytes, 0)?;
+
+        // Program headers
+        if h.phnum != 0 && (h.phentsize as usize) < PHDR_SIZE {
+            return Err(ElfError::Unsupported)
+        }
+        let segments = (0..h.phnum as usize)
+            .map(|i| load(bytes, h.phoff as usize + i * h.phentsize as usize))
+            .collect::<Result<Vec<ElfSegment>, _>>()?;
+
+        // Section headers
+        if h.shnum != 0 && (h.shentsize as usize) < SHDR_SIZE {
+            return Err(ElfError::Unsupported)
+        }
+        let headers = (0..h.shnum as usize)
+            .map(|i| load(bytes, h.shoff as usize + i * h.shentsize as usize))
+            .collect::<Result<Vec<SectionHeader>, _>>()?;
+        let names = match h.shstrndx {
+            SHN_UNDEF => &[][..],
+            n         => content(bytes, &headers, n as usize)?,
+        };
+
+        // Sections with content or reserved space
+        let mut sections = vec![];
+        let mut index    = vec![None; headers.len()];
+        for (i, sh) in headers.iter().enumerate().skip(1) {
+            let kept = match sh.kind {
+                SHT_NULL | SHT_SYMTAB | SHT_RELA | SHT_REL => false,
+                SHT_STRTAB                                 => sh.flags & SHF_ALLOC != 0,
+                _                                          => true,
+            };
+            if !kept {
+                continue
+            }
+            index[i] = Some(sections.len());
+            sections.push(ElfSection {
+                name:   string(names, sh.name).ok_or(ElfError::BadSection(i))?,
+                kind:   sh.kind,
+                flags:  sh.flags,
+                addr:   sh.addr,
+                align:  sh.align,
+                size:   sh.size,
+                data:   content(bytes, &headers, i)?.to_vec(),
+                relocs: vec![],
+            });
+        }
+
+        // Symbols
+        let mut symbols = vec![];
+        if let Some(t) = headers.iter().position(|sh| sh.kind == SHT_SYMTAB) {
+            let table   = content(bytes, &headers, t)?;
+            let strings = content(bytes, &headers, headers[t].link as usize)?;
+            let size    = (headers[t].entsize as usize).max(SYM_SIZE);
+            for n in 1..table.len() / size {
+                let e: SymbolEntry = load(table, n * size)?;
+                let section = match e.shndx {
+                    SHN_UNDEF  => ElfSymbolSection::Undefined,
+                    SHN_ABS    => ElfSymbolSection::Absolute,
+                    SHN_COMMON => ElfSymbolSection::Common,
+                    s          => match index.get(s as usize) {
+                        Some(&Some(s)) => ElfSymbolSection::Section(s),
+                        _              => return Err(ElfError::BadSymbol(n)),
+                    },
+                };
+                symbols.push(ElfSymbol {
+                    name:    string(strings, e.name).ok_or(ElfError::BadSymbol(n))?,
+                    value:   e.value,
+                    size:    e.size,
+                    kind:    e.info & 0xF,
+                    binding: e.info >> 4,
+                    section,
+                });
+            }
+        }
+
+        // Relocations
+        for (i, sh) in headers.iter().enumerate() {
+            let (rela, min) = match sh.kind {
+                SHT_RELA => (true,  RELA_SIZE),
+                SHT_REL  => (false, REL_SIZE),
+                _        => continue,
+            };
+            let target = match index.get(sh.info as usize) {
+                Some(&Some(s)) => s,
+                _              => return Err(ElfError::BadSection(i)),
+            };
+            let table = content(bytes, &headers, i)?;
+            let size  = (sh.entsize as usize).max(min);
+            for n in 0..table.len() / size {
+                let e: RelocEntry = load(table, n * size)?;
+                let kind = e.info as u8;
+                let symbol = match e.info >> 8 {
+                    0                                  => None,
+                    s if s as usize <= symbols.len() => Some(s as usize - 1),
+                    s                                  => return Err(ElfError::BadSymbol(s as usize)),
+                };
+                let addend = match rela {
+                    true  => load(table, n * size + 8)?,
+                    false => implicit_addend(&sections[target].data, e.offset, kind),
+                };
+                sections[target].relocs.push(ElfReloc { offset: e.offset, kind, symbol, addend });
+            }
+        }
+
+        Ok(Self {
+            file_type: h.file_type,
+            machine:   h.machine,
+            flags:     h.flags,
+            entry:     h.entry,
+            sections,
+            symbols,
+            segments,
+        })
+    }
+
+    /// Returns the address in memory of each section, or `None` for a
+    /// section that does not occupy memory.
+    ///
+    /// In an executable file, each allocated section is at its address.  In
+    /// a relocatable file, where sections have no addresses, the allocated
+    /// sections follow one another from address 0, each aligned as it
+    /// requires.
+    pub fn section_addrs(&self) -> Vec<Option<u32>> {
+        let mut next = 0_u32;
+        self.sections.iter()
+            .map(|s| match (s.flags & SHF_ALLOC != 0, self.file_type) {
+                (false, _) => None,
+                (true, ET_REL) => {
+                    let align = s.align.max(1);
+                    let addr  = next.div_ceil(align).wrapping_mul(align);
+                    next = addr.wrapping_add(s.size);
+                    Some(addr)
+                },
+                (true, _) => Some(s.addr),
+            })
+            .collect()
+    }
+
+    /// Returns a memory image of the allocated sections, at the addresses
+    /// given by `section_addrs`.
+    ///
+    /// Sections that hold code (`SHF_EXECINSTR`) become code regions,
+    /// sections of strings (`SHF_STRINGS`) become string regions, and others
+    /// become data regions.  A section of type `SHT_NOBITS` becomes reserved
+    /// space.  The load address of a section within a `PT_LOAD` segment is
+    /// relative to the physical address of the segment; that of any other
+    /// section equals its address.
+    ///
+    /// If sections overlap, this method returns the index of one of them as
+    /// an error.
+    pub fn image(&self) -> Result<Image, ElfError> {
+        let mut image = Image::new();
+        for (i, (s, addr)) in self.sections.iter().zip(self.section_addrs()).enumerate() {
+            let vma = match addr {
+                Some(a) if s.size != 0 => a,
+                _                      => continue,
+            };
+            let lma = self.segments.iter()
+                .find(|p| p.kind == PT_LOAD
+                    && vma >= p.vaddr
+                    && vma as u64 + s.size as u64 <= p.vaddr as u64 + p.mem_size as u64)
+                .map_or(vma, |p| p.paddr.wrapping_add(vma - p.vaddr));
+            let kind = if s.is_code() {
+                RegionKind::Code
+            } else if s.flags & SHF_STRINGS != 0 {
+                RegionKind::Strings
+            } else {
+                RegionKind::Data
+            };
+            if vma.checked_add(s.size - 1).is_none() || lma.checked_add(s.size - 1).is_none() {
+                return Err(ElfError::Overlap(i))
+            }
+            let mut block = Block::new(Region::new(lma, vma, s.size, kind), s.data.clone());
+            block.section = Some(s.name.clone());
+            image.add(block).map_err(|_| ElfError::Overlap(i))?;
+        }
+        Ok(image)
+    }
+}
+
+// File header (`Elf32_Ehdr`)
+struct Header {
+    file_type: u16,
+    machine:   u16,
+    entry:     u32,
+    phoff:     u32,
+    shoff:     u32,
+    flags:     u32,
+    phentsize: u16,
+    phnum:     u16,
+    shentsize: u16,
+    shnum:     u16,
+    shstrndx:  u16,
+}
+
+impl Load<[u8], ByteOrder> for Header {
+    fn load(buf: &[u8], fmt: ByteOrder) -> Option<(Self, &[u8])> {
+        let buf              = buf.get(16..)?;
+        let (file_type, buf) = u16::load(buf, fmt)?;
+        let (machine,   buf) = u16::load(buf, fmt)?;
+        let (_,         buf) = u32::load(buf, fmt)?;
+        let (entry,     buf) = u32::load(buf, fmt)?;
+        let (phoff,     buf) = u32::load(buf, fmt)?;
+        let (shoff,     buf) = u32::load(buf, fmt)?;
+        let (flags,     buf) = u32::load(buf, fmt)?;
+        let (_,         buf) = u16::load(buf, fmt)?;
+        let (phentsize, buf) = u16::load(buf, fmt)?;
+        let (phnum,     buf) = u16::load(buf, fmt)?;
+        let (shentsize, buf) = u16::load(buf, fmt)?;
+        let (shnum,     buf) = u16::load(buf, fmt)?;
+        let (shstrndx,  buf) = u16::load(buf, fmt)?;
+        let h = Self { file_type, machine, entry, phoff, shoff, flags, phentsize, phnum, shentsize, shnum, shstrndx };
+        Some((h, buf))
+    }
+}
+
+/// Loads a program header (`Elf32_Phdr`).
+impl Load<[u8], ByteOrder> for ElfSegment {
+    fn load(buf: &[u8], fmt: ByteOrder) -> Option<(Self, &[u8])> {
+        let (kind,      buf) = u32::load(buf, fmt)?;
+        let (offset,    buf) = u32::load(buf, fmt)?;
+        let (vaddr,     buf) = u32::load(buf, fmt)?;
+        let (paddr,     buf) = u32::load(buf, fmt)?;
+        let (file_size, buf) = u32::load(buf, fmt)?;
+        let (mem_size,  buf) = u32::load(buf, fmt)?;
+        let (flags,     buf) = u32::load(buf, fmt)?;
+        let (align,     buf) = u32::load(buf, fmt)?;
+        Some((Self { kind, offset, vaddr, paddr, file_size, mem_size, flags, align }, buf))
+    }
+}
+
+// Section header (`Elf32_Shdr`)
+struct SectionHeader {
+    name:    u32,
+    kind:    u32,
+    flags:   u32,
+    addr:    u32,
+    offset:  u32,
+    size:    u32,
+    link:    u32,
+    info:    u32,
+    align:   u32,
+    entsize: u32,
+}
+
+impl Load<[u8], ByteOrder> for SectionHeader {
+    fn load(buf: &[u8], fmt: ByteOrder) -> Option<(Self, &[u8])> {
+        let (name,    buf) = u32::load(buf, fmt)?;
+        let (kind,    buf) = u32::load(buf, fmt)?;
+        let (flags,   buf) = u32::load(buf, fmt)?;
+        let (addr,    buf) = u32::load(buf, fmt)?;
+        let (offset,  buf) = u32::load(buf, fmt)?;
+        let (size,    buf) = u32::load(buf, fmt)?;
+        let (link,    buf) = u32::load(buf, fmt)?;
+        let (info,    buf) = u32::load(buf, fmt)?;
+        let (align,   buf) = u32::load(buf, fmt)?;
+        let (entsize, buf) = u32::load(buf, fmt)?;
+        Some((Self { name, kind, flags, addr, offset, size, link, info, align, entsize }, buf))
+    }
+}
+
+// Symbol table entry (`Elf32_Sym`)
+struct SymbolEntry {
+    name:  u32,
+    value: u32,
+    size:  u32,
+    info:  u8,
+    shndx: u16,
+}
+
+impl Load<[u8], ByteOrder> for SymbolEntry {
+    fn load(buf: &[u8], fmt: ByteOrder) -> Option<(Self, &[u8])> {
+        let (name,  buf) = u32::load(buf, fmt)?;
+        let (value, buf) = u32::load(buf, fmt)?;
+        let (size,  buf) = u32::load(buf, fmt)?;
+        let (info,  buf) = u8::load(buf, ())?;
+        let (_,     buf) = u8::load(buf, ())?;
+        let (shndx, buf) = u16::load(buf, fmt)?;
+        Some((Self { name, value, size, info, shndx }, buf))
+    }
+}
+
+// Relocation entry (`Elf32_Rel`, or the start of `Elf32_Rela`)
+struct RelocEntry {
+    offset: u32,
+    info:   u32,
+}
+
+impl Load<[u8], ByteOrder> for RelocEntry {
+    fn load(buf: &[u8], fmt: ByteOrder) -> Option<(Self, &[u8])> {
+        let (offset, buf) = u32::load(buf, fmt)?;
+        let (info,   buf) = u32::load(buf, fmt)?;
+        Some((Self { offset, info }, buf))
+    }
+}
+
+// Loads a `T` from `bytes` at `offset`.
+fn load<T>(bytes: &[u8], offset: usize) -> Result<T, ElfError> where T: Load<[u8], ByteOrder> {
+    bytes.get(offset..)
+        .and_then(|b| T::load(b, BE))
+        .map(|(v, _)| v)
+        .ok_or(ElfError::Truncated(offset))
+}
+
+// Returns the content of section `i`: empty for reserved space.
+fn content<'a>(bytes: &'a [u8], headers: &[SectionHeader], i: usize) -> Result<&'a [u8], ElfError> {
+    let sh = headers.get(i).ok_or(ElfError::BadSection(i))?;
+    if sh.kind == SHT_NOBITS {
+        return Ok(&[])
+    }
+    let start = sh.offset as usize;
+    bytes.get(start..start + sh.size as usize).ok_or(ElfError::Truncated(start))
+}
+
+// Returns the string at `offset` in string table `table`.
+fn string(table: &[u8], offset: u32) -> Option<String> {
+    let s   = table.get(offset as usize..)?;
+    let len = s.iter().position(|&b| b == 0)?;
+    Some(String::from_utf8_lossy(&s[..len]).into_owned())
+}
+
+// Returns the addend stored in the field of a relocation of type `kind`
+// at `offset` in `data`.
+fn implicit_addend(data: &[u8], offset: u32, kind: u8) -> i32 {
+    let field = data.get(offset as usize..).unwrap_or(&[]);
+    let value = match kind {
+        R_68K_32 | R_68K_PC32 => i32::load(field, BE).map(|(v, _)| v),
+        R_68K_16 | R_68K_PC16 => i16::load(field, BE).map(|(v, _)| v as i32),
+        R_68K_8  | R_68K_PC8  => field.first().map(|&v| v as i8 as i32),
+        _                     => None,
+    };
+    value.unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object() -> ElfFile {
+        let mut elf = ElfFile::new(ET_REL, EM_68K, EF_M68K_CF_ISA_A);
+        elf.sections.push(ElfSection::progbits(".text", SHF_ALLOC | SHF_EXECINSTR, 2, vec![0x4E, 0xB9, 0, 0, 0, 0]));
+        elf.sections.push(ElfSection::progbits(".data", SHF_ALLOC | SHF_WRITE, 4, vec![1, 2, 3]));
+        elf.sections.push(ElfSection::nobits(".bss", SHF_ALLOC | SHF_WRITE, 4, 0x100));
+        elf.symbols.push(ElfSymbol::section(1));
+        elf.symbols.push(ElfSymbol {
+            name: "start".into(), value: 0, size: 6, kind: STT_FUNC, binding: STB_GLOBAL,
+            section: ElfSymbolSection::Section(0),
+        });
+        elf.symbols.push(ElfSymbol {
+            name: "ext".into(), value: 0, size: 0, kind: STT_NOTYPE, binding: STB_GLOBAL,
+            section: ElfSymbolSection::Undefined,
+        });
+        elf.sections[0].relocs.push(ElfReloc { offset: 2, kind: R_68K_32, symbol: Some(2), addend: 4 });
+        elf.sections[1].relocs.push(ElfReloc { offset: 0, kind: R_68K_8,  symbol: Some(0), addend: -1 });
+        elf
+    }
+
+    #[test]
+    pub fn read_object() {
+        let elf = object();
+        assert_eq!( ElfFile::read(&elf.write()), Ok(elf) );
+    }
+
+    #[test]
+    pub fn read_executable() {
+        let mut elf = object();
+        elf.file_type = ET_EXEC;
+        elf.entry     = 0x1000;
+        elf.sections[0].addr = 0x1000;
+        elf.sections[1].addr = 0x2000;
+        elf.sections[2].addr = 0x2004;
+        let read = ElfFile::read(&elf.write()).unwrap();
+
+        assert_eq!( read.entry, 0x1000 );
+        assert_eq!( read.sections, elf.sections );
+        let loads: Vec<(u32, u32, u32, u32)> = read.segments.iter()
+            .map(|p| (p.vaddr, p.file_size, p.mem_size, p.flags))
+            .collect();
+        assert_eq!( loads, [
+            (0x1000, 6, 6,     PF_R | PF_X),
+            (0x2000, 3, 3,     PF_R | PF_W),
+            (0x2004, 0, 0x100, PF_R | PF_W),
+        ]);
+        assert!( read.segments.iter().all(|p| p.kind == PT_LOAD) );
+
+        let image = read.image().unwrap();
+        let blocks: Vec<(u32, u32, RegionKind, usize)> = image.blocks().iter()
+            .map(|b| (b.region.vma, b.region.len, b.region.kind, b.bytes.len()))
+            .collect();
+        assert_eq!( blocks, [
+            (0x1000, 6,     RegionKind::Code, 6),
+            (0x2000, 3,     RegionKind::Data, 3),
+            (0x2004, 0x100, RegionKind::Data, 0),
+        ]);
+        assert_eq!( image.blocks()[0].section.as_deref(), Some(".text") );
+    }
+
+    #[test]
+    pub fn image_of_object() {
+        let mut elf = object();
+        elf.sections.push(ElfSection::progbits(".comment", 0, 1, b"cfda\0".to_vec()));
+        assert_eq!( elf.section_addrs(), [Some(0), Some(8), Some(12), None] );
+        let vmas: Vec<u32> = elf.image().unwrap().blocks().iter().map(|b| b.region.vma).collect();
+        assert_eq!( vmas, [0, 8, 12] );
+    }
+
+    #[test]
+    pub fn image_load_addresses() {
+        let mut elf = ElfFile::new(ET_EXEC, EM_68K, EF_M68K_CF_ISA_A);
+        let mut text = ElfSection::progbits(".text", SHF_ALLOC | SHF_EXECINSTR, 2, vec![0x4E, 0x75]);
+        let mut data = ElfSection::progbits(".data", SHF_ALLOC | SHF_WRITE, 4, vec![1, 2, 3, 4]);
+        let mut strs = ElfSection::progbits(".rodata.str", SHF_ALLOC | SHF_MERGE | SHF_STRINGS, 1, b"hi\0".to_vec());
+        text.addr = 0x0000_0400;
+        strs.addr = 0x0000_0402;
+        data.addr = 0x2000_0000;
+        elf.sections = vec![text, strs, data];
+
+        // .data is stored after .rodata.str in flash, and copied to RAM
+        let load = |offset, vaddr, paddr, size| ElfSegment {
+            kind: PT_LOAD, offset, vaddr, paddr, file_size: size, mem_size: size, flags: PF_R, align: 4,
+        };
+        elf.segments = vec![load(0x34, 0x400, 0x400, 5), load(0x3C, 0x2000_0000, 0x408, 4)];
+
+        let image = elf.image().unwrap();
+        let blocks: Vec<(u32, u32, RegionKind)> = image.blocks().iter()
+            .map(|b| (b.region.lma, b.region.vma, b.region.kind))
+            .collect();
+        assert_eq!( blocks, [
+            (0x400, 0x0000_0400, RegionKind::Code),
+            (0x402, 0x0000_0402, RegionKind::Strings),
+            (0x408, 0x2000_0000, RegionKind::Data),
+        ]);
+        assert_eq!( image.contents().last(), Some(&(0x408, &[1, 2, 3, 4][..])) );
+
+        // Overlapping sections
+        elf.sections[2].addr = 0x0000_0403;
+        elf.segments.clear();
+        assert_eq!( elf.image(), Err(ElfError::Overlap(2)) );
+    }
+
+    #[test]
+    pub fn read_errors() {
+        let bytes = object().write();
+        assert_eq!( ElfFile::read(b"\x7FELG"), Err(ElfError::NotElf) );
+
+        let mut wrong = bytes.clone();
+        wrong[5] = 1; // ELFDATA2LSB
+        assert_eq!( ElfFile::read(&wrong), Err(ElfError::Unsupported) );
+
+        let shoff = u32::load(&bytes[32..], BE).unwrap().0 as usize;
+        assert_eq!( ElfFile::read(&bytes[..shoff + 8]), Err(ElfError::Truncated(shoff)) );
+        assert_eq!( ElfError::Truncated(0x34).to_string(), "file truncated at offset 0x34" );
+    }
+}
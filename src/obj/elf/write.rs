@@ -0,0 +1,275 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::mem::size_of;
+use crate::mem::{BE, Endian, Save};
+use super::*;
+
+impl ElfFile {
+    /// Returns the file as big-endian ELF32.
+    ///
+    /// Sections follow the file header in order, then a `.rela` section for
+    /// each section with relocations, then `.symtab`, `.strtab`, and
+    /// `.shstrtab`.  Local symbols precede others in the symbol table, as
+    /// ELF requires.  An executable file has a `PT_LOAD` program header for
+    /// each allocated section.
+    pub fn write(&self) -> Vec<u8> {
+        let mut out = vec![0; EHDR_SIZE];
+
+        // Program headers
+        let loads: Vec<&ElfSection> = match self.file_type {
+            ET_EXEC => self.sections.iter().filter(|s| s.flags & SHF_ALLOC != 0).collect(),
+            _       => vec![],
+        };
+        let phoff = if loads.is_empty() { 0 } else { out.len() };
+        out.resize(out.len() + loads.len() * PHDR_SIZE, 0);
+
+        // Section content
+        let mut offsets = vec![];
+        for s in &self.sections {
+            align(&mut out, s.align);
+            offsets.push(out.len() as u32);
+            out.extend_from_slice(&s.data);
+        }
+
+        // Symbols: locals first
+        let mut order: Vec<usize> = (0..self.symbols.len()).collect();
+        order.sort_by_key(|&i| self.symbols[i].binding != STB_LOCAL);
+        let mut index = vec![0; self.symbols.len()];
+        for (n, &i) in order.iter().enumerate() {
+            index[i] = n as u32 + 1;
+        }
+        let first_global = 1 + self.symbols.iter().filter(|s| s.binding == STB_LOCAL).count() as u32;
+
+        let relocated: Vec<usize> = (0..self.sections.len())
+            .filter(|&i| !self.sections[i].relocs.is_empty())
+            .collect();
+        let symtab_index = 1 + self.sections.len() + relocated.len();
+
+        // Relocations
+        let mut rela_offsets = vec![];
+        for &i in &relocated {
+            align(&mut out, 4);
+            rela_offsets.push(out.len() as u32);
+            for r in &self.sections[i].relocs {
+                let sym = r.symbol.map_or(0, |s| index[s]);
+                put(&mut out, r.offset);
+                put(&mut out, sym << 8 | r.kind as u32);
+                put(&mut out, r.addend);
+            }
+        }
+
+        // Symbol table and its strings
+        let mut strtab = Strings::new();
+        align(&mut out, 4);
+        let symtab_offset = out.len() as u32;
+        out.resize(out.len() + SYM_SIZE, 0);
+        for &i in &order {
+            let s = &self.symbols[i];
+            let shndx = match s.section {
+                ElfSymbolSection::Undefined  => SHN_UNDEF,
+                ElfSymbolSection::Absolute   => SHN_ABS,
+                ElfSymbolSection::Common     => SHN_COMMON,
+                ElfSymbolSection::Section(n) => n as u16 + 1,
+            };
+            put(&mut out, strtab.add(&s.name));
+            put(&mut out, s.value);
+            put(&mut out, s.size);
+            out.push(s.binding << 4 | s.kind & 0xF);
+            out.push(0);
+            put(&mut out, shndx);
+        }
+        let strtab_offset = out.len() as u32;
+        out.extend_from_slice(&strtab.bytes);
+
+        // Section names
+        let mut shstrtab = Strings::new();
+        let names: Vec<u32> = self.sections.iter().map(|s| shstrtab.add(&s.name)).collect();
+        let rela_names: Vec<u32> = relocated.iter()
+            .map(|&i| shstrtab.add(&format!(".rela{}", self.sections[i].name)))
+            .collect();
+        let symtab_name   = shstrtab.add(".symtab");
+        let strtab_name   = shstrtab.add(".strtab");
+        let shstrtab_name = shstrtab.add(".shstrtab");
+        let shstrtab_offset = out.len() as u32;
+        out.extend_from_slice(&shstrtab.bytes);
+
+        // Section headers
+        align(&mut out, 4);
+        let shoff = out.len();
+        out.resize(out.len() + SHDR_SIZE, 0);
+        for (i, s) in self.sections.iter().enumerate() {
+            let size = if s.kind == SHT_NOBITS { s.size } else { s.data.len() as u32 };
+            section_header(&mut out, names[i], s.kind, s.flags, s.addr, offsets[i], size, 0, 0, s.align, 0);
+        }
+        for (n, &i) in relocated.iter().enumerate() {
+            let size = (self.sections[i].relocs.len() * RELA_SIZE) as u32;
+            section_header(&mut out, rela_names[n], SHT_RELA, SHF_INFO_LINK, 0, rela_offsets[n], size,
+                symtab_index as u32, i as u32 + 1, 4, RELA_SIZE as u32);
+        }
+        let symtab_size = ((self.symbols.len() + 1) * SYM_SIZE) as u32;
+        section_header(&mut out, symtab_name, SHT_SYMTAB, 0, 0, symtab_offset, symtab_size,
+            symtab_index as u32 + 1, first_global, 4, SYM_SIZE as u32);
+        section_header(&mut out, strtab_name, SHT_STRTAB, 0, 0, strtab_offset,
+            strtab.bytes.len() as u32, 0, 0, 1, 0);
+        section_header(&mut out, shstrtab_name, SHT_STRTAB, 0, 0, shstrtab_offset,
+            shstrtab.bytes.len() as u32, 0, 0, 1, 0);
+        let shnum = symtab_index + 3;
+
+        // Program headers, now that offsets are known
+        for (n, s) in loads.iter().enumerate() {
+            let i = self.sections.iter().position(|t| std::ptr::eq(t, *s)).unwrap();
+            let mut flags = PF_R;
+            if s.flags & SHF_WRITE     != 0 { flags |= PF_W }
+            if s.flags & SHF_EXECINSTR != 0 { flags |= PF_X }
+            let mut ph = vec![];
+            put(&mut ph, PT_LOAD);
+            put(&mut ph, offsets[i]);
+            put(&mut ph, s.addr);
+            put(&mut ph, s.addr);
+            put(&mut ph, s.data.len() as u32);
+            put(&mut ph, s.size);
+            put(&mut ph, flags);
+            put(&mut ph, s.align.max(1));
+            let at = phoff + n * PHDR_SIZE;
+            out[at..at + PHDR_SIZE].copy_from_slice(&ph);
+        }
+
+        // File header
+        let mut eh = vec![];
+        eh.extend_from_slice(&ELF_MAGIC);
+        eh.extend_from_slice(&[ELFCLASS32, ELFDATA2MSB, EV_CURRENT]);
+        eh.resize(16, 0);
+        put(&mut eh, self.file_type);
+        put(&mut eh, self.machine);
+        put(&mut eh, EV_CURRENT as u32);
+        put(&mut eh, self.entry);
+        put(&mut eh, phoff as u32);
+        put(&mut eh, shoff as u32);
+        put(&mut eh, self.flags);
+        put(&mut eh, EHDR_SIZE as u16);
+        put(&mut eh, PHDR_SIZE as u16);
+        put(&mut eh, loads.len() as u16);
+        put(&mut eh, SHDR_SIZE as u16);
+        put(&mut eh, shnum as u16);
+        put(&mut eh, shnum as u16 - 1);
+        out[..EHDR_SIZE].copy_from_slice(&eh);
+
+        out
+    }
+}
+
+// A string table under construction
+struct Strings {
+    bytes: Vec<u8>,
+}
+
+impl Strings {
+    fn new() -> Self {
+        Self { bytes: vec![0] }
+    }
+
+    // Adds `s` to the table and returns its offset.  The empty string is at
+    // offset 0.
+    fn add(&mut self, s: &str) -> u32 {
+        if s.is_empty() {
+            return 0
+        }
+        let offset = self.bytes.len() as u32;
+        self.bytes.extend_from_slice(s.as_bytes());
+        self.bytes.push(0);
+        offset
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn section_header(
+    out: &mut Vec<u8>, name: u32, kind: u32, flags: u32, addr: u32, offset: u32, size: u32,
+    link: u32, info: u32, align: u32, entsize: u32,
+) {
+    for v in [name, kind, flags, addr, offset, size, link, info, align, entsize] {
+        put(out, v);
+    }
+}
+
+fn align(out: &mut Vec<u8>, align: u32) {
+    let align = align.max(1) as usize;
+    out.resize(out.len().div_ceil(align) * align, 0);
+}
+
+fn put<T>(out: &mut Vec<u8>, value: T) where T: Copy + Endian {
+    let n = out.len();
+    out.resize(n + size_of::<T>(), 0);
+    value.save(&mut out[n..], BE);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mem::Load;
+    use super::*;
+
+    fn u16_at(b: &[u8], at: usize) -> u16 { u16::load(&b[at..], BE).unwrap().0 }
+    fn u32_at(b: &[u8], at: usize) -> u32 { u32::load(&b[at..], BE).unwrap().0 }
+
+    #[test]
+    pub fn write_object() {
+        let mut elf = ElfFile::new(ET_REL, EM_68K, EF_M68K_CF_ISA_A);
+        elf.sections.push(ElfSection::progbits(".text", SHF_ALLOC | SHF_EXECINSTR, 2, vec![0x4E, 0x75]));
+        elf.sections.push(ElfSection::nobits(".bss", SHF_ALLOC | SHF_WRITE, 4, 0x100));
+        elf.symbols.push(ElfSymbol {
+            name: "ext".into(), value: 0, size: 0, kind: STT_NOTYPE, binding: STB_GLOBAL,
+            section: ElfSymbolSection::Undefined,
+        });
+        elf.symbols.push(ElfSymbol::section(0));
+        elf.sections[0].relocs.push(ElfReloc { offset: 0, kind: R_68K_16, symbol: Some(0), addend: -2 });
+        let b = elf.write();
+
+        assert_eq!( b[..7], [0x7F, b'E', b'L', b'F', ELFCLASS32, ELFDATA2MSB, EV_CURRENT] );
+        assert_eq!( u16_at(&b, 16), ET_REL );
+        assert_eq!( u16_at(&b, 18), EM_68K );
+        assert_eq!( u32_at(&b, 36), EF_M68K_CF_ISA_A );
+        assert_eq!( u16_at(&b, 44), 0 );                // no program headers
+
+        // null, .text, .bss, .rela.text, .symtab, .strtab, .shstrtab
+        let shoff = u32_at(&b, 32) as usize;
+        assert_eq!( u16_at(&b, 48), 7 );
+        assert_eq!( u16_at(&b, 50), 6 );
+        let sh = |n: usize, field: usize| u32_at(&b, shoff + n * SHDR_SIZE + field * 4);
+
+        assert_eq!( sh(1, 1), SHT_PROGBITS );
+        assert_eq!( b[sh(1, 4) as usize..][..2], [0x4E, 0x75] );
+        assert_eq!( sh(2, 1), SHT_NOBITS );
+        assert_eq!( sh(2, 5), 0x100 );
+
+        // The section symbol is local, so precedes the global symbol
+        assert_eq!( sh(4, 1), SHT_SYMTAB );
+        assert_eq!( sh(4, 6), 5 );                      // link: .strtab
+        assert_eq!( sh(4, 7), 2 );                      // first global
+        assert_eq!( b[sh(4, 4) as usize + SYM_SIZE + 12], STT_SECTION );
+
+        assert_eq!( sh(3, 1), SHT_RELA );
+        assert_eq!( sh(3, 6), 4 );                      // link: .symtab
+        assert_eq!( sh(3, 7), 1 );                      // info: .text
+        let rela = sh(3, 4) as usize;
+        assert_eq!( u32_at(&b, rela + 4), 2 << 8 | R_68K_16 as u32 );
+        assert_eq!( u32_at(&b, rela + 8), -2i32 as u32 );
+
+        // Names
+        let strtab = sh(5, 4) as usize;
+        let name   = u32_at(&b, sh(4, 4) as usize + 2 * SYM_SIZE) as usize;
+        assert_eq!( &b[strtab + name..][..4], b"ext\0" );
+    }
+}
@@ -0,0 +1,365 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt::{self, Display, Formatter};
+use crate::mem::{BE, Block, ByteOrder, Image, Load, Region, RegionKind};
+
+/// Magic number that begins a flat file.
+pub const FLAT_MAGIC: [u8; 4] = *b"bFLT";
+
+/// Version of the flat format supported.
+pub const FLAT_VERSION: u32 = 4;
+
+/// Size of the flat file header.
+pub const FLAT_HDR_SIZE: usize = 64;
+
+// Header flags
+pub const FLAT_FLAG_RAM:    u32 = 0x1;  // Load the whole file into RAM
+pub const FLAT_FLAG_GOTPIC: u32 = 0x2;  // Position-independent code with a GOT
+pub const FLAT_FLAG_GZIP:   u32 = 0x4;  // All but the header is compressed
+pub const FLAT_FLAG_GZDATA: u32 = 0x8;  // Only data and relocations are compressed
+
+// Value that ends the GOT
+const GOT_END: u32 = 0xFFFF_FFFF;
+
+/// An error encountered while reading a flat file.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum FlatError {
+    /// The file does not begin with the flat magic number.
+    NotFlat,
+
+    /// The file is not an uncompressed flat file of version 4.
+    Unsupported,
+
+    /// A structure at the given offset extends past the end of the file.
+    Truncated(usize),
+
+    /// The segments described by the header are out of order, or the entry
+    /// point lies outside of text.
+    BadLayout,
+
+    /// The relocation with the given index lies outside of text and data.
+    BadReloc(usize),
+
+    /// The file does not fit in the address space at the load address.
+    Overflow,
+}
+
+impl Display for FlatError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            FlatError::NotFlat      => write!(f, "not a bFLT file"),
+            FlatError::Unsupported  => write!(f, "not an uncompressed version 4 bFLT file"),
+            FlatError::Truncated(o) => write!(f, "file truncated at offset 0x{:X}", o),
+            FlatError::BadLayout    => write!(f, "invalid segment layout"),
+            FlatError::BadReloc(i)  => write!(f, "invalid relocation {}", i),
+            FlatError::Overflow     => write!(f, "file extends beyond end of address space"),
+        }
+    }
+}
+
+/// A uClinux flat executable (bFLT), version 4.
+///
+/// Text begins after the header and is followed by data and then bss.
+/// Addresses within the file are relative to the start of text.  In
+/// position-independent code (`FLAT_FLAG_GOTPIC`), data begins with the
+/// global offset table (GOT): a list of addresses ended by `0xFFFFFFFF`,
+/// which code reaches relative to register A5.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct FlatFile {
+    /// Address of the entry point.
+    pub entry: u32,
+
+    /// Flags (`FLAT_FLAG_*`).
+    pub flags: u32,
+
+    /// Size of the stack to allocate, in bytes.
+    pub stack_size: u32,
+
+    /// Build date, in seconds since the Unix epoch, or 0 if unknown.
+    pub build_date: u32,
+
+    /// Content of text.
+    pub text: Vec<u8>,
+
+    /// Content of data.
+    pub data: Vec<u8>,
+
+    /// Size of bss, in bytes.
+    pub bss_size: u32,
+
+    /// Addresses of the 32-bit words that hold addresses, and so move with
+    /// the load address.
+    pub relocs: Vec<u32>,
+}
+
+impl FlatFile {
+    /// Reads a flat file from `bytes`.
+    pub fn read(bytes: &[u8]) -> Result<Self, FlatError> {
+        if bytes.len() < 8 || bytes[..4] != FLAT_MAGIC {
+            return Err(FlatError::NotFlat)
+        }
+        let h: Header = load(bytes, 0)?;
+        if h.rev != FLAT_VERSION || h.flags & (FLAT_FLAG_GZIP | FLAT_FLAG_GZDATA) != 0 {
+            return Err(FlatError::Unsupported)
+        }
+        let (data_start, data_end) = (h.data_start as usize, h.data_end as usize);
+        if data_start < FLAT_HDR_SIZE
+            || data_start > data_end
+            || h.data_end > h.bss_end
+            || (h.entry as usize) < FLAT_HDR_SIZE
+            || h.entry as usize > data_start {
+            return Err(FlatError::BadLayout)
+        }
+        if data_end > bytes.len() {
+            return Err(FlatError::Truncated(data_start))
+        }
+
+        let text = bytes[FLAT_HDR_SIZE..data_start].to_vec();
+        let data = bytes[data_start..data_end].to_vec();
+        let size = text.len() + data.len();
+
+        let mut relocs = vec![];
+        for i in 0..h.reloc_count as usize {
+            let r: u32 = load(bytes, h.reloc_start as usize + i * 4)?;
+            if r as usize + 4 > size {
+                return Err(FlatError::BadReloc(i))
+            }
+            relocs.push(r);
+        }
+
+        Ok(Self {
+            entry:      h.entry - FLAT_HDR_SIZE as u32,
+            flags:      h.flags,
+            stack_size: h.stack_size,
+            build_date: h.build_date,
+            bss_size:   h.bss_end - h.data_end,
+            text,
+            data,
+            relocs,
+        })
+    }
+
+    /// Returns the address of data.
+    pub fn data_addr(&self) -> u32 {
+        self.text.len() as u32
+    }
+
+    /// Returns the number of entries in the GOT, not counting the entry that
+    /// ends it, or `None` if the file has no GOT.
+    pub fn got_len(&self) -> Option<usize> {
+        if self.flags & FLAT_FLAG_GOTPIC == 0 {
+            return None
+        }
+        self.data.chunks_exact(4).position(|w| u32::load(w, BE).unwrap().0 == GOT_END)
+    }
+
+    /// Returns a memory image of the file loaded at `base`.
+    ///
+    /// Text, data, and bss become sections `.text`, `.data`, and `.bss`,
+    /// one after another from `base`.  The GOT, if any, becomes section
+    /// `.got`, which includes the entry that ends it.  The words named by
+    /// `relocs`, and the nonzero entries of the GOT, are relocated by
+    /// `base`, as the loader relocates them.
+    pub fn image(&self, base: u32) -> Result<Image, FlatError> {
+        let size = self.text.len() as u64 + self.data.len() as u64 + self.bss_size as u64;
+        if base as u64 + size > 1 << 32 {
+            return Err(FlatError::Overflow)
+        }
+
+        let mut mem = self.text.clone();
+        mem.extend_from_slice(&self.data);
+        for &r in &self.relocs {
+            relocate(&mut mem, r, base);
+        }
+        let got = self.got_len();
+        for i in 0..got.unwrap_or(0) {
+            let r = self.data_addr() + 4 * i as u32;
+            if u32::load(&mem[r as usize..], BE).unwrap().0 != 0 {
+                relocate(&mut mem, r, base);
+            }
+        }
+
+        let text = self.text.len();
+        let data = text + got.map_or(0, |n| 4 * (n + 1));
+        let mut image = Image::new();
+        let mut add = |name: &str, start: usize, len: usize, kind, bytes: &[u8]| {
+            if len == 0 {
+                return
+            }
+            let addr = base + start as u32;
+            let mut block = Block::new(Region::new(addr, addr, len as u32, kind), bytes.to_vec());
+            block.section = Some(name.into());
+            image.add(block).unwrap();
+        };
+        let end = mem.len();
+        add(".text", 0,    text,                  RegionKind::Code, &mem[..text]);
+        add(".got",  text, data - text,           RegionKind::Data, &mem[text..data]);
+        add(".data", data, end - data,            RegionKind::Data, &mem[data..]);
+        add(".bss",  end,  self.bss_size as usize, RegionKind::Data, &[]);
+        Ok(image)
+    }
+}
+
+// File header (`struct flat_hdr`)
+struct Header {
+    rev:         u32,
+    entry:       u32,
+    data_start:  u32,
+    data_end:    u32,
+    bss_end:     u32,
+    stack_size:  u32,
+    reloc_start: u32,
+    reloc_count: u32,
+    flags:       u32,
+    build_date:  u32,
+}
+
+impl Load<[u8], ByteOrder> for Header {
+    fn load(buf: &[u8], fmt: ByteOrder) -> Option<(Self, &[u8])> {
+        let buf                = buf.get(4..)?;
+        let (rev,         buf) = u32::load(buf, fmt)?;
+        let (entry,       buf) = u32::load(buf, fmt)?;
+        let (data_start,  buf) = u32::load(buf, fmt)?;
+        let (data_end,    buf) = u32::load(buf, fmt)?;
+        let (bss_end,     buf) = u32::load(buf, fmt)?;
+        let (stack_size,  buf) = u32::load(buf, fmt)?;
+        let (reloc_start, buf) = u32::load(buf, fmt)?;
+        let (reloc_count, buf) = u32::load(buf, fmt)?;
+        let (flags,       buf) = u32::load(buf, fmt)?;
+        let (build_date,  buf) = u32::load(buf, fmt)?;
+        let buf                = buf.get(FLAT_HDR_SIZE - 44..)?;
+        let h = Self { rev, entry, data_start, data_end, bss_end, stack_size, reloc_start, reloc_count, flags, build_date };
+        Some((h, buf))
+    }
+}
+
+// Adds `base` to the 32-bit word at `addr` in `mem`.
+fn relocate(mem: &mut [u8], addr: u32, base: u32) {
+    let field = &mut mem[addr as usize..addr as usize + 4];
+    let value = u32::load(field, BE).unwrap().0.wrapping_add(base);
+    field.copy_from_slice(&value.to_be_bytes());
+}
+
+// Loads a `T` from `bytes` at `offset`.
+fn load<T>(bytes: &[u8], offset: usize) -> Result<T, FlatError> where T: Load<[u8], ByteOrder> {
+    bytes.get(offset..)
+        .and_then(|b| T::load(b, BE))
+        .map(|(v, _)| v)
+        .ok_or(FlatError::Truncated(offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A position-independent program: text, a GOT of two entries, a word
+    // of data that holds an address, and 8 bytes of bss
+    fn program() -> Vec<u8> {
+        let text = [
+            0x20, 0x6D, 0x00, 0x04,     // movea.l (4,a5),a0
+            0x4E, 0x90,                 // jsr (a0)
+            0x4E, 0x75,                 // rts
+            0x4E, 0x75,                 // rts
+            0x4E, 0x71,                 // nop
+        ];
+        let data = [
+            0x00, 0x00, 0x00, 0x18,     // GOT: var
+            0x00, 0x00, 0x00, 0x08,     // GOT: func
+            0xFF, 0xFF, 0xFF, 0xFF,     // GOT end
+            0x00, 0x00, 0x00, 0x08,     // var: func
+        ];
+        let data_start = (FLAT_HDR_SIZE + text.len()) as u32;
+        let data_end   = data_start + data.len() as u32;
+        let header = [
+            FLAT_VERSION, 0x40, data_start, data_end, data_end + 8,
+            0x1000, data_end, 1, FLAT_FLAG_RAM | FLAT_FLAG_GOTPIC, 0,
+        ];
+
+        let mut bytes = FLAT_MAGIC.to_vec();
+        for w in &header {
+            bytes.extend(w.to_be_bytes());
+        }
+        bytes.resize(FLAT_HDR_SIZE, 0);
+        bytes.extend(text);
+        bytes.extend(data);
+        bytes.extend(0x18_u32.to_be_bytes());
+        bytes
+    }
+
+    #[test]
+    pub fn read() {
+        let file = FlatFile::read(&program()).unwrap();
+        assert_eq!( file.entry, 0 );
+        assert_eq!( file.flags, FLAT_FLAG_RAM | FLAT_FLAG_GOTPIC );
+        assert_eq!( file.stack_size, 0x1000 );
+        assert_eq!( file.text.len(), 12 );
+        assert_eq!( file.data.len(), 16 );
+        assert_eq!( file.bss_size, 8 );
+        assert_eq!( file.relocs, vec![0x18] );
+        assert_eq!( file.data_addr(), 12 );
+        assert_eq!( file.got_len(), Some(2) );
+
+        let file = FlatFile { flags: 0, ..file };
+        assert_eq!( file.got_len(), None );
+    }
+
+    #[test]
+    pub fn image() {
+        let file  = FlatFile::read(&program()).unwrap();
+        let image = file.image(0x10000).unwrap();
+        let names: Vec<_> = image.blocks().iter()
+            .map(|b| (b.section.as_deref().unwrap(), b.region.vma, b.region.len))
+            .collect();
+        assert_eq!( names, vec![
+            (".text", 0x10000, 12),
+            (".got",  0x1000C, 12),
+            (".data", 0x10018,  4),
+            (".bss",  0x1001C,  8),
+        ]);
+
+        // GOT entries and relocations move with the load address
+        assert_eq!( image.read(0x1000C, 12).unwrap(), &[0, 1, 0, 0x18, 0, 1, 0, 0x08, 0xFF, 0xFF, 0xFF, 0xFF] );
+        assert_eq!( image.read(0x10018, 4).unwrap(), &[0, 1, 0, 0x08] );
+
+        assert_eq!( file.image(0xFFFF_FFF0), Err(FlatError::Overflow) );
+    }
+
+    #[test]
+    pub fn read_errors() {
+        let bytes = program();
+        assert_eq!( FlatFile::read(b"\x7FELF"), Err(FlatError::NotFlat) );
+
+        let mut b = bytes.clone();
+        b[7] = 2;
+        assert_eq!( FlatFile::read(&b), Err(FlatError::Unsupported) );
+
+        let mut b = bytes.clone();
+        b[39] |= FLAT_FLAG_GZIP as u8;
+        assert_eq!( FlatFile::read(&b), Err(FlatError::Unsupported) );
+
+        let mut b = bytes.clone();
+        b[11] = 0x60;
+        assert_eq!( FlatFile::read(&b), Err(FlatError::BadLayout) );
+
+        assert_eq!( FlatFile::read(&bytes[..0x50]), Err(FlatError::Truncated(0x4C)) );
+        assert_eq!( FlatFile::read(&bytes[..0x5E]), Err(FlatError::Truncated(0x5C)) );
+
+        let mut b = bytes.clone();
+        b[0x5F] = 0x1E;
+        assert_eq!( FlatFile::read(&b), Err(FlatError::BadReloc(0)) );
+    }
+}
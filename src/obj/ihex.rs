@@ -0,0 +1,248 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::mem::Image;
+use super::*;
+
+// Record types
+const DATA:          u8 = 0;
+const END_OF_FILE:   u8 = 1;
+const EXT_SEGMENT:   u8 = 2;
+const START_SEGMENT: u8 = 3;
+const EXT_LINEAR:    u8 = 4;
+const START_LINEAR:  u8 = 5;
+
+/// Address at which execution begins, as given by a start address record.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum HexStart {
+    /// A segment and offset (`CS:IP`), from a start segment address record.
+    Segment(u16, u16),
+
+    /// A linear address (`EIP`), from a start linear address record.
+    Linear(u32),
+}
+
+impl HexStart {
+    /// Returns the address at which execution begins.
+    pub fn addr(self) -> u32 {
+        match self {
+            HexStart::Segment(cs, ip) => ((cs as u32) << 4).wrapping_add(ip as u32),
+            HexStart::Linear(eip)     => eip,
+        }
+    }
+}
+
+/// The content of an Intel HEX file.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct HexFile {
+    /// Memory content given by data records.
+    pub image: Image,
+
+    /// Address given by the start address record, if any.
+    pub start: Option<HexStart>,
+}
+
+impl HexFile {
+    /// Reads an Intel HEX file from `text`.
+    ///
+    /// Extended segment and extended linear address records set the base
+    /// address of subsequent data records.  Blank lines and anything after
+    /// the end-of-file record are ignored.  Each record must have a correct
+    /// byte count and checksum.
+    pub fn read(text: &str) -> Result<Self, RecordError> {
+        let mut file = Self::default();
+        let mut base = 0_u32;
+        let mut wrap = false; // whether offsets wrap within a segment
+
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue
+            }
+            let err = |kind| RecordError::new(n + 1, kind);
+
+            // Byte count, offset, type, content, and checksum
+            let bytes = match line.strip_prefix(':') {
+                Some(hex) => hex_bytes(hex).ok_or_else(|| err(RecordErrorKind::Syntax))?,
+                None      => return Err(err(RecordErrorKind::Syntax)),
+            };
+            if bytes.len() < 5 || bytes[0] as usize != bytes.len() - 5 {
+                return Err(err(RecordErrorKind::Length))
+            }
+            let (&sum, body) = bytes.split_last().unwrap();
+            let check = body.iter().fold(0_u8, |s, &b| s.wrapping_add(b)).wrapping_neg();
+            if sum != check {
+                return Err(err(RecordErrorKind::Checksum(check)))
+            }
+            let offset = (body[1] as u32) << 8 | body[2] as u32;
+            let kind   = body[3];
+            let data   = &body[4..];
+
+            // Required content lengths
+            let len = match kind {
+                DATA                         => data.len(),
+                END_OF_FILE                  => 0,
+                EXT_SEGMENT   | EXT_LINEAR   => 2,
+                START_SEGMENT | START_LINEAR => 4,
+                _                            => return Err(err(RecordErrorKind::RecordType(kind))),
+            };
+            if data.len() != len {
+                return Err(err(RecordErrorKind::Length))
+            }
+            let value = data.iter().fold(0_u32, |v, &b| v << 8 | b as u32);
+
+            match kind {
+                DATA if wrap => {
+                    let (low, high) = data.split_at(data.len().min(0x1_0000 - offset as usize));
+                    file.image.write(base.wrapping_add(offset), low);
+                    file.image.write(base, high);
+                },
+                DATA          => file.image.write(base.wrapping_add(offset), data),
+                END_OF_FILE   => break,
+                EXT_SEGMENT   => { base = value << 4;  wrap = true  },
+                EXT_LINEAR    => { base = value << 16; wrap = false },
+                START_SEGMENT => file.start = Some(HexStart::Segment((value >> 16) as u16, value as u16)),
+                _             => file.start = Some(HexStart::Linear(value)),
+            }
+        }
+
+        Ok(file)
+    }
+
+    /// Returns the file as text, with at most `record_size` data bytes per
+    /// record.
+    ///
+    /// The text is data records, with an extended linear address record
+    /// before each that lies in a different 64 KiB page than the last; then
+    /// a start address record, if any; then an end-of-file record.  Data
+    /// records give the content of the image at load addresses, and break
+    /// at gaps and at page boundaries.  The record size
+    /// is limited to 255.
+    pub fn write(&self, record_size: usize) -> String {
+        let size = record_size.clamp(1, 0xFF) as u64;
+
+        let mut out  = String::new();
+        let mut page = 0_u32;
+        for (lma, bytes) in self.image.contents() {
+            let (start, end) = (lma as u64, lma as u64 + bytes.len() as u64);
+            let mut addr = start;
+            while addr < end {
+                let next = (addr + size).min(end).min((addr | 0xFFFF) + 1);
+                let data = &bytes[(addr - start) as usize..(next - start) as usize];
+                if (addr >> 16) as u32 != page {
+                    page = (addr >> 16) as u32;
+                    record(&mut out, EXT_LINEAR, 0, &(page as u16).to_be_bytes());
+                }
+                record(&mut out, DATA, addr as u16, data);
+                addr = next;
+            }
+        }
+        match self.start {
+            Some(HexStart::Segment(cs, ip)) => {
+                let value = (cs as u32) << 16 | ip as u32;
+                record(&mut out, START_SEGMENT, 0, &value.to_be_bytes());
+            },
+            Some(HexStart::Linear(eip)) => {
+                record(&mut out, START_LINEAR, 0, &eip.to_be_bytes());
+            },
+            None => {},
+        }
+        record(&mut out, END_OF_FILE, 0, &[]);
+        out
+    }
+}
+
+// Appends a record of type `kind` with the given offset and data.
+fn record(out: &mut String, kind: u8, offset: u16, data: &[u8]) {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend_from_slice(&offset.to_be_bytes());
+    bytes.push(kind);
+    bytes.extend_from_slice(data);
+    bytes.push(bytes.iter().fold(0_u8, |s, &b| s.wrapping_add(b)).wrapping_neg());
+    out.push(':');
+    push_hex(out, &bytes);
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn read_hex() {
+        let file = HexFile::read(
+            ":10010000214601360121470136007EFE09D2190140\n\
+             :100110002146017E17C20001FF5F16002148011928\r\n\
+             \n\
+             :020000040800F2\n\
+             :04000000DEADBEEFC4\n\
+             :0400000508000123CB\n\
+             :00000001FF\n\
+             garbage after the end\n"
+        ).unwrap();
+
+        let spans: Vec<(u32, usize)> = file.image.blocks().iter().map(|b| (b.region.vma, b.bytes.len())).collect();
+        assert_eq!( spans, [(0x0100, 0x20), (0x0800_0000, 4)] );
+        assert_eq!( file.image.read(0x0800_0000, 4), Some(&[0xDE, 0xAD, 0xBE, 0xEF][..]) );
+        assert_eq!( file.start, Some(HexStart::Linear(0x0800_0123)) );
+    }
+
+    #[test]
+    pub fn read_segments() {
+        let file = HexFile::read(
+            ":020000021000EC\n\
+             :04FFFE0001020304F5\n\
+             :0400000300120034B3\n"
+        ).unwrap();
+
+        // Offsets wrap within the segment
+        assert_eq!( file.image.read(0x1FFFE, 2), Some(&[1, 2][..]) );
+        assert_eq!( file.image.read(0x10000, 2), Some(&[3, 4][..]) );
+        assert_eq!( file.start.map(HexStart::addr), Some(0x154) );
+    }
+
+    #[test]
+    pub fn read_errors() {
+        let kind = |text: &str| HexFile::read(text).unwrap_err().kind;
+        assert_eq!( kind("00000001FF\n"),     RecordErrorKind::Syntax );
+        assert_eq!( kind(":0200000001FF\n"),  RecordErrorKind::Length );
+        assert_eq!( kind(":00000001FE\n"),    RecordErrorKind::Checksum(0xFF) );
+        assert_eq!( kind(":00000006FA\n"),    RecordErrorKind::RecordType(6) );
+        assert_eq!( kind(":0100000400FB\n"),  RecordErrorKind::Length );
+    }
+
+    #[test]
+    pub fn write_hex() {
+        let mut file = HexFile::default();
+        file.image.write(0x0000_FFFE, &[1, 2, 3, 4]);
+        file.image.write(0x0002_0000, &[5, 6, 7]);
+        file.start = Some(HexStart::Linear(0x0001_0000));
+
+        let text = file.write(2);
+        assert_eq!(
+            text,
+            ":02FFFE000102FE\n\
+             :020000040001F9\n\
+             :020000000304F7\n\
+             :020000040002F8\n\
+             :020000000506F3\n\
+             :0100020007F6\n\
+             :0400000500010000F6\n\
+             :00000001FF\n"
+        );
+        assert_eq!( HexFile::read(&text), Ok(file) );
+    }
+}
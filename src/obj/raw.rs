@@ -0,0 +1,233 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::mem::{Block, Image, Region, RegionKind};
+use crate::parse::ParseError;
+
+/// Returns a memory image of the raw binary `bytes`, as code at `base`.
+pub fn raw_image(bytes: &[u8], base: u32) -> Image {
+    let mut image = Image::new();
+    image.write(base, bytes);
+    image
+}
+
+/// A description of the regions within a raw binary file.
+///
+/// The text form has one region per line:
+///
+/// ```text
+/// ADDRESS  LENGTH  OFFSET  KIND  [NAME]
+/// ```
+///
+/// `ADDRESS` is the address of the region in memory, `LENGTH` its length in
+/// bytes, and `OFFSET` the offset of its content within the file.  Numbers
+/// are decimal, or hexadecimal with a `0x` or `$` prefix.  A length of `*`
+/// extends the region to the end of the file.  An offset of `-` makes the
+/// region reserved space, without content from the file.  `KIND` is one of
+/// `code`, `data`, `strings`, or `vectors`.  The optional `NAME` names the
+/// section that the region becomes.  A `#` or `;` begins a comment, and
+/// blank lines are ignored.  For example:
+///
+/// ```text
+/// # MCF51 flash dump
+/// $FFE00000  $400  $000  vectors
+/// $FFE00400  $18   $400  data     .flash_config
+/// $FFE00418  *     $418  code
+/// ```
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct MemoryMap {
+    /// Regions, in the order described.
+    pub entries: Vec<MapEntry>,
+}
+
+/// A region described by a memory map.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MapEntry {
+    /// Address of the region in memory.
+    pub addr: u32,
+
+    /// Length in bytes, or `None` to extend to the end of the file.
+    pub len: Option<u32>,
+
+    /// Offset of the content within the file, or `None` for reserved space.
+    pub offset: Option<u32>,
+
+    /// Kind of content.
+    pub kind: RegionKind,
+
+    /// Name of the section that the region becomes, if any.
+    pub name: Option<String>,
+
+    /// Line number of the description, starting from 1.
+    pub line: usize,
+}
+
+impl MemoryMap {
+    /// Parses a memory map from its text form (see `MemoryMap`).
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut map = Self::default();
+
+        for (n, text) in text.lines().enumerate() {
+            let line = n + 1;
+            let text = &text[..text.find(['#', ';']).unwrap_or(text.len())];
+
+            // Fields, each with its column
+            let fields: Vec<(usize, &str)> = text.split_whitespace()
+                .map(|f| (f.as_ptr() as usize - text.as_ptr() as usize + 1, f))
+                .collect();
+            match fields.len() {
+                0     => continue,
+                4 | 5 => {},
+                _     => return Err(ParseError::new(line, 1,
+                    "expected address, length, offset, kind, and optional name")),
+            }
+            let err = |i: usize, msg: &str| ParseError::new(line, fields[i].0, msg);
+
+            let addr = number(fields[0].1).ok_or_else(|| err(0, "expected address"))?;
+            let len = match fields[1].1 {
+                "*" => None,
+                f   => Some(number(f).ok_or_else(|| err(1, "expected length or '*'"))?),
+            };
+            let offset = match fields[2].1 {
+                "-" => None,
+                f   => Some(number(f).ok_or_else(|| err(2, "expected file offset or '-'"))?),
+            };
+            let kind = match fields[3].1 {
+                "code"    => RegionKind::Code,
+                "data"    => RegionKind::Data,
+                "strings" => RegionKind::Strings,
+                "vectors" => RegionKind::Vectors,
+                _         => return Err(err(3, "expected code, data, strings, or vectors")),
+            };
+            if len.is_none() && offset.is_none() {
+                return Err(err(1, "reserved space requires a length"))
+            }
+            let name = fields.get(4).map(|&(_, f)| f.to_string());
+
+            map.entries.push(MapEntry { addr, len, offset, kind, name, line });
+        }
+
+        Ok(map)
+    }
+
+    /// Returns a memory image of the raw binary `bytes`, with the regions
+    /// of the map.
+    ///
+    /// If a region extends beyond the end of the file or of the address
+    /// space, or overlaps another region, this method returns an error at
+    /// the line that describes the region.
+    pub fn load(&self, bytes: &[u8]) -> Result<Image, ParseError> {
+        let mut image = Image::new();
+
+        for e in &self.entries {
+            let err = |msg: String| ParseError::new(e.line, 1, msg);
+
+            let content = match e.offset {
+                Some(offset) => {
+                    let start = offset as usize;
+                    let end   = match e.len {
+                        Some(len) => start.checked_add(len as usize),
+                        None      => Some(bytes.len().max(start)),
+                    };
+                    match end.and_then(|end| bytes.get(start..end)) {
+                        Some(c) => c.to_vec(),
+                        None    => return Err(err("region extends beyond end of file".into())),
+                    }
+                },
+                None => vec![],
+            };
+            let len = e.len.unwrap_or(content.len() as u32);
+            if e.addr as u64 + len as u64 > 1 << 32 {
+                return Err(err("region extends beyond end of address space".into()))
+            }
+
+            let mut block = Block::new(Region::new(e.addr, e.addr, len, e.kind), content);
+            block.section = e.name.clone();
+            if let Err(r) = image.add(block) {
+                return Err(err(format!("region overlaps region at ${:08X}", r.vma)))
+            }
+        }
+
+        Ok(image)
+    }
+}
+
+// Parses a number: decimal, or hexadecimal with a `0x` or `$` prefix.
+fn number(s: &str) -> Option<u32> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix('$')) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None      => s.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str =
+        "# MCF51 flash dump\n\
+         $FFE00000  $10  $00  vectors\n\
+         \n\
+         0xFFE00010 8    0x10 data     .flash_config ; after the vectors\n\
+         $FFE00018  *    24   code\n\
+         $00800000  $100 -    data     .bss\n";
+
+    #[test]
+    pub fn parse_map() {
+        let map = MemoryMap::parse(MAP).unwrap();
+        assert_eq!( map.entries.len(), 4 );
+        assert_eq!( map.entries[1], MapEntry {
+            addr: 0xFFE0_0010, len: Some(8), offset: Some(0x10), kind: RegionKind::Data,
+            name: Some(".flash_config".into()), line: 4,
+        });
+        assert_eq!( map.entries[2].len, None );
+        assert_eq!( map.entries[3].offset, None );
+    }
+
+    #[test]
+    pub fn load_map() {
+        let bytes: Vec<u8> = (0..0x1C).collect();
+        let image = MemoryMap::parse(MAP).unwrap().load(&bytes).unwrap();
+        let blocks: Vec<(u32, u32, RegionKind, usize)> = image.blocks().iter()
+            .map(|b| (b.region.vma, b.region.len, b.region.kind, b.bytes.len()))
+            .collect();
+        assert_eq!( blocks, [
+            (0x0080_0000, 0x100, RegionKind::Data,    0),
+            (0xFFE0_0000, 0x10,  RegionKind::Vectors, 0x10),
+            (0xFFE0_0010, 8,     RegionKind::Data,    8),
+            (0xFFE0_0018, 4,     RegionKind::Code,    4),
+        ]);
+        assert_eq!( image.read(0xFFE0_0018, 4), Some(&[0x18, 0x19, 0x1A, 0x1B][..]) );
+        assert_eq!( image.blocks()[2].section.as_deref(), Some(".flash_config") );
+
+        let raw = raw_image(&bytes, 0xFFE0_0000);
+        assert_eq!( raw.blocks().len(), 1 );
+        assert_eq!( raw.blocks()[0].region, Region::new(0xFFE0_0000, 0xFFE0_0000, 0x1C, RegionKind::Code) );
+    }
+
+    #[test]
+    pub fn map_errors() {
+        let err = |text: &str| MemoryMap::parse(text).unwrap_err().to_string();
+        assert_eq!( err("0 1 2\n"),                "1:1: expected address, length, offset, kind, and optional name" );
+        assert_eq!( err("\n0 1 2 stack\n"),        "2:7: expected code, data, strings, or vectors" );
+        assert_eq!( err("0 $1G 0 code\n"),         "1:3: expected length or '*'" );
+        assert_eq!( err("0 * - data\n"),           "1:3: reserved space requires a length" );
+
+        let load = |text: &str| MemoryMap::parse(text).unwrap().load(&[0; 16]).unwrap_err().to_string();
+        assert_eq!( load("0 17 0 code\n"),         "1:1: region extends beyond end of file" );
+        assert_eq!( load("0 * 0 code\n8 4 0 data\n"), "2:1: region overlaps region at $00000000" );
+    }
+}
@@ -0,0 +1,106 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt::{self, Display, Formatter};
+
+/// An error in a file of text records, such as S-records or Intel HEX.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct RecordError {
+    /// Line number of the record, starting from 1.
+    pub line: usize,
+
+    /// Kind of error.
+    pub kind: RecordErrorKind,
+}
+
+/// Kinds of errors in files of text records.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum RecordErrorKind {
+    /// The line is not a record: it lacks the start code, or its content is
+    /// not pairs of hexadecimal digits.
+    Syntax,
+
+    /// The byte count of the record does not match its length.
+    Length,
+
+    /// The checksum of the record is not the given correct value.
+    Checksum(u8),
+
+    /// The record type is not valid.
+    RecordType(u8),
+
+    /// The record count in a count record is not the given number of data
+    /// records.
+    RecordCount(u32),
+}
+
+impl RecordError {
+    /// Creates a new `RecordError`.
+    pub fn new(line: usize, kind: RecordErrorKind) -> Self {
+        Self { line, kind }
+    }
+}
+
+impl Display for RecordError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match self.kind {
+            RecordErrorKind::Syntax         => write!(f, "not a record"),
+            RecordErrorKind::Length         => write!(f, "byte count does not match record length"),
+            RecordErrorKind::Checksum(c)    => write!(f, "incorrect checksum; expected {:02X}", c),
+            RecordErrorKind::RecordType(t)  => write!(f, "invalid record type {}", t),
+            RecordErrorKind::RecordCount(n) => write!(f, "record count does not match {} data records", n),
+        }
+    }
+}
+
+/// Returns the bytes given as pairs of hexadecimal digits in `text`, or
+/// `None` if `text` is not such pairs.
+pub fn hex_bytes(text: &str) -> Option<Vec<u8>> {
+    let text = text.as_bytes();
+    if !text.len().is_multiple_of(2) {
+        return None
+    }
+    text.chunks(2)
+        .map(|p| Some((hex_digit(p[0])? << 4) | hex_digit(p[1])?))
+        .collect()
+}
+
+/// Appends `bytes` to `out` as pairs of uppercase hexadecimal digits.
+pub fn push_hex(out: &mut String, bytes: &[u8]) {
+    for b in bytes {
+        out.push_str(&format!("{:02X}", b));
+    }
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|d| d as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn hex_round_trip() {
+        assert_eq!( hex_bytes("00a5FF"), Some(vec![0x00, 0xA5, 0xFF]) );
+        assert_eq!( hex_bytes("0"),      None );
+        assert_eq!( hex_bytes("0g"),     None );
+        let mut s = String::new();
+        push_hex(&mut s, &[0x00, 0xA5, 0xFF]);
+        assert_eq!( s, "00A5FF" );
+    }
+}
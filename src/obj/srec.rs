@@ -0,0 +1,264 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::mem::Image;
+use super::*;
+
+/// S-record formats, by address width.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SRecordFormat {
+    /// 16-bit addresses: S1 data records and an S9 termination record.
+    S19,
+
+    /// 24-bit addresses: S2 data records and an S8 termination record.
+    S28,
+
+    /// 32-bit addresses: S3 data records and an S7 termination record.
+    S37,
+}
+
+impl SRecordFormat {
+    /// Returns the narrowest format that can represent the addresses of
+    /// `image` and `entry`.
+    pub fn fit(image: &Image, entry: Option<u32>) -> Self {
+        let top = image.contents().last()
+            .map_or(0, |&(lma, bytes)| lma as u64 + bytes.len() as u64 - 1)
+            .max(entry.unwrap_or(0) as u64);
+        match top {
+            0x0000..=0xFFFF      => SRecordFormat::S19,
+            0x1_0000..=0xFF_FFFF => SRecordFormat::S28,
+            _                    => SRecordFormat::S37,
+        }
+    }
+
+    /// Returns the size in bytes of an address.
+    #[inline]
+    pub fn addr_size(self) -> usize {
+        match self {
+            SRecordFormat::S19 => 2,
+            SRecordFormat::S28 => 3,
+            SRecordFormat::S37 => 4,
+        }
+    }
+
+    // Returns the types of data and termination records.
+    fn record_types(self) -> (u8, u8) {
+        match self {
+            SRecordFormat::S19 => (1, 9),
+            SRecordFormat::S28 => (2, 8),
+            SRecordFormat::S37 => (3, 7),
+        }
+    }
+}
+
+/// The content of an S-record file.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct SRecordFile {
+    /// Content of the S0 header record, typically a module name.
+    pub header: Vec<u8>,
+
+    /// Memory content given by S1, S2, and S3 data records.
+    pub image: Image,
+
+    /// Address given by the S7, S8, or S9 termination record, if any.
+    pub entry: Option<u32>,
+}
+
+impl SRecordFile {
+    /// Reads an S-record file from `text`.
+    ///
+    /// Data records of any address width may mix.  Blank lines are ignored.
+    /// Each record must have a correct byte count and checksum, and S5 and
+    /// S6 count records must match the number of data records before them.
+    pub fn read(text: &str) -> Result<Self, RecordError> {
+        let mut file  = Self::default();
+        let mut count = 0_u32;
+
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue
+            }
+            let err = |kind| RecordError::new(n + 1, kind);
+
+            // Type, byte count, content, and checksum
+            let kind = match line.as_bytes() {
+                [b'S', t @ b'0'..=b'9', ..] => t - b'0',
+                _                           => return Err(err(RecordErrorKind::Syntax)),
+            };
+            let bytes = hex_bytes(&line[2..]).ok_or_else(|| err(RecordErrorKind::Syntax))?;
+            match bytes.split_first() {
+                Some((&len, rest)) if len as usize == rest.len() => {},
+                _ => return Err(err(RecordErrorKind::Length)),
+            }
+            let (&sum, body) = bytes.split_last().unwrap();
+            let check = !body.iter().fold(0_u8, |s, &b| s.wrapping_add(b));
+            if sum != check {
+                return Err(err(RecordErrorKind::Checksum(check)))
+            }
+
+            // Address and data
+            let addr_size = match kind {
+                0 | 1 | 5 | 9 => 2,
+                2 | 6 | 8     => 3,
+                3 | 7         => 4,
+                _             => return Err(err(RecordErrorKind::RecordType(kind))),
+            };
+            let body = &body[1..];
+            if body.len() < addr_size {
+                return Err(err(RecordErrorKind::Length))
+            }
+            let (addr, data) = body.split_at(addr_size);
+            let addr = addr.iter().fold(0_u32, |a, &b| a << 8 | b as u32);
+
+            match kind {
+                0         => file.header = data.to_vec(),
+                1..=3     => { file.image.write(addr, data); count += 1 },
+                5 | 6     => if addr != count { return Err(err(RecordErrorKind::RecordCount(count))) },
+                _         => file.entry = Some(addr),
+            }
+        }
+
+        Ok(file)
+    }
+
+    /// Returns the file as text in the given `format`, with at most
+    /// `record_size` data bytes per record.
+    ///
+    /// The text is an S0 header record, data records, an S5 or S6 count
+    /// record if the count fits, and a termination record with the entry
+    /// address, or 0 if none.  Data records give the content of the image at
+    /// load addresses, and break at gaps.  The record size is limited to
+    /// what the format permits, and the header to the 252 bytes that fit in
+    /// one record.  If an address does not fit the format, this method
+    /// returns the address as an error.
+    pub fn write(&self, format: SRecordFormat, record_size: usize) -> Result<String, u32> {
+        let addr_size = format.addr_size();
+        let limit     = 1_u64 << (addr_size * 8);
+        let size      = record_size.clamp(1, 0xFF - addr_size - 1);
+        let (data, term) = format.record_types();
+
+        let mut out   = String::new();
+        let mut count = 0_u32;
+        record(&mut out, 0, 0, 2, &self.header[..self.header.len().min(0xFF - 3)]);
+        for (lma, bytes) in self.image.contents() {
+            if lma as u64 + bytes.len() as u64 > limit {
+                return Err(lma.max(limit as u32))
+            }
+            for (i, chunk) in bytes.chunks(size).enumerate() {
+                record(&mut out, data, lma + (i * size) as u32, addr_size, chunk);
+                count += 1;
+            }
+        }
+        match count {
+            0x0000..=0xFFFF      => record(&mut out, 5, count, 2, &[]),
+            0x1_0000..=0xFF_FFFF => record(&mut out, 6, count, 3, &[]),
+            _                    => {},
+        }
+        let entry = self.entry.unwrap_or(0);
+        if entry as u64 >= limit {
+            return Err(entry)
+        }
+        record(&mut out, term, entry, addr_size, &[]);
+        Ok(out)
+    }
+}
+
+// Appends a record of type `kind` with the given address and data.
+fn record(out: &mut String, kind: u8, addr: u32, addr_size: usize, data: &[u8]) {
+    let mut bytes = vec![(addr_size + data.len() + 1) as u8];
+    bytes.extend_from_slice(&addr.to_be_bytes()[4 - addr_size..]);
+    bytes.extend_from_slice(data);
+    bytes.push(!bytes.iter().fold(0_u8, |s, &b| s.wrapping_add(b)));
+    out.push('S');
+    out.push((b'0' + kind) as char);
+    push_hex(out, &bytes);
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn read_srecords() {
+        let file = SRecordFile::read(
+            "S00F000068656C6C6F202020202000003C\n\
+             S11F00007C0802A6900100049421FFF07C6C1B787C8C23783C6000003863000026\n\
+             S11F001C4BFFFFE5398000007D83637880010014382100107C0803A64E800020E9\r\n\
+             \n\
+             S111003848656C6C6F20776F726C642E0A0042\n\
+             S5030003F9\n\
+             S9030000FC\n"
+        ).unwrap();
+        assert_eq!( file.header, b"hello     \0\0" );
+        assert_eq!( file.entry, Some(0) );
+        assert_eq!( file.image.blocks().len(), 1 );
+        assert_eq!( file.image.len(), 0x46 );
+        assert_eq!( file.image.read(0x38, 14), Some(&b"Hello world.\n\0"[..]) );
+    }
+
+    #[test]
+    pub fn read_errors() {
+        let kind = |text: &str| SRecordFile::read(text).unwrap_err().kind;
+        assert_eq!( kind("X1030000FC\n"),        RecordErrorKind::Syntax );
+        assert_eq!( kind("S10300FC\n"),          RecordErrorKind::Length );
+        assert_eq!( kind("S1030000FD\n"),        RecordErrorKind::Checksum(0xFC) );
+        assert_eq!( kind("S4030000FC\n"),        RecordErrorKind::RecordType(4) );
+        assert_eq!( kind("S5030001FB\n"),        RecordErrorKind::RecordCount(0) );
+
+        let err = SRecordFile::read("S9030000FC\n\nS1030000FD\n").unwrap_err();
+        assert_eq!( err.to_string(), "line 3: incorrect checksum; expected FC" );
+    }
+
+    #[test]
+    pub fn write_srecords() {
+        let mut file = SRecordFile { header: b"cf".to_vec(), ..SRecordFile::default() };
+        file.image.write(0x1000, &[0x4E, 0x71, 0x4E, 0x71, 0x4E, 0x75]);
+        file.image.write(0x2000, &[0xAA]);
+        file.entry = Some(0x1000);
+
+        assert_eq!( SRecordFormat::fit(&file.image, file.entry), SRecordFormat::S19 );
+        let text = file.write(SRecordFormat::S19, 4).unwrap();
+        assert_eq!(
+            text,
+            "S0050000636631\n\
+             S10710004E714E716A\n\
+             S10510044E7523\n\
+             S1042000AA31\n\
+             S5030003F9\n\
+             S9031000EC\n"
+        );
+        assert_eq!( SRecordFile::read(&text), Ok(file.clone()) );
+
+        // Wider addresses
+        let text = file.write(SRecordFormat::S37, 16).unwrap();
+        assert!( text.contains("\nS30B000010004E714E714E75") );
+        assert!( text.ends_with("\nS70500001000EA\n") );
+        assert_eq!( SRecordFile::read(&text), Ok(file.clone()) );
+
+        // Addresses that do not fit
+        file.image.write(0x1_0000, &[0]);
+        assert_eq!( SRecordFormat::fit(&file.image, None), SRecordFormat::S28 );
+        assert_eq!( file.write(SRecordFormat::S19, 16), Err(0x1_0000) );
+
+        // A long header is truncated
+        let file = SRecordFile { header: vec![b'x'; 300], ..SRecordFile::default() };
+        let text = file.write(SRecordFormat::S19, 16).unwrap();
+        assert!( text.starts_with("S0FF0000787878") );
+        assert_eq!( SRecordFile::read(&text).unwrap().header, [b'x'; 252] );
+    }
+}
@@ -0,0 +1,325 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::convert::TryInto;
+use std::fmt::{self, Display, Formatter};
+use crate::mem::{BE, Block, ByteOrder, Image, Load, Region, RegionKind};
+
+/// Magic number that begins a U-Boot legacy image.
+pub const IH_MAGIC: u32 = 0x2705_1956;
+
+/// Size of the image header.
+pub const IH_SIZE: usize = 64;
+
+/// Length of the image name field.
+pub const IH_NMLEN: usize = 32;
+
+// Operating systems
+pub const IH_OS_INVALID:       u8 =  0;
+pub const IH_OS_LINUX:         u8 =  5;
+pub const IH_OS_U_BOOT:        u8 = 17;
+pub const IH_OS_RTEMS:         u8 = 18;
+
+// Architectures
+pub const IH_ARCH_INVALID:     u8 =  0;
+pub const IH_ARCH_M68K:        u8 = 12;
+
+// Image types
+pub const IH_TYPE_INVALID:     u8 =  0;
+pub const IH_TYPE_STANDALONE:  u8 =  1;  // Program run directly by U-Boot
+pub const IH_TYPE_KERNEL:      u8 =  2;  // Operating system kernel
+pub const IH_TYPE_RAMDISK:     u8 =  3;  // Initial RAM disk
+pub const IH_TYPE_MULTI:       u8 =  4;  // Several images in one
+pub const IH_TYPE_FIRMWARE:    u8 =  5;  // Firmware to write to flash
+pub const IH_TYPE_SCRIPT:      u8 =  6;  // U-Boot script
+
+// Compression types
+pub const IH_COMP_NONE:        u8 =  0;
+pub const IH_COMP_GZIP:        u8 =  1;
+pub const IH_COMP_BZIP2:       u8 =  2;
+pub const IH_COMP_LZMA:        u8 =  3;
+pub const IH_COMP_LZO:         u8 =  4;
+
+/// An error encountered while reading a U-Boot image.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum UImageError {
+    /// The file does not begin with the image magic number.
+    NotUImage,
+
+    /// The header or payload extends past the end of the file, which ends
+    /// at the given offset.
+    Truncated(usize),
+
+    /// The header does not match its CRC, given here as computed.
+    HeaderCrc(u32),
+
+    /// The payload does not match its CRC, given here as computed.
+    DataCrc(u32),
+
+    /// The payload does not fit in the address space at the load address.
+    Overflow,
+}
+
+impl Display for UImageError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            UImageError::NotUImage    => write!(f, "not a U-Boot image"),
+            UImageError::Truncated(o) => write!(f, "file truncated at offset 0x{:X}", o),
+            UImageError::HeaderCrc(c) => write!(f, "header CRC mismatch (computed 0x{:08X})", c),
+            UImageError::DataCrc(c)   => write!(f, "data CRC mismatch (computed 0x{:08X})", c),
+            UImageError::Overflow     => write!(f, "payload extends beyond end of address space"),
+        }
+    }
+}
+
+/// A U-Boot legacy image (uImage): a 64-byte header and a payload.
+///
+/// The payload is not decompressed.  The payload of a multi-file image
+/// (`IH_TYPE_MULTI`), with its table of sizes, remains whole.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct UImage {
+    /// Time of creation, in seconds since the Unix epoch.
+    pub time: u32,
+
+    /// Address at which to load the payload.
+    pub load: u32,
+
+    /// Address of the entry point.
+    pub entry: u32,
+
+    /// Operating system (`IH_OS_*`).
+    pub os: u8,
+
+    /// Architecture (`IH_ARCH_*`).
+    pub arch: u8,
+
+    /// Type of image (`IH_TYPE_*`).
+    pub kind: u8,
+
+    /// Compression of the payload (`IH_COMP_*`).
+    pub comp: u8,
+
+    /// Name of the image, of at most 32 bytes.
+    pub name: String,
+
+    /// Payload.
+    pub data: Vec<u8>,
+}
+
+impl UImage {
+    /// Reads an image from `bytes`, verifying the CRCs of its header and
+    /// payload.  Bytes after the payload are ignored.
+    pub fn read(bytes: &[u8]) -> Result<Self, UImageError> {
+        if bytes.len() < 4 || u32::load(bytes, BE).unwrap().0 != IH_MAGIC {
+            return Err(UImageError::NotUImage)
+        }
+        let (h, _) = Header::load(bytes, BE).ok_or(UImageError::Truncated(bytes.len()))?;
+
+        let mut header = bytes[..IH_SIZE].to_vec();
+        header[4..8].copy_from_slice(&[0; 4]);
+        let crc = crc32(&header);
+        if crc != h.hcrc {
+            return Err(UImageError::HeaderCrc(crc))
+        }
+
+        let end  = IH_SIZE as u64 + h.size as u64;
+        let data = match bytes.get(IH_SIZE..) {
+            Some(d) if end <= bytes.len() as u64 => &d[..h.size as usize],
+            _                                    => return Err(UImageError::Truncated(bytes.len())),
+        };
+        let crc = crc32(data);
+        if crc != h.dcrc {
+            return Err(UImageError::DataCrc(crc))
+        }
+
+        let len = h.name.iter().position(|&b| b == 0).unwrap_or(IH_NMLEN);
+        Ok(Self {
+            time:  h.time,
+            load:  h.load,
+            entry: h.entry,
+            os:    h.os,
+            arch:  h.arch,
+            kind:  h.kind,
+            comp:  h.comp,
+            name:  String::from_utf8_lossy(&h.name[..len]).into_owned(),
+            data:  data.to_vec(),
+        })
+    }
+
+    /// Returns the bytes of the image, with the CRCs of its header and
+    /// payload.  A name longer than 32 bytes is truncated.
+    pub fn write(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(IH_SIZE + self.data.len());
+        for &w in &[IH_MAGIC, 0, self.time, self.data.len() as u32, self.load, self.entry, crc32(&self.data)] {
+            bytes.extend_from_slice(&w.to_be_bytes());
+        }
+        bytes.extend_from_slice(&[self.os, self.arch, self.kind, self.comp]);
+
+        let name = self.name.as_bytes();
+        bytes.extend_from_slice(&name[..name.len().min(IH_NMLEN)]);
+        bytes.resize(IH_SIZE, 0);
+
+        let crc = crc32(&bytes);
+        bytes[4..8].copy_from_slice(&crc.to_be_bytes());
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    /// Returns a memory image of the payload at the load address.
+    ///
+    /// An uncompressed payload becomes a code region, and a compressed one a
+    /// data region.
+    pub fn image(&self) -> Result<Image, UImageError> {
+        let mut image = Image::new();
+        if self.data.is_empty() {
+            return Ok(image)
+        }
+        if self.load as u64 + self.data.len() as u64 > 1 << 32 {
+            return Err(UImageError::Overflow)
+        }
+        let kind = match self.comp {
+            IH_COMP_NONE => RegionKind::Code,
+            _            => RegionKind::Data,
+        };
+        let region = Region::new(self.load, self.load, self.data.len() as u32, kind);
+        image.add(Block::new(region, self.data.clone())).unwrap();
+        Ok(image)
+    }
+}
+
+// Image header (`image_header_t`)
+struct Header {
+    hcrc:  u32,
+    time:  u32,
+    size:  u32,
+    load:  u32,
+    entry: u32,
+    dcrc:  u32,
+    os:    u8,
+    arch:  u8,
+    kind:  u8,
+    comp:  u8,
+    name:  [u8; IH_NMLEN],
+}
+
+impl Load<[u8], ByteOrder> for Header {
+    fn load(buf: &[u8], fmt: ByteOrder) -> Option<(Self, &[u8])> {
+        let buf          = buf.get(4..)?;
+        let (hcrc,  buf) = u32::load(buf, fmt)?;
+        let (time,  buf) = u32::load(buf, fmt)?;
+        let (size,  buf) = u32::load(buf, fmt)?;
+        let (load,  buf) = u32::load(buf, fmt)?;
+        let (entry, buf) = u32::load(buf, fmt)?;
+        let (dcrc,  buf) = u32::load(buf, fmt)?;
+        let (os,    buf) = u8 ::load(buf, ())?;
+        let (arch,  buf) = u8 ::load(buf, ())?;
+        let (kind,  buf) = u8 ::load(buf, ())?;
+        let (comp,  buf) = u8 ::load(buf, ())?;
+        let name         = buf.get(..IH_NMLEN)?.try_into().ok()?;
+        let h = Self { hcrc, time, size, load, entry, dcrc, os, arch, kind, comp, name };
+        Some((h, &buf[IH_NMLEN..]))
+    }
+}
+
+// Returns the CRC-32 of `bytes`, as computed by zlib.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0_u32;
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kernel() -> UImage {
+        UImage {
+            time:  0x5C00_0000,
+            load:  0x4002_0000,
+            entry: 0x4002_0004,
+            os:    IH_OS_LINUX,
+            arch:  IH_ARCH_M68K,
+            kind:  IH_TYPE_KERNEL,
+            comp:  IH_COMP_NONE,
+            name:  "Linux-2.6.25".into(),
+            data:  vec![0x4E, 0x71, 0x4E, 0x71, 0x4E, 0x75],
+        }
+    }
+
+    #[test]
+    pub fn crc() {
+        assert_eq!( crc32(b""), 0 );
+        assert_eq!( crc32(b"123456789"), 0xCBF4_3926 );
+    }
+
+    #[test]
+    pub fn write_read() {
+        let bytes = kernel().write();
+        assert_eq!( bytes.len(), IH_SIZE + 6 );
+        assert_eq!( &bytes[..4], &[0x27, 0x05, 0x19, 0x56] );
+        assert_eq!( &bytes[12..16], &[0, 0, 0, 6] );
+        assert_eq!( &bytes[24..28], &crc32(&bytes[IH_SIZE..]).to_be_bytes() );
+        assert_eq!( &bytes[28..40], &[5, 12, 2, 0, b'L', b'i', b'n', b'u', b'x', b'-', b'2', b'.'] );
+
+        assert_eq!( UImage::read(&bytes), Ok(kernel()) );
+
+        // Trailing bytes are ignored
+        let mut b = bytes.clone();
+        b.extend_from_slice(&[0xFF; 4]);
+        assert_eq!( UImage::read(&b), Ok(kernel()) );
+
+        // A long name is truncated
+        let long = UImage { name: "x".repeat(40), ..kernel() };
+        assert_eq!( UImage::read(&long.write()).unwrap().name, "x".repeat(32) );
+    }
+
+    #[test]
+    pub fn read_errors() {
+        let bytes = kernel().write();
+        assert_eq!( UImage::read(b"\x7FELF"), Err(UImageError::NotUImage) );
+        assert_eq!( UImage::read(&bytes[..40]), Err(UImageError::Truncated(40)) );
+        assert_eq!( UImage::read(&bytes[..68]), Err(UImageError::Truncated(68)) );
+
+        let mut b = bytes.clone();
+        b[40] = b'l';
+        let mut h = b[..IH_SIZE].to_vec();
+        h[4..8].copy_from_slice(&[0; 4]);
+        assert_eq!( UImage::read(&b), Err(UImageError::HeaderCrc(crc32(&h))) );
+
+        let mut b = bytes.clone();
+        b[IH_SIZE + 5] = 0x74;
+        assert_eq!( UImage::read(&b), Err(UImageError::DataCrc(crc32(&b[IH_SIZE..]))) );
+    }
+
+    #[test]
+    pub fn image() {
+        let image = kernel().image().unwrap();
+        assert_eq!( image.blocks().len(), 1 );
+        assert_eq!( image.blocks()[0].region, Region::new(0x4002_0000, 0x4002_0000, 6, RegionKind::Code) );
+        assert_eq!( image.read(0x4002_0004, 2).unwrap(), &[0x4E, 0x75] );
+
+        let gzip = UImage { comp: IH_COMP_GZIP, ..kernel() };
+        assert_eq!( gzip.image().unwrap().blocks()[0].region.kind, RegionKind::Data );
+
+        let high = UImage { load: 0xFFFF_FFFC, ..kernel() };
+        assert_eq!( high.image(), Err(UImageError::Overflow) );
+    }
+}
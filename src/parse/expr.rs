@@ -0,0 +1,331 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::ops::Range;
+use crate::ast::{BinaryOp, Expr, IdentPool, Slot, Span, UnaryOp};
+use super::{ParseError, Token};
+
+/// A cursor over the tokens of one line of assembly source.
+#[derive(Clone, Debug)]
+pub struct Tokens<'t> {
+    tokens: &'t [(Token, Range<usize>)],
+    pos:    usize,
+    line:   usize,
+    end:    usize,
+}
+
+impl<'t> Tokens<'t> {
+    /// Creates a cursor over `tokens` from line number `line`, whose code
+    /// portion ends at byte offset `end`.
+    pub fn new(tokens: &'t [(Token, Range<usize>)], line: usize, end: usize) -> Self {
+        Self { tokens, pos: 0, line, end }
+    }
+
+    /// Returns the next token without consuming it.
+    #[inline]
+    pub fn peek(&self) -> Option<&'t Token> {
+        self.tokens.get(self.pos).map(|t| &t.0)
+    }
+
+    /// Returns the token after the next one without consuming anything.
+    #[inline]
+    pub fn peek2(&self) -> Option<&'t Token> {
+        self.tokens.get(self.pos + 1).map(|t| &t.0)
+    }
+
+    /// Consumes and returns the next token.
+    #[inline]
+    pub fn next(&mut self) -> Option<&'t Token> {
+        let token = self.peek()?;
+        self.pos += 1;
+        Some(token)
+    }
+
+    /// Consumes the next token if it equals `token`.
+    pub fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) { self.pos += 1; true } else { false }
+    }
+
+    /// Consumes the next token, which must equal `token`.
+    pub fn expect(&mut self, token: &Token, what: &str) -> Result<(), ParseError> {
+        if self.eat(token) { Ok(()) } else { Err(self.error(format!("expected {}", what))) }
+    }
+
+    /// Returns whether all tokens have been consumed.
+    #[inline]
+    pub fn at_end(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    /// Returns the current position, for use with `reset`.
+    #[inline]
+    pub fn mark(&self) -> usize {
+        self.pos
+    }
+
+    /// Returns to a position previously obtained from `mark`.
+    #[inline]
+    pub fn reset(&mut self, mark: usize) {
+        self.pos = mark;
+    }
+
+    /// Returns the line number of the tokens.
+    #[inline]
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Returns the zero-based byte offset of the next token, or of the end
+    /// of the code portion of the line if no tokens remain.
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end, |t| t.1.start)
+    }
+
+    /// Returns the zero-based byte offset of the end of the last consumed
+    /// token, or of the start of the line if no tokens have been consumed.
+    #[inline]
+    pub fn prev_end(&self) -> usize {
+        match self.pos {
+            0 => 0,
+            n => self.tokens[n - 1].1.end,
+        }
+    }
+
+    /// Returns the span from byte offset `start` to the end of the last
+    /// consumed token.
+    #[inline]
+    pub fn span_from(&self, start: usize) -> Span {
+        let len = self.prev_end().saturating_sub(start);
+        Span::new(self.line as u32, start as u32 + 1, len as u32)
+    }
+
+    /// Creates an error at the next token.
+    pub fn error<S: Into<String>>(&self, msg: S) -> ParseError {
+        ParseError::new(self.line, self.offset() + 1, msg)
+    }
+
+    // -------------------------------------------------------------------------
+    // Expressions
+
+    /// Parses an expression.
+    ///
+    /// An expression consisting of a lone identifier yields `Slot::Ident`.
+    /// Identifiers nested within larger expressions likewise appear as
+    /// `Slot::Ident` subexpressions, so that a resolution pass can replace
+    /// them with their values.
+    ///
+    /// Each subexpression, and the slot that holds it, records its source
+    /// location, from its first token to its last, excluding any enclosing
+    /// parentheses.
+    pub fn expr(&mut self, pool: &mut IdentPool) -> Result<Slot<Expr>, ParseError> {
+        let start = self.offset();
+        let lhs   = self.unary(pool)?;
+        self.binary(lhs, start, 0, pool)
+    }
+
+    /// Continues parsing an expression whose leftmost operand, `lhs`, has
+    /// already been parsed, beginning at byte offset `start`.
+    pub fn expr_from(&mut self, lhs: Slot<Expr>, start: usize, pool: &mut IdentPool)
+        -> Result<Slot<Expr>, ParseError>
+    {
+        self.binary(lhs, start, 0, pool)
+    }
+
+    // Precedence climbing over binary operators at or above `min` precedence.
+    fn binary(&mut self, mut lhs: Slot<Expr>, start: usize, min: u8, pool: &mut IdentPool)
+        -> Result<Slot<Expr>, ParseError>
+    {
+        while let Some((op, prec)) = self.peek().and_then(binary_op) {
+            if prec < min { break }
+            self.next();
+
+            let rhs_start = self.offset();
+            let mut rhs   = self.unary(pool)?;
+            while let Some((_, next)) = self.peek().and_then(binary_op) {
+                if next <= prec { break }
+                rhs = self.binary(rhs, rhs_start, next, pool)?;
+            }
+
+            let span = self.span_from(start);
+            lhs = Slot::Value(Expr::binary(op, lhs, rhs).with_span(span), Some(span));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self, pool: &mut IdentPool) -> Result<Slot<Expr>, ParseError> {
+        let op = match self.peek() {
+            Some(&Token::Plus)  => UnaryOp::Pos,
+            Some(&Token::Minus) => UnaryOp::Neg,
+            Some(&Token::Tilde) => UnaryOp::BitNot,
+            Some(&Token::Bang)  => UnaryOp::LogNot,
+            _                   => return self.primary(pool),
+        };
+        let start = self.offset();
+        self.next();
+        let expr = self.unary(pool)?;
+        let span = self.span_from(start);
+        Ok(Slot::Value(Expr::unary(op, expr).with_span(span), Some(span)))
+    }
+
+    fn primary(&mut self, pool: &mut IdentPool) -> Result<Slot<Expr>, ParseError> {
+        let start = self.offset();
+        let expr  = match self.peek() {
+            Some(Token::Ident(s)) => {
+                self.next();
+                return Ok(Slot::Ident(pool.intern(s), Some(self.span_from(start))))
+            },
+            Some(Token::Int  (i)) => Expr::LitInt  (*i, None),
+            Some(Token::Float(x)) => Expr::LitFloat(*x, None),
+            Some(Token::Str  (s)) => Expr::LitStr  (s.clone(), None),
+            Some(Token::Char (s)) => Expr::LitChar (s.clone(), None),
+            Some(Token::LParen)   => {
+                self.next();
+                let expr = self.expr(pool)?;
+                self.expect(&Token::RParen, "')'")?;
+                return Ok(expr)
+            },
+            _ => return Err(self.error("expected expression")),
+        };
+        self.next();
+        let span = self.span_from(start);
+        Ok(Slot::Value(expr.with_span(span), Some(span)))
+    }
+}
+
+/// Returns the binary operator and its precedence for the given token.
+/// Higher precedence binds more tightly.
+fn binary_op(token: &Token) -> Option<(BinaryOp, u8)> {
+    use BinaryOp::*;
+    Some(match *token {
+        Token::Star     => (Mul,    10),
+        Token::Slash    => (Div,    10),
+        Token::Percent  => (Mod,    10),
+        Token::Plus     => (Add,     9),
+        Token::Minus    => (Sub,     9),
+        Token::Shl      => (Shl,     8),
+        Token::Shr      => (Shr,     8),
+        Token::ShrS     => (ShrS,    8),
+        Token::Lt       => (Lt,      7),
+        Token::LtEq     => (LtEq,    7),
+        Token::Gt       => (Gt,      7),
+        Token::GtEq     => (GtEq,    7),
+        Token::EqEq     => (Eq,      6),
+        Token::NotEq    => (NotEq,   6),
+        Token::Amp      => (BitAnd,  5),
+        Token::Caret    => (BitXor,  4),
+        Token::Pipe     => (BitOr,   3),
+        Token::AmpAmp   => (LogAnd,  2),
+        Token::PipePipe => (LogOr,   1),
+        _               => return None,
+    })
+}
+
+/// Converts a parsed expression slot into a plain expression.
+#[inline]
+pub fn into_expr(slot: Slot<Expr>) -> Expr {
+    match slot {
+        Slot::Ident    (i,    s) => Expr::Ident(i, s),
+        Slot::Resolved (_, e, _) => e,
+        Slot::Value    (   e, _) => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse::lex_line;
+    use super::*;
+
+    fn parse(s: &str) -> (Slot<Expr>, IdentPool) {
+        let line = lex_line(s, 1).unwrap();
+        let mut pool = IdentPool::new();
+        let mut toks  = Tokens::new(&line.tokens, 1, line.end);
+        let expr = toks.expr(&mut pool).unwrap();
+        assert!(toks.at_end());
+        (expr, pool)
+    }
+
+    fn int(i: i64) -> Slot<Expr> {
+        Slot::Value(Expr::LitInt(i, None), None)
+    }
+
+    #[test]
+    pub fn parse_precedence() {
+        let (expr, _) = parse("1 + 2 * 3 - 4");
+        assert_eq!(
+            expr,
+            Slot::Value(Expr::binary(BinaryOp::Sub,
+                Slot::Value(Expr::binary(BinaryOp::Add,
+                    int(1),
+                    Slot::Value(Expr::binary(BinaryOp::Mul, int(2), int(3)), None)
+                ), None),
+                int(4)
+            ), None)
+        );
+    }
+
+    #[test]
+    pub fn parse_parens_and_unary() {
+        let (expr, _) = parse("-(1 | 2)");
+        assert_eq!(
+            expr,
+            Slot::Value(Expr::unary(UnaryOp::Neg,
+                Slot::Value(Expr::binary(BinaryOp::BitOr, int(1), int(2)), None)
+            ), None)
+        );
+    }
+
+    #[test]
+    pub fn parse_ident() {
+        let (expr, pool) = parse("start");
+        let id = expr.ident().unwrap();
+        assert_eq!( expr, Slot::Ident(id, None) );
+        assert_eq!( pool.name(id), "start" );
+    }
+
+    #[test]
+    pub fn parse_spans() {
+        let (expr, _) = parse("1 + (-x * 3)");
+        let expr = into_expr(expr);
+        assert_eq!( expr.span(), Some(Span::new(1, 1, 12)) );
+        let rhs = match expr {
+            Expr::Binary(ref b) => b.rhs.value().unwrap().clone(),
+            _                   => panic!("expected binary expression"),
+        };
+        assert_eq!( rhs.span(), Some(Span::new(1, 6, 6)) );
+        let (neg, three) = match rhs {
+            Expr::Binary(ref b) => (b.lhs.value().unwrap().clone(), *b.rhs.clone()),
+            _                   => panic!("expected binary expression"),
+        };
+        assert_eq!( neg.span(),   Some(Span::new(1,  6, 2)) );
+        assert_eq!( three.span(), Some(Span::new(1, 11, 1)) );
+        match neg {
+            Expr::Unary(ref u) => assert_eq!( u.expr.span(), Some(Span::new(1, 7, 1)) ),
+            _                  => panic!("expected unary expression"),
+        }
+
+        // Source locations do not affect comparison
+        assert_eq!( three, int(3) );
+    }
+
+    #[test]
+    pub fn parse_error() {
+        let line = lex_line("1 +", 5).unwrap();
+        let mut pool = IdentPool::new();
+        let err = Tokens::new(&line.tokens, 5, line.end).expr(&mut pool).unwrap_err();
+        assert_eq!( (err.line, err.col), (5, 4) );
+    }
+}
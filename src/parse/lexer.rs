@@ -0,0 +1,514 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::ops::Range;
+use crate::ast::Float;
+use super::ParseError;
+
+/// A lexical token in assembly source.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Token {
+    // Atoms -----
+
+    /// An identifier, mnemonic, or register name.  A register name may
+    /// have a `%` prefix, as in GNU syntax.
+    Ident(String),
+
+    /// An integer literal.
+    Int(i64),
+
+    /// A decimal floating-point literal.
+    Float(Float),
+
+    /// A double-quoted string literal.
+    Str(String),
+
+    /// A single-quoted character literal.
+    Char(String),
+
+    // Punctuation -----
+
+    LParen,     // (
+    RParen,     // )
+    Comma,      // ,
+    Hash,       // #
+    Colon,      // :
+    Assign,     // =
+
+    // Operators -----
+
+    Plus,       // +
+    Minus,      // -
+    Star,       // *
+    Slash,      // /
+    Percent,    // %
+    Shl,        // <<
+    Shr,        // >>
+    ShrS,       // ->>
+    Amp,        // &
+    Pipe,       // |
+    Caret,      // ^
+    Tilde,      // ~
+    Bang,       // !
+    Lt,         // <
+    LtEq,       // <=
+    Gt,         // >
+    GtEq,       // >=
+    EqEq,       // ==
+    NotEq,      // !=
+    AmpAmp,     // &&
+    PipePipe,   // ||
+}
+
+impl Token {
+    /// Returns whether the token can end an operand, in which case a
+    /// following `%` is the modulo operator rather than a binary prefix.
+    fn ends_operand(&self) -> bool {
+        matches!(*self,
+            Token::Ident(_) | Token::Int(_) | Token::Float(_) | Token::Str(_) | Token::Char(_)
+                | Token::RParen
+        )
+    }
+}
+
+/// The tokens of one line of assembly source.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Line<'a> {
+    /// Tokens, each with the zero-based byte offsets at which it begins
+    /// and ends.
+    pub tokens: Vec<(Token, Range<usize>)>,
+
+    /// Text of the trailing comment, if any, without the comment character.
+    pub comment: Option<&'a str>,
+
+    /// Zero-based byte offset of the end of the code portion of the line.
+    pub end: usize,
+}
+
+/// Splits one line of assembly source into tokens.
+///
+/// A `;` outside of a literal begins a comment that extends to the end of the
+/// line.  So does a `/*`, as GNU syntax writes comments; the closing `*/`,
+/// and one space before it, are not part of the comment text.  Whitespace
+/// separates tokens but is otherwise insignificant; callers that care about
+/// leading whitespace should inspect the line directly.
+pub fn lex_line(text: &str, line: usize) -> Result<Line<'_>, ParseError> {
+    Lexer { text, pos: 0, line }.run()
+}
+
+struct Lexer<'a> {
+    text: &'a str,
+    pos:  usize,
+    line: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn run(mut self) -> Result<Line<'a>, ParseError> {
+        let mut tokens = Vec::new();
+
+        loop {
+            self.skip_space();
+
+            let start = self.pos;
+            let c = match self.peek() {
+                None      => break,
+                Some(';') => {
+                    let comment = &self.text[start + 1..];
+                    return Ok(Line { tokens, comment: Some(comment), end: start })
+                },
+                Some('/') if self.peek_at(1) == Some('*') => {
+                    let text    = &self.text[start + 2..];
+                    let comment = match text.trim_end().strip_suffix("*/") {
+                        Some(c) => c.strip_suffix(' ').unwrap_or(c),
+                        None    => text,
+                    };
+                    return Ok(Line { tokens, comment: Some(comment), end: start })
+                },
+                Some(c)   => c,
+            };
+
+            // A % directly after an operand is the modulo operator.  After
+            // whitespace, it is a prefix if it is directly followed by what
+            // it prefixes: `x % 2`, but `move.l %d0` and `dc.b %101`.
+            let after_operand = tokens.last()
+                .is_some_and(|t| t.0.ends_operand())
+                && !self.text[..start].ends_with(char::is_whitespace);
+
+            let token = match c {
+                'a'..='z' | 'A'..='Z' | '_' | '.' => self.ident(),
+                '0'..='9'                         => self.number(10)?,
+                '$'                               => { self.bump(); self.number(16)? },
+                '@'                               => { self.bump(); self.number( 8)? },
+                '%' if !after_operand
+                    && matches!(self.peek_at(1), Some('0') | Some('1'))
+                                                  => { self.bump(); self.number( 2)? },
+                '%' if !after_operand
+                    && self.peek_at(1).is_some_and(|c| c.is_ascii_alphabetic())
+                                                  => { self.bump(); self.ident_from(start) },
+                '"'                               => Token::Str  (self.quoted('"' )?),
+                '\''                              => Token::Char (self.quoted('\'')?),
+                _                                 => self.punct()?,
+            };
+
+            tokens.push((token, start..self.pos));
+        }
+
+        Ok(Line { tokens, comment: None, end: self.text.len() })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn peek_at(&self, n: usize) -> Option<char> {
+        self.text[self.pos..].chars().nth(n)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) { self.bump(); true } else { false }
+    }
+
+    fn skip_space(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    fn error<S: Into<String>>(&self, pos: usize, msg: S) -> ParseError {
+        ParseError::new(self.line, pos + 1, msg)
+    }
+
+    fn ident(&mut self) -> Token {
+        self.ident_from(self.pos)
+    }
+
+    // Scans the rest of an identifier that begins at `start`.
+    fn ident_from(&mut self, start: usize) -> Token {
+        while let Some(c) = self.peek() {
+            match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '.' | '$' => self.bump(),
+                _ => break,
+            };
+        }
+        Token::Ident(self.text[start..self.pos].to_string())
+    }
+
+    fn number(&mut self, mut radix: u32) -> Result<Token, ParseError> {
+        let start = self.pos;
+
+        // C-style prefixes
+        if radix == 10 && self.peek() == Some('0') {
+            match self.peek_at(1) {
+                Some('x') | Some('X') => { radix = 16; self.pos += 2 },
+                Some('b') | Some('B')
+                    if self.peek_at(2).is_some_and(|c| c.is_digit(2))
+                                      => { radix =  2; self.pos += 2 },
+                _                     => (),
+            }
+        }
+
+        let digits = self.pos;
+        if radix == 10 && digits == start {
+            if let Some(token) = self.local_ref() { return Ok(token) }
+            if let Some(token) = self.float()?    { return Ok(token) }
+        }
+
+        let mut value = 0u64;
+        while let Some(c) = self.peek() {
+            if c == '_' { self.bump(); continue }
+            let d = match c.to_digit(radix) {
+                Some(d)                       => d,
+                None if c.is_alphanumeric()   => {
+                    return Err(self.error(self.pos, format!(
+                        "invalid digit '{}' in base-{} literal", c, radix
+                    )))
+                },
+                None                          => break,
+            };
+            value = match value.checked_mul(radix as u64).and_then(|v| v.checked_add(d as u64)) {
+                Some(v) => v,
+                None    => return Err(self.error(start, "integer literal is too large")),
+            };
+            self.bump();
+        }
+
+        if self.pos == digits {
+            return Err(self.error(start, "expected digits"))
+        }
+
+        Ok(Token::Int(value as i64))
+    }
+
+    // Scans a reference to a numeric local label: digits followed by `b` or
+    // `f`, as in `1b`.  Returns `None`, consuming nothing, if the digits are
+    // not followed by just one of those letters.
+    fn local_ref(&mut self) -> Option<Token> {
+        let bytes = self.text.as_bytes();
+        let mut pos = self.pos;
+        while bytes.get(pos).is_some_and(u8::is_ascii_digit) { pos += 1 }
+
+        if !matches!(bytes.get(pos), Some(b'b') | Some(b'f')) {
+            return None
+        }
+        if bytes.get(pos + 1).is_some_and(|&b| b.is_ascii_alphanumeric() || b == b'_' || b == b'.') {
+            return None
+        }
+
+        let start = self.pos;
+        self.pos = pos + 1;
+        Some(Token::Ident(self.text[start..self.pos].to_string()))
+    }
+
+    // Scans a decimal floating-point literal: digits followed by a fraction,
+    // an exponent, or both.  Returns `None`, consuming nothing, if the digits
+    // are just an integer.  A `.` not followed by a digit is not a fraction,
+    // so that `1234.w` remains an integer with a size suffix.
+    fn float(&mut self) -> Result<Option<Token>, ParseError> {
+        let start = self.pos;
+        let digit = |c: Option<char>| c.is_some_and(|c| c.is_ascii_digit());
+
+        let mut pos = start;
+        let at = |p: usize| self.text[p..].chars().next();
+        while digit(at(pos)) || at(pos) == Some('_') { pos += 1 }
+
+        let mut is_float = false;
+        if at(pos) == Some('.') && digit(at(pos + 1)) {
+            pos += 1;
+            while digit(at(pos)) || at(pos) == Some('_') { pos += 1 }
+            is_float = true;
+        }
+        if matches!(at(pos), Some('e') | Some('E')) {
+            let sign = matches!(at(pos + 1), Some('+') | Some('-')) as usize;
+            if digit(at(pos + 1 + sign)) {
+                pos += 1 + sign;
+                while digit(at(pos)) { pos += 1 }
+                is_float = true;
+            }
+        }
+        if !is_float {
+            return Ok(None)
+        }
+        if at(pos).is_some_and(|c| c.is_alphanumeric()) {
+            return Err(self.error(pos, "invalid character in floating-point literal"))
+        }
+
+        let text = self.text[start..pos].replace('_', "");
+        self.pos = pos;
+        match text.parse() {
+            Ok(f)  => Ok(Some(Token::Float(f))),
+            Err(_) => Err(self.error(start, "invalid floating-point literal")),
+        }
+    }
+
+    fn quoted(&mut self, quote: char) -> Result<String, ParseError> {
+        let start = self.pos;
+        let mut s = String::new();
+        self.bump();
+
+        loop {
+            let c = match self.bump() {
+                Some(c) => c,
+                None    => return Err(self.error(start, "unterminated literal")),
+            };
+            match c {
+                // Doubled quote is an escaped quote
+                _ if c == quote => {
+                    if self.eat(quote) { s.push(quote) } else { break }
+                },
+                '\\' => {
+                    let pos = self.pos;
+                    let e = match self.bump() {
+                        Some('0')  => '\0',
+                        Some('b')  => '\x08',
+                        Some('t')  => '\t',
+                        Some('n')  => '\n',
+                        Some('f')  => '\x0C',
+                        Some('r')  => '\r',
+                        Some('x')  => self.hex_escape(pos)?,
+                        Some(c @ '\\') | Some(c @ '\'') | Some(c @ '"') => c,
+                        _ => return Err(self.error(pos, "invalid escape sequence")),
+                    };
+                    s.push(e);
+                },
+                _ => s.push(c),
+            }
+        }
+
+        Ok(s)
+    }
+
+    fn hex_escape(&mut self, pos: usize) -> Result<char, ParseError> {
+        let mut value = 0;
+        for _ in 0..2 {
+            match self.peek().and_then(|c| c.to_digit(16)) {
+                Some(d) => { value = value << 4 | d; self.bump(); },
+                None    => return Err(self.error(pos, "invalid hex escape")),
+            }
+        }
+        Ok(value as u8 as char)
+    }
+
+    fn punct(&mut self) -> Result<Token, ParseError> {
+        let start = self.pos;
+        let c = self.bump().unwrap();
+        let t = match c {
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            ',' => Token::Comma,
+            '#' => Token::Hash,
+            ':' => Token::Colon,
+            '+' => Token::Plus,
+            '*' => Token::Star,
+            '/' => Token::Slash,
+            '%' => Token::Percent,
+            '^' => Token::Caret,
+            '~' => Token::Tilde,
+            '-' => if self.text[self.pos..].starts_with(">>") {
+                       self.pos += 2; Token::ShrS
+                   } else {
+                       Token::Minus
+                   },
+            '<' => if self.eat('<') { Token::Shl  }
+              else if self.eat('=') { Token::LtEq }
+              else                  { Token::Lt   },
+            '>' => if self.eat('>') { Token::Shr  }
+              else if self.eat('=') { Token::GtEq }
+              else                  { Token::Gt   },
+            '=' => if self.eat('=') { Token::EqEq } else { Token::Assign },
+            '!' => if self.eat('=') { Token::NotEq    } else { Token::Bang  },
+            '&' => if self.eat('&') { Token::AmpAmp   } else { Token::Amp   },
+            '|' => if self.eat('|') { Token::PipePipe } else { Token::Pipe  },
+            _   => return Err(self.error(start, format!("unexpected character '{}'", c))),
+        };
+        Ok(t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::Token::*;
+
+    fn lex(s: &str) -> Vec<Token> {
+        lex_line(s, 1).unwrap().tokens.into_iter().map(|t| t.0).collect()
+    }
+
+    #[test]
+    pub fn lex_instruction() {
+        assert_eq!(
+            lex("loop: move.l (8,a0),d1"),
+            vec![
+                Ident("loop".into()), Colon, Ident("move.l".into()),
+                LParen, Int(8), Comma, Ident("a0".into()), RParen,
+                Comma, Ident("d1".into()),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn lex_radix() {
+        assert_eq!(
+            lex("$FF,@17,%101,0x10,0b11,1_000"),
+            vec![
+                Int(0xFF), Comma, Int(0o17), Comma, Int(0b101), Comma,
+                Int(0x10), Comma, Int(0b11), Comma, Int(1000),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn lex_local_labels() {
+        assert_eq!(
+            lex("1: bne 1b,12f,0b"),
+            vec![
+                Int(1), Colon, Ident("bne".into()),
+                Ident("1b".into()), Comma, Ident("12f".into()), Comma, Ident("0b".into()),
+            ]
+        );
+        assert!( lex_line("1bf", 1).is_err() );
+    }
+
+    #[test]
+    pub fn lex_float() {
+        let x = |v: f64| Float(crate::ast::Float::new(v));
+        assert_eq!(
+            lex("2.71,1e3,2.5E-2,1_000.5,1234.w"),
+            vec![
+                x(2.71), Comma, x(1e3), Comma, x(2.5e-2), Comma, x(1000.5), Comma,
+                Int(1234), Ident(".w".into()),
+            ]
+        );
+        assert!( lex_line("1.5x", 1).is_err() );
+    }
+
+    #[test]
+    pub fn lex_percent_after_operand() {
+        assert_eq!( lex("7%10"), vec![Int(7), Percent, Int(10)] );
+        assert_eq!( lex("7*%10"), vec![Int(7), Star, Int(2)] );
+        assert_eq!( lex("8(%a0)"), vec![Int(8), LParen, Ident("%a0".into()), RParen] );
+        assert_eq!( lex("x%y"), vec![Ident("x".into()), Percent, Ident("y".into())] );
+        assert_eq!( lex("x % 10"), vec![Ident("x".into()), Percent, Int(10)] );
+        assert_eq!( lex("dc.b %101"), vec![Ident("dc.b".into()), Int(5)] );
+    }
+
+    #[test]
+    pub fn lex_operators() {
+        assert_eq!(
+            lex("<< >> ->> <= >= == != && || - > < ="),
+            vec![Shl, Shr, ShrS, LtEq, GtEq, EqEq, NotEq, AmpAmp, PipePipe, Minus, Gt, Lt, Assign]
+        );
+    }
+
+    #[test]
+    pub fn lex_literals() {
+        assert_eq!(
+            lex(r#""a\"b\x41" 'it''s'"#),
+            vec![Str("a\"bA".into()), Char("it's".into())]
+        );
+    }
+
+    #[test]
+    pub fn lex_comment() {
+        let line = lex_line("  nop ; do nothing", 1).unwrap();
+        assert_eq!( line.tokens, [(Ident("nop".into()), 2..5)] );
+        assert_eq!( line.comment, Some(" do nothing") );
+        assert_eq!( line.end, 6 );
+
+        let line = lex_line("  nop /* do nothing */", 1).unwrap();
+        assert_eq!( line.tokens, [(Ident("nop".into()), 2..5)] );
+        assert_eq!( line.comment, Some(" do nothing") );
+        assert_eq!( line.end, 6 );
+    }
+
+    #[test]
+    pub fn lex_error() {
+        let err = lex_line("  dc.b \"abc", 3).unwrap_err();
+        assert_eq!( (err.line, err.col), (3, 8) );
+    }
+
+    #[test]
+    pub fn lex_overflow() {
+        assert_eq!( lex("$FFFFFFFFFFFFFFFF"), vec![Int(-1)] );
+        let err = lex_line(" dc.l $10000000000000000", 1).unwrap_err();
+        assert_eq!( (err.col, err.msg.as_str()), (8, "integer literal is too large") );
+    }
+}
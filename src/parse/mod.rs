@@ -0,0 +1,52 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+mod expr;
+mod lexer;
+mod preproc;
+
+pub use self::expr::*;
+pub use self::lexer::*;
+#[allow(unused_imports)] // not yet used outside of tests
+pub use self::preproc::*;
+
+use std::fmt::{self, Display, Formatter};
+
+/// An error encountered while parsing assembly source.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseError {
+    /// Line number, starting from 1.
+    pub line: usize,
+
+    /// Column number, starting from 1.
+    pub col: usize,
+
+    /// Description of the error.
+    pub msg: String,
+}
+
+impl ParseError {
+    /// Creates a new `ParseError`.
+    pub fn new<S: Into<String>>(line: usize, col: usize, msg: S) -> Self {
+        Self { line, col, msg: msg.into() }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.col, self.msg)
+    }
+}
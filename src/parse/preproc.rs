@@ -0,0 +1,894 @@
+// This file is part of cfda, a fun little disassembler project.
+// Copyright (C) 2019 Jeffrey Sharp
+//
+// cfda is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// cfda is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with cfda.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use crate::ast::{eval, IdentPool};
+use super::{into_expr, lex_line, ParseError, Token, Tokens};
+
+/// Maximum nesting of macro expansions, repetitions, and included files.
+const MAX_DEPTH: usize = 64;
+
+/// A source of files to preprocess.
+pub trait SourceFiles {
+    /// Reads the text of the file at `path`.
+    fn read(&mut self, path: &Path) -> io::Result<String>;
+}
+
+/// Files in the file system.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FileSystem;
+
+impl SourceFiles for FileSystem {
+    fn read(&mut self, path: &Path) -> io::Result<String> {
+        std::fs::read_to_string(path)
+    }
+}
+
+/// Files in memory, by path.
+impl SourceFiles for HashMap<PathBuf, String> {
+    fn read(&mut self, path: &Path) -> io::Result<String> {
+        self.get(path).cloned().ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+}
+
+/// The output of preprocessing.
+#[derive(Clone, Default, Debug)]
+pub struct Preprocessed {
+    /// Expanded source text, ready for parsing.
+    pub text: String,
+
+    /// Origin of each line of `text`: an index into `files`, and a line
+    /// number, starting from 1.  Lines expanded from a macro or repetition
+    /// originate at the line that invoked it.
+    pub origins: Vec<(usize, usize)>,
+
+    /// Paths of the files read.  The first is the main file.
+    pub files: Vec<PathBuf>,
+}
+
+impl Preprocessed {
+    /// Returns the path and line number from which line number `line` of
+    /// the expanded text originates.
+    pub fn origin(&self, line: usize) -> Option<(&Path, usize)> {
+        let &(file, line) = self.origins.get(line.checked_sub(1)?)?;
+        Some((&self.files[file], line))
+    }
+}
+
+/// An error encountered while preprocessing.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PreprocError {
+    /// Path of the file containing the error.
+    pub file: PathBuf,
+
+    /// Location and description of the error.
+    pub error: ParseError,
+}
+
+impl Display for PreprocError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.file.display(), self.error)
+    }
+}
+
+/// Preprocesses the assembly source file at `path`, read from `files`.
+///
+/// See `preprocess_str` for the directives recognized.
+pub fn preprocess<S>(path: &Path, files: &mut S, search: &[PathBuf])
+    -> Result<Preprocessed, Vec<PreprocError>>
+where
+    S: SourceFiles + ?Sized
+{
+    match files.read(path) {
+        Ok(src) => preprocess_str(&src, path, files, search),
+        Err(e)  => Err(vec![PreprocError {
+            file:  path.to_path_buf(),
+            error: ParseError::new(0, 0, format!("cannot read file: {}", e)),
+        }]),
+    }
+}
+
+/// Preprocesses assembly source `src`, which has path `path`, expanding
+/// macros, repetitions, conditionals, included files, and symbol
+/// assignments.  Files to include are read from `files`.
+///
+/// Directives may be written with or without a leading `.`, in any case:
+///
+/// ```text
+/// .macro  name p1, p2=default     ; or: name macro p1, p2
+///         move.l  \p1,\p2         ; \1, \2 also name the parameters
+/// l\@:    bra.s   l\@             ; \@ is unique to each expansion
+/// .endm
+///         name    d0, keyword=d1  ; invocation
+/// .rept   count      ... .endr    ; repetition
+/// .irp    p, a, b, c ... .endr    ; repetition with \p = a, b, then c
+/// .if     expr   ... .else ... .endif
+/// .ifdef  name   ... .endif       ; also .ifndef; .endc ends any
+/// .include "file"
+/// .equ    name, expr              ; or: name equ expr
+/// .set    name, expr              ; or: name set expr, or name = expr
+/// ```
+///
+/// `.equ` defines a constant; `.set` defines a symbol that may be set again.
+/// Each expression is evaluated when its directive is reached, using the
+/// symbols defined so far, and later references to a symbol in operands are
+/// replaced by its value.  Labels are not symbols to the preprocessor.
+///
+/// An included file is sought relative to the directory of the including
+/// file, then in each directory of `search` in order.
+///
+/// On success, returns the expanded text and the origin of each line of it.
+/// Otherwise, returns every error encountered; preprocessing continues with
+/// the next line after an error.
+pub fn preprocess_str<S>(src: &str, path: &Path, files: &mut S, search: &[PathBuf])
+    -> Result<Preprocessed, Vec<PreprocError>>
+where
+    S: SourceFiles + ?Sized
+{
+    let mut p = Preprocessor {
+        files,
+        search,
+        macros:  HashMap::new(),
+        symbols: HashMap::new(),
+        conds:   vec![],
+        pool:    IdentPool::new(),
+        out:     Preprocessed::default(),
+        errors:  vec![],
+        unique:  0,
+        depth:   0,
+    };
+
+    p.out.files.push(path.to_path_buf());
+    p.file(src, 0);
+
+    if p.errors.is_empty() {
+        Ok(p.out)
+    } else {
+        Err(p.errors)
+    }
+}
+
+struct Preprocessor<'a, S: SourceFiles + ?Sized> {
+    files:   &'a mut S,
+    search:  &'a [PathBuf],
+    macros:  HashMap<String, Rc<Macro>>,
+    symbols: HashMap<String, Symbol>,
+    conds:   Vec<Cond>,
+    pool:    IdentPool,
+    out:     Preprocessed,
+    errors:  Vec<PreprocError>,
+    unique:  usize,
+    depth:   usize,
+}
+
+// A line of source, with its origin
+#[derive(Clone, Debug)]
+struct SrcLine {
+    text: String,
+    file: usize,
+    line: usize,
+}
+
+// A macro definition
+#[derive(Clone, Debug)]
+struct Macro {
+    params: Vec<(String, Option<String>)>,
+    body:   Vec<String>,
+}
+
+// A symbol defined by .equ or .set
+#[derive(Clone, Copy, Debug)]
+struct Symbol {
+    value:    u32,
+    constant: bool,
+}
+
+// State of a conditional: whether the current branch is active, whether any
+// branch has been taken, and the line of the .if
+#[derive(Clone, Copy, Debug)]
+struct Cond {
+    active: bool,
+    taken:  bool,
+    outer:  bool,
+    file:   usize,
+    line:   usize,
+}
+
+// Preprocessor directives
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Dir {
+    Macro, Endm, Rept, Irp, Endr, If, Ifdef, Ifndef, Else, Endif, Include, Equ, Set,
+}
+
+impl<'a, S: SourceFiles + ?Sized> Preprocessor<'a, S> {
+    // Preprocesses the text of the file with index `file`.
+    fn file(&mut self, src: &str, file: usize) {
+        let lines: Vec<SrcLine> = src.lines().enumerate()
+            .map(|(n, text)| SrcLine { text: text.to_string(), file, line: n + 1 })
+            .collect();
+
+        let conds = self.conds.len();
+        self.run(&lines);
+
+        // Conditionals do not span files
+        for c in self.conds.drain(conds..).collect::<Vec<_>>() {
+            let at = SrcLine { text: String::new(), file: c.file, line: c.line };
+            self.error(&at, ParseError::new(c.line, 1, "'.if' without '.endif'"));
+        }
+    }
+
+    fn run(&mut self, lines: &[SrcLine]) {
+        let mut i = 0;
+        while i < lines.len() {
+            let line = &lines[i];
+            i += 1;
+            if let Err(e) = self.line(line, lines, &mut i) {
+                self.error(line, e);
+            }
+        }
+    }
+
+    fn error(&mut self, at: &SrcLine, error: ParseError) {
+        let file = self.out.files[at.file].clone();
+        self.errors.push(PreprocError { file, error });
+    }
+
+    fn active(&self) -> bool {
+        self.conds.last().is_none_or(|c| c.active)
+    }
+
+    fn emit(&mut self, at: &SrcLine, text: String) {
+        self.out.text.push_str(&text);
+        self.out.text.push('\n');
+        self.out.origins.push((at.file, at.line));
+    }
+
+    // Processes one line.  Directives that take a body consume the lines of
+    // the body from `lines`, advancing `next` past them.
+    fn line(&mut self, at: &SrcLine, lines: &[SrcLine], next: &mut usize)
+        -> Result<(), ParseError>
+    {
+        let parts = split(&at.text);
+        let dir   = directive(parts.op);
+        let err   = |msg: &str| ParseError::new(at.line, parts.op_start + 1, msg);
+
+        // Conditionals
+        match dir {
+            Some(Dir::If) | Some(Dir::Ifdef) | Some(Dir::Ifndef) => {
+                let outer = self.active();
+                let value = outer && match dir {
+                    Some(Dir::If)    => self.eval(at, parts.args_start)? != 0,
+                    Some(Dir::Ifdef) => self.defined(at, parts.args_start)?,
+                    _                => !self.defined(at, parts.args_start)?,
+                };
+                self.conds.push(Cond {
+                    active: value, taken: value, outer, file: at.file, line: at.line
+                });
+                return Ok(())
+            },
+            Some(Dir::Else) => {
+                let c = self.conds.last_mut().ok_or_else(|| err("'.else' without '.if'"))?;
+                c.active = c.outer && !c.taken;
+                c.taken  = true;
+                return Ok(())
+            },
+            Some(Dir::Endif) => {
+                self.conds.pop().ok_or_else(|| err("'.endif' without '.if'"))?;
+                return Ok(())
+            },
+            _ => (),
+        }
+
+        if !self.active() {
+            return Ok(())
+        }
+
+        // Labels on directive lines stand alone
+        let label = |p: &mut Self| if let Some(l) = parts.label {
+            p.emit(at, format!("{}:", l));
+        };
+
+        match dir {
+            Some(Dir::Macro) => {
+                let mut names = split_words(parts.args);
+                let name = match parts.label {
+                    Some(l) => l.to_string(),
+                    None if !names.is_empty() => names.remove(0),
+                    None => return Err(err("expected macro name")),
+                };
+                let params = names.into_iter().map(|p| match p.split_once('=') {
+                    Some((n, d)) => (n.trim().to_string(), Some(d.trim().to_string())),
+                    None         => (p, None),
+                }).collect();
+                let body = collect(lines, next, &[Dir::Macro], Dir::Endm)
+                    .ok_or_else(|| err("'.macro' without '.endm'"))?;
+                self.macros.insert(name.to_ascii_lowercase(), Rc::new(Macro { params, body }));
+            },
+            Some(Dir::Rept) => {
+                label(self);
+                let count = self.eval(at, parts.args_start)? as i32;
+                let body  = collect(lines, next, &[Dir::Rept, Dir::Irp], Dir::Endr)
+                    .ok_or_else(|| err("'.rept' without '.endr'"))?;
+                for _ in 0..count.max(0) {
+                    self.expand(at, &body, &[])?;
+                }
+            },
+            Some(Dir::Irp) => {
+                label(self);
+                let mut args = split_args(parts.args);
+                if args.is_empty() || args[0].is_empty() {
+                    return Err(err("expected parameter name"))
+                }
+                let param = args.remove(0);
+                let body  = collect(lines, next, &[Dir::Rept, Dir::Irp], Dir::Endr)
+                    .ok_or_else(|| err("'.irp' without '.endr'"))?;
+                for value in args {
+                    self.expand(at, &body, &[(param.clone(), value)])?;
+                }
+            },
+            Some(Dir::Endm) => return Err(err("'.endm' without '.macro'")),
+            Some(Dir::Endr) => return Err(err("'.endr' without '.rept' or '.irp'")),
+            Some(Dir::Include) => {
+                label(self);
+                self.include(at, parts.args_start)?;
+            },
+            Some(Dir::Equ) | Some(Dir::Set) => {
+                self.assign(at, &parts, dir == Some(Dir::Equ))?;
+            },
+            _ => {
+                let mac = self.macros.get(&parts.op.to_ascii_lowercase()).cloned();
+                match mac {
+                    Some(mac) => {
+                        label(self);
+                        let args = bind(&mac, parts.args).map_err(|m| err(&m))?;
+                        self.unique += 1;
+                        self.expand(at, &mac.body, &args)?;
+                    },
+                    None => {
+                        let text = self.substitute(&at.text, parts.args_start);
+                        self.emit(at, text);
+                    },
+                }
+            },
+        }
+        Ok(())
+    }
+
+    // Preprocesses `body` with parameters replaced by their values, as if
+    // the lines appeared at `at`.
+    fn expand(&mut self, at: &SrcLine, body: &[String], args: &[(String, String)])
+        -> Result<(), ParseError>
+    {
+        if self.depth >= MAX_DEPTH {
+            return Err(ParseError::new(at.line, 1, "macros or repetitions are nested too deeply"))
+        }
+
+        let unique = self.unique.to_string();
+        let lines: Vec<SrcLine> = body.iter()
+            .map(|b| SrcLine { text: replace_params(b, args, &unique), file: at.file, line: at.line })
+            .collect();
+
+        self.depth += 1;
+        self.run(&lines);
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn include(&mut self, at: &SrcLine, start: usize) -> Result<(), ParseError> {
+        let lexed = lex_line(&at.text, at.line)?;
+        let mut t = tokens_from(&lexed.tokens, at.line, lexed.end, start);
+        let name = match t.next() {
+            Some(Token::Str(s)) => s.clone(),
+            _ => return Err(t.error("expected file name in quotes")),
+        };
+        if !t.at_end() {
+            return Err(t.error("expected end of line"))
+        }
+        if self.depth >= MAX_DEPTH {
+            return Err(ParseError::new(at.line, start + 1, "included files are nested too deeply"))
+        }
+
+        // Relative to the including file, then in each search directory
+        let base = self.out.files[at.file].parent().map(Path::to_path_buf).unwrap_or_default();
+        let dirs = Some(base).into_iter().chain(self.search.iter().cloned());
+        for dir in dirs {
+            let path = dir.join(&name);
+            let text = match self.files.read(&path) {
+                Ok(text)                                         => text,
+                Err(e) if e.kind() == io::ErrorKind::NotFound    => continue,
+                Err(e) => return Err(ParseError::new(at.line, start + 1, format!(
+                    "cannot read '{}': {}", path.display(), e
+                ))),
+            };
+            self.out.files.push(path);
+            let file = self.out.files.len() - 1;
+            self.depth += 1;
+            self.file(&text, file);
+            self.depth -= 1;
+            return Ok(())
+        }
+
+        Err(ParseError::new(at.line, start + 1, format!("cannot find include file '{}'", name)))
+    }
+
+    // .equ and .set, in either the label or the operand form
+    fn assign(&mut self, at: &SrcLine, parts: &Parts, constant: bool) -> Result<(), ParseError> {
+        let lexed = lex_line(&at.text, at.line)?;
+        let mut t = tokens_from(&lexed.tokens, at.line, lexed.end, parts.args_start);
+
+        let name = match parts.label {
+            Some(l) => l.to_string(),
+            None    => match t.next() {
+                Some(Token::Ident(n)) if t.eat(&Token::Comma) => n.clone(),
+                _ => return Err(t.error("expected symbol name and ','")),
+            },
+        };
+        let value = self.eval_tokens(&mut t)?;
+
+        match self.symbols.get(&name) {
+            Some(s) if s.constant || constant => {
+                return Err(ParseError::new(at.line, 1, format!("'{}' is already defined", name)))
+            },
+            _ => (),
+        }
+        self.symbols.insert(name, Symbol { value, constant });
+        Ok(())
+    }
+
+    // Evaluates the expression beginning at byte offset `start` of the line.
+    fn eval(&mut self, at: &SrcLine, start: usize) -> Result<u32, ParseError> {
+        let lexed = lex_line(&at.text, at.line)?;
+        let mut t = tokens_from(&lexed.tokens, at.line, lexed.end, start);
+        self.eval_tokens(&mut t)
+    }
+
+    fn eval_tokens(&mut self, t: &mut Tokens) -> Result<u32, ParseError> {
+        let start = t.offset();
+        let expr  = into_expr(t.expr(&mut self.pool)?);
+        if !t.at_end() {
+            return Err(t.error("expected end of line"))
+        }
+        let pool    = &self.pool;
+        let symbols = &self.symbols;
+        eval(&expr, &|i| symbols.get(pool.name(i)).map(|s| s.value))
+            .map(|v| v.0)
+            .map_err(|e| ParseError::new(t.line(), start + 1, e.message(pool)))
+    }
+
+    // Whether the symbol named at byte offset `start` of the line is defined.
+    fn defined(&mut self, at: &SrcLine, start: usize) -> Result<bool, ParseError> {
+        let lexed = lex_line(&at.text, at.line)?;
+        let mut t = tokens_from(&lexed.tokens, at.line, lexed.end, start);
+        match (t.next(), t.at_end()) {
+            (Some(Token::Ident(n)), true) => Ok(self.symbols.contains_key(n)),
+            _                             => Err(t.error("expected symbol name")),
+        }
+    }
+
+    // Replaces references to symbols at or after byte offset `start` with
+    // their values.  A line that does not lex is left for the parser to
+    // report.
+    fn substitute(&self, text: &str, start: usize) -> String {
+        if self.symbols.is_empty() {
+            return text.to_string()
+        }
+        let lexed = match lex_line(text, 0) {
+            Ok(l)  => l,
+            Err(_) => return text.to_string(),
+        };
+
+        let mut out = String::new();
+        let mut pos = 0;
+        for (token, range) in &lexed.tokens {
+            let value = match *token {
+                Token::Ident(ref n) if range.start >= start => self.symbols.get(n),
+                _                                           => None,
+            };
+            if let Some(s) = value {
+                out.push_str(&text[pos..range.start]);
+                match s.value as i32 {
+                    v if v < 0 => out.push_str(&format!("({})", v)),
+                    v          => out.push_str(&v.to_string()),
+                }
+                pos = range.end;
+            }
+        }
+        out.push_str(&text[pos..]);
+        out
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+
+// Parts of a source line
+#[derive(Clone, Copy, Debug)]
+struct Parts<'a> {
+    label:      Option<&'a str>,
+    op:         &'a str,
+    op_start:   usize,
+    args:       &'a str,
+    args_start: usize,
+}
+
+// Splits a line into label, operation, and arguments, without the comment.
+// As in the parser, a label begins in the first column or ends with ':'.
+fn split(text: &str) -> Parts<'_> {
+    let none = Parts { label: None, op: "", op_start: 0, args: "", args_start: text.len() };
+    if text.starts_with('*') {
+        return none
+    }
+
+    let code  = &text[..comment_start(text)];
+    let word  = |from: usize| {
+        let len = code[from..]
+            .find(|c: char| !is_word_char(c))
+            .unwrap_or(code.len() - from);
+        from + len
+    };
+    let space = |from: usize| {
+        let len = code[from..]
+            .find(|c: char| !c.is_whitespace())
+            .unwrap_or(code.len() - from);
+        from + len
+    };
+
+    let mut label = None;
+    let mut pos   = 0;
+
+    // Label in first column, with optional colon, unless a directive
+    if code.starts_with(is_word_char) {
+        let end = word(0);
+        if code[end..].starts_with(':') || directive(&code[..end]).is_none() {
+            pos   = end;
+            label = Some(&code[..pos]);
+            if code[pos..].starts_with(':') { pos += 1 }
+        }
+    }
+
+    // Label elsewhere, with required colon
+    pos = space(pos);
+    let end = word(pos);
+    if end > pos && code[end..].starts_with(':') {
+        label = Some(&code[pos..end]);
+        pos   = space(end + 1);
+    }
+
+    // Operation, or symbol in 'name = value'
+    let mut op_start = pos;
+    let mut op_end   = word(pos);
+    let eq = space(op_end);
+    if code[eq..].starts_with('=') && !code[eq..].starts_with("==") {
+        if op_end > op_start { label = Some(&code[op_start..op_end]) }
+        op_start = eq;
+        op_end   = eq + 1;
+    }
+
+    let args_start = space(op_end);
+    Parts {
+        label,
+        op: &code[op_start..op_end],
+        op_start,
+        args: code[args_start..].trim_end(),
+        args_start,
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$' | '\\' | '@')
+}
+
+// Byte offset of the ';' that begins a comment, or of the end of the line
+fn comment_start(text: &str) -> usize {
+    let mut quote = None;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (None,    ';' )                 => return i,
+            (None,    '"' ) | (None, '\'')  => quote = Some(c),
+            (Some(q), _   ) if c == q       => quote = None,
+            _                               => (),
+        }
+    }
+    text.len()
+}
+
+// Returns the directive named by an operation, if any.
+fn directive(op: &str) -> Option<Dir> {
+    let lower = op.to_ascii_lowercase();
+    Some(match lower.strip_prefix('.').unwrap_or(&lower) {
+        "macro"             => Dir::Macro,
+        "endm"              => Dir::Endm,
+        "rept"              => Dir::Rept,
+        "irp"               => Dir::Irp,
+        "endr"              => Dir::Endr,
+        "if"                => Dir::If,
+        "ifdef"             => Dir::Ifdef,
+        "ifndef"            => Dir::Ifndef,
+        "else"              => Dir::Else,
+        "endif" | "endc"    => Dir::Endif,
+        "include"           => Dir::Include,
+        "equ"               => Dir::Equ,
+        "set" | "="         => Dir::Set,
+        _                   => return None,
+    })
+}
+
+// Collects the lines of a body up to its closing directive, allowing nested
+// bodies opened by any of `open`.  Returns `None` if the body is not closed.
+fn collect(lines: &[SrcLine], next: &mut usize, open: &[Dir], close: Dir) -> Option<Vec<String>> {
+    let mut depth = 1;
+    let mut body  = vec![];
+    while let Some(line) = lines.get(*next) {
+        *next += 1;
+        match directive(split(&line.text).op) {
+            Some(d) if d == close        => { depth -= 1; if depth == 0 { return Some(body) } },
+            Some(d) if open.contains(&d) => depth += 1,
+            _                            => (),
+        }
+        body.push(line.text.clone());
+    }
+    None
+}
+
+// Returns a cursor over the tokens at or after byte offset `start`.
+fn tokens_from<'t>(tokens: &'t [(Token, std::ops::Range<usize>)], line: usize, end: usize, start: usize)
+    -> Tokens<'t>
+{
+    let first = tokens.iter().position(|t| t.1.start >= start).unwrap_or(tokens.len());
+    Tokens::new(&tokens[first..], line, end)
+}
+
+// Splits arguments at commas outside parentheses and quotes.
+fn split_args(text: &str) -> Vec<String> {
+    let mut args  = vec![];
+    let mut depth = 0;
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (Some(q), _  ) if c == q           => quote = None,
+            (Some(_), _  )                     => (),
+            (None,    '"') | (None, '\'')      => quote = Some(c),
+            (None,    '(')                     => depth += 1,
+            (None,    ')')                     => depth -= 1,
+            (None,    ',') if depth == 0       => {
+                args.push(text[start..i].trim().to_string());
+                start = i + 1;
+            },
+            _                                  => (),
+        }
+    }
+    if !text.trim().is_empty() || !args.is_empty() {
+        args.push(text[start..].trim().to_string());
+    }
+    args
+}
+
+// Splits macro parameter names at commas and whitespace.
+fn split_words(text: &str) -> Vec<String> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|w| !w.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// Binds the arguments of a macro invocation to the macro's parameters.
+// Arguments are positional, or 'name=value' for a named parameter.
+fn bind(mac: &Macro, text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut values: Vec<Option<String>> = vec![None; mac.params.len()];
+    for (n, arg) in split_args(text).into_iter().enumerate() {
+        let named = arg.split_once('=')
+            .filter(|(_, v)| !v.starts_with('='))
+            .and_then(|(k, v)| {
+                let k = k.trim();
+                mac.params.iter().position(|p| p.0 == k).map(|i| (i, v.trim().to_string()))
+            });
+        match named {
+            Some((i, v))                  => values[i] = Some(v),
+            None if n < values.len()      => values[n] = Some(arg),
+            None                          => return Err("too many arguments to macro".into()),
+        }
+    }
+    Ok(mac.params.iter().zip(values)
+        .map(|(p, v)| (p.0.clone(), v.or_else(|| p.1.clone()).unwrap_or_default()))
+        .collect())
+}
+
+// Replaces \name and \1..\9 with the values of parameters, \@ with
+// `unique`, and removes \() separators.
+fn replace_params(text: &str, args: &[(String, String)], unique: &str) -> String {
+    let mut out  = String::new();
+    let mut rest = text;
+
+    while let Some(n) = rest.find('\\') {
+        out.push_str(&rest[..n]);
+        let after = &rest[n + 1..];
+
+        if let Some(r) = after.strip_prefix('@') {
+            out.push_str(unique);
+            rest = r;
+            continue
+        }
+        if let Some(r) = after.strip_prefix("()") {
+            rest = r;
+            continue
+        }
+        if let Some(d) = after.chars().next().and_then(|c| c.to_digit(10)).filter(|&d| d > 0) {
+            if let Some((_, v)) = args.get(d as usize - 1) {
+                out.push_str(v);
+                rest = &after[1..];
+                continue
+            }
+        }
+        let len  = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+        match args.iter().find(|a| a.0 == after[..len]) {
+            Some((_, v)) if len > 0 => { out.push_str(v); rest = &after[len..] },
+            _                       => { out.push('\\');  rest = after         },
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(list: &[(&str, &str)]) -> HashMap<PathBuf, String> {
+        list.iter().map(|&(p, s)| (PathBuf::from(p), s.to_string())).collect()
+    }
+
+    fn pp(src: &str) -> String {
+        preprocess_str(src, Path::new("main.s"), &mut files(&[]), &[]).unwrap().text
+    }
+
+    fn pp_err(src: &str) -> Vec<String> {
+        preprocess_str(src, Path::new("main.s"), &mut files(&[]), &[]).unwrap_err()
+            .iter().map(PreprocError::to_string).collect()
+    }
+
+    #[test]
+    pub fn pp_macro() {
+        assert_eq!(
+            pp(".macro  copy src, dst=d0\n\
+                \x20       move.l  \\src,\\dst\n\
+                l\\@:    bra.s   l\\@\n\
+                .endm\n\
+                start:  copy    (a0)+\n\
+                \x20       copy    d1, dst=(a1)   ; named\n\
+                twice   macro\n\
+                \x20       nop\n\
+                \x20       nop\n\
+                \x20       endm\n\
+                \x20       TWICE\n"),
+            "start:\n\
+             \x20       move.l  (a0)+,d0\n\
+             l1:    bra.s   l1\n\
+             \x20       move.l  d1,(a1)\n\
+             l2:    bra.s   l2\n\
+             \x20       nop\n\
+             \x20       nop\n"
+        );
+    }
+
+    #[test]
+    pub fn pp_positional_and_nested() {
+        assert_eq!(
+            pp(" .macro inner a\n dc.b \\1\n .endm\n\
+                \x20.macro outer x, y\n inner \\x+\\y\n inner \\x\\()0\n .endm\n\
+                \x20outer 1, 2\n"),
+            " dc.b 1+2\n dc.b 10\n"
+        );
+    }
+
+    #[test]
+    pub fn pp_repeat() {
+        assert_eq!(
+            pp(" .set n, 0\n .rept 3\n dc.b n\n .set n, n+1\n .endr\n\
+                \x20.irp r, d0, d1, (a0)\n clr.l \\r\n .endr\n"),
+            " dc.b 0\n dc.b 1\n dc.b 2\n clr.l d0\n clr.l d1\n clr.l (a0)\n"
+        );
+    }
+
+    #[test]
+    pub fn pp_conditional() {
+        assert_eq!(
+            pp("SIZE    equ     4\n\
+                debug = 0\n\
+                \x20       .if     SIZE == 4 && !debug\n\
+                \x20       dc.l    SIZE\n\
+                \x20       .if     debug\n\
+                \x20       dc.b    1\n\
+                \x20       .else\n\
+                \x20       dc.b    -SIZE\n\
+                \x20       .endif\n\
+                \x20       .else\n\
+                \x20       dc.w    SIZE\n\
+                \x20       .endif\n\
+                \x20       .ifdef  SIZE\n\
+                \x20       nop\n\
+                \x20       .endif\n\
+                \x20       .ifndef SIZE\n\
+                \x20       .bogus  \\\n\
+                \x20       .endif\n"),
+            "        dc.l    4\n\
+             \x20       dc.b    -4\n\
+             \x20       nop\n"
+        );
+    }
+
+    #[test]
+    pub fn pp_symbols() {
+        assert_eq!(
+            pp("base: .equ $100\n .set off, -2\n\
+                \x20move.l (base+off,a0),d0 ; base\n\
+                \x20.set off, 4\n\
+                base2 dc.l off,'off'\n"),
+            " move.l (256+(-2),a0),d0 ; base\n\
+             base2 dc.l 4,'off'\n"
+        );
+        assert_eq!(
+            pp_err(" .equ x, 1\n .set x, 2\n .equ y, z\n"),
+            [
+                "main.s:2:1: 'x' is already defined",
+                "main.s:3:10: undefined symbol 'z'",
+            ]
+        );
+    }
+
+    #[test]
+    pub fn pp_include() {
+        let mut fs = files(&[
+            ("src/main.s",       " .include \"defs.i\"\n .include \"regs.i\"\n dc.b X,Y\n"),
+            ("src/defs.i",       "X .equ 1\n"),
+            ("inc/regs.i",       " nop\n .include \"sub/y.i\"\n"),
+            ("inc/sub/y.i",      "Y .equ 2\n"),
+        ]);
+        let search = [PathBuf::from("inc")];
+        let out = preprocess(Path::new("src/main.s"), &mut fs, &search).unwrap();
+        assert_eq!( out.text, " nop\n dc.b 1,2\n" );
+        assert_eq!( out.origin(1), Some((Path::new("inc/regs.i"), 1)) );
+        assert_eq!( out.origin(2), Some((Path::new("src/main.s"), 3)) );
+
+        let err = preprocess(Path::new("src/main.s"), &mut files(&[
+            ("src/main.s", "\n .include \"none.i\"\n"),
+        ]), &search).unwrap_err();
+        assert_eq!( err[0].to_string(), "src/main.s:2:11: cannot find include file 'none.i'" );
+    }
+
+    #[test]
+    pub fn pp_errors() {
+        assert_eq!(
+            pp_err(" .endr\n .endm\n .if 1\n .else\n .endm\n"),
+            [
+                "main.s:1:2: '.endr' without '.rept' or '.irp'",
+                "main.s:2:2: '.endm' without '.macro'",
+                "main.s:3:1: '.if' without '.endif'",
+            ]
+        );
+        assert_eq!( pp_err(" .macro m\n nop\n"), ["main.s:1:2: '.macro' without '.endm'"] );
+        assert_eq!( pp_err(" .endif\n"), ["main.s:1:2: '.endif' without '.if'"] );
+        assert_eq!(
+            pp_err(" .macro m\n m\n .endm\n m\n"),
+            ["main.s:4:1: macros or repetitions are nested too deeply"]
+        );
+    }
+}